@@ -241,6 +241,14 @@ pub fn add_local_apic_id(id: u8) {
 	CPU_LOCAL_APIC_IDS.lock().push(id);
 }
 
+/// Returns the id of the local APIC of the given core, if the core exists.
+pub fn local_apic_id(core_id: CoreId) -> Option<u8> {
+	CPU_LOCAL_APIC_IDS
+		.lock()
+		.get(usize::try_from(core_id).ok()?)
+		.copied()
+}
+
 #[cfg(feature = "smp")]
 pub fn local_apic_id_count() -> u32 {
 	CPU_LOCAL_APIC_IDS.lock().len() as u32
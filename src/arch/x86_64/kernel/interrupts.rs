@@ -1,6 +1,6 @@
 use alloc::collections::BTreeMap;
 use core::arch::asm;
-use core::sync::atomic::{AtomicU64, Ordering};
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
 
 use ahash::RandomState;
 use hashbrown::HashMap;
@@ -270,6 +270,24 @@ extern "x86-interrupt" fn virtualization_exception(stack_frame: ExceptionStackFr
 	scheduler::abort();
 }
 
+/// First interrupt line, which is not routed via the I/O APIC. Lines from here on are
+/// handed out for message signalled interrupts.
+const FIRST_MSI_IRQ: u8 = 24;
+/// Last interrupt line, whose vector precedes the vectors used by the local APIC.
+const LAST_MSI_IRQ: u8 = 79;
+
+static NEXT_MSI_IRQ: AtomicU8 = AtomicU8::new(FIRST_MSI_IRQ);
+
+/// Returns an interrupt line for message signalled interrupts, which is not used
+/// by another device, or None, if all lines have been handed out.
+pub fn allocate_irq() -> Option<u8> {
+	NEXT_MSI_IRQ
+		.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |irq| {
+			(irq <= LAST_MSI_IRQ).then_some(irq + 1)
+		})
+		.ok()
+}
+
 static IRQ_NAMES: InterruptTicketMutex<HashMap<u32, &'static str, RandomState>> =
 	InterruptTicketMutex::new(HashMap::with_hasher(RandomState::with_seeds(0, 0, 0, 0)));
 
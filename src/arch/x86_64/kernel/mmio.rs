@@ -10,8 +10,7 @@ use crate::arch::x86_64::mm::paging::{
 use crate::arch::x86_64::mm::{paging, PhysAddr};
 use crate::drivers::net::virtio_net::VirtioNetDriver;
 use crate::drivers::net::NetworkInterface;
-use crate::drivers::virtio::transport::mmio as mmio_virtio;
-use crate::drivers::virtio::transport::mmio::{DevId, MmioRegisterLayout, VirtioDriver};
+use crate::drivers::virtio::transport::mmio::{DevId, MmioRegisterLayout};
 
 pub const MAGIC_VALUE: u32 = 0x74726976;
 
@@ -134,7 +133,9 @@ pub fn init_drivers() {
 				"Found MMIO device, but we guess the interrupt number {}!",
 				IRQ_NUMBER
 			);
-			if let Ok(VirtioDriver::Network(drv)) = mmio_virtio::init_device(mmio, IRQ_NUMBER) {
+			let base = mmio as *mut MmioRegisterLayout as usize;
+			// SAFETY: detect_network has mapped the register block permanently.
+			if let Ok(drv) = unsafe { VirtioNetDriver::init_mmio(base, IRQ_NUMBER) } {
 				register_driver(MmioDriver::VirtioNet(InterruptTicketMutex::new(drv)))
 			}
 		} else {
@@ -505,6 +505,12 @@ pub mod error {
 		NoBar(u16),
 		NoCapPtr(u16),
 		BadCapPtr(u16),
+		/// A capability of the list does not fit into the configuration space.
+		TruncatedCapList(u16),
+		/// The capabilities list does not terminate.
+		CapListLoop(u16),
 		NoVirtioCaps(u16),
+		/// None of the virtio capabilities is a common configuration structure.
+		NoComCfg(u16),
 	}
 }
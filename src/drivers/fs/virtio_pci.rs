@@ -125,7 +125,7 @@ impl VirtioFsDriver {
 				}
 			},
 			Err(pci_error) => {
-				error!("Mapping capabilities failed. Aborting!");
+				error!("Mapping capabilities failed: {:?}. Aborting!", pci_error);
 				return Err(VirtioError::FromPci(pci_error));
 			}
 		};
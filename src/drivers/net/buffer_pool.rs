@@ -0,0 +1,306 @@
+//! Pool of packet buffers, which are recycled instead of being freed.
+//!
+//! The DMA buffers of the receive queues are allocated once and returned to the
+//! device after each packet. A packet, which occupies a single DMA buffer, can be
+//! handed up without copying, by lending the DMA buffer itself (see [BufferPool::lend]).
+//! Otherwise, the pool avoids the allocation of the buffers, into which the received
+//! packets are copied before they are handed up.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ops::{Deref, DerefMut};
+use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use hermit_sync::InterruptTicketMutex;
+
+/// State shared by the pool and its handed out buffers.
+struct Shared {
+	free: InterruptTicketMutex<Vec<Vec<u8>>>,
+	/// Ids of lent buffers, which have been dropped, but not yet reclaimed by the lender.
+	returned: InterruptTicketMutex<Vec<usize>>,
+	in_use: AtomicUsize,
+	capacity: usize,
+	buf_len: usize,
+}
+
+/// Utilization of a [BufferPool].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PoolUsage {
+	/// Number of buffers, which are currently handed out, including lent buffers.
+	pub in_use: usize,
+	/// Number of buffers, which are ready to be handed out.
+	pub free: usize,
+	/// Number of buffers the pool keeps at most.
+	pub capacity: usize,
+}
+
+/// Fixed number of buffers of equal size.
+///
+/// All buffers are allocated upon creation. If the pool is exhausted, additional buffers
+/// are allocated, which are only kept on their return, if the pool has room for them.
+pub struct BufferPool {
+	shared: Arc<Shared>,
+}
+
+impl BufferPool {
+	/// Creates a pool of `capacity` buffers, which are able to hold `buf_len` bytes
+	/// without reallocation.
+	pub fn new(capacity: usize, buf_len: usize) -> Self {
+		let free = (0..capacity).map(|_| Vec::with_capacity(buf_len)).collect();
+
+		Self {
+			shared: Arc::new(Shared {
+				free: InterruptTicketMutex::new(free),
+				returned: InterruptTicketMutex::new(Vec::new()),
+				in_use: AtomicUsize::new(0),
+				capacity,
+				buf_len,
+			}),
+		}
+	}
+
+	/// Returns an empty buffer, which returns to the pool when dropped.
+	pub fn get(&self) -> PooledBuffer {
+		let buf = self
+			.shared
+			.free
+			.lock()
+			.pop()
+			.unwrap_or_else(|| Vec::with_capacity(self.shared.buf_len));
+		self.shared.in_use.fetch_add(1, Ordering::Relaxed);
+
+		PooledBuffer {
+			data: Data::Owned(buf),
+			pool: Arc::clone(&self.shared),
+		}
+	}
+
+	/// Lends the `len` bytes at `ptr`, which belong to a buffer of the lender, under the
+	/// given `id`. Once the returned handle is dropped, `id` is reported by
+	/// [take_returned](Self::take_returned).
+	///
+	/// # Safety
+	///
+	/// The memory must stay valid and must not be accessed by the lender, until `id` has
+	/// been reported by [take_returned](Self::take_returned). A lender, which is unable to
+	/// wait for it, has to leak the memory.
+	pub(super) unsafe fn lend(&self, id: usize, ptr: *mut u8, len: usize) -> PooledBuffer {
+		self.shared.in_use.fetch_add(1, Ordering::Relaxed);
+
+		PooledBuffer {
+			data: Data::Lent { id, ptr, len },
+			pool: Arc::clone(&self.shared),
+		}
+	}
+
+	/// Returns the ids of all lent buffers, which have been dropped since the last call.
+	pub(super) fn take_returned(&self) -> Vec<usize> {
+		core::mem::take(&mut *self.shared.returned.lock())
+	}
+
+	/// Returns the current utilization of the pool.
+	pub fn usage(&self) -> PoolUsage {
+		PoolUsage {
+			in_use: self.shared.in_use.load(Ordering::Relaxed),
+			free: self.shared.free.lock().len(),
+			capacity: self.shared.capacity,
+		}
+	}
+}
+
+enum Data {
+	Owned(Vec<u8>),
+	/// Memory of the lender. See [BufferPool::lend].
+	Lent {
+		id: usize,
+		ptr: *mut u8,
+		len: usize,
+	},
+}
+
+/// Buffer of a [BufferPool], which is returned to the pool when dropped.
+pub struct PooledBuffer {
+	data: Data,
+	pool: Arc<Shared>,
+}
+
+// SAFETY: Owned buffers are plain vectors. The memory of lent buffers is reserved for
+// the holder of the handle, until the handle is dropped. See [BufferPool::lend].
+unsafe impl Send for PooledBuffer {}
+
+impl PooledBuffer {
+	/// Detaches the buffer from the pool. The pool replaces it by allocating
+	/// a new buffer, once it is exhausted. A lent buffer is copied and returned
+	/// to its lender.
+	pub fn into_vec(mut self) -> Vec<u8> {
+		if self.is_lent() {
+			return self.to_vec();
+		}
+
+		core::mem::take(self.as_vec_mut())
+	}
+
+	/// Returns true, if the buffer is lent by the owner of the pool.
+	pub fn is_lent(&self) -> bool {
+		matches!(self.data, Data::Lent { .. })
+	}
+
+	/// Returns the underlying vector. A lent buffer is copied into a buffer of the
+	/// pool beforehand and returned to its lender.
+	pub(super) fn as_vec_mut(&mut self) -> &mut Vec<u8> {
+		if self.is_lent() {
+			let mut buf = self
+				.pool
+				.free
+				.lock()
+				.pop()
+				.unwrap_or_else(|| Vec::with_capacity(self.pool.buf_len));
+			buf.extend_from_slice(&self[..]);
+			if let Data::Lent { id, .. } = core::mem::replace(&mut self.data, Data::Owned(buf)) {
+				self.pool.returned.lock().push(id);
+			}
+		}
+
+		match &mut self.data {
+			Data::Owned(buf) => buf,
+			Data::Lent { .. } => unreachable!(),
+		}
+	}
+
+	/// Removes the first `len` bytes of the buffer.
+	pub(super) fn strip_front(&mut self, len: usize) {
+		match &mut self.data {
+			Data::Owned(buf) => {
+				buf.drain(..len);
+			}
+			Data::Lent { ptr, len: lent, .. } => {
+				assert!(len <= *lent);
+				// SAFETY: The offset stays inside the lent memory.
+				*ptr = unsafe { (*ptr).add(len) };
+				*lent -= len;
+			}
+		}
+	}
+}
+
+impl Deref for PooledBuffer {
+	type Target = [u8];
+
+	fn deref(&self) -> &[u8] {
+		match &self.data {
+			Data::Owned(buf) => buf,
+			// SAFETY: The memory is reserved for the handle. See [BufferPool::lend].
+			Data::Lent { ptr, len, .. } => unsafe { slice::from_raw_parts(*ptr, *len) },
+		}
+	}
+}
+
+impl DerefMut for PooledBuffer {
+	fn deref_mut(&mut self) -> &mut [u8] {
+		match &mut self.data {
+			Data::Owned(buf) => buf,
+			// SAFETY: The memory is reserved for the handle. See [BufferPool::lend].
+			Data::Lent { ptr, len, .. } => unsafe { slice::from_raw_parts_mut(*ptr, *len) },
+		}
+	}
+}
+
+impl Drop for PooledBuffer {
+	fn drop(&mut self) {
+		self.pool.in_use.fetch_sub(1, Ordering::Relaxed);
+
+		// Buffers detached by into_vec have no capacity left.
+		let mut buf = match core::mem::replace(&mut self.data, Data::Owned(Vec::new())) {
+			Data::Owned(buf) => buf,
+			Data::Lent { id, .. } => {
+				self.pool.returned.lock().push(id);
+				return;
+			}
+		};
+		if buf.capacity() < self.pool.buf_len {
+			return;
+		}
+
+		let mut free = self.pool.free.lock();
+		if free.len() < self.pool.capacity {
+			buf.clear();
+			free.push(buf);
+		}
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn buffers_are_recycled() {
+		let pool = BufferPool::new(2, 64);
+
+		let mut buf = pool.get();
+		buf.as_vec_mut().extend_from_slice(&[1, 2, 3]);
+		let ptr = buf.as_ptr();
+		assert_eq!(
+			pool.usage(),
+			PoolUsage {
+				in_use: 1,
+				free: 1,
+				capacity: 2
+			}
+		);
+
+		drop(buf);
+		assert_eq!(pool.usage().in_use, 0);
+		assert_eq!(pool.usage().free, 2);
+
+		let buf = pool.get();
+		assert!(buf.is_empty());
+		assert_eq!(buf.as_ptr(), ptr);
+	}
+
+	#[test]
+	fn pool_does_not_grow_beyond_capacity() {
+		let pool = BufferPool::new(1, 64);
+
+		let first = pool.get();
+		let second = pool.get();
+		assert_eq!(pool.usage().in_use, 2);
+		assert_eq!(pool.usage().free, 0);
+
+		drop(first);
+		drop(second);
+		assert_eq!(pool.usage().free, 1);
+
+		let detached = pool.get().into_vec();
+		assert!(detached.capacity() >= 64);
+		assert_eq!(pool.usage().in_use, 0);
+		assert_eq!(pool.usage().free, 0);
+	}
+
+	#[test]
+	fn lent_buffers_are_returned_to_the_lender() {
+		let pool = BufferPool::new(1, 64);
+		let mut dma = [1u8, 2, 3, 4, 5];
+
+		let mut buf = unsafe { pool.lend(7, dma.as_mut_ptr(), dma.len()) };
+		buf.strip_front(2);
+		assert!(buf.is_lent());
+		assert_eq!(&*buf, &[3, 4, 5]);
+		assert_eq!(pool.usage().in_use, 1);
+
+		drop(buf);
+		assert_eq!(pool.usage().in_use, 0);
+		assert_eq!(pool.take_returned(), [7]);
+		assert!(pool.take_returned().is_empty());
+
+		// A lent buffer, which must grow, is copied and returned immediately.
+		let mut buf = unsafe { pool.lend(8, dma.as_mut_ptr(), dma.len()) };
+		buf.as_vec_mut().push(6);
+		assert!(!buf.is_lent());
+		assert_eq!(pool.take_returned(), [8]);
+		assert_eq!(&*buf, &[1, 2, 3, 4, 5, 6]);
+		drop(buf);
+		assert_eq!(pool.usage().free, 1);
+	}
+}
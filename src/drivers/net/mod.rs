@@ -1,3 +1,4 @@
+pub mod buffer_pool;
 #[cfg(feature = "pci")]
 pub mod rtl8139;
 #[cfg(not(feature = "pci"))]
@@ -5,8 +6,8 @@ pub mod virtio_mmio;
 pub mod virtio_net;
 #[cfg(feature = "pci")]
 pub mod virtio_pci;
-
-use alloc::vec::Vec;
+#[cfg(feature = "tcp")]
+pub mod virtio_phy;
 
 use crate::arch::kernel::apic;
 use crate::arch::kernel::core_local::*;
@@ -15,6 +16,7 @@ use crate::arch::kernel::interrupts::ExceptionStackFrame;
 use crate::arch::kernel::mmio as hardware;
 #[cfg(feature = "pci")]
 use crate::arch::kernel::pci as hardware;
+use crate::drivers::net::buffer_pool::PooledBuffer;
 
 /// A trait for accessing the network interface
 pub trait NetworkInterface {
@@ -33,11 +35,19 @@ pub trait NetworkInterface {
 	/// Check if a packet is available
 	fn has_packet(&self) -> bool;
 	/// Get RX buffer with an received packet
-	fn receive_rx_buffer(&mut self) -> Result<Vec<u8>, ()>;
+	///
+	/// The buffer is recycled by the driver, once it is dropped.
+	fn receive_rx_buffer(&mut self) -> Result<PooledBuffer, ()>;
 	/// Enable / disable the polling mode of the network interface
 	fn set_polling_mode(&mut self, value: bool);
 	/// Handle interrupt and check if a packet is available
 	fn handle_interrupt(&mut self) -> bool;
+	/// Handle an interrupt, which signals a change of the device configuration.
+	/// Only used by devices, which signal such changes separately.
+	fn handle_config_interrupt(&mut self) {}
+	/// Informs the driver about the IPv4 address of the interface. Devices, which need to
+	/// announce the interface, e.g. after a live migration, announce this address.
+	fn set_ipv4_addr(&mut self, _addr: [u8; 4]) {}
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -59,3 +69,15 @@ pub extern "x86-interrupt" fn network_irqhandler(_stack_frame: ExceptionStackFra
 		core_scheduler.scheduler();
 	}
 }
+
+#[cfg(target_arch = "x86_64")]
+pub extern "x86-interrupt" fn network_cfg_irqhandler(_stack_frame: ExceptionStackFrame) {
+	debug!("Receive network configuration interrupt");
+	apic::eoi();
+
+	if let Some(driver) = hardware::get_network_driver() {
+		driver.lock().handle_config_interrupt();
+	} else {
+		debug!("Unable to handle interrupt!");
+	}
+}
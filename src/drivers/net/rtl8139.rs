@@ -3,7 +3,6 @@
 #![allow(dead_code)]
 
 use alloc::boxed::Box;
-use alloc::vec::Vec;
 use core::mem;
 
 use x86::io::*;
@@ -14,12 +13,15 @@ use crate::arch::kernel::pci;
 use crate::arch::mm::paging::virt_to_phys;
 use crate::arch::mm::VirtAddr;
 use crate::drivers::error::DriverError;
+use crate::drivers::net::buffer_pool::{BufferPool, PooledBuffer};
 use crate::drivers::net::{network_irqhandler, NetworkInterface};
 
 /// size of the receive buffer
 const RX_BUF_LEN: usize = 8192;
 /// size of the send buffer
 const TX_BUF_LEN: usize = 4096;
+/// number of buffers, into which received packets are copied
+const RX_POOL_SIZE: usize = 16;
 
 /// the ethernet ID (6bytes) => MAC address
 const IDR0: u16 = 0x0;
@@ -209,6 +211,7 @@ pub struct RTL8139Driver {
 	rxbuffer: Box<[u8]>,
 	rxpos: usize,
 	txbuffer: Box<[u8]>,
+	rx_pool: BufferPool,
 	polling_mode_counter: u32,
 }
 
@@ -270,7 +273,7 @@ impl NetworkInterface for RTL8139Driver {
 		false
 	}
 
-	fn receive_rx_buffer(&mut self) -> Result<Vec<u8>, ()> {
+	fn receive_rx_buffer(&mut self) -> Result<PooledBuffer, ()> {
 		let cmd = unsafe { inb(self.iobase + CR) };
 
 		if (cmd & CR_BUFE) != CR_BUFE {
@@ -283,13 +286,16 @@ impl NetworkInterface for RTL8139Driver {
 
 				// do we reach the end of the receive buffers?
 				// in this case, we conact the two slices to one vec
-				let buf = if pos + length as usize > RX_BUF_LEN {
+				let mut buf = self.rx_pool.get();
+				if pos + length as usize > RX_BUF_LEN {
 					let first = &self.rxbuffer[pos..RX_BUF_LEN];
 					let second = &self.rxbuffer[..length as usize - first.len()];
-					[first, second].concat()
+					buf.as_vec_mut().extend_from_slice(first);
+					buf.as_vec_mut().extend_from_slice(second);
 				} else {
-					(self.rxbuffer[pos..][..length.into()]).to_vec()
-				};
+					buf.as_vec_mut()
+						.extend_from_slice(&self.rxbuffer[pos..][..length.into()]);
+				}
 
 				self.consume_current_buffer();
 
@@ -593,6 +599,7 @@ pub fn init_device(adapter: &pci::PciAdapter) -> Result<RTL8139Driver, DriverErr
 		rxbuffer,
 		rxpos: 0,
 		txbuffer,
+		rx_pool: BufferPool::new(RX_POOL_SIZE, TX_BUF_LEN),
 		polling_mode_counter: 0,
 	})
 }
@@ -4,16 +4,26 @@
 
 use alloc::collections::VecDeque;
 use alloc::rc::Rc;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::arch::x86_64::_mm_mfence;
 use core::cell::RefCell;
 use core::convert::TryInto;
 use core::ptr::read_volatile;
 
+use crate::drivers::error::DriverError;
+use crate::drivers::net::buffer_pool::BufferPool;
 use crate::drivers::net::virtio_net::constants::{FeatureSet, Status};
-use crate::drivers::net::virtio_net::{CtrlQueue, NetDevCfg, RxQueues, TxQueues, VirtioNetDriver};
+use crate::drivers::net::virtio_net::{
+	CtrlQueue, DevCfgLayout, InterruptMode, IrqStorm, LinkState, NetCounters, NetDevCfg, RxQueues,
+	SteeringMode, TxQueues, VirtioNetConfig, VirtioNetDriver,
+};
+use crate::drivers::virtio::env::memory::MmioRef;
 use crate::drivers::virtio::error::{VirtioError, VirtioNetError};
-use crate::drivers::virtio::transport::mmio::{ComCfg, IsrStatus, MmioRegisterLayout, NotifCfg};
+use crate::drivers::virtio::transport::mmio::{
+	self, MmioRegisterLayout, MmioTransport, VirtioDriver,
+};
+use crate::drivers::virtio::transport::VirtioTransport;
 use crate::drivers::virtio::virtqueue::Virtq;
 
 /// Virtio's network device configuration structure.
@@ -30,6 +40,16 @@ pub struct NetDevCfgRaw {
 	max_virtqueue_pairs: u16,
 	// Indicates the maximum MTU driver should use. Only valid if VIRTIONET_F_MTU is set.
 	mtu: u16,
+	// Speed of the link in units of 1 MBit/s. Only valid if VIRTIO_NET_F_SPEED_DUPLEX is set.
+	speed: u32,
+	// Duplex mode of the link. Only valid if VIRTIO_NET_F_SPEED_DUPLEX is set.
+	duplex: u8,
+	// Maximum length of the RSS hash key in bytes. Only valid if VIRTIO_NET_F_RSS is set.
+	rss_max_key_size: u8,
+	// Maximum number of entries of the RSS indirection table. Only valid if VIRTIO_NET_F_RSS is set.
+	rss_max_indirection_table_length: u16,
+	// Bit mask of the supported hash types. Only valid if VIRTIO_NET_F_RSS is set.
+	supported_hash_types: u32,
 }
 
 impl NetDevCfgRaw {
@@ -87,6 +107,92 @@ impl NetDevCfgRaw {
 		}
 	}
 
+	pub fn get_speed(&self) -> u32 {
+		// see Virtio specification v1.1 -  2.4.1
+		unsafe {
+			loop {
+				let before = read_volatile(&self.config_generation);
+				_mm_mfence();
+				let speed = read_volatile(&self.speed);
+				_mm_mfence();
+				let after = read_volatile(&self.config_generation);
+
+				if before == after {
+					return speed;
+				}
+			}
+		}
+	}
+
+	pub fn get_duplex(&self) -> u8 {
+		// see Virtio specification v1.1 -  2.4.1
+		unsafe {
+			loop {
+				let before = read_volatile(&self.config_generation);
+				_mm_mfence();
+				let duplex = read_volatile(&self.duplex);
+				_mm_mfence();
+				let after = read_volatile(&self.config_generation);
+
+				if before == after {
+					return duplex;
+				}
+			}
+		}
+	}
+
+	pub fn get_rss_max_key_size(&self) -> u8 {
+		// see Virtio specification v1.1 -  2.4.1
+		unsafe {
+			loop {
+				let before = read_volatile(&self.config_generation);
+				_mm_mfence();
+				let rss_max_key_size = read_volatile(&self.rss_max_key_size);
+				_mm_mfence();
+				let after = read_volatile(&self.config_generation);
+
+				if before == after {
+					return rss_max_key_size;
+				}
+			}
+		}
+	}
+
+	pub fn get_rss_max_indirection_table_length(&self) -> u16 {
+		// see Virtio specification v1.1 -  2.4.1
+		unsafe {
+			loop {
+				let before = read_volatile(&self.config_generation);
+				_mm_mfence();
+				let rss_max_indirection_table_length =
+					read_volatile(&self.rss_max_indirection_table_length);
+				_mm_mfence();
+				let after = read_volatile(&self.config_generation);
+
+				if before == after {
+					return rss_max_indirection_table_length;
+				}
+			}
+		}
+	}
+
+	pub fn get_supported_hash_types(&self) -> u32 {
+		// see Virtio specification v1.1 -  2.4.1
+		unsafe {
+			loop {
+				let before = read_volatile(&self.config_generation);
+				_mm_mfence();
+				let supported_hash_types = read_volatile(&self.supported_hash_types);
+				_mm_mfence();
+				let after = read_volatile(&self.config_generation);
+
+				if before == after {
+					return supported_hash_types;
+				}
+			}
+		}
+	}
+
 	pub fn get_max_virtqueue_pairs(&self) -> u16 {
 		// see Virtio specification v1.1 -  2.4.1
 		unsafe {
@@ -107,24 +213,46 @@ impl NetDevCfgRaw {
 
 // Backend-dependent interface for Virtio network driver
 impl VirtioNetDriver {
-	pub fn new(
-		dev_id: u16,
-		registers: &'static mut MmioRegisterLayout,
-		irq: u8,
-	) -> Result<Self, VirtioNetError> {
+	/// Instantiates a new [VirtioNetDriver] from the configuration structures of the
+	/// given transport.
+	pub fn new(mut transport: MmioTransport, irq: u8) -> Result<Self, VirtioNetError> {
+		let dev_id = transport.dev_id();
 		let dev_cfg_raw: &'static NetDevCfgRaw =
-			unsafe { &*(((registers as *const _ as usize) + 0xFC) as *const NetDevCfgRaw) };
+			unsafe { &*(transport.dev_cfg_addr() as *const NetDevCfgRaw) };
+		let com_cfg = match transport.take_com_cfg() {
+			Some(com_cfg) => com_cfg,
+			None => {
+				error!("No common config. Aborting!");
+				return Err(VirtioNetError::NoComCfg(dev_id));
+			}
+		};
+		let isr_stat = match transport.take_isr_cfg() {
+			Some(isr_stat) => isr_stat,
+			None => {
+				error!("No ISR status config. Aborting!");
+				return Err(VirtioNetError::NoIsrCfg(dev_id));
+			}
+		};
+		let notif_cfg = match transport.take_notif_cfg() {
+			Some(notif_cfg) => notif_cfg,
+			None => {
+				error!("No notif config. Aborting!");
+				return Err(VirtioNetError::NoNotifCfg(dev_id));
+			}
+		};
 		let dev_cfg = NetDevCfg {
-			raw: dev_cfg_raw,
+			// SAFETY: The device configuration is part of the register layout of the device.
+			raw: unsafe { MmioRef::new(dev_cfg_raw) },
 			dev_id,
+			// The legacy interface is rejected by the transport.
+			legacy: false,
+			layout: DevCfgLayout::Modern,
 			features: FeatureSet::new(0),
 		};
-		let isr_stat = IsrStatus::new(registers);
-		let notif_cfg = NotifCfg::new(registers);
 
 		Ok(VirtioNetDriver {
 			dev_cfg,
-			com_cfg: ComCfg::new(registers, 1),
+			com_cfg,
 			isr_stat,
 			notif_cfg,
 			ctrl_vq: CtrlQueue::new(None),
@@ -140,14 +268,32 @@ impl VirtioNetDriver {
 				false,
 			),
 			num_vqs: 0,
+			steering: SteeringMode::Single,
+			initial_link: LinkState::Down,
+			link: LinkState::Down,
+			interrupt_mode: InterruptMode::Legacy,
 			irq,
 			polling_mode_counter: 0,
+			irq_storm: IrqStorm::default(),
+			stats: vec![NetCounters::default()],
+			rx_drop: false,
+			notif_enabled: true,
+			generated_mac: [0; 6],
+			assigned_mac: None,
+			ipv4_addr: None,
+			vlans: Vec::new(),
+			promiscuous: false,
+			rx_handler: None,
+			rx_wakers: Vec::new(),
+			last_tx_completion: None,
+			rx_pool: BufferPool::new(0, 0),
+			config: VirtioNetConfig::default(),
 		})
 	}
 
 	pub fn print_information(&mut self) {
 		self.com_cfg.print_information();
-		if self.dev_status() == u16::from(Status::VIRTIO_NET_S_LINK_UP) {
+		if self.dev_status() & u16::from(Status::VIRTIO_NET_S_LINK_UP) != 0 {
 			info!("The link of the network device is up!");
 		}
 	}
@@ -157,12 +303,40 @@ impl VirtioNetDriver {
 	///
 	/// Returns a driver instance of
 	/// [VirtioNetDriver](structs.virtionetdriver.html) or an [VirtioError](enums.virtioerror.html).
-	pub fn init(
-		dev_id: u16,
-		registers: &'static mut MmioRegisterLayout,
+	pub fn init(transport: MmioTransport, irq_no: u32) -> Result<VirtioNetDriver, VirtioError> {
+		VirtioNetDriver::init_with_config(transport, irq_no, VirtioNetConfig::default())
+	}
+
+	/// Probes the virtio MMIO register block, which is mapped at the virtual address
+	/// `base`, and initializes the network device behind it. The interrupt handler is
+	/// installed for `irq_no`.
+	///
+	/// Platforms without PCI use this function for the devices, which they find at a
+	/// fixed address or via a device tree. See Virtio specification v1.1. - 4.2.2
+	///
+	/// # Safety
+	///
+	/// `base` must be the virtual address of a mapped MMIO register block, which stays
+	/// mapped as long as the device is present.
+	pub unsafe fn init_mmio(base: usize, irq_no: u32) -> Result<VirtioNetDriver, VirtioError> {
+		let registers = unsafe { &mut *(base as *mut MmioRegisterLayout) };
+
+		// Devices of other types are rejected, as only the network driver is supported.
+		match mmio::init_device(registers, irq_no) {
+			Ok(VirtioDriver::Network(drv)) => Ok(drv),
+			Err(DriverError::InitVirtioDevFail(virtio_error)) => Err(virtio_error),
+		}
+	}
+
+	/// Initializes the virtio network device like [VirtioNetDriver::init], but uses the
+	/// given driver configuration instead of the default one.
+	pub fn init_with_config(
+		transport: MmioTransport,
 		irq_no: u32,
+		config: VirtioNetConfig,
 	) -> Result<VirtioNetDriver, VirtioError> {
-		if let Ok(mut drv) = VirtioNetDriver::new(dev_id, registers, irq_no.try_into().unwrap()) {
+		if let Ok(mut drv) = VirtioNetDriver::new(transport, irq_no.try_into().unwrap()) {
+			drv.config = config;
 			match drv.init_dev() {
 				Err(error_code) => Err(VirtioError::NetDriver(error_code)),
 				_ => {
@@ -6,43 +6,658 @@ use alloc::boxed::Box;
 use alloc::collections::VecDeque;
 use alloc::rc::Rc;
 use alloc::vec::Vec;
-use core::cell::RefCell;
+use core::cell::{Cell, RefCell};
 use core::cmp::Ordering;
 use core::mem;
 use core::result::Result;
+use core::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
 
 use zerocopy::AsBytes;
 
-use self::constants::{FeatureSet, Features, NetHdrGSO, Status, MAX_NUM_VQ};
-use self::error::VirtioNetError;
+use self::constants::{
+	FeatureSet, Features, NetHdrFlag, NetHdrGSO, Status, CTRL_CMD_TIMEOUT, INDIRECT_TX_THRESHOLD,
+	IRQ_STORM_WINDOW, ISR_CFG_CHANGE, ISR_QUEUE, MAX_DIRECT_TX_CHAIN, MAX_GSO_LEN, MAX_NUM_VQ,
+	MIN_TSO_MSS, RSS_DEFAULT_HASH_TYPES, RSS_DEFAULT_KEY, RSS_DEFAULT_TABLE_LEN,
+	TX_BORROWED_TIMEOUT,
+};
+use self::error::{FeatureDiff, LoopbackStage, VirtioNetError};
 use crate::arch::kernel::core_local::increment_irq_counter;
+#[cfg(feature = "pci")]
+use crate::arch::kernel::pci::PciAdapter;
+use crate::arch::kernel::processor::get_timer_ticks;
+use crate::arch::mm::paging::{BasePageSize, PageSize};
 use crate::config::VIRTIO_MAX_QUEUE_SIZE;
+use crate::drivers::net::buffer_pool::{BufferPool, PoolUsage, PooledBuffer};
 #[cfg(not(feature = "pci"))]
 use crate::drivers::net::virtio_mmio::NetDevCfgRaw;
 #[cfg(feature = "pci")]
 use crate::drivers::net::virtio_pci::NetDevCfgRaw;
 use crate::drivers::net::NetworkInterface;
+use crate::drivers::virtio::device;
+use crate::drivers::virtio::env::dma::{DmaAllocator, KernelAllocator};
+use crate::drivers::virtio::env::memory::MmioRef;
 #[cfg(not(feature = "pci"))]
 use crate::drivers::virtio::transport::mmio::{ComCfg, IsrStatus, NotifCfg};
 #[cfg(feature = "pci")]
-use crate::drivers::virtio::transport::pci::{ComCfg, IsrStatus, NotifCfg};
+use crate::drivers::virtio::transport::pci::{
+	BarInfo, ComCfg, IsrStatus, MsixCap, NotifCfg, ShMemCfg,
+};
+use crate::drivers::virtio::virtqueue::error::VirtqError;
 use crate::drivers::virtio::virtqueue::{
-	BuffSpec, BufferToken, Bytes, Transfer, Virtq, VqIndex, VqSize, VqType,
+	BuffSpec, BufferToken, Bytes, DescInfo, QueueDebug, Transfer, Virtq, VqIndex, VqSize, VqType,
 };
 
 pub const ETH_HDR: usize = 14usize;
+/// Length of an IEEE 802.1Q tag, which may be inserted into the ethernet header.
+pub const VLAN_HDR: usize = 4usize;
+/// Minimal length of an ethernet frame without the frame check sequence.
+pub const ETH_MIN_FRAME: usize = 60usize;
+
+/// PCI vendor and device ids of the devices handled by this driver.
+///
+/// Contains the transitional (0x1000) as well as the modern (0x1041) network
+/// device id. The PCI enumeration and the driver's own probe both consult this
+/// list, so a device is claimed if and only if it is listed here.
+pub const SUPPORTED_IDS: &[(u16, u16)] = &[(0x1af4, 0x1000), (0x1af4, 0x1041)];
+
+/// Returns true, if the given vendor and device id identify a virtio network device.
+pub fn is_supported(vendor_id: u16, device_id: u16) -> bool {
+	SUPPORTED_IDS.contains(&(vendor_id, device_id))
+}
+
+/// Device independent configuration of the virtio network driver.
+///
+/// The configuration is handed to the driver upon initialization and
+/// does not change afterwards.
+#[derive(Debug, Copy, Clone)]
+pub struct VirtioNetConfig {
+	/// If set, the driver does NOT return received buffers to the receive queues
+	/// automatically. Buffers are parked inside the driver after their content has
+	/// been handed to the caller and must be explicitly returned to the device via
+	/// [VirtioNetDriver::post_rx_buffer].
+	///
+	/// **WARN:** If the parked buffers are not posted back, the device runs out of
+	/// receive buffers and silently drops all incoming packets!
+	///
+	/// Default is `false`, i.e. buffers are refilled automatically.
+	pub manual_refill: bool,
+	/// Number of interrupts of a receive queue within [IRQ_STORM_WINDOW](constants::IRQ_STORM_WINDOW),
+	/// which are regarded as an interrupt storm. If exceeded, the notifications of the receive
+	/// queue are masked for `irq_storm_cooldown` microseconds.
+	///
+	/// A value of zero disables the detection.
+	pub irq_storm_threshold: u32,
+	/// Time in microseconds a receive queue stays in polling mode after an
+	/// interrupt storm has been detected for it.
+	pub irq_storm_cooldown: u64,
+	/// Directions of traffic, the driver is used for. Queues and offloading features,
+	/// which are only needed for the other direction, are neither created nor negotiated.
+	/// Sending with [Direction::RxOnly] fails with [VirtioNetError::NoTxBuffAvail], receiving
+	/// with [Direction::TxOnly] never returns a packet.
+	///
+	/// Default is [Direction::Both].
+	pub direction: Direction,
+	/// If set, the driver reads back the feature bits accepted by the device after the
+	/// feature negotiation and warns, if they differ from the negotiated ones. Intended
+	/// for debugging of device backends, as the spec guarantees both to be identical.
+	///
+	/// Default is `false`.
+	pub verify_features: bool,
+	/// If set, VIRTIO_F_RING_PACKED is not negotiated and split virtqueues are used,
+	/// even if the device supports packed virtqueues. Intended for hypervisors with a
+	/// faulty implementation of packed virtqueues.
+	///
+	/// Default is `false`, i.e. packed virtqueues are used, if the device offers them.
+	pub force_split: bool,
+	/// Backend, which provides the memory of all virtqueues of the driver.
+	///
+	/// Default is the [KernelAllocator].
+	pub allocator: &'static dyn DmaAllocator,
+	/// Function, which is called for each [NetEvent] of the driver, e.g. to record the
+	/// events of the initialization in tests. The function is called with the driver locked.
+	///
+	/// Default is `None`.
+	pub event_hook: Option<fn(&NetEvent)>,
+	/// Number of buffers, which are allocated upon initialization to hold received
+	/// packets. See [VirtioNetDriver::rx_pool_usage].
+	///
+	/// Default is `32`.
+	pub rx_pool_size: usize,
+	/// If set, the device may hand large TCP and UDP packets to the driver without segmenting
+	/// them (VIRTIO_NET_F_GUEST_TSO4, _TSO6 and _UFO), whose original segment size is reported
+	/// via [RxMeta]. Requires VIRTIO_NET_F_GUEST_CSUM, i.e. the checksums of received packets
+	/// may be incomplete. Each receive buffer is enlarged to hold 64 KiB.
+	///
+	/// Default is `false`, i.e. all received frames are fully segmented.
+	pub guest_gso: bool,
+	/// Records the time, at which the driver detected the completion of sent packets.
+	/// See [last_tx_completion_time](VirtioNetDriver::last_tx_completion_time) and
+	/// [SendHandle::completion_time].
+	///
+	/// Default is `false`, as reading the timer slows down the transmit path.
+	pub tx_timestamps: bool,
+	/// Source of the current time in microseconds, which bounds the time the driver waits
+	/// for the device.
+	///
+	/// Default is the timer of the processor.
+	pub clock: fn() -> u64,
+}
+
+impl Default for VirtioNetConfig {
+	fn default() -> Self {
+		VirtioNetConfig {
+			manual_refill: false,
+			irq_storm_threshold: 50_000,
+			irq_storm_cooldown: 10_000,
+			direction: Direction::Both,
+			verify_features: false,
+			force_split: false,
+			allocator: &KernelAllocator,
+			event_hook: None,
+			rx_pool_size: 32,
+			guest_gso: false,
+			tx_timestamps: false,
+			clock: get_timer_ticks,
+		}
+	}
+}
+
+/// Directions of traffic handled by the driver. See [VirtioNetConfig::direction].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+	/// Packets are sent and received.
+	Both,
+	/// Packets are only received (e.g. for monitoring).
+	RxOnly,
+	/// Packets are only sent (e.g. for traffic generation).
+	TxOnly,
+}
+
+impl Direction {
+	/// Offloading features, which only affect transmission of packets.
+	const TX_FEATS: [Features; 5] = [
+		Features::VIRTIO_NET_F_CSUM,
+		Features::VIRTIO_NET_F_HOST_TSO4,
+		Features::VIRTIO_NET_F_HOST_TSO6,
+		Features::VIRTIO_NET_F_HOST_ECN,
+		Features::VIRTIO_NET_F_HOST_UFO,
+	];
+
+	/// Offloading features, which only affect reception of packets.
+	const RX_FEATS: [Features; 7] = [
+		Features::VIRTIO_NET_F_GUEST_CSUM,
+		Features::VIRTIO_NET_F_CTRL_GUEST_OFFLOADS,
+		Features::VIRTIO_NET_F_GUEST_TSO4,
+		Features::VIRTIO_NET_F_GUEST_TSO6,
+		Features::VIRTIO_NET_F_GUEST_ECN,
+		Features::VIRTIO_NET_F_GUEST_UFO,
+		Features::VIRTIO_NET_F_MRG_RXBUF,
+	];
+
+	fn has_rx(self) -> bool {
+		self != Direction::TxOnly
+	}
+
+	fn has_tx(self) -> bool {
+		self != Direction::RxOnly
+	}
+
+	/// Returns true, if the feature is irrelevant for the direction and should
+	/// hence not be negotiated.
+	fn excludes(self, feat: Features) -> bool {
+		let irrelevant: &[Features] = match self {
+			Direction::Both => &[],
+			Direction::RxOnly => &Self::TX_FEATS,
+			Direction::TxOnly => &Self::RX_FEATS,
+		};
+
+		irrelevant
+			.iter()
+			.any(|other| u64::from(*other) == u64::from(feat))
+	}
+}
+
+/// Keeps track of the interrupt frequency of each receive queue, in order to
+/// detect interrupt storms.
+#[derive(Default)]
+pub struct IrqStorm {
+	/// Start of the current measurement window in microseconds
+	window_start: u64,
+	/// Number of interrupts of each receive queue inside the current window
+	counts: Vec<u32>,
+	/// Point in time per receive queue, when its notifications are allowed to be
+	/// unmasked again. `None` if no storm of the queue is currently handled.
+	masked_until: Vec<Option<u64>>,
+}
+
+impl IrqStorm {
+	/// Counts an interrupt of the receive queue `queue` at time `now`. Returns true, if the
+	/// queue exceeds `threshold` interrupts within the window and has to be masked, until
+	/// `cooldown` microseconds have passed.
+	fn record(&mut self, queue: usize, now: u64, threshold: u32, cooldown: u64) -> bool {
+		if self.is_masked(queue) {
+			return false;
+		}
+
+		if now.saturating_sub(self.window_start) > IRQ_STORM_WINDOW {
+			self.window_start = now;
+			self.counts.iter_mut().for_each(|count| *count = 0);
+		}
+		if self.counts.len() <= queue {
+			self.counts.resize(queue + 1, 0);
+		}
+		self.counts[queue] += 1;
+		if self.counts[queue] <= threshold {
+			return false;
+		}
+
+		if self.masked_until.len() <= queue {
+			self.masked_until.resize(queue + 1, None);
+		}
+		self.masked_until[queue] = Some(now.saturating_add(cooldown));
+		true
+	}
+
+	/// Ends the handling of all storms, whose cooldown has passed at time `now`, and
+	/// returns the queues, whose notifications may be unmasked again.
+	fn expire(&mut self, now: u64) -> Vec<usize> {
+		let mut expired = Vec::new();
+		for (queue, masked_until) in self.masked_until.iter_mut().enumerate() {
+			if masked_until.map_or(false, |until| now >= until) {
+				*masked_until = None;
+				if let Some(count) = self.counts.get_mut(queue) {
+					*count = 0;
+				}
+				expired.push(queue);
+			}
+		}
+		expired
+	}
+
+	/// Returns true, if the notifications of the queue are masked due to a storm.
+	fn is_masked(&self, queue: usize) -> bool {
+		self.masked_until
+			.get(queue)
+			.map_or(false, |masked_until| masked_until.is_some())
+	}
+
+	/// Returns true, if the notifications of any queue are masked due to a storm.
+	fn any_masked(&self) -> bool {
+		self.masked_until.iter().any(Option::is_some)
+	}
+}
 
 /// A wrapper struct for the raw configuration structure.
 /// Handling the right access to fields, as some are read-only
 /// for the driver.
 pub struct NetDevCfg {
-	pub raw: &'static NetDevCfgRaw,
+	pub raw: MmioRef<NetDevCfgRaw>,
 	pub dev_id: u16,
+	/// Legacy devices use the byte order of the guest, modern devices little-endian.
+	pub legacy: bool,
+	/// Placement of the fields, as detected by the transport.
+	pub layout: DevCfgLayout,
 
-	// Feature booleans
+	/// Features negotiated with the device, set once the device accepted them.
 	pub features: FeatureSet,
 }
 
+/// Length of the header of the legacy interface without the MSI-X fields.
+/// See Virtio specification v1.1. - 4.1.4.8
+const LEGACY_HDR_LEN: usize = 20;
+
+/// Placement of the fields of the network device configuration.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DevCfgLayout {
+	/// The fields are placed as specified. See Virtio specification v1.1. - 5.1.4
+	Modern,
+	/// The mac field follows the header of the legacy interface, as placed by some
+	/// transitional devices even in the structure of the modern interface. The other
+	/// fields are read like the ones of the modern layout.
+	Transitional,
+}
+
+impl DevCfgLayout {
+	/// Returns the offset of the mac field within the device configuration.
+	pub const fn mac_offset(self) -> usize {
+		match self {
+			DevCfgLayout::Modern => 0,
+			DevCfgLayout::Transitional => LEGACY_HDR_LEN,
+		}
+	}
+}
+
+/// All fields are read from device memory with volatile loads upon each call, hence values
+/// changed by the device, e.g. before a configuration change interrupt, are never cached.
+/// Multi-byte fields are decoded from the byte order of the device.
+impl NetDevCfg {
+	/// Returns the mac field at the offset of the [layout](DevCfgLayout). Only valid if
+	/// VIRTIO_NET_F_MAC is negotiated.
+	pub fn mac(&self) -> [u8; 6] {
+		match self.layout {
+			DevCfgLayout::Modern => self.raw.get_mac(),
+			DevCfgLayout::Transitional => {
+				let base: *const NetDevCfgRaw = &*self.raw;
+				// SAFETY: The transport only detects the layout, if the mapped device
+				// configuration covers the shifted mac field.
+				let src = unsafe { base.cast::<u8>().add(self.layout.mac_offset()) };
+				// Read byte by byte, as the field resides in device memory.
+				let mut mac = [0u8; 6];
+				for (i, byte) in mac.iter_mut().enumerate() {
+					*byte = unsafe { core::ptr::read_volatile(src.add(i)) };
+				}
+				mac
+			}
+		}
+	}
+
+	/// Returns the MTU field. Only valid if VIRTIO_NET_F_MTU is negotiated.
+	pub fn mtu(&self) -> u16 {
+		from_dev_endian(self.raw.get_mtu(), self.legacy)
+	}
+
+	/// Returns the MTU reported by the device, if VIRTIO_NET_F_MTU is negotiated, and
+	/// the default ethernet MTU of 1500 bytes otherwise.
+	pub fn effective_mtu(&self) -> u16 {
+		if self.features.is_feature(Features::VIRTIO_NET_F_MTU) {
+			self.mtu()
+		} else {
+			1500
+		}
+	}
+
+	/// Returns the length of the largest frame of the effective MTU, including the
+	/// ethernet header.
+	pub fn max_frame_len(&self) -> usize {
+		usize::from(self.effective_mtu()) + ETH_HDR
+	}
+
+	/// Returns the status field. Only valid if VIRTIO_NET_F_STATUS is negotiated.
+	pub fn status(&self) -> u16 {
+		from_dev_endian(self.raw.get_status(), self.legacy)
+	}
+
+	/// Returns the max_virtqueue_pairs field. Only valid if VIRTIO_NET_F_MQ is negotiated.
+	pub fn max_virtqueue_pairs(&self) -> u16 {
+		from_dev_endian(self.raw.get_max_virtqueue_pairs(), self.legacy)
+	}
+
+	/// Returns the speed field in units of 1 MBit/s. Only valid if VIRTIO_NET_F_SPEED_DUPLEX
+	/// is negotiated.
+	pub fn speed(&self) -> u32 {
+		from_dev_endian32(self.raw.get_speed(), self.legacy)
+	}
+
+	/// Returns the duplex field. Only valid if VIRTIO_NET_F_SPEED_DUPLEX is negotiated.
+	pub fn duplex(&self) -> u8 {
+		self.raw.get_duplex()
+	}
+
+	/// Returns the maximal length of the RSS hash key in bytes. Only valid if
+	/// VIRTIO_NET_F_RSS is negotiated.
+	pub fn rss_max_key_size(&self) -> u8 {
+		self.raw.get_rss_max_key_size()
+	}
+
+	/// Returns the maximal number of entries of the RSS indirection table. Only valid if
+	/// VIRTIO_NET_F_RSS is negotiated.
+	pub fn rss_max_indirection_table_length(&self) -> u16 {
+		from_dev_endian(self.raw.get_rss_max_indirection_table_length(), self.legacy)
+	}
+
+	/// Returns the hash types supported for RSS as bit mask of `RSS_HASH_TYPE_*`. Only valid
+	/// if VIRTIO_NET_F_RSS is negotiated, which requires a modern device.
+	pub fn supported_hash_types(&self) -> u32 {
+		from_dev_endian32(self.raw.get_supported_hash_types(), self.legacy)
+	}
+}
+
+/// Returns the size of the packet data of a single receive buffer for frames of up to
+/// `max_frame` bytes, excluding the VirtioNetHdr.
+///
+/// Receive buffers must be at least 65562 bytes large if VIRTIO_NET_F_GUEST_TSO4, _TSO6 or _UFO
+/// are set (`gso`) and at least 1526 bytes otherwise. See Virtio specification v1.1 - 5.1.6.3.1
+/// Buffers are enlarged to hold jumbo frames, if the device reports a larger MTU.
+fn rx_data_len(gso: bool, max_frame: usize) -> usize {
+	if gso {
+		MAX_GSO_LEN
+	} else {
+		max_frame.max(1500 + ETH_HDR)
+	}
+}
+
+/// Returns the length of the largest frame, which is accepted from the device, for frames
+/// of up to `max_frame` bytes. Frames may carry a VLAN tag on top and are only limited by
+/// [MAX_GSO_LEN], if the device is allowed to hand unsegmented packets to the driver (`gso`).
+fn max_rx_frame_len(gso: bool, max_frame: usize) -> usize {
+	if gso {
+		MAX_GSO_LEN
+	} else {
+		max_frame + VLAN_HDR
+	}
+}
+
+/// Returns true, if a received frame of `len` bytes holds at least an ethernet header and
+/// does not exceed `max_len` bytes.
+fn rx_frame_len_valid(len: usize, max_len: usize) -> bool {
+	(ETH_HDR..=max_len).contains(&len)
+}
+
+/// Copies the frame `data` to the start of `buf` and zeroes the remainder of `buf`, which
+/// pads frames shorter than [ETH_MIN_FRAME] if `buf` has been sized via [tx_frame_len].
+fn write_padded(buf: &mut [u8], data: &[u8]) {
+	let (frame, padding) = buf.split_at_mut(data.len());
+	frame.copy_from_slice(data);
+	padding.fill(0);
+}
+
+/// Returns the broadcast frame, which announces the interface with the given addresses.
+///
+/// With a known IPv4 address, this is a gratuitous ARP request. Otherwise, a RARP request
+/// is sent like QEMU does on its own, which at least updates the tables of switches.
+fn announce_frame(mac: [u8; 6], ipv4: Option<[u8; 4]>) -> [u8; ETH_HDR + 28] {
+	let mut frame = [0u8; ETH_HDR + 28];
+	frame[..6].copy_from_slice(&[0xff; 6]);
+	frame[6..12].copy_from_slice(&mac);
+	// Ethernet hardware and IPv4 protocol addresses. See RFC 826
+	frame[ETH_HDR..ETH_HDR + 8].copy_from_slice(&[0, 1, 0x08, 0x00, 6, 4, 0, 0]);
+	frame[ETH_HDR + 8..ETH_HDR + 14].copy_from_slice(&mac);
+	match ipv4 {
+		Some(ip) => {
+			frame[12..14].copy_from_slice(&ARP_ETHER_TYPE.to_be_bytes());
+			frame[ETH_HDR + 7] = ARP_REQUEST;
+			frame[ETH_HDR + 14..ETH_HDR + 18].copy_from_slice(&ip);
+			frame[ETH_HDR + 24..ETH_HDR + 28].copy_from_slice(&ip);
+		}
+		None => {
+			frame[12..14].copy_from_slice(&RARP_ETHER_TYPE.to_be_bytes());
+			frame[ETH_HDR + 7] = RARP_REQUEST;
+			frame[ETH_HDR + 18..ETH_HDR + 24].copy_from_slice(&mac);
+		}
+	}
+	frame
+}
+
+/// Returns the length of the transmitted frame for `len` bytes to send.
+///
+/// The device does not pad runt frames and no feature indicates, that it does. Hence
+/// the driver pads them itself.
+fn tx_frame_len(len: usize) -> usize {
+	len.max(ETH_MIN_FRAME)
+}
+
+/// Returns the maximal number of fragments of a transmitted packet for the given queue size,
+/// if placed in an `indirect` descriptor table or directly in the queue. The VirtioNetHdr
+/// occupies one descriptor of the chain.
+fn tx_segment_limit(indirect: bool, queue_size: u16) -> u16 {
+	let chain = if indirect {
+		queue_size
+	} else {
+		queue_size.min(MAX_DIRECT_TX_CHAIN)
+	};
+	chain.saturating_sub(1)
+}
+
+/// Returns the number of descriptors needed for the slice, as descriptors must not cross
+/// page boundaries. See [Virtq::prep_transfer_from_slices].
+fn slice_descriptors(slice: &[u8]) -> usize {
+	if slice.is_empty() {
+		return 0;
+	}
+	let page_size = BasePageSize::SIZE as usize;
+	let offset = slice.as_ptr() as usize % page_size;
+	(offset + slice.len() + page_size - 1) / page_size
+}
+
+/// Returns the wanted features, which are offered by the device (`dev_feats`) and whose
+/// dependencies are offered as well. Fails, if a feature of `min_feat_set` lacks a dependency.
+fn offered_features(
+	dev_feats: FeatureSet,
+	min_feat_set: FeatureSet,
+	wanted_feats: &[Features],
+) -> Result<Vec<Features>, VirtioNetError> {
+	let mut feats: Vec<Features> = wanted_feats
+		.iter()
+		.copied()
+		.filter(|feat| dev_feats.is_feature(*feat))
+		.collect();
+
+	// Drop features, which depend on features not offered by the device.
+	loop {
+		let mut feat_set = FeatureSet::new(0);
+		feat_set.set_features(&feats);
+		match FeatureSet::validate_feature_dependencies(feat_set.into()) {
+			Ok(()) => return Ok(feats),
+			Err(VirtioNetError::IncompatibleFeatures(feat, _))
+				if !min_feat_set.is_feature(feat) =>
+			{
+				feats.retain(|f| u64::from(*f) != u64::from(feat));
+			}
+			Err(vnet_err) => return Err(vnet_err),
+		}
+	}
+}
+
+/// Returns the `gso_type` field of the VirtioNetHdr for the given type of segmentation,
+/// optionally with the ECN bit set.
+fn gso_type_bits(gso_type: NetHdrGSO, ecn: bool) -> u8 {
+	if ecn {
+		gso_type | NetHdrGSO::VIRTIO_NET_HDR_GSO_ECN
+	} else {
+		gso_type.into()
+	}
+}
+
+/// Returns the command-specific data of VIRTIO_NET_CTRL_MQ_RSS_CONFIG, with packets of
+/// unclassified hash types being steered to the first receive queue.
+/// See Virtio specification v1.2. - 5.1.6.5.7.1
+fn rss_config_bytes(hash_types: u32, indirection: &[u16], max_tx_vq: u16, key: &[u8]) -> Vec<u8> {
+	let mut data = Vec::with_capacity(11 + 2 * indirection.len() + key.len());
+	data.extend_from_slice(&hash_types.to_le_bytes());
+	// The table length is a power of two, hence the mask is one less.
+	let mask = u16::try_from(indirection.len() - 1).unwrap();
+	data.extend_from_slice(&mask.to_le_bytes());
+	// unclassified_queue
+	data.extend_from_slice(&0u16.to_le_bytes());
+	for entry in indirection {
+		data.extend_from_slice(&entry.to_le_bytes());
+	}
+	data.extend_from_slice(&max_tx_vq.to_le_bytes());
+	data.push(u8::try_from(key.len()).unwrap());
+	data.extend_from_slice(key);
+	data
+}
+
+/// Returns the indirection table of the RSS configuration used upon initialization, which
+/// distributes the packets evenly over `pairs` receive queues. The table has the largest
+/// length, which is a power of two and does not exceed `max_len` or [RSS_DEFAULT_TABLE_LEN].
+/// It is empty, if the device does not support a table.
+fn default_indirection_table(pairs: u16, max_len: u16) -> Vec<u16> {
+	let max_len = max_len.min(RSS_DEFAULT_TABLE_LEN);
+	if max_len == 0 {
+		return Vec::new();
+	}
+	let len = 1u16 << (15 - max_len.leading_zeros());
+	(0..len).map(|entry| entry % pairs).collect()
+}
+
+/// Returns the frame sent by [VirtioNetDriver::loopback_test] from and to `mac`.
+fn loopback_frame(mac: [u8; 6]) -> Vec<u8> {
+	let mut frame = Vec::with_capacity(ETH_HDR + 46);
+	frame.extend_from_slice(&mac);
+	frame.extend_from_slice(&mac);
+	frame.extend_from_slice(&constants::LOOPBACK_ETHER_TYPE.to_be_bytes());
+	// Padding to the minimal frame size with a recognizable pattern.
+	frame.extend((0..46u8).map(|i| i ^ 0xa5));
+	frame
+}
+
+/// Stores `waker` in `slot`, unless the stored waker already wakes the same task.
+fn register_waker(slot: &mut Option<Waker>, waker: &Waker) {
+	if !slot
+		.as_ref()
+		.map_or(false, |stored| stored.will_wake(waker))
+	{
+		*slot = Some(waker.clone());
+	}
+}
+
+/// Hands all packets returned by `next` to the handler and returns their number.
+fn deliver_rx<B: core::ops::Deref<Target = [u8]>>(
+	handler: fn(&[u8]),
+	mut next: impl FnMut() -> Option<B>,
+) -> usize {
+	let mut delivered = 0;
+	while let Some(packet) = next() {
+		handler(&packet);
+		delivered += 1;
+	}
+	delivered
+}
+
+/// Decodes the speed field, where `0xffffffff` indicates an unknown speed.
+fn decode_speed(speed: u32) -> Option<u32> {
+	match speed {
+		u32::MAX => None,
+		speed => Some(speed),
+	}
+}
+
+/// Decodes the duplex field, where 0x01 indicates full and 0x00 half duplex.
+/// All other values, including `0xff`, indicate an unknown duplex mode.
+fn decode_duplex(duplex: u8) -> Option<bool> {
+	match duplex {
+		0x00 => Some(false),
+		0x01 => Some(true),
+		_ => None,
+	}
+}
+
+/// Converts a field of the device configuration, as read from the device, into
+/// native byte order.
+/// See Virtio specification v1.1. - 2.4.3 and 4.1.4.3
+///
+/// The conversion is symmetric, hence it also converts native values into the byte
+/// order of the device.
+pub(super) fn from_dev_endian(raw: u16, legacy: bool) -> u16 {
+	if legacy {
+		raw
+	} else {
+		u16::from_le(raw)
+	}
+}
+
+/// Converts a 32 bit field like [from_dev_endian].
+pub(super) fn from_dev_endian32(raw: u32, legacy: bool) -> u32 {
+	if legacy {
+		raw
+	} else {
+		u32::from_le(raw)
+	}
+}
+
 #[derive(AsBytes, Debug)]
 #[repr(C)]
 pub struct VirtioNetHdr {
@@ -73,6 +688,7 @@ impl VirtioNetHdr {
 		}
 	}
 
+	#[allow(dead_code)]
 	pub fn get_rx_hdr() -> VirtioNetHdr {
 		VirtioNetHdr {
 			flags: 0,
@@ -84,126 +700,720 @@ impl VirtioNetHdr {
 			num_buffers: 0,
 		}
 	}
-}
 
-pub struct CtrlQueue(Option<Rc<Virtq>>);
+	/// Returns the size of the header in front of each packet on the data queues.
+	/// The `num_buffers` field is only present with VIRTIO_F_VERSION_1 or
+	/// VIRTIO_NET_F_MRG_RXBUF, otherwise the header is 10 bytes long.
+	///
+	/// See Virtio specification v1.1. - 5.1.6
+	pub fn hdr_size(features: FeatureSet) -> usize {
+		if features.is_feature(Features::VIRTIO_F_VERSION_1)
+			|| features.is_feature(Features::VIRTIO_NET_F_MRG_RXBUF)
+		{
+			mem::size_of::<VirtioNetHdr>()
+		} else {
+			mem::size_of::<VirtioNetHdr>() - mem::size_of::<u16>()
+		}
+	}
+
+	/// Parses the header at the start of a buffer received from a device with the given
+	/// features. Returns None, if the buffer is shorter than the header.
+	///
+	/// Without the `num_buffers` field, the packet is regarded to occupy a single buffer.
+	/// Without VIRTIO_F_VERSION_1, the fields are stored in the byte order of the guest.
+	pub fn from_bytes(buf: &[u8], features: FeatureSet) -> Option<VirtioNetHdr> {
+		let size = Self::hdr_size(features);
+		if buf.len() < size {
+			return None;
+		}
 
-impl CtrlQueue {
-	pub fn new(vq: Option<Rc<Virtq>>) -> Self {
-		CtrlQueue(vq)
+		let legacy = !features.is_feature(Features::VIRTIO_F_VERSION_1);
+		let dev16 =
+			|pos: usize| from_dev_endian(u16::from_ne_bytes([buf[pos], buf[pos + 1]]), legacy);
+		Some(VirtioNetHdr {
+			flags: buf[0],
+			gso_type: buf[1],
+			hdr_len: dev16(2),
+			gso_size: dev16(4),
+			csum_start: dev16(6),
+			csum_offset: dev16(8),
+			num_buffers: if size == mem::size_of::<VirtioNetHdr>() {
+				dev16(10)
+			} else {
+				1
+			},
+		})
+	}
+
+	/// Returns the header in the byte order of a device with the given features, see
+	/// [from_bytes](Self::from_bytes). Only the first [hdr_size](Self::hdr_size) bytes
+	/// are handed to the device.
+	pub fn to_bytes(&self, features: FeatureSet) -> [u8; mem::size_of::<VirtioNetHdr>()] {
+		let legacy = !features.is_feature(Features::VIRTIO_F_VERSION_1);
+		let mut buf = [0u8; mem::size_of::<VirtioNetHdr>()];
+		buf[0] = self.flags;
+		buf[1] = self.gso_type;
+		let fields = [
+			self.hdr_len,
+			self.gso_size,
+			self.csum_start,
+			self.csum_offset,
+			self.num_buffers,
+		];
+		for (field, chunk) in fields.iter().zip(buf[2..].chunks_mut(2)) {
+			chunk.copy_from_slice(&from_dev_endian(*field, legacy).to_ne_bytes());
+		}
+		buf
+	}
+
+	/// Returns the number of receive buffers the packet spans.
+	pub fn num_buffers(&self) -> u16 {
+		self.num_buffers
+	}
+
+	/// Returns the metadata of a received packet. The segmentation fields are only
+	/// interpreted, if the device may hand unsegmented packets to the driver (`gso`).
+	/// Returns the `gso_type` as error, if it is unknown.
+	fn rx_meta(&self, gso: bool) -> Result<RxMeta, u8> {
+		let needs_csum = self.flags & NetHdrFlag::VIRTIO_NET_HDR_F_NEEDS_CSUM as u8 != 0;
+		if !gso {
+			return Ok(RxMeta {
+				gso: None,
+				needs_csum,
+			});
+		}
+
+		let ecn = self.gso_type & u8::from(NetHdrGSO::VIRTIO_NET_HDR_GSO_ECN) != 0;
+		let kind = match self.gso_type & !u8::from(NetHdrGSO::VIRTIO_NET_HDR_GSO_ECN) {
+			0 => None,
+			1 => Some(NetHdrGSO::VIRTIO_NET_HDR_GSO_TCPV4),
+			3 => Some(NetHdrGSO::VIRTIO_NET_HDR_GSO_UDP),
+			4 => Some(NetHdrGSO::VIRTIO_NET_HDR_GSO_TCPV6),
+			_ => return Err(self.gso_type),
+		};
+
+		Ok(RxMeta {
+			gso: kind.map(|kind| RxGso {
+				kind,
+				segment_size: self.gso_size,
+				ecn,
+			}),
+			needs_csum,
+		})
 	}
 }
 
-#[allow(dead_code, non_camel_case_types)]
-#[derive(Copy, Clone, Debug)]
-#[repr(u8)]
-enum CtrlClass {
-	VIRTIO_NET_CTRL_RX = 1 << 0,
-	VIRTIO_NET_CTRL_MAC = 1 << 1,
-	VIRTIO_NET_CTRL_VLAN = 1 << 2,
-	VIRTIO_NET_CTRL_ANNOUNCE = 1 << 3,
-	VIRTIO_NET_CTRL_MQ = 1 << 4,
+/// Iterator over the packets, which have been received when it was created.
+/// See [drain_rx](VirtioNetDriver::drain_rx).
+pub struct RxDrain<'a> {
+	driver: &'a mut VirtioNetDriver,
+	/// Number of receive buffers, which are still to be inspected. Limits the iteration
+	/// to the packets, which were available upon creation.
+	remaining: usize,
 }
 
-impl From<CtrlClass> for u8 {
-	fn from(val: CtrlClass) -> Self {
-		match val {
-			CtrlClass::VIRTIO_NET_CTRL_RX => 1 << 0,
-			CtrlClass::VIRTIO_NET_CTRL_MAC => 1 << 1,
-			CtrlClass::VIRTIO_NET_CTRL_VLAN => 1 << 2,
-			CtrlClass::VIRTIO_NET_CTRL_ANNOUNCE => 1 << 3,
-			CtrlClass::VIRTIO_NET_CTRL_MQ => 1 << 4,
+impl Iterator for RxDrain<'_> {
+	type Item = PooledBuffer;
+
+	fn next(&mut self) -> Option<PooledBuffer> {
+		// Each packet occupies at least one buffer, hence the loop terminates.
+		while self.remaining > 0 {
+			self.remaining -= 1;
+			if let Some(packet) = self.driver.receive_packet(None) {
+				return Some(packet);
+			}
+
+			// Dropped packets are skipped, unless nothing is left.
+			if self.driver.rx_pending() == 0 {
+				break;
+			}
 		}
+
+		self.remaining = 0;
+		None
 	}
 }
 
-#[allow(dead_code, non_camel_case_types)]
-#[derive(Copy, Clone, Debug)]
-#[repr(u8)]
-enum RxCmd {
-	VIRTIO_NET_CTRL_RX_PROMISC = 1 << 0,
-	VIRTIO_NET_CTRL_RX_ALLMULTI = 1 << 1,
-	VIRTIO_NET_CTRL_RX_ALLUNI = 1 << 2,
-	VIRTIO_NET_CTRL_RX_NOMULTI = 1 << 3,
-	VIRTIO_NET_CTRL_RX_NOUNI = 1 << 4,
-	VIRTIO_NET_CTRL_RX_NOBCAST = 1 << 5,
+impl core::iter::FusedIterator for RxDrain<'_> {}
+
+/// Metadata of a received packet, which is taken from its VirtioNetHdr.
+/// See [receive_packet_meta](VirtioNetDriver::receive_packet_meta).
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct RxMeta {
+	/// Segmentation of the packet, if the device did not segment it. See
+	/// [VirtioNetConfig::guest_gso].
+	pub gso: Option<RxGso>,
+	/// The checksum of the transport layer is incomplete and must not be verified.
+	/// Only set, if VIRTIO_NET_F_GUEST_CSUM has been negotiated.
+	pub needs_csum: bool,
 }
 
-#[allow(dead_code, non_camel_case_types)]
-#[derive(Copy, Clone, Debug)]
-#[repr(u8)]
-enum MacCmd {
-	VIRTIO_NET_CTRL_MAC_TABLE_SET = 1 << 0,
-	VIRTIO_NET_CTRL_MAC_ADDR_SET = 1 << 1,
+/// Segmentation of a received packet, which has been merged from multiple segments.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RxGso {
+	/// Protocol of the segments, without VIRTIO_NET_HDR_GSO_ECN.
+	pub kind: NetHdrGSO,
+	/// Size of the payload of a single segment, i.e. the original segment size.
+	pub segment_size: u16,
+	/// The segments carried explicit congestion notifications.
+	pub ecn: bool,
 }
 
-#[allow(dead_code, non_camel_case_types)]
-#[derive(Copy, Clone, Debug)]
-#[repr(u8)]
-enum VlanCmd {
-	VIRTIO_NET_CTRL_VLAN_ADD = 1 << 0,
-	VIRTIO_NET_CTRL_VLAN_DEL = 1 << 1,
+/// Describes how the device distributes received packets over the
+/// available receive queues.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SteeringMode {
+	/// Only a single queue pair is in use. No steering takes place.
+	Single,
+	/// Packets are steered via the plain multiqueue mechanism
+	/// (`VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET`).
+	///
+	/// See Virtio specification v1.1. - 5.1.6.5.5
+	MultiQueue,
+	/// Packets are steered by the hash based receive side scaling
+	/// (`VIRTIO_NET_CTRL_MQ_RSS_CONFIG`).
+	Rss,
 }
 
-#[allow(dead_code, non_camel_case_types)]
-#[derive(Copy, Clone, Debug)]
-#[repr(u8)]
-enum AnceCmd {
-	VIRTIO_NET_CTRL_ANNOUNCE_ACK = 1 << 0,
+impl SteeringMode {
+	/// Chooses the steering mode for the given set of negotiated features and number
+	/// of used queue pairs. RSS is preferred over plain multiqueue, as it distributes
+	/// flows better between the queues.
+	///
+	/// The selected mode has to be configured via the control queue, hence the selection
+	/// takes place once the device is live, see [VirtioNetDriver::steering_init].
+	fn select(feats: FeatureSet, num_pairs: u16) -> SteeringMode {
+		if num_pairs <= 1 {
+			SteeringMode::Single
+		} else if feats.is_feature(Features::VIRTIO_NET_F_RSS) {
+			SteeringMode::Rss
+		} else if feats.is_feature(Features::VIRTIO_NET_F_MQ) {
+			SteeringMode::MultiQueue
+		} else {
+			SteeringMode::Single
+		}
+	}
 }
 
-#[allow(dead_code, non_camel_case_types)]
-#[derive(Copy, Clone, Debug)]
-#[repr(u8)]
-enum MqCmd {
-	VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET = 1 << 0,
-	VIRTIO_NET_CTRL_MQ_VQ_PAIRS_MIN = 1 << 1,
-	VIRTIO_NET_CTRL_MQ_VQ_PAIRS_MAX = 0x80,
+/// State of the link of a network device.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LinkState {
+	Up,
+	Down,
 }
 
-pub struct RxQueues {
-	vqs: Vec<Rc<Virtq>>,
-	poll_queue: Rc<RefCell<VecDeque<Transfer>>>,
-	is_multi: bool,
+/// Events of the driver, which are logged via a single sink. Each event is logged with
+/// its [Display](core::fmt::Display) representation and handed to
+/// [VirtioNetConfig::event_hook], if set.
+#[derive(Debug, Copy, Clone)]
+pub enum NetEvent {
+	/// The device has been reset at the start of the initialization.
+	ResetDone,
+	/// The device accepted the feature bits of `mask`.
+	FeaturesNegotiated {
+		mask: u64,
+	},
+	/// The given number of data virtqueues has been created.
+	QueuesAllocated {
+		count: u16,
+	},
+	LinkUp,
+	LinkDown,
+	/// The initialization failed with the given error.
+	InitFailed {
+		err: VirtioNetError,
+	},
 }
 
-impl RxQueues {
-	pub fn new(
-		vqs: Vec<Rc<Virtq>>,
-		poll_queue: Rc<RefCell<VecDeque<Transfer>>>,
-		is_multi: bool,
-	) -> Self {
-		Self {
-			vqs,
-			poll_queue,
-			is_multi,
+impl core::fmt::Display for NetEvent {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			NetEvent::ResetDone => write!(f, "Device has been reset"),
+			NetEvent::FeaturesNegotiated { mask } => {
+				write!(f, "Features {mask:#x} have been negotiated")
+			}
+			NetEvent::QueuesAllocated { count } => {
+				write!(f, "{count} data virtqueues have been allocated")
+			}
+			NetEvent::LinkUp => write!(f, "Link is up"),
+			NetEvent::LinkDown => write!(f, "Link is down"),
+			NetEvent::InitFailed { err } => write!(f, "Initialization failed: {err}"),
 		}
 	}
+}
 
-	/// Takes care if handling packets correctly which need some processing after being received.
-	/// This currently include nothing. But in the future it might include among others::
-	/// * Calculating missing checksums
-	/// * Merging receive buffers, by simply checking the poll_queue (if VIRTIO_NET_F_MRG_BUF)
-	fn post_processing(transfer: Transfer) -> Result<Transfer, VirtioNetError> {
-		if transfer.poll() {
-			// Here we could implement all features.
-			Ok(transfer)
-		} else {
-			warn!("Unfinished transfer in post processing. Returning buffer to queue. This will need explicit cleanup.");
-			transfer.close();
-			Err(VirtioNetError::ProcessOngoing)
+impl From<LinkState> for NetEvent {
+	fn from(link: LinkState) -> Self {
+		match link {
+			LinkState::Up => NetEvent::LinkUp,
+			LinkState::Down => NetEvent::LinkDown,
 		}
 	}
+}
 
-	/// Adds a given queue to the underlying vector and populates the queue with RecvBuffers.
-	///
-	/// Queues are all populated according to Virtio specification v1.1. - 5.1.6.3.1
-	fn add(&mut self, vq: Virtq, dev_cfg: &NetDevCfg) {
-		// Safe virtqueue
-		let rc_vq = Rc::new(vq);
-		let vq = &rc_vq;
+/// Way in which the device signals interrupts.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InterruptMode {
+	/// Shared interrupt line, the cause is read from the ISR status.
+	Legacy,
+	/// MSI-X, with separate vectors for configuration changes and virtqueues.
+	MsiX,
+}
 
-		if dev_cfg
+/// Snapshot of the statistics of the network driver.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NetStats {
+	pub tx_packets: u64,
+	pub tx_bytes: u64,
+	pub rx_packets: u64,
+	pub rx_bytes: u64,
+	/// Packets, which could not be sent, e.g. as the transmit queue was full.
+	pub tx_errors: u64,
+	/// Received buffers, which have been discarded as they are malformed.
+	pub rx_errors: u64,
+	/// Received packets, which have been dropped as not all of their buffers were
+	/// available in the receive queue.
+	pub rx_dropped: u64,
+	/// Received packets, which have been dropped as shedding was enabled via
+	/// [set_rx_drop](VirtioNetDriver::set_rx_drop).
+	pub rx_shed: u64,
+	/// Interrupts, which have not been raised by the device, e.g. as the interrupt
+	/// line is shared with another device. A high number hints at misconfigured
+	/// interrupt routing.
+	pub spurious_interrupts: u64,
+}
+
+impl core::ops::AddAssign for NetStats {
+	fn add_assign(&mut self, rhs: Self) {
+		self.tx_packets += rhs.tx_packets;
+		self.tx_bytes += rhs.tx_bytes;
+		self.rx_packets += rhs.rx_packets;
+		self.rx_bytes += rhs.rx_bytes;
+		self.tx_errors += rhs.tx_errors;
+		self.rx_errors += rhs.rx_errors;
+		self.rx_dropped += rhs.rx_dropped;
+		self.rx_shed += rhs.rx_shed;
+		self.spurious_interrupts += rhs.spurious_interrupts;
+	}
+}
+
+/// Counters of the network driver, which are updated by the receive and transmit
+/// paths. The counters can be read and cleared without locking the data path.
+#[derive(Default)]
+pub struct NetCounters {
+	tx_packets: AtomicU64,
+	tx_bytes: AtomicU64,
+	rx_packets: AtomicU64,
+	rx_bytes: AtomicU64,
+	tx_errors: AtomicU64,
+	rx_errors: AtomicU64,
+	rx_dropped: AtomicU64,
+	rx_shed: AtomicU64,
+	spurious_interrupts: AtomicU64,
+}
+
+impl NetCounters {
+	fn count_tx(&self, bytes: usize) {
+		self.tx_packets.fetch_add(1, AtomicOrdering::Relaxed);
+		self.tx_bytes
+			.fetch_add(bytes as u64, AtomicOrdering::Relaxed);
+	}
+
+	fn count_rx(&self, bytes: usize) {
+		self.rx_packets.fetch_add(1, AtomicOrdering::Relaxed);
+		self.rx_bytes
+			.fetch_add(bytes as u64, AtomicOrdering::Relaxed);
+	}
+
+	fn count_shed(&self) {
+		self.rx_shed.fetch_add(1, AtomicOrdering::Relaxed);
+	}
+
+	fn count_tx_error(&self) {
+		self.tx_errors.fetch_add(1, AtomicOrdering::Relaxed);
+	}
+
+	fn count_rx_error(&self) {
+		self.rx_errors.fetch_add(1, AtomicOrdering::Relaxed);
+	}
+
+	fn count_rx_dropped(&self) {
+		self.rx_dropped.fetch_add(1, AtomicOrdering::Relaxed);
+	}
+
+	fn count_spurious_interrupt(&self) {
+		self.spurious_interrupts
+			.fetch_add(1, AtomicOrdering::Relaxed);
+	}
+
+	/// Returns the current values of all counters.
+	fn snapshot(&self) -> NetStats {
+		NetStats {
+			tx_packets: self.tx_packets.load(AtomicOrdering::Relaxed),
+			tx_bytes: self.tx_bytes.load(AtomicOrdering::Relaxed),
+			rx_packets: self.rx_packets.load(AtomicOrdering::Relaxed),
+			rx_bytes: self.rx_bytes.load(AtomicOrdering::Relaxed),
+			tx_errors: self.tx_errors.load(AtomicOrdering::Relaxed),
+			rx_errors: self.rx_errors.load(AtomicOrdering::Relaxed),
+			rx_dropped: self.rx_dropped.load(AtomicOrdering::Relaxed),
+			rx_shed: self.rx_shed.load(AtomicOrdering::Relaxed),
+			spurious_interrupts: self.spurious_interrupts.load(AtomicOrdering::Relaxed),
+		}
+	}
+
+	/// Returns the current values of all counters and sets them to zero.
+	///
+	/// Each counter is read and cleared in a single atomic operation, hence no
+	/// increments are lost between reading and clearing.
+	fn take(&self) -> NetStats {
+		NetStats {
+			tx_packets: self.tx_packets.swap(0, AtomicOrdering::Relaxed),
+			tx_bytes: self.tx_bytes.swap(0, AtomicOrdering::Relaxed),
+			rx_packets: self.rx_packets.swap(0, AtomicOrdering::Relaxed),
+			rx_bytes: self.rx_bytes.swap(0, AtomicOrdering::Relaxed),
+			tx_errors: self.tx_errors.swap(0, AtomicOrdering::Relaxed),
+			rx_errors: self.rx_errors.swap(0, AtomicOrdering::Relaxed),
+			rx_dropped: self.rx_dropped.swap(0, AtomicOrdering::Relaxed),
+			rx_shed: self.rx_shed.swap(0, AtomicOrdering::Relaxed),
+			spurious_interrupts: self.spurious_interrupts.swap(0, AtomicOrdering::Relaxed),
+		}
+	}
+}
+
+/// Returns true, if `mac` can be used as address of the device, i.e. it is neither zero
+/// nor the broadcast or any other multicast address.
+pub(super) fn is_unicast_mac(mac: &[u8; 6]) -> bool {
+	*mac != [0; 6] && mac[0] & 0x01 == 0
+}
+
+/// Returns the status word of the device, which is read via `read`, if VIRTIO_NET_F_STATUS
+/// has been negotiated. Otherwise the field is not valid and is not read. Instead, a link,
+/// which is up, is reported.
+fn status_word(features: FeatureSet, read: impl FnOnce() -> u16) -> u16 {
+	if features.is_feature(Features::VIRTIO_NET_F_STATUS) {
+		read()
+	} else {
+		u16::from(Status::VIRTIO_NET_S_LINK_UP)
+	}
+}
+
+/// Returns the state of the link indicated by the status word of the device.
+fn link_state_of(status: u16) -> LinkState {
+	if status & u16::from(Status::VIRTIO_NET_S_LINK_UP) != 0 {
+		LinkState::Up
+	} else {
+		LinkState::Down
+	}
+}
+
+/// Checks, that `pairs` queue pairs can be activated, if at most `max` pairs exist.
+fn check_queue_pairs(pairs: u16, max: u16) -> Result<(), VirtioNetError> {
+	if pairs == 0 || pairs > max {
+		Err(VirtioNetError::InvalidQueuePairs(pairs, max))
+	} else {
+		Ok(())
+	}
+}
+
+/// Returns the number of queue pairs to set up for a device, which reports `max_pairs`
+/// in its max_virtqueue_pairs field and provides `dev_queues` virtqueues in total, if known.
+///
+/// A device must report between 1 and 0x8000 pairs, but the driver also accepts buggy
+/// devices, which report zero pairs or more pairs than queues, by clamping the count.
+fn usable_queue_pairs(max_pairs: u16, dev_queues: Option<u16>) -> u16 {
+	// The optional control queue does not form a pair of its own.
+	let limit = dev_queues.map_or(MAX_NUM_VQ / 2, |num| (num / 2).min(MAX_NUM_VQ / 2));
+	max_pairs.min(limit).max(1)
+}
+
+/// Returns true, if the ISR status indicates neither a used buffer notification
+/// nor a configuration change.
+fn isr_is_spurious(status: u32) -> bool {
+	status & (ISR_QUEUE | ISR_CFG_CHANGE) == 0
+}
+
+pub struct CtrlQueue {
+	vq: Option<Rc<Virtq>>,
+	/// Number of consecutive commands, which have not been finished by the device
+	/// in time. Tracked independent of the data queues, as a stuck control queue
+	/// does not necessarily affect the data path.
+	timeouts: u32,
+	/// Source of time for the timeouts of commands, see [VirtioNetConfig::clock].
+	clock: fn() -> u64,
+}
+
+impl CtrlQueue {
+	pub fn new(vq: Option<Rc<Virtq>>) -> Self {
+		CtrlQueue {
+			vq,
+			timeouts: 0,
+			clock: get_timer_ticks,
+		}
+	}
+
+	/// Replaces the source of time for the timeouts of commands.
+	fn with_clock(mut self, clock: fn() -> u64) -> Self {
+		self.clock = clock;
+		self
+	}
+
+	/// Returns true, if the control queue exists and the last command has
+	/// been finished by the device in time.
+	fn is_healthy(&self) -> bool {
+		self.vq.is_some() && self.timeouts == 0
+	}
+
+	/// Polls the control queue until the given transfer is finished or `timeout`
+	/// microseconds have passed. Updates the health state of the queue accordingly.
+	///
+	/// Upon a timeout the transfer is returned to the queue, which takes care of
+	/// the cleanup, if the device should finish the command later.
+	fn wait(&mut self, transfer: Transfer, timeout: u64) -> Result<Transfer, VirtioNetError> {
+		let vq = match self.vq.as_ref() {
+			Some(vq) => vq,
+			None => return Err(VirtioNetError::NoCtrlQueue),
+		};
+		let start = (self.clock)();
+
+		while !transfer.poll() {
+			if (self.clock)() - start > timeout {
+				self.timeouts += 1;
+				warn!(
+					"Command on network control queue timed out. {} consecutive timeouts.",
+					self.timeouts
+				);
+				transfer.close();
+				return Err(VirtioNetError::Timeout);
+			}
+			vq.poll();
+		}
+
+		self.timeouts = 0;
+		Ok(transfer)
+	}
+
+	/// Sends the command `cmd` of the given class with the command specific `data` to the
+	/// device and waits for its acknowledgement.
+	///
+	/// See Virtio specification v1.1. - 5.1.6.5
+	fn send_cmd(&mut self, class: CtrlClass, cmd: u8, data: &[u8]) -> Result<(), VirtioNetError> {
+		let vq = match self.vq.as_ref() {
+			Some(vq) => Rc::clone(vq),
+			None => return Err(VirtioNetError::NoCtrlQueue),
+		};
+
+		// Class, command and data are read by the device, the ack is written by the device.
+		let len = 2 + data.len();
+		let send_spec =
+			BuffSpec::Single(Bytes::new(len).ok_or(VirtioNetError::BufferTooLarge(len))?);
+		let recv_spec = BuffSpec::Single(Bytes::new(1).unwrap());
+		let mut buff_tkn = vq
+			.prep_buffer(Rc::clone(&vq), Some(send_spec), Some(recv_spec))
+			.map_err(|vq_err| match vq_err {
+				VirtqError::BufferToLarge => VirtioNetError::BufferTooLarge(len),
+				_ => VirtioNetError::NoTxBuffAvail,
+			})?;
+
+		let (send_ptrs, _) = buff_tkn.raw_ptrs();
+		let (buff_ptr, _) = send_ptrs.unwrap()[0];
+		let buff = unsafe { core::slice::from_raw_parts_mut(buff_ptr, len) };
+		buff[0] = class.into();
+		buff[1] = cmd;
+		buff[2..].copy_from_slice(data);
+
+		let transfer = self.wait(buff_tkn.provide().dispatch(true), CTRL_CMD_TIMEOUT)?;
+		let ack = match transfer.as_slices() {
+			Ok((_, Some(recv))) => recv.first().and_then(|ack| ack.first().copied()),
+			_ => None,
+		};
+		transfer.close();
+
+		match ack {
+			Some(VIRTIO_NET_OK) => Ok(()),
+			_ => Err(VirtioNetError::CtrlCmdFailed(class.into(), cmd)),
+		}
+	}
+}
+
+#[allow(dead_code, non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+#[repr(u8)]
+enum CtrlClass {
+	VIRTIO_NET_CTRL_RX = 0,
+	VIRTIO_NET_CTRL_MAC = 1,
+	VIRTIO_NET_CTRL_VLAN = 2,
+	VIRTIO_NET_CTRL_ANNOUNCE = 3,
+	VIRTIO_NET_CTRL_MQ = 4,
+	VIRTIO_NET_CTRL_GUEST_OFFLOADS = 5,
+	VIRTIO_NET_CTRL_NOTF_COAL = 6,
+}
+
+impl From<CtrlClass> for u8 {
+	fn from(val: CtrlClass) -> Self {
+		match val {
+			CtrlClass::VIRTIO_NET_CTRL_RX => 0,
+			CtrlClass::VIRTIO_NET_CTRL_MAC => 1,
+			CtrlClass::VIRTIO_NET_CTRL_VLAN => 2,
+			CtrlClass::VIRTIO_NET_CTRL_ANNOUNCE => 3,
+			CtrlClass::VIRTIO_NET_CTRL_MQ => 4,
+			CtrlClass::VIRTIO_NET_CTRL_GUEST_OFFLOADS => 5,
+			CtrlClass::VIRTIO_NET_CTRL_NOTF_COAL => 6,
+		}
+	}
+}
+
+impl TryFrom<u8> for CtrlClass {
+	type Error = VirtioNetError;
+
+	fn try_from(val: u8) -> Result<Self, Self::Error> {
+		match val {
+			0 => Ok(CtrlClass::VIRTIO_NET_CTRL_RX),
+			1 => Ok(CtrlClass::VIRTIO_NET_CTRL_MAC),
+			2 => Ok(CtrlClass::VIRTIO_NET_CTRL_VLAN),
+			3 => Ok(CtrlClass::VIRTIO_NET_CTRL_ANNOUNCE),
+			4 => Ok(CtrlClass::VIRTIO_NET_CTRL_MQ),
+			5 => Ok(CtrlClass::VIRTIO_NET_CTRL_GUEST_OFFLOADS),
+			6 => Ok(CtrlClass::VIRTIO_NET_CTRL_NOTF_COAL),
+			_ => Err(VirtioNetError::UnknownCtrlClass(val)),
+		}
+	}
+}
+
+/// Acknowledgement written by the device upon success of a control command.
+const VIRTIO_NET_OK: u8 = 0;
+
+#[allow(dead_code, non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+#[repr(u8)]
+enum NotfCoalCmd {
+	VIRTIO_NET_CTRL_NOTF_COAL_TX_SET = 0,
+	VIRTIO_NET_CTRL_NOTF_COAL_RX_SET = 1,
+	VIRTIO_NET_CTRL_NOTF_COAL_VQ_SET = 2,
+	VIRTIO_NET_CTRL_NOTF_COAL_VQ_GET = 3,
+}
+
+#[allow(dead_code, non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+#[repr(u8)]
+enum RxCmd {
+	VIRTIO_NET_CTRL_RX_PROMISC = 0,
+	VIRTIO_NET_CTRL_RX_ALLMULTI = 1,
+	VIRTIO_NET_CTRL_RX_ALLUNI = 2,
+	VIRTIO_NET_CTRL_RX_NOMULTI = 3,
+	VIRTIO_NET_CTRL_RX_NOUNI = 4,
+	VIRTIO_NET_CTRL_RX_NOBCAST = 5,
+}
+
+#[allow(dead_code, non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+#[repr(u8)]
+enum MacCmd {
+	VIRTIO_NET_CTRL_MAC_TABLE_SET = 0,
+	VIRTIO_NET_CTRL_MAC_ADDR_SET = 1,
+}
+
+#[allow(dead_code, non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+#[repr(u8)]
+enum VlanCmd {
+	VIRTIO_NET_CTRL_VLAN_ADD = 0,
+	VIRTIO_NET_CTRL_VLAN_DEL = 1,
+}
+
+#[allow(dead_code, non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+#[repr(u8)]
+enum AnceCmd {
+	VIRTIO_NET_CTRL_ANNOUNCE_ACK = 0,
+}
+
+#[allow(dead_code, non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+#[repr(u8)]
+enum MqCmd {
+	VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET = 0,
+	VIRTIO_NET_CTRL_MQ_RSS_CONFIG = 1,
+	VIRTIO_NET_CTRL_MQ_HASH_CONFIG = 2,
+}
+
+#[allow(dead_code, non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+#[repr(u8)]
+enum GuestOffloadsCmd {
+	VIRTIO_NET_CTRL_GUEST_OFFLOADS_SET = 0,
+}
+
+pub struct RxQueues {
+	vqs: Vec<Rc<Virtq>>,
+	poll_queue: Rc<RefCell<VecDeque<Transfer>>>,
+	/// Buffers, which have been received but are not yet returned to the
+	/// device. Only used if [VirtioNetConfig::manual_refill] is set.
+	parked: Vec<BufferToken>,
+	/// Size of a single receive buffer in bytes, including the VirtioNetHdr.
+	buf_len: usize,
+	/// Buffers, which are lent to the network stack, together with the id of their loan.
+	/// See [VirtioNetDriver::receive_packet_lent].
+	lent: Vec<(usize, RxLoan)>,
+	next_loan: usize,
+	/// Number of queues, which are in use. The queues behind them keep their buffers,
+	/// but are not polled.
+	active: usize,
+	is_multi: bool,
+}
+
+impl RxQueues {
+	pub fn new(
+		vqs: Vec<Rc<Virtq>>,
+		poll_queue: Rc<RefCell<VecDeque<Transfer>>>,
+		is_multi: bool,
+	) -> Self {
+		Self {
+			active: vqs.len(),
+			vqs,
+			poll_queue,
+			parked: Vec::new(),
+			buf_len: 0,
+			lent: Vec::new(),
+			next_loan: 0,
+			is_multi,
+		}
+	}
+
+	/// Returns the queues, which are in use.
+	fn active(&self) -> &[Rc<Virtq>] {
+		&self.vqs[..self.active.min(self.vqs.len())]
+	}
+
+	/// Uses only the first `num` queues. The remaining queues are polled a last time,
+	/// so packets, which have already been received, can still be fetched.
+	fn set_active(&mut self, num: usize) {
+		let num = num.min(self.vqs.len());
+		for vq in &self.vqs[num..] {
+			vq.disable_notifs();
+			vq.poll();
+		}
+		self.active = num;
+		self.is_multi = num > 1;
+	}
+
+	/// Takes care if handling packets correctly which need some processing after being received.
+	/// This currently include nothing. But in the future it might include among others::
+	/// * Calculating missing checksums
+	/// * Merging receive buffers, by simply checking the poll_queue (if VIRTIO_NET_F_MRG_BUF)
+	fn post_processing(transfer: Transfer) -> Result<Transfer, VirtioNetError> {
+		if transfer.poll() {
+			// Here we could implement all features.
+			Ok(transfer)
+		} else {
+			warn!("Unfinished transfer in post processing. Returning buffer to queue. This will need explicit cleanup.");
+			transfer.close();
+			Err(VirtioNetError::ProcessOngoing)
+		}
+	}
+
+	/// Returns true, if the device may hand large, unsegmented packets to the driver.
+	fn guest_gso(dev_cfg: &NetDevCfg) -> bool {
+		dev_cfg
 			.features
 			.is_feature(Features::VIRTIO_NET_F_GUEST_TSO4)
 			| dev_cfg
@@ -212,69 +1422,67 @@ impl RxQueues {
 			| dev_cfg
 				.features
 				.is_feature(Features::VIRTIO_NET_F_GUEST_UFO)
-		{
-			// Receive Buffers must be at least 65562 bytes large with these features set.
-			// See Virtio specification v1.1 - 5.1.6.3.1
-
-			// Currently we choose indirect descriptors if possible in order to allow
-			// as many packages as possible inside the queue.
-			let buff_def = [
-				Bytes::new(mem::size_of::<VirtioNetHdr>()).unwrap(),
-				Bytes::new(65550).unwrap(),
-			];
+	}
 
-			let spec = if dev_cfg
-				.features
-				.is_feature(Features::VIRTIO_F_RING_INDIRECT_DESC)
-			{
-				BuffSpec::Indirect(&buff_def)
-			} else {
-				BuffSpec::Single(Bytes::new(mem::size_of::<VirtioNetHdr>() + 65550).unwrap())
-			};
+	/// Returns the size of the packet data of a single receive buffer, excluding the VirtioNetHdr.
+	/// See [rx_data_len].
+	fn data_len(dev_cfg: &NetDevCfg) -> usize {
+		rx_data_len(Self::guest_gso(dev_cfg), dev_cfg.max_frame_len())
+	}
 
-			let num_buff: u16 = vq.size().into();
+	/// Returns the length of the largest frame the device is allowed to hand to the driver.
+	/// See [max_rx_frame_len].
+	fn max_frame_len(dev_cfg: &NetDevCfg) -> usize {
+		max_rx_frame_len(Self::guest_gso(dev_cfg), dev_cfg.max_frame_len())
+	}
 
-			for _ in 0..num_buff {
-				let buff_tkn = match vq.prep_buffer(Rc::clone(vq), None, Some(spec.clone())) {
-					Ok(tkn) => tkn,
-					Err(_vq_err) => {
-						error!("Setup of network queue failed, which should not happen!");
-						panic!("setup of network queue failed!");
-					}
-				};
+	/// Returns the layout of the receive buffers and passes it to `f`.
+	///
+	/// Currently we choose indirect descriptors if possible in order to allow
+	/// as many packages as possible inside the queue.
+	fn with_buff_spec<T>(dev_cfg: &NetDevCfg, f: impl FnOnce(BuffSpec<'_>) -> T) -> T {
+		let data_len = Self::data_len(dev_cfg);
+		let buff_def = [
+			Bytes::new(mem::size_of::<VirtioNetHdr>()).unwrap(),
+			Bytes::new(data_len).unwrap(),
+		];
 
-				// BufferTokens are directly provided to the queue
-				// TransferTokens are directly dispatched
-				// Transfers will be awaited at the queue
-				buff_tkn
-					.provide()
-					.dispatch_await(Rc::clone(&self.poll_queue), false);
-			}
+		if dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_F_RING_INDIRECT_DESC)
+		{
+			f(BuffSpec::Indirect(&buff_def))
 		} else {
-			// If above features not set, buffers must be at least 1526 bytes large.
-			// See Virtio specification v1.1 - 5.1.6.3.1
-			//
-			let buff_def = [
-				Bytes::new(mem::size_of::<VirtioNetHdr>()).unwrap(),
-				Bytes::new(1514).unwrap(),
-			];
-			let spec = if dev_cfg
-				.features
-				.is_feature(Features::VIRTIO_F_RING_INDIRECT_DESC)
-			{
-				BuffSpec::Indirect(&buff_def)
-			} else {
-				BuffSpec::Single(Bytes::new(mem::size_of::<VirtioNetHdr>() + 1514).unwrap())
-			};
+			f(BuffSpec::Single(
+				Bytes::new(mem::size_of::<VirtioNetHdr>() + data_len).unwrap(),
+			))
+		}
+	}
+
+	/// Adds a given queue to the underlying vector and populates the queue with RecvBuffers.
+	///
+	/// Queues are all populated according to Virtio specification v1.1. - 5.1.6.3.1
+	///
+	/// Fails with [VirtioNetError::QueueAllocFailed], if the buffers can not be allocated.
+	fn add(&mut self, vq: Virtq, dev_cfg: &NetDevCfg) -> Result<(), VirtioNetError> {
+		// Safe virtqueue
+		let rc_vq = Rc::new(vq);
+		let vq = &rc_vq;
+		let index = u16::from(vq.index());
 
+		self.buf_len = mem::size_of::<VirtioNetHdr>() + Self::data_len(dev_cfg);
+		Self::with_buff_spec(dev_cfg, |spec| {
 			let num_buff: u16 = vq.size().into();
 
 			for _ in 0..num_buff {
 				let buff_tkn = match vq.prep_buffer(Rc::clone(vq), None, Some(spec.clone())) {
 					Ok(tkn) => tkn,
-					Err(_vq_err) => {
-						error!("Setup of network queue failed, which should not happen!");
-						panic!("setup of network queue failed!");
+					Err(vq_err) => {
+						error!(
+							"Receive buffers of virtqueue {} could not be allocated: {:?}",
+							index, vq_err
+						);
+						return Err(VirtioNetError::QueueAllocFailed(index));
 					}
 				};
 
@@ -285,14 +1493,19 @@ impl RxQueues {
 					.provide()
 					.dispatch_await(Rc::clone(&self.poll_queue), false);
 			}
-		}
+
+			Ok(())
+		})?;
 
 		// Safe virtqueue
 		self.vqs.push(rc_vq);
+		self.active = self.vqs.len();
 
 		if self.vqs.len() > 1 {
 			self.is_multi = true;
 		}
+
+		Ok(())
 	}
 
 	fn get_next(&mut self) -> Option<Transfer> {
@@ -306,49 +1519,145 @@ impl RxQueues {
 		})
 	}
 
+	/// Returns the next finished transfer of the virtqueue with the given index.
+	fn get_next_of(&mut self, index: VqIndex) -> Option<Transfer> {
+		let take = |poll_queue: &RefCell<VecDeque<Transfer>>| {
+			let mut poll_queue = poll_queue.borrow_mut();
+			let pos = poll_queue
+				.iter()
+				.position(|transfer| transfer.vq_index() == index)?;
+			poll_queue.remove(pos)
+		};
+
+		take(&self.poll_queue).or_else(|| {
+			self.poll();
+			take(&self.poll_queue)
+		})
+	}
+
 	fn poll(&self) {
 		if self.is_multi {
-			for vq in &self.vqs {
+			for vq in self.active() {
 				vq.poll();
 			}
-		} else {
-			self.vqs[0].poll();
+		} else if let Some(vq) = self.vqs.first() {
+			vq.poll();
 		}
 	}
 
 	fn enable_notifs(&self) {
 		if self.is_multi {
-			for vq in &self.vqs {
+			for vq in self.active() {
 				vq.enable_notifs();
 			}
-		} else {
-			self.vqs[0].enable_notifs();
+		} else if let Some(vq) = self.vqs.first() {
+			vq.enable_notifs();
 		}
 	}
 
 	fn disable_notifs(&self) {
 		if self.is_multi {
-			for vq in &self.vqs {
+			for vq in self.active() {
 				vq.disable_notifs();
 			}
-		} else {
-			self.vqs[0].disable_notifs();
+		} else if let Some(vq) = self.vqs.first() {
+			vq.disable_notifs();
+		}
+	}
+
+	/// Enables the notifications of the active receive queue of the given pair.
+	fn enable_notifs_of(&self, queue: usize) {
+		if let Some(vq) = self.active().get(queue) {
+			vq.enable_notifs();
+		}
+	}
+
+	/// Disables the notifications of the active receive queue of the given pair.
+	fn disable_notifs_of(&self, queue: usize) {
+		if let Some(vq) = self.active().get(queue) {
+			vq.disable_notifs();
+		}
+	}
+
+	/// Stops using all but the first `num` queues.
+	///
+	/// Packets, which have already been received by the removed queues, are
+	/// kept inside the poll_queue. All other buffers are dropped.
+	fn truncate(&mut self, num: usize) {
+		if num >= self.vqs.len() {
+			return;
+		}
+
+		for vq in &self.vqs[num..] {
+			vq.disable_notifs();
+			vq.poll();
+		}
+		self.vqs.truncate(num);
+		self.active = self.active.min(num);
+		self.is_multi = self.active > 1;
+
+		// Lent buffers of the removed queues must not be returned to them.
+		for (_, loan) in self.lent.iter_mut() {
+			if VirtioNetDriver::pair_of(loan.transfer.vq_index()) >= num {
+				loan.stale = true;
+			}
+		}
+	}
+
+	/// Closes all finished transfers and drops all buffers and queues.
+	///
+	/// Must only be called after the device has been reset.
+	fn release(&mut self) {
+		for transfer in self.poll_queue.borrow_mut().drain(..) {
+			transfer.close();
 		}
+		self.parked.clear();
+		// The network stack might still access the lent buffers, hence they are leaked.
+		for (_, loan) in self.lent.drain(..) {
+			mem::forget(loan);
+		}
+		self.vqs.clear();
+		self.active = 0;
+		self.is_multi = false;
 	}
 }
 
+/// Receive buffer, which is lent to the network stack.
+struct RxLoan {
+	transfer: Transfer,
+	/// Set, if the queue of the buffer has been removed. The buffer is freed instead
+	/// of being returned to the device.
+	stale: bool,
+}
+
 /// Structure which handles transmission of packets and delegation
 /// to the respective queue structures.
 pub struct TxQueues {
 	vqs: Vec<Rc<Virtq>>,
 	poll_queue: Rc<RefCell<VecDeque<Transfer>>>,
 	ready_queue: Vec<BufferToken>,
+	/// Transfers, whose completion must be reported to the sender
+	/// via the attached callback.
+	notify_queue: Vec<(Transfer, Box<dyn FnOnce() + Send>)>,
+	/// Number of queues, which are in use. See [RxQueues::active].
+	active: usize,
 	/// Indicates, whether the Driver/Device are using multiple
 	/// queues for communication.
 	is_multi: bool,
 }
 
 impl TxQueues {
+	/// Returns the layout of the pre-allocated send buffers.
+	fn buff_spec(dev_cfg: &NetDevCfg) -> BuffSpec<'static> {
+		// Virtio specification v1.1. - 5.1.6.2 point 5.
+		//      Header and data are added as ONE output descriptor to the transmitvq.
+		//      Hence we are interpreting this, as the fact, that send packets must be inside a single descriptor.
+		// As usize is currently safe as the minimal usize is defined as 16bit in rust.
+		BuffSpec::Single(
+			Bytes::new(mem::size_of::<VirtioNetHdr>() + dev_cfg.max_frame_len()).unwrap(),
+		)
+	}
+
 	pub fn new(
 		vqs: Vec<Rc<Virtq>>,
 		poll_queue: Rc<RefCell<VecDeque<Transfer>>>,
@@ -356,70 +1665,96 @@ impl TxQueues {
 		is_multi: bool,
 	) -> Self {
 		Self {
+			active: vqs.len(),
 			vqs,
 			poll_queue,
 			ready_queue,
+			notify_queue: Vec::new(),
 			is_multi,
 		}
 	}
+
+	/// Returns the queues, which are in use.
+	fn active(&self) -> &[Rc<Virtq>] {
+		&self.vqs[..self.active.min(self.vqs.len())]
+	}
+
+	/// Uses only the first `num` queues. The remaining queues are polled a last time,
+	/// so the buffers of finished transfers are returned to the poll_queue.
+	fn set_active(&mut self, num: usize) {
+		let num = num.min(self.vqs.len());
+		for vq in &self.vqs[num..] {
+			vq.disable_notifs();
+			vq.poll();
+		}
+		self.active = num;
+		self.is_multi = num > 1;
+	}
 	#[allow(dead_code)]
 	fn enable_notifs(&self) {
 		if self.is_multi {
-			for vq in &self.vqs {
+			for vq in self.active() {
 				vq.enable_notifs();
 			}
-		} else {
-			self.vqs[0].enable_notifs();
+		} else if let Some(vq) = self.vqs.first() {
+			vq.enable_notifs();
 		}
 	}
 
 	#[allow(dead_code)]
 	fn disable_notifs(&self) {
 		if self.is_multi {
-			for vq in &self.vqs {
+			for vq in self.active() {
 				vq.disable_notifs();
 			}
-		} else {
-			self.vqs[0].disable_notifs();
+		} else if let Some(vq) = self.vqs.first() {
+			vq.disable_notifs();
 		}
 	}
 
 	fn poll(&self) {
 		if self.is_multi {
-			for vq in &self.vqs {
+			for vq in self.active() {
 				vq.poll();
 			}
-		} else {
-			self.vqs[0].poll();
+		} else if let Some(vq) = self.vqs.first() {
+			vq.poll();
 		}
 	}
 
-	fn add(&mut self, vq: Virtq, dev_cfg: &NetDevCfg) {
+	/// Adds a given queue to the underlying vector. The first queue is populated with the
+	/// send buffers.
+	///
+	/// Fails with [VirtioNetError::QueueAllocFailed], if the buffers can not be allocated.
+	fn add(&mut self, vq: Virtq, dev_cfg: &NetDevCfg) -> Result<(), VirtioNetError> {
 		// Safe virtqueue
 		self.vqs.push(Rc::new(vq));
+		self.active = self.vqs.len();
 		if self.vqs.len() == 1 {
 			// Unwrapping is safe, as one virtq will be definitely in the vector.
 			let vq = self.vqs.get(0).unwrap();
+			let index = u16::from(vq.index());
 
-			// Virtio specification v1.1. - 5.1.6.2 point 5.
-			//      Header and data are added as ONE output descriptor to the transmitvq.
-			//      Hence we are interpreting this, as the fact, that send packets must be inside a single descriptor.
-			// As usize is currently safe as the minimal usize is defined as 16bit in rust.
-			let buff_def = Bytes::new(
-				mem::size_of::<VirtioNetHdr>() + (dev_cfg.raw.get_mtu() as usize) + ETH_HDR,
-			)
-			.unwrap();
-			let spec = BuffSpec::Single(buff_def);
+			let spec = Self::buff_spec(dev_cfg);
 
 			let num_buff: u16 = vq.size().into();
 
 			for _ in 0..num_buff {
-				self.ready_queue.push(
-					vq.prep_buffer(Rc::clone(vq), Some(spec.clone()), None)
-						.unwrap()
-						.write_seq(Some(&VirtioNetHdr::get_tx_hdr()), None::<&VirtioNetHdr>)
-						.unwrap(),
-				)
+				let buff_tkn = vq
+					.prep_buffer(Rc::clone(vq), Some(spec.clone()), None)
+					.and_then(|tkn| {
+						tkn.write_seq(Some(&VirtioNetHdr::get_tx_hdr()), None::<&VirtioNetHdr>)
+					});
+				match buff_tkn {
+					Ok(tkn) => self.ready_queue.push(tkn),
+					Err(vq_err) => {
+						error!(
+							"Send buffers of virtqueue {} could not be allocated: {:?}",
+							index, vq_err
+						);
+						return Err(VirtioNetError::QueueAllocFailed(index));
+					}
+				}
 			}
 		} else {
 			self.is_multi = true;
@@ -428,6 +1763,105 @@ impl TxQueues {
 		}
 	}
 
+	/// Stops using all but the first `num` queues.
+	///
+	/// The removed queues are polled a last time, so transfers, which have
+	/// already been finished by the device, are returned to the poll_queue.
+	fn truncate(&mut self, num: usize) {
+		if num >= self.vqs.len() {
+			return;
+		}
+
+		for vq in &self.vqs[num..] {
+			vq.disable_notifs();
+			vq.poll();
+		}
+		self.vqs.truncate(num);
+		self.active = self.active.min(num);
+		self.is_multi = self.active > 1;
+	}
+
+	/// Closes all finished transfers and drops all buffers and queues. Callbacks of
+	/// transfers, which have not been finished, are not invoked.
+	///
+	/// Must only be called after the device has been reset.
+	fn release(&mut self) {
+		for transfer in self.poll_queue.borrow_mut().drain(..) {
+			transfer.close();
+		}
+		self.ready_queue.clear();
+		self.notify_queue.clear();
+		self.vqs.clear();
+		self.active = 0;
+		self.is_multi = false;
+	}
+
+	/// Invokes the callbacks of all notifying transfers, which have been
+	/// finished by the device, and returns the number of invoked callbacks.
+	///
+	/// Finished transfers are handed to the poll_queue, so their buffers
+	/// can be reused. The queues are polled even without notifying transfers,
+	/// so the completion of other transfers, e.g. of a [SendHandle], is detected.
+	fn complete_notified(&mut self) -> usize {
+		self.poll();
+
+		let mut completed = 0;
+		let mut i = 0;
+		while i < self.notify_queue.len() {
+			if self.notify_queue[i].0.poll() {
+				// Keep the order of the pending callbacks intact.
+				let (transfer, on_sent) = self.notify_queue.remove(i);
+				on_sent();
+				self.poll_queue.borrow_mut().push_back(transfer);
+				completed += 1;
+			} else {
+				i += 1;
+			}
+		}
+
+		completed
+	}
+
+	/// Collects the send buffers of all transfers, which have been finished by the device,
+	/// and returns their number.
+	///
+	/// The descriptors of finished transfers are released by the queues upon polling,
+	/// for packed queues according to the used flags of the descriptors. The buffers are
+	/// handed to the poll_queue, so they are reused for the next packets.
+	fn reclaim(&mut self) -> usize {
+		let before = self.poll_queue.borrow().len();
+		self.complete_notified();
+
+		self.poll_queue.borrow().len() - before
+	}
+
+	/// Returns a buffertoken of the given size for the queue with the given index
+	/// in the TxQueues.vqs vector. Returns None, if the queue does not exist or is full.
+	fn get_tkn_of(&mut self, queue: usize, len: usize) -> Option<BufferToken> {
+		if queue == 0 {
+			return self.get_tkn(len).map(|(tkn, _)| tkn);
+		}
+
+		let vq = self.active().get(queue)?;
+		let spec = BuffSpec::Single(Bytes::new(len).unwrap());
+		vq.prep_buffer(Rc::clone(vq), Some(spec), None).ok()
+	}
+
+	/// Returns a buffertoken for the queue with the given index in the TxQueues.vqs
+	/// vector, which consists of one descriptor per entry of `lens`.
+	///
+	/// With `indirect`, the descriptors are placed in an indirect descriptor table and
+	/// occupy a single descriptor of the queue.
+	fn get_sg_tkn(&mut self, queue: usize, lens: &[Bytes], indirect: bool) -> Option<BufferToken> {
+		let vq = self.active().get(queue)?;
+		let spec = if indirect {
+			BuffSpec::Indirect(lens)
+		} else {
+			BuffSpec::Multiple(lens)
+		};
+		vq.prep_buffer(Rc::clone(vq), Some(spec), None).ok()
+	}
+
 	/// Returns either a buffertoken and the corresponding index of the
 	/// virtqueue it is coming from. (Index in the TxQueues.vqs vector)
 	///
@@ -454,8 +1888,20 @@ impl TxQueues {
 			self.poll();
 		}
 
+		// Only buffers of the first queue are reused, other queues allocate their buffers on demand.
+		let primary = self.vqs.first()?.index();
 		while let Some(transfer) = self.poll_queue.borrow_mut().pop_back() {
+			if transfer.vq_index() != primary {
+				transfer.close();
+				continue;
+			}
+
 			let mut tkn = transfer.reuse().unwrap();
+			// Scatter-gather buffers are not reused. Dropping the token releases its
+			// descriptors, including an indirect descriptor table.
+			if tkn.raw_ptrs().0.map_or(0, |ptrs| ptrs.len()) != 1 {
+				continue;
+			}
 			let (send_len, _) = tkn.len();
 
 			match send_len.cmp(&len) {
@@ -471,7 +1917,8 @@ impl TxQueues {
 		// As usize is currently safe as the minimal usize is defined as 16bit in rust.
 		let spec = BuffSpec::Single(Bytes::new(len).unwrap());
 
-		match self.vqs[0].prep_buffer(Rc::clone(&self.vqs[0]), Some(spec), None) {
+		let vq = self.vqs.first()?;
+		match vq.prep_buffer(Rc::clone(vq), Some(spec), None) {
 			Ok(tkn) => Some((tkn, 0)),
 			Err(_) => {
 				// Here it is possible if multiple queues are enabled to get another buffertoken from them!
@@ -482,6 +1929,101 @@ impl TxQueues {
 	}
 }
 
+/// Send buffer of the driver, which is filled in place by the caller and sent
+/// without copying via [VirtioNetDriver::send_buffer].
+///
+/// The buffer is part of the memory of the transmit queue. Hence it can be handed to the
+/// device directly, as the virtqueue already knows its physical address.
+///
+/// The buffer refers to the transmit queue of the driver. It must only be used and dropped
+/// while the lock of the driver is held, on the core holding it.
+pub struct DmaBuffer {
+	buff_tkn: BufferToken,
+	/// Size of the VirtioNetHdr in front of the frame.
+	hdr_len: usize,
+}
+
+impl DmaBuffer {
+	/// Returns the length of the frame in bytes.
+	pub fn len(&self) -> usize {
+		self.buff_tkn.len().0 - self.hdr_len
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Shortens the frame to `len` bytes. Does nothing, if the frame is not longer.
+	pub fn truncate(&mut self, len: usize) {
+		if len < self.len() {
+			self.buff_tkn
+				.restr_size(Some(len + self.hdr_len), None)
+				.unwrap();
+		}
+	}
+
+	/// Returns the memory of the frame, excluding the VirtioNetHdr.
+	pub fn as_mut_slice(&mut self) -> &mut [u8] {
+		let len = self.len();
+		let (send_ptrs, _) = self.buff_tkn.raw_ptrs();
+		// Send buffers of the transmit queue consist of a single descriptor.
+		let (buff_ptr, _) = send_ptrs.unwrap()[0];
+		unsafe { core::slice::from_raw_parts_mut(buff_ptr.add(self.hdr_len), len) }
+	}
+}
+
+/// Handle of a [DmaBuffer], which is owned by the device until the send completed.
+///
+/// The device may read the buffer as long as the send is not complete. Hence the buffer is
+/// only returned via [into_buffer](SendHandle::into_buffer) after the completion. Dropping an
+/// incomplete handle hands the buffer over to the virtqueue, which frees it after completion.
+///
+/// Like [DmaBuffer], the handle must only be used and dropped while the lock of the driver
+/// is held, on the core holding it.
+pub struct SendHandle {
+	transfer: Transfer,
+	hdr_len: usize,
+	/// Set, if [VirtioNetConfig::tx_timestamps] is enabled.
+	timestamps: bool,
+	completed_at: Cell<Option<u64>>,
+}
+
+impl SendHandle {
+	/// Returns true, if the device consumed the buffer. Completions are detected while the
+	/// driver polls the transmit queues, e.g. via
+	/// [process_tx_completions](VirtioNetDriver::process_tx_completions).
+	pub fn is_complete(&self) -> bool {
+		let complete = self.transfer.poll();
+		if complete && self.timestamps && self.completed_at.get().is_none() {
+			self.completed_at.set(Some(get_timer_ticks()));
+		}
+		complete
+	}
+
+	/// Returns the time in microseconds, at which the completion was observed first via
+	/// [is_complete](Self::is_complete). The time is an upper bound of the point in time,
+	/// at which the frame left the device.
+	///
+	/// Returns `None`, if the send is not known to be complete or
+	/// [VirtioNetConfig::tx_timestamps] is not set.
+	pub fn completion_time(&self) -> Option<u64> {
+		self.completed_at.get()
+	}
+
+	/// Returns the buffer for reuse, if the send completed, and the handle otherwise.
+	/// The frame of the returned buffer has its initial length again.
+	pub fn into_buffer(self) -> Result<DmaBuffer, SendHandle> {
+		if !self.is_complete() {
+			return Err(self);
+		}
+
+		Ok(DmaBuffer {
+			buff_tkn: self.transfer.reuse().unwrap(),
+			hdr_len: self.hdr_len,
+		})
+	}
+}
+
 /// Virtio network driver struct.
 ///
 /// Struct allows to control devices virtqueues as also
@@ -497,30 +2039,99 @@ pub struct VirtioNetDriver {
 	pub(super) send_vqs: TxQueues,
 
 	pub(super) num_vqs: u16,
+	pub(super) steering: SteeringMode,
+	pub(super) initial_link: LinkState,
+	/// State of the link, as observed at the last configuration change.
+	pub(super) link: LinkState,
 	pub(super) irq: u8,
+	pub(super) interrupt_mode: InterruptMode,
 	pub(super) polling_mode_counter: u32,
+	pub(super) irq_storm: IrqStorm,
+	/// Counters of each queue pair. The first entry always exists.
+	pub(super) stats: Vec<NetCounters>,
+	/// If set, received packets are dropped instead of handed up.
+	pub(super) rx_drop: bool,
+	/// Notifications of the device are suppressed for all queues, if unset.
+	pub(super) notif_enabled: bool,
+	/// MAC address created by the driver, if the device does not provide one.
+	pub(super) generated_mac: [u8; 6],
+	/// MAC address set at runtime via [set_mac_address](VirtioNetDriver::set_mac_address).
+	pub(super) assigned_mac: Option<[u8; 6]>,
+	/// IPv4 address of the interface, see [NetworkInterface::set_ipv4_addr].
+	pub(super) ipv4_addr: Option<[u8; 4]>,
+	/// VLAN ids, which have been added to the filter table of the device.
+	pub(super) vlans: Vec<u16>,
+	/// Promiscuous mode has been switched on via [set_promiscuous](VirtioNetDriver::set_promiscuous).
+	pub(super) promiscuous: bool,
+	/// Handler, to which received packets are delivered upon interrupts.
+	pub(super) rx_handler: Option<fn(&[u8])>,
+	/// Wakers of [poll_receive](VirtioNetDriver::poll_receive), indexed by queue pair.
+	pub(super) rx_wakers: Vec<Option<Waker>>,
+	/// Time of the last observed completion of a sent packet.
+	/// Only recorded with [VirtioNetConfig::tx_timestamps].
+	pub(super) last_tx_completion: Option<u64>,
+	/// Buffers, into which received packets are copied.
+	pub(super) rx_pool: BufferPool,
+	/// Locations of the configuration structures used by the driver.
+	#[cfg(feature = "pci")]
+	pub(super) bar_layout: Vec<BarInfo>,
+	/// Shared memory regions of the device, identified by their id.
+	#[cfg(feature = "pci")]
+	pub(super) sh_mem: Vec<ShMemCfg>,
+	/// MSI-X capability and the adapter it belongs to, if MSI-X is used.
+	#[cfg(feature = "pci")]
+	pub(super) msix: Option<(MsixCap, PciAdapter)>,
+	/// Interrupt line, which has been allocated for configuration changes in MSI-X mode.
+	#[cfg(feature = "pci")]
+	pub(super) msix_cfg_irq: Option<u8>,
+	/// Processor, to which the interrupts of each queue pair are delivered, as set via
+	/// [set_queue_affinity](VirtioNetDriver::set_queue_affinity).
+	#[cfg(feature = "pci")]
+	pub(super) queue_affinity: Vec<Option<u32>>,
+
+	pub(super) config: VirtioNetConfig,
+}
+
+/// Summarizes the state of the driver. The configuration structures of the device are
+/// printed as their address only, hence no device memory is read besides the MTU.
+impl core::fmt::Debug for VirtioNetDriver {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		let raw: *const NetDevCfgRaw = &*self.dev_cfg.raw;
+
+		f.debug_struct("VirtioNetDriver")
+			.field("dev_id", &self.dev_cfg.dev_id)
+			.field("dev_cfg", &raw)
+			.field(
+				"features",
+				&Features::from_set(self.dev_cfg.features).unwrap_or_default(),
+			)
+			.field("link", &self.link)
+			.field("mtu", &self.get_mtu())
+			.field("num_queues", &self.num_queues())
+			.field("stats", &self.stats())
+			.finish_non_exhaustive()
+	}
 }
 
 impl NetworkInterface for VirtioNetDriver {
 	/// Returns the mac address of the device.
-	/// If VIRTIO_NET_F_MAC is not set, the function panics currently!
+	/// If VIRTIO_NET_F_MAC is not set or the device reports an invalid address, the address
+	/// generated by the driver is returned. An address set at runtime takes precedence over both.
 	fn get_mac_address(&self) -> [u8; 6] {
-		if self.dev_cfg.features.is_feature(Features::VIRTIO_NET_F_MAC) {
-			self.dev_cfg.raw.get_mac()
+		if let Some(mac) = self.assigned_mac {
+			mac
+		} else if let Some(mac) = self.device_mac() {
+			mac
 		} else {
-			unreachable!("Currently VIRTIO_NET_F_MAC must be negotiated!")
+			self.generated_mac
 		}
 	}
 
 	/// Returns the current MTU of the device.
-	/// Currently, if VIRTIO_NET_F_MAC is not set
-	//  MTU is set static to 1500 bytes.
+	/// If VIRTIO_NET_F_MTU is not negotiated, the default
+	/// ethernet MTU of 1500 bytes is returned.
 	fn get_mtu(&self) -> u16 {
-		if self.dev_cfg.features.is_feature(Features::VIRTIO_NET_F_MTU) {
-			self.dev_cfg.raw.get_mtu()
-		} else {
-			1500
-		}
+		self.dev_cfg.effective_mtu()
 	}
 
 	/// Provides the "user-space" with a pointer to usable memory.
@@ -531,21 +2142,33 @@ impl NetworkInterface for VirtioNetDriver {
 	/// it to the queue after the "user-space" driver has written to the buffer.
 	///
 	/// If not BufferToken is found the functions returns an error.
+	///
+	/// Runt frames are padded, see [tx_frame_len]. Hence the buffer is large enough for the
+	/// padded frame and the padding is zeroed, while only `len` bytes are exposed.
 	fn get_tx_buffer(&mut self, len: usize) -> Result<(*mut u8, usize), ()> {
-		// Adding virtio header size to length.
-		let len = len + core::mem::size_of::<VirtioNetHdr>();
+		self.irq_storm_cooldown();
+		let hdr_len = VirtioNetHdr::hdr_size(self.dev_cfg.features);
+		let frame_len = tx_frame_len(len);
 
-		match self.send_vqs.get_tkn(len) {
+		match self.send_vqs.get_tkn(frame_len + hdr_len) {
 			Some((mut buff_tkn, _vq_index)) => {
 				let (send_ptrs, _) = buff_tkn.raw_ptrs();
 				// Currently we have single Buffers in the TxQueue of size: MTU + ETH_HDR + VIRTIO_NET_HDR
 				// see TxQueue.add()
 				let (buff_ptr, _) = send_ptrs.unwrap()[0];
 
+				let hdr = VirtioNetHdr::get_tx_hdr();
+				unsafe {
+					core::ptr::copy_nonoverlapping(
+						hdr.to_bytes(self.dev_cfg.features).as_ptr(),
+						buff_ptr,
+						hdr_len,
+					);
+					buff_ptr.add(hdr_len + len).write_bytes(0, frame_len - len);
+				}
+
 				// Do not show user-space memory for VirtioNetHdr.
-				let buff_ptr = unsafe {
-					buff_ptr.offset(isize::try_from(core::mem::size_of::<VirtioNetHdr>()).unwrap())
-				};
+				let buff_ptr = unsafe { buff_ptr.add(hdr_len) };
 
 				Ok((buff_ptr, Box::into_raw(Box::new(buff_tkn)) as usize))
 			}
@@ -557,13 +2180,26 @@ impl NetworkInterface for VirtioNetDriver {
 		unsafe { drop(Box::from_raw(token as *mut BufferToken)) }
 	}
 
-	fn send_tx_buffer(&mut self, tkn_handle: usize, _len: usize) -> Result<(), ()> {
+	fn send_tx_buffer(&mut self, tkn_handle: usize, len: usize) -> Result<(), ()> {
 		// This does not result in a new assignment, or in a drop of the BufferToken, which
 		// would be dangerous, as the memory is freed then.
-		let tkn = *unsafe { Box::from_raw(tkn_handle as *mut BufferToken) };
+		let mut tkn = *unsafe { Box::from_raw(tkn_handle as *mut BufferToken) };
+
+		// The frame might be shorter than announced via get_tx_buffer. Then the bytes
+		// behind it become padding or are not sent at all.
+		let hdr_len = VirtioNetHdr::hdr_size(self.dev_cfg.features);
+		let frame_len = tx_frame_len(len);
+		let (send_len, _) = tkn.len();
+		if send_len > frame_len + hdr_len {
+			let (send_ptrs, _) = tkn.raw_ptrs();
+			let (buff_ptr, _) = send_ptrs.unwrap()[0];
+			unsafe { buff_ptr.add(hdr_len + len).write_bytes(0, frame_len - len) };
+			tkn.restr_size(Some(frame_len + hdr_len), None).unwrap();
+		}
 
 		tkn.provide()
 			.dispatch_await(Rc::clone(&self.send_vqs.poll_queue), false);
+		self.stats[0].count_tx(len);
 
 		Ok(())
 	}
@@ -573,70 +2209,11 @@ impl NetworkInterface for VirtioNetDriver {
 		!self.recv_vqs.poll_queue.borrow().is_empty()
 	}
 
-	fn receive_rx_buffer(&mut self) -> Result<Vec<u8>, ()> {
-		match self.recv_vqs.get_next() {
-			Some(transfer) => {
-				let transfer = match RxQueues::post_processing(transfer) {
-					Ok(trf) => trf,
-					Err(vnet_err) => {
-						error!("Post processing failed. Err: {:?}", vnet_err);
-						return Err(());
-					}
-				};
-
-				let (_, recv_data_opt) = transfer.as_slices().unwrap();
-				let mut recv_data = recv_data_opt.unwrap();
-
-				// If the given length is zero, we currently fail.
-				if recv_data.len() == 2 {
-					let recv_payload = recv_data.pop().unwrap();
-					// Create static reference for the user-space
-					// As long as we keep the Transfer in a raw reference this reference is static,
-					// so this is fine.
-					let recv_ref = (recv_payload as *const [u8]) as *mut [u8];
-					let ref_data: &'static mut [u8] = unsafe { &mut *(recv_ref) };
-					let vec_data = ref_data.to_vec();
-					transfer
-						.reuse()
-						.unwrap()
-						.provide()
-						.dispatch_await(Rc::clone(&self.recv_vqs.poll_queue), false);
-
-					Ok(vec_data)
-				} else if recv_data.len() == 1 {
-					let packet = recv_data.pop().unwrap();
-					let payload_ptr =
-						(&packet[mem::size_of::<VirtioNetHdr>()] as *const u8) as *mut u8;
-
-					let ref_data: &'static mut [u8] = unsafe {
-						core::slice::from_raw_parts_mut(
-							payload_ptr,
-							packet.len() - mem::size_of::<VirtioNetHdr>(),
-						)
-					};
-					let vec_data = ref_data.to_vec();
-					transfer
-						.reuse()
-						.unwrap()
-						.provide()
-						.dispatch_await(Rc::clone(&self.recv_vqs.poll_queue), false);
-
-					Ok(vec_data)
-				} else {
-					error!("Empty transfer, or with wrong buffer layout. Reusing and returning error to user-space network driver...");
-					transfer
-						.reuse()
-						.unwrap()
-						.write_seq(None::<&VirtioNetHdr>, Some(&VirtioNetHdr::get_rx_hdr()))
-						.unwrap()
-						.provide()
-						.dispatch_await(Rc::clone(&self.recv_vqs.poll_queue), false);
-
-					Err(())
-				}
-			}
-			None => Err(()),
-		}
+	/// Returns the next received packet without copying it, if possible. See
+	/// [receive_packet_lent](VirtioNetDriver::receive_packet_lent), which also merges
+	/// packets spanning multiple buffers.
+	fn receive_rx_buffer(&mut self) -> Result<PooledBuffer, ()> {
+		self.receive_packet_lent(None).ok_or(())
 	}
 
 	fn set_polling_mode(&mut self, value: bool) {
@@ -647,7 +2224,8 @@ impl NetworkInterface for VirtioNetDriver {
 			self.polling_mode_counter += 1;
 		} else {
 			self.polling_mode_counter -= 1;
-			if self.polling_mode_counter == 0 {
+			self.irq_storm_cooldown();
+			if self.polling_mode_counter == 0 && self.notif_enabled {
 				self.enable_interrupts();
 			}
 		}
@@ -655,19 +2233,72 @@ impl NetworkInterface for VirtioNetDriver {
 
 	fn handle_interrupt(&mut self) -> bool {
 		increment_irq_counter((32 + self.irq).into());
+		self.irq_storm_cooldown();
 
-		let result = if self.isr_stat.is_interrupt() {
-			true
-		} else if self.isr_stat.is_cfg_change() {
-			info!("Configuration changes are not possible! Aborting");
-			todo!("Implement possibiity to change config on the fly...")
+		// With MSI-X, the ISR status is not used and the vector identifies the cause.
+		if self.interrupt_mode == InterruptMode::MsiX {
+			self.detect_irq_storm();
+			return self.handle_queue_interrupt();
+		}
+
+		// Both causes may be signalled by a single interrupt.
+		let status = self.isr_stat.read_and_acknowledge();
+		if isr_is_spurious(status) {
+			// Raised by another device on the same line, hence the queues are left
+			// alone and the interrupt does not count towards a storm.
+			self.stats[0].count_spurious_interrupt();
+			return false;
+		}
+
+		self.detect_irq_storm();
+		if status & ISR_CFG_CHANGE != 0 {
+			self.handle_config_change();
+		}
+
+		if status & ISR_QUEUE != 0 {
+			self.handle_queue_interrupt()
 		} else {
 			false
-		};
+		}
+	}
 
-		self.isr_stat.acknowledge();
+	fn handle_config_interrupt(&mut self) {
+		self.handle_config_change();
+	}
 
-		result
+	fn set_ipv4_addr(&mut self, addr: [u8; 4]) {
+		self.ipv4_addr = Some(addr);
+	}
+}
+
+// SAFETY: Moving the driver to another core is sound, as long as no Rc of its queues is
+// used on the previous core afterwards. Within the driver the Rc are only touched via
+// `&mut self` or `&self`. The only clones outside of the driver are held by [DmaBuffer]
+// and [SendHandle], which are not Send themselves and must only be used while the lock
+// of the driver is held, see their documentation. The callbacks of `send_packet_notify`
+// are required to be Send. Device memory is accessed via MmioRef and the configuration
+// structures of the transport, which stay mapped for as long as the device is present.
+//
+// The driver is not Sync, as `&self` methods like `has_packet` poll the queues and
+// modify their state through RefCell. Shared access requires a lock, e.g. the
+// InterruptTicketMutex of the driver list.
+unsafe impl Send for VirtioNetDriver {}
+
+impl Drop for VirtioNetDriver {
+	/// Resets the device, so it stops accessing the memory of the queues, and
+	/// releases all buffers held by the driver afterwards.
+	///
+	/// The device is reset first, independent of how far the initialization
+	/// proceeded, hence dropping a partially initialized driver is safe. The
+	/// configuration structures are part of the mapped BARs and stay mapped.
+	fn drop(&mut self) {
+		debug!("Dropping VirtioNetDriver {:x}!", self.dev_cfg.dev_id);
+
+		self.com_cfg.reset_dev();
+
+		self.recv_vqs.release();
+		self.send_vqs.release();
+		self.ctrl_vq.vq = None;
 	}
 }
 
@@ -684,48 +2315,139 @@ impl VirtioNetDriver {
 	}
 
 	/// Returns the current status of the device, if VIRTIO_NET_F_STATUS
-	/// has been negotiated. Otherwise returns zero.
+	/// has been negotiated. Otherwise the link is reported to be up.
 	#[cfg(not(feature = "pci"))]
 	pub fn dev_status(&self) -> u16 {
-		if self
-			.dev_cfg
-			.features
-			.is_feature(Features::VIRTIO_NET_F_STATUS)
-		{
-			self.dev_cfg.raw.get_status()
-		} else {
-			0
+		status_word(self.dev_cfg.features, || self.dev_cfg.status())
+	}
+
+	/// Returns the bit mask of the features negotiated with the device.
+	/// Before the negotiation finished, no feature is set.
+	pub fn negotiated_features(&self) -> u64 {
+		u64::from(self.dev_cfg.features)
+	}
+
+	/// Returns true, if the given feature has been negotiated with the device.
+	pub fn has_feature(&self, feature: Features) -> bool {
+		self.dev_cfg.features.is_feature(feature)
+	}
+
+	/// Sets the handler, to which received packets are delivered, or removes it with `None`.
+	///
+	/// With a handler, all received packets are handed to it upon a queue interrupt instead
+	/// of being kept for [receive_packet](Self::receive_packet). The handler runs in interrupt
+	/// context with the driver locked, hence it must be short and must not use the driver.
+	pub fn set_rx_handler(&mut self, handler: Option<fn(&[u8])>) {
+		self.rx_handler = handler;
+	}
+
+	/// Handles a used buffer notification of the virtqueues. Returns true, if received
+	/// packets are waiting to be fetched.
+	fn handle_queue_interrupt(&mut self) -> bool {
+		self.send_vqs.complete_notified();
+
+		match self.rx_handler {
+			Some(handler) => {
+				deliver_rx(handler, || self.receive_packet(None));
+				false
+			}
+			None => {
+				self.wake_rx();
+				true
+			}
+		}
+	}
+
+	/// Receives a packet from any receive queue without blocking.
+	///
+	/// If no packet is available, the waker of `cx` is registered for all queue pairs and
+	/// `Pending` is returned. The waker is woken by the next interrupt of a receive queue,
+	/// which holds a packet, hence notifications must not be disabled. A waker, which is
+	/// registered again, replaces the previous one. Allows to build a `Future` or `Stream`
+	/// on top of the driver without busy polling.
+	pub fn poll_receive(&mut self, cx: &mut Context<'_>) -> Poll<PooledBuffer> {
+		if let Some(packet) = self.receive_packet(None) {
+			return Poll::Ready(packet);
+		}
+
+		let pairs = usize::from(self.num_queues()).max(1);
+		self.rx_wakers.resize_with(pairs, || None);
+		for slot in self.rx_wakers.iter_mut() {
+			register_waker(slot, cx.waker());
+		}
+
+		// A packet, which arrived before the waker was registered, does not wake it.
+		match self.receive_packet(None) {
+			Some(packet) => Poll::Ready(packet),
+			None => Poll::Pending,
+		}
+	}
+
+	/// Wakes the wakers of all receive queues, which hold received packets.
+	fn wake_rx(&mut self) {
+		for queue in 0..self.rx_wakers.len() {
+			if self.rx_available(u16::try_from(queue).unwrap()) == 0 {
+				continue;
+			}
+			if let Some(waker) = self.rx_wakers[queue].take() {
+				waker.wake();
+			}
+		}
+	}
+
+	/// Returns the speed of the link in MBit/s, if the device reports it.
+	///
+	/// Requires VIRTIO_NET_F_SPEED_DUPLEX. See Virtio specification v1.1. - 5.1.4
+	pub fn link_speed_mbps(&self) -> Option<u32> {
+		if !self.has_feature(Features::VIRTIO_NET_F_SPEED_DUPLEX) {
+			return None;
+		}
+
+		decode_speed(self.dev_cfg.speed())
+	}
+
+	/// Returns true for a full duplex and false for a half duplex link, if the device
+	/// reports it.
+	///
+	/// Requires VIRTIO_NET_F_SPEED_DUPLEX. See Virtio specification v1.1. - 5.1.4
+	pub fn is_full_duplex(&self) -> Option<bool> {
+		if !self.has_feature(Features::VIRTIO_NET_F_SPEED_DUPLEX) {
+			return None;
 		}
+
+		decode_duplex(self.dev_cfg.duplex())
+	}
+
+	/// Returns, how the device signals interrupts.
+	pub fn interrupt_mode(&self) -> InterruptMode {
+		self.interrupt_mode
 	}
 
-	/// Returns the links status.
+	/// Returns the links status, as it has been observed at the last configuration change.
 	/// If feature VIRTIO_NET_F_STATUS has not been negotiated, then we assume the link is up!
-	#[cfg(feature = "pci")]
 	pub fn is_link_up(&self) -> bool {
-		if self
-			.dev_cfg
-			.features
-			.is_feature(Features::VIRTIO_NET_F_STATUS)
-		{
-			self.dev_cfg.raw.get_status() & u16::from(Status::VIRTIO_NET_S_LINK_UP)
-				== u16::from(Status::VIRTIO_NET_S_LINK_UP)
-		} else {
-			true
-		}
+		self.link == LinkState::Up
+	}
+
+	/// Reads the current state of the link from the device.
+	/// If feature VIRTIO_NET_F_STATUS has not been negotiated, then we assume the link is up!
+	pub fn link_state(&self) -> LinkState {
+		link_state_of(status_word(self.dev_cfg.features, || self.dev_cfg.status()))
+	}
+
+	/// Returns the state of the link, as it has been observed at the end of the
+	/// initialization of the device.
+	///
+	/// Independent of later changes of the link, this allows to distinguish devices,
+	/// which never came up, from devices with a flapping link.
+	pub fn initial_link_state(&self) -> LinkState {
+		self.initial_link
 	}
 
-	#[allow(dead_code)]
 	pub fn is_announce(&self) -> bool {
-		if self
-			.dev_cfg
-			.features
-			.is_feature(Features::VIRTIO_NET_F_STATUS)
-		{
-			self.dev_cfg.raw.get_status() & u16::from(Status::VIRTIO_NET_S_ANNOUNCE)
-				== u16::from(Status::VIRTIO_NET_S_ANNOUNCE)
-		} else {
-			false
-		}
+		status_word(self.dev_cfg.features, || self.dev_cfg.status())
+			& u16::from(Status::VIRTIO_NET_S_ANNOUNCE)
+			== u16::from(Status::VIRTIO_NET_S_ANNOUNCE)
 	}
 
 	/// Returns the maximal number of virtqueue pairs allowed. This is the
@@ -736,1069 +2458,5093 @@ impl VirtioNetDriver {
 	#[allow(dead_code)]
 	pub fn get_max_vq_pairs(&self) -> u16 {
 		if self.dev_cfg.features.is_feature(Features::VIRTIO_NET_F_MQ) {
-			self.dev_cfg.raw.get_max_virtqueue_pairs()
+			self.dev_cfg.max_virtqueue_pairs()
 		} else {
 			1
 		}
 	}
 
-	pub fn disable_interrupts(&self) {
-		// For send and receive queues?
-		// Only for receive? Because send is off anyway?
-		self.recv_vqs.disable_notifs();
-	}
+	/// Counts the interrupt for every receive queue, which holds received packets, and
+	/// masks the notifications of each queue, whose interrupt frequency exceeds
+	/// [VirtioNetConfig::irq_storm_threshold]. The other queues stay in interrupt mode.
+	fn detect_irq_storm(&mut self) {
+		if self.config.irq_storm_threshold == 0 {
+			return;
+		}
 
-	pub fn enable_interrupts(&self) {
-		// For send and receive queues?
-		// Only for receive? Because send is off anyway?
-		self.recv_vqs.enable_notifs();
+		let now = get_timer_ticks();
+		for queue in 0..self.active_queue_pairs() {
+			if self.rx_available(queue) == 0 {
+				continue;
+			}
+			if self.irq_storm.record(
+				usize::from(queue),
+				now,
+				self.config.irq_storm_threshold,
+				self.config.irq_storm_cooldown,
+			) {
+				warn!(
+					"Interrupt storm detected for receive queue {} of network device {:x}. Switching the queue to polling mode for {} us.",
+					queue, self.dev_cfg.dev_id, self.config.irq_storm_cooldown
+				);
+				self.recv_vqs.disable_notifs_of(usize::from(queue));
+			}
+		}
 	}
 
-	/// Initializes the device in adherence to specification. Returns Some(VirtioNetError)
-	/// upon failure and None in case everything worked as expected.
+	/// Unmasks the notifications of the receive queues again, whose cooldown after an
+	/// interrupt storm has passed.
 	///
-	/// See Virtio specification v1.1. - 3.1.1.
-	///                      and v1.1. - 5.1.5
-	pub fn init_dev(&mut self) -> Result<(), VirtioNetError> {
-		// Reset
-		self.com_cfg.reset_dev();
+	/// As a masked queue does not raise interrupts anymore, the cooldown is checked on the
+	/// receive and the transmit path, as well as upon interrupts of the other queues.
+	fn irq_storm_cooldown(&mut self) {
+		if !self.irq_storm.any_masked() {
+			return;
+		}
 
-		// Indiacte device, that OS noticed it
-		self.com_cfg.ack_dev();
+		for queue in self.irq_storm.expire(get_timer_ticks()) {
+			info!(
+				"Cooldown after interrupt storm for receive queue {} of network device {:x} passed. Switching back to interrupt mode.",
+				queue, self.dev_cfg.dev_id
+			);
+			if self.polling_mode_counter == 0 && self.notif_enabled {
+				self.recv_vqs.enable_notifs_of(queue);
+			}
+		}
+	}
 
-		// Indicate device, that driver is able to handle it
-		self.com_cfg.set_drv();
+	/// Returns true, if the control queue is available and processes commands. If the device
+	/// stopped finishing commands in time, this returns false, although the data queues might
+	/// still work. Returns false, if VIRTIO_NET_F_CTRL_VQ has not been negotiated.
+	pub fn control_queue_healthy(&self) -> bool {
+		self.ctrl_vq.is_healthy()
+	}
 
-		// Define minimal feature set
-		let min_feats: Vec<Features> = vec![
-			Features::VIRTIO_F_VERSION_1,
-			Features::VIRTIO_NET_F_MAC,
-			Features::VIRTIO_NET_F_STATUS,
-		];
+	/// Handles a change of the device specific configuration, signaled by the device
+	/// via a configuration change interrupt.
+	fn handle_config_change(&mut self) {
+		info!("Configuration of virtio network device changed.");
 
-		let mut min_feat_set = FeatureSet::new(0);
-		min_feat_set.set_features(&min_feats);
-		let mut feats: Vec<Features> = min_feats;
+		let link = self.link_state();
+		if link != self.link {
+			self.emit(link.into());
+			self.link = link;
+		}
 
-		// If wanted, push new features into feats here:
-		//
-		// Indirect descriptors can be used
-		feats.push(Features::VIRTIO_F_RING_INDIRECT_DESC);
-		// MTU setting can be used
-		feats.push(Features::VIRTIO_NET_F_MTU);
-		// Packed Vq can be used
-		feats.push(Features::VIRTIO_F_RING_PACKED);
-
-		// Currently the driver does NOT support the features below.
-		// In order to provide functionality for these, the driver
-		// needs to take care of calculating checksum in
-		// RxQueues.post_processing()
-		// feats.push(Features::VIRTIO_NET_F_GUEST_CSUM);
-		// feats.push(Features::VIRTIO_NET_F_GUEST_TSO4);
-		// feats.push(Features::VIRTIO_NET_F_GUEST_TSO6);
-
-		// Negotiate features with device. Automatically reduces selected feats in order to meet device capabilities.
-		// Aborts in case incompatible features are selected by the dricer or the device does not support min_feat_set.
-		match self.negotiate_features(&feats) {
-			Ok(_) => info!(
-				"Driver found a subset of features for virtio device {:x}. Features are: {:?}",
-				self.dev_cfg.dev_id, &feats
-			),
-			Err(vnet_err) => {
-				match vnet_err {
-					VirtioNetError::FeatReqNotMet(feat_set) => {
-						error!("Network drivers feature set {:x} does not satisfy rules in section 5.1.3.1 of specification v1.1. Aborting!", u64::from(feat_set));
-						return Err(vnet_err);
-					}
-					VirtioNetError::IncompFeatsSet(drv_feats, dev_feats) => {
-						// Create a new matching feature set for device and driver if the minimal set is met!
-						if (min_feat_set & dev_feats) != min_feat_set {
-							error!("Device features set, does not satisfy minimal features needed. Aborting!");
-							return Err(VirtioNetError::FailFeatureNeg(self.dev_cfg.dev_id));
-						} else {
-							feats = match Features::from_set(dev_feats & drv_feats) {
-								Some(feats) => feats,
-								None => {
-									error!("Feature negotiation failed with minimal feature set. Aborting!");
-									return Err(VirtioNetError::FailFeatureNeg(
-										self.dev_cfg.dev_id,
-									));
-								}
-							};
-
-							match self.negotiate_features(&feats) {
-                                Ok(_) => info!("Driver found a subset of features for virtio device {:x}. Features are: {:?}", self.dev_cfg.dev_id, &feats),
-                                Err(vnet_err) => {
-                                    match vnet_err {
-                                        VirtioNetError::FeatReqNotMet(feat_set) => {
-                                            error!("Network device offers a feature set {:x} when used completely does not satisfy rules in section 5.1.3.1 of specification v1.1. Aborting!", u64::from(feat_set));
-                                            return Err(vnet_err);
-                                        },
-                                        _ => {
-                                            error!("Feature Set after reduction still not usable. Set: {:?}. Aborting!", feats);
-                                            return Err(vnet_err);
-                                        }
-                                    }
-                                }
-                            }
-						}
-					}
-					_ => {
-						error!(
-							"Wanted set of features is NOT supported by device. Set: {:?}",
-							feats
-						);
-						return Err(vnet_err);
-					}
-				}
+		// Virtio specification v1.1. - 5.1.4: max_virtqueue_pairs is only valid with VIRTIO_NET_F_MQ.
+		if self.dev_cfg.features.is_feature(Features::VIRTIO_NET_F_MQ) {
+			let max_pairs = self.dev_cfg.max_virtqueue_pairs();
+			if max_pairs < self.num_vqs / 2 {
+				// The first queue pair is always available.
+				self.shrink_queue_pairs(max_pairs.max(1));
 			}
 		}
 
-		// Indicates the device, that the current feature set is final for the driver
-		// and will not be changed.
-		self.com_cfg.features_ok();
+		self.check_announce();
+	}
 
-		// Checks if the device has accepted final set. This finishes feature negotiation.
-		if self.com_cfg.check_features() {
-			info!(
-				"Features have been negotiated between virtio network device {:x} and driver.",
-				self.dev_cfg.dev_id
-			);
-			// Set feature set in device config fur future use.
-			self.dev_cfg.features.set_features(&feats);
-		} else {
-			return Err(VirtioNetError::FailFeatureNeg(self.dev_cfg.dev_id));
+	/// Announces the interface upon a request of the device, e.g. after a live migration, and
+	/// acknowledges the request. The device raises the request until it is acknowledged.
+	///
+	/// The announcement is a gratuitous ARP request for the address set via
+	/// [NetworkInterface::set_ipv4_addr], or a RARP request, if the address is unknown.
+	///
+	/// Requires VIRTIO_NET_F_GUEST_ANNOUNCE. See Virtio specification v1.1. - 5.1.6.5.4
+	pub fn check_announce(&mut self) {
+		if !self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_NET_F_GUEST_ANNOUNCE)
+			|| !self.is_announce()
+		{
+			return;
 		}
 
-		match self.dev_spec_init() {
-			Ok(_) => info!(
-				"Device specific initialization for Virtio network device {:x} finished",
-				self.dev_cfg.dev_id
-			),
-			Err(vnet_err) => return Err(vnet_err),
+		info!(
+			"Network device {:x} requests an announcement of the guest.",
+			self.dev_cfg.dev_id
+		);
+		let frame = announce_frame(self.get_mac_address(), self.ipv4_addr);
+		if let Err(vnet_err) = self.send_packet(&frame, None) {
+			warn!("Failed to announce the guest: {:?}", vnet_err);
 		}
-		// At this point the device is "live"
-		self.com_cfg.drv_ok();
+		if let Err(vnet_err) = self.ctrl_vq.send_cmd(
+			CtrlClass::VIRTIO_NET_CTRL_ANNOUNCE,
+			AnceCmd::VIRTIO_NET_CTRL_ANNOUNCE_ACK as u8,
+			&[],
+		) {
+			warn!("Failed to acknowledge announcement request: {:?}", vnet_err);
+		}
+	}
 
-		Ok(())
+	/// Stops using the queue pairs, which exceed the given number of pairs.
+	///
+	/// The device must not be handed buffers for queues, which it does not support
+	/// anymore. Hence, the excess queues are drained and removed from the driver.
+	fn shrink_queue_pairs(&mut self, pairs: u16) {
+		if pairs >= self.num_vqs / 2 {
+			return;
+		}
+
+		warn!(
+			"Device reduced number of virtqueue pairs from {} to {}. Disabling excess queues.",
+			self.num_vqs / 2,
+			pairs
+		);
+		self.recv_vqs.truncate(pairs.into());
+		self.send_vqs.truncate(pairs.into());
+		self.num_vqs = pairs * 2;
+
+		if pairs == 1 {
+			self.steering = SteeringMode::Single;
+		}
 	}
 
-	/// Negotiates a subset of features, understood and wanted by both the OS
-	/// and the device.
-	fn negotiate_features(&mut self, wanted_feats: &[Features]) -> Result<(), VirtioNetError> {
-		let mut drv_feats = FeatureSet::new(0);
+	/// Returns the number of queue pairs, which are used for sending and receiving.
+	pub fn active_queue_pairs(&self) -> u16 {
+		u16::try_from(self.recv_vqs.active().len()).unwrap()
+	}
 
-		for feat in wanted_feats.iter() {
-			drv_feats |= *feat;
+	/// Changes the number of queue pairs used by the device and the driver at runtime.
+	///
+	/// `pairs` must be at least one and must not exceed the pairs supported by the device.
+	/// Queues behind the last active pair are not polled anymore, but keep their buffers,
+	/// so they can be activated again cheaply.
+	///
+	/// The command switches the device to plain multiqueue steering, i.e. receive-side
+	/// scaling is disabled and has to be configured again via [configure_rss](Self::configure_rss).
+	///
+	/// See Virtio specification v1.1. - 5.1.6.5.5
+	pub fn set_active_queue_pairs(&mut self, pairs: u16) -> Result<(), VirtioNetError> {
+		if !self.has_feature(Features::VIRTIO_NET_F_CTRL_VQ) || self.ctrl_vq.vq.is_none() {
+			return Err(VirtioNetError::NoCtrlQueue);
+		}
+		if !self.has_feature(Features::VIRTIO_NET_F_MQ) {
+			return Err(VirtioNetError::FeatureNotNegotiated(
+				Features::VIRTIO_NET_F_MQ,
+			));
 		}
 
-		let dev_feats = FeatureSet::new(self.com_cfg.dev_features());
+		// Only queues, which have been set up, can be used.
+		let max = self
+			.dev_cfg
+			.max_virtqueue_pairs()
+			.min(u16::try_from(self.recv_vqs.vqs.len()).unwrap());
+		check_queue_pairs(pairs, max)?;
+		// Under RSS, the command is sent anyway, as it switches the device to plain multiqueue.
+		if pairs == self.active_queue_pairs() && self.steering != SteeringMode::Rss {
+			return Ok(());
+		}
 
-		// Checks if the selected feature set is compatible with requirements for
-		// features according to Virtio spec. v1.1 - 5.1.3.1.
-		match FeatureSet::check_features(wanted_feats) {
-			Ok(_) => {
-				info!("Feature set wanted by network driver are in conformance with specification.")
-			}
-			Err(vnet_err) => return Err(vnet_err),
+		self.ctrl_vq.send_cmd(
+			CtrlClass::VIRTIO_NET_CTRL_MQ,
+			MqCmd::VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET as u8,
+			&pairs.to_le_bytes(),
+		)?;
+
+		self.recv_vqs.set_active(pairs.into());
+		self.send_vqs.set_active(pairs.into());
+		if self.notif_enabled {
+			self.recv_vqs.enable_notifs();
 		}
+		self.steering = if pairs > 1 {
+			SteeringMode::MultiQueue
+		} else {
+			SteeringMode::Single
+		};
+		info!(
+			"Network device {:x} uses {} of {} queue pairs with steering mode {:?}",
+			self.dev_cfg.dev_id, pairs, max, self.steering
+		);
 
-		if (dev_feats & drv_feats) == drv_feats {
-			// If device supports subset of features write feature set to common config
-			self.com_cfg.set_drv_features(drv_feats.into());
-			Ok(())
+		Ok(())
+	}
+
+	/// Returns the raw content of the first `count` descriptors of the virtqueue with the
+	/// given index for inspection, e.g. from a debug shell.
+	///
+	/// Indexes follow the device's numbering, i.e. `2 * i` for the i-th receive queue,
+	/// `2 * i + 1` for the i-th transmit queue and [ctrl_vq_index](Self::ctrl_vq_index) for
+	/// the control queue.
+	/// Returns an empty vector for unknown queues.
+	pub fn dump_descriptors(&self, queue: u16, count: usize) -> Vec<DescInfo> {
+		let pair = usize::from(queue / 2);
+		let vq = if queue == self.ctrl_vq_index() {
+			self.ctrl_vq.vq.as_ref()
+		} else if queue % 2 == 0 {
+			self.recv_vqs.vqs.get(pair)
 		} else {
-			Err(VirtioNetError::IncompFeatsSet(drv_feats, dev_feats))
-		}
+			self.send_vqs.vqs.get(pair)
+		};
+
+		vq.map(|vq| vq.dump_descriptors(count)).unwrap_or_default()
 	}
 
-	/// Device Specific initialization according to Virtio specifictation v1.1. - 5.1.5
-	fn dev_spec_init(&mut self) -> Result<(), VirtioNetError> {
-		match self.virtqueue_init() {
-			Ok(_) => info!("Network driver successfully initialized virtqueues."),
-			Err(vnet_err) => return Err(vnet_err),
+	/// Returns the state of the ring of the virtqueue with the given index, for inspection
+	/// of a queue, which does not make progress. Its [Display](core::fmt::Display)
+	/// implementation prints a table. Indexes follow [dump_descriptors](Self::dump_descriptors).
+	pub fn dump_queue(&self, queue: u16) -> Result<QueueDebug, VirtioNetError> {
+		let pair = usize::from(queue / 2);
+		let vq = if queue == self.ctrl_vq_index() {
+			self.ctrl_vq.vq.as_ref()
+		} else if queue % 2 == 0 {
+			self.recv_vqs.vqs.get(pair)
+		} else {
+			self.send_vqs.vqs.get(pair)
+		};
+
+		vq.map(|vq| vq.debug_state())
+			.ok_or(VirtioNetError::InvalidQueue(queue))
+	}
+
+	/// Returns the number of bytes the driver allocates for a device with the given
+	/// configuration and negotiated features, without allocating anything.
+	///
+	/// Covers the rings of all virtqueues, the receive and send buffers allocated
+	/// during initialization and the token bookkeeping of each queue. Mirrors the setup
+	/// in `virtqueue_init()`, assuming the device accepts a queue size of
+	/// [VIRTIO_MAX_QUEUE_SIZE]. Buffers allocated on demand (e.g. for control commands or
+	/// scatter-gather sends) are not included.
+	pub fn estimate_memory(config: &VirtioNetConfig, dev_cfg: &NetDevCfg) -> usize {
+		let vq_type = if dev_cfg.features.is_feature(Features::VIRTIO_F_RING_PACKED) {
+			VqType::Packed
+		} else {
+			VqType::Split
+		};
+		let size = VIRTIO_MAX_QUEUE_SIZE;
+		// Every queue keeps a reference per descriptor id to the token in flight.
+		let queue_len =
+			Virtq::ring_mem_len(&vq_type, size) + (usize::from(size) + 1) * mem::size_of::<usize>();
+		// The number of virtqueues of the device is unknown before its initialization.
+		let pairs = usize::from(Self::num_data_vqs(dev_cfg, None) / 2);
+
+		let mut total = 0;
+		if config.direction.has_rx() {
+			let buff_len = RxQueues::with_buff_spec(dev_cfg, |spec| spec.mem_len());
+			total += pairs * (queue_len + usize::from(size) * buff_len);
+			total += config.rx_pool_size
+				* (mem::size_of::<VirtioNetHdr>() + RxQueues::data_len(dev_cfg));
+		}
+		if config.direction.has_tx() {
+			// Only the first send queue is populated with buffers.
+			total += pairs * queue_len + usize::from(size) * TxQueues::buff_spec(dev_cfg).mem_len();
+		}
+		if dev_cfg.features.is_feature(Features::VIRTIO_NET_F_CTRL_VQ) {
+			total += queue_len;
 		}
 
-		// Add a control if feature is negotiated
-		if self
+		total
+	}
+
+	/// Sets the notification coalescing parameters of the data queue with the given index. The
+	/// device delays notifications for the queue for at most `usecs` microseconds or until
+	/// `frames` packets have been processed.
+	///
+	/// Requires VIRTIO_NET_F_VQ_NOTF_COAL. If only VIRTIO_NET_F_NOTF_COAL is available,
+	/// [VirtioNetError::OnlyGlobalCoalescing] is returned and
+	/// [set_coalesce](VirtioNetDriver::set_coalesce) must be used instead.
+	pub fn set_vq_coalesce(
+		&mut self,
+		queue: u16,
+		usecs: u32,
+		frames: u32,
+	) -> Result<(), VirtioNetError> {
+		if !self
 			.dev_cfg
 			.features
-			.is_feature(Features::VIRTIO_NET_F_CTRL_VQ)
+			.is_feature(Features::VIRTIO_NET_F_VQ_NOTF_COAL)
 		{
-			if self
+			return if self
 				.dev_cfg
 				.features
-				.is_feature(Features::VIRTIO_F_RING_PACKED)
+				.is_feature(Features::VIRTIO_NET_F_NOTF_COAL)
 			{
-				self.ctrl_vq = CtrlQueue(Some(Rc::new(Virtq::new(
-					&mut self.com_cfg,
-					&self.notif_cfg,
-					VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
-					VqType::Packed,
-					VqIndex::from(self.num_vqs),
-					self.dev_cfg.features.into(),
-				))));
+				Err(VirtioNetError::OnlyGlobalCoalescing)
 			} else {
-				self.ctrl_vq = CtrlQueue(Some(Rc::new(Virtq::new(
-					&mut self.com_cfg,
-					&self.notif_cfg,
-					VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
-					VqType::Split,
-					VqIndex::from(self.num_vqs),
-					self.dev_cfg.features.into(),
-				))));
-			}
+				Err(VirtioNetError::CoalescingNotSupported)
+			};
+		}
+
+		let pair = usize::from(queue / 2);
+		let exists = if queue % 2 == 0 {
+			pair < self.recv_vqs.vqs.len()
+		} else {
+			pair < self.send_vqs.vqs.len()
+		};
+		if queue >= self.num_vqs || !exists {
+			return Err(VirtioNetError::InvalidQueue(queue));
+		}
+
+		// struct virtio_net_ctrl_coal_vq { le16 vqn; le16 reserved; le32 max_packets; le32 max_usecs; }
+		let mut data = [0u8; 12];
+		data[0..2].copy_from_slice(&queue.to_le_bytes());
+		data[4..8].copy_from_slice(&frames.to_le_bytes());
+		data[8..12].copy_from_slice(&usecs.to_le_bytes());
+
+		self.ctrl_vq.send_cmd(
+			CtrlClass::VIRTIO_NET_CTRL_NOTF_COAL,
+			NotfCoalCmd::VIRTIO_NET_CTRL_NOTF_COAL_VQ_SET as u8,
+			&data,
+		)
+	}
 
-			self.ctrl_vq.0.as_ref().unwrap().enable_notifs();
+	/// Sets the notification coalescing parameters of all receive and all transmit
+	/// queues. See [set_vq_coalesce](VirtioNetDriver::set_vq_coalesce).
+	///
+	/// Requires VIRTIO_NET_F_NOTF_COAL.
+	pub fn set_coalesce(&mut self, usecs: u32, frames: u32) -> Result<(), VirtioNetError> {
+		if !self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_NET_F_NOTF_COAL)
+		{
+			return Err(VirtioNetError::CoalescingNotSupported);
 		}
 
-		// If device does not take care of MAC address, the driver has to create one
-		if !self.dev_cfg.features.is_feature(Features::VIRTIO_NET_F_MAC) {
-			todo!("Driver created MAC address should be passed to device here.")
+		// struct virtio_net_ctrl_coal { le32 max_packets; le32 max_usecs; }
+		let mut data = [0u8; 8];
+		data[0..4].copy_from_slice(&frames.to_le_bytes());
+		data[4..8].copy_from_slice(&usecs.to_le_bytes());
+
+		for cmd in [
+			NotfCoalCmd::VIRTIO_NET_CTRL_NOTF_COAL_TX_SET,
+			NotfCoalCmd::VIRTIO_NET_CTRL_NOTF_COAL_RX_SET,
+		] {
+			self.ctrl_vq
+				.send_cmd(CtrlClass::VIRTIO_NET_CTRL_NOTF_COAL, cmd as u8, &data)?;
 		}
 
 		Ok(())
 	}
 
-	/// Initialize virtqueues via the queue interface and populates receiving queues
-	fn virtqueue_init(&mut self) -> Result<(), VirtioNetError> {
-		// We are assuming here, that the device single source of truth is the
-		// device specific configuration. Hence we do NOT check if
-		//
-		// max_virtqueue_pairs + 1 < num_queues
-		//
-		// - the plus 1 is due to the possibility of an existing control queue
-		// - the num_queues is found in the ComCfg struct of the device and defines the maximal number
-		// of supported queues.
-		if self.dev_cfg.features.is_feature(Features::VIRTIO_NET_F_MQ) {
-			if self.dev_cfg.raw.get_max_virtqueue_pairs() * 2 >= MAX_NUM_VQ {
-				self.num_vqs = MAX_NUM_VQ;
-			} else {
-				self.num_vqs = self.dev_cfg.raw.get_max_virtqueue_pairs() * 2;
-			}
-		} else {
-			// Minimal number of virtqueues defined in the standard v1.1. - 5.1.5 Step 1
-			self.num_vqs = 2;
-		}
+	/// Sends the command `cmd` of the control class `class` with the command specific
+	/// `data` over the control queue and waits for the device to acknowledge it.
+	///
+	/// Requires VIRTIO_NET_F_CTRL_VQ. See Virtio specification v1.1. - 5.1.6.5
+	pub fn send_ctrl_cmd(&mut self, class: u8, cmd: u8, data: &[u8]) -> Result<(), VirtioNetError> {
+		let class = CtrlClass::try_from(class)?;
+		self.ctrl_vq.send_cmd(class, cmd, data)
+	}
 
-		// The loop is running from 0 to num_vqs and the indexes are provided to the VqIndex::from function in this way
-		// in order to allow the indexes of the queues to be in a form of:
-		//
-		// index i for receiv queue
-		// index i+1 for send queue
-		//
-		// as it is wanted by the network network device.
-		// see Virtio specification v1.1. - 5.1.2
-		// Assure that we have always an even number of queues (i.e. pairs of queues).
-		assert_eq!(self.num_vqs % 2, 0);
+	/// Switches promiscuous mode on or off. In promiscuous mode all frames are
+	/// received, independent of their destination address.
+	///
+	/// Requires VIRTIO_NET_F_CTRL_RX.
+	pub fn set_promiscuous(&mut self, on: bool) -> Result<(), VirtioNetError> {
+		self.set_rx_mode(RxCmd::VIRTIO_NET_CTRL_RX_PROMISC, on)?;
+		self.promiscuous = on;
+		Ok(())
+	}
 
-		for i in 0..(self.num_vqs / 2) {
-			if self
-				.dev_cfg
-				.features
-				.is_feature(Features::VIRTIO_F_RING_PACKED)
-			{
-				let vq = Virtq::new(
-					&mut self.com_cfg,
-					&self.notif_cfg,
-					VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
-					VqType::Packed,
-					VqIndex::from(2 * i),
-					self.dev_cfg.features.into(),
-				);
-				// Interrupt for receiving packets is wanted
-				vq.enable_notifs();
+	/// Sends a frame addressed to the device itself in promiscuous mode and checks, that
+	/// it is received within [LOOPBACK_TIMEOUT](constants::LOOPBACK_TIMEOUT) microseconds.
+	/// Intended to validate the transmit and receive path during bring-up.
+	///
+	/// Requires VIRTIO_NET_F_CTRL_RX. The promiscuous setting is restored afterwards. Other
+	/// frames received during the test are dropped. Fails with
+	/// `VirtioNetError::LoopbackFailed` and the failed stage.
+	pub fn loopback_test(&mut self) -> Result<(), VirtioNetError> {
+		let was_promiscuous = self.promiscuous;
+		self.set_promiscuous(true)?;
 
-				self.recv_vqs.add(vq, &self.dev_cfg);
+		let result = self.run_loopback();
 
-				let vq = Virtq::new(
-					&mut self.com_cfg,
-					&self.notif_cfg,
-					VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
-					VqType::Packed,
-					VqIndex::from(2 * i + 1),
-					self.dev_cfg.features.into(),
-				);
-				// Interrupt for comunicating that a sended packet left, is not needed
-				vq.disable_notifs();
+		self.set_promiscuous(was_promiscuous)?;
+		result
+	}
 
-				self.send_vqs.add(vq, &self.dev_cfg);
-			} else {
-				let vq = Virtq::new(
-					&mut self.com_cfg,
-					&self.notif_cfg,
-					VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
-					VqType::Split,
-					VqIndex::from(2 * i),
-					self.dev_cfg.features.into(),
-				);
-				// Interrupt for receiving packets is wanted
-				vq.enable_notifs();
+	fn run_loopback(&mut self) -> Result<(), VirtioNetError> {
+		let mac = self.get_mac_address();
+		let frame = loopback_frame(mac);
 
-				self.recv_vqs.add(vq, &self.dev_cfg);
+		if let Err(vnet_err) = self.send_packet(&frame, None) {
+			error!("Loopback test could not send frame: {:?}", vnet_err);
+			return Err(VirtioNetError::LoopbackFailed(LoopbackStage::Send));
+		}
 
-				let vq = Virtq::new(
-					&mut self.com_cfg,
-					&self.notif_cfg,
-					VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
-					VqType::Split,
-					VqIndex::from(2 * i + 1),
-					self.dev_cfg.features.into(),
-				);
-				// Interrupt for comunicating that a sended packet left, is not needed
-				vq.disable_notifs();
+		let deadline = get_timer_ticks() + constants::LOOPBACK_TIMEOUT;
+		while get_timer_ticks() < deadline {
+			let Some(packet) = self.receive_packet(None) else {
+				core::hint::spin_loop();
+				continue;
+			};
 
-				self.send_vqs.add(vq, &self.dev_cfg);
+			// Only frames of the test protocol are considered.
+			if packet.get(12..ETH_HDR) != Some(&constants::LOOPBACK_ETHER_TYPE.to_be_bytes()) {
+				continue;
 			}
+
+			return if packet.get(..frame.len()) == Some(&frame[..]) {
+				Ok(())
+			} else {
+				Err(VirtioNetError::LoopbackFailed(
+					LoopbackStage::PayloadMismatch,
+				))
+			};
 		}
 
-		Ok(())
+		Err(VirtioNetError::LoopbackFailed(LoopbackStage::Receive))
 	}
-}
 
-pub mod constants {
-	use alloc::vec::Vec;
-	use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign};
+	/// Switches the reception of all multicast frames on or off.
+	///
+	/// Requires VIRTIO_NET_F_CTRL_RX.
+	pub fn set_all_multicast(&mut self, on: bool) -> Result<(), VirtioNetError> {
+		self.set_rx_mode(RxCmd::VIRTIO_NET_CTRL_RX_ALLMULTI, on)
+	}
 
-	pub use super::error::VirtioNetError;
+	/// Sends the receive filtering command `cmd` with a single on/off byte.
+	///
+	/// See Virtio specification v1.1. - 5.1.6.5.1
+	fn set_rx_mode(&mut self, cmd: RxCmd, on: bool) -> Result<(), VirtioNetError> {
+		if !self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_NET_F_CTRL_RX)
+		{
+			return Err(VirtioNetError::FeatureNotNegotiated(
+				Features::VIRTIO_NET_F_CTRL_RX,
+			));
+		}
 
-	// Configuration constants
-	pub const MAX_NUM_VQ: u16 = 2;
+		self.ctrl_vq
+			.send_cmd(CtrlClass::VIRTIO_NET_CTRL_RX, cmd as u8, &[u8::from(on)])
+	}
 
-	/// Enum containing Virtios netword header flags
-	///
-	/// See Virtio specification v1.1. - 5.1.6
-	#[allow(dead_code, non_camel_case_types)]
-	#[derive(Copy, Clone, Debug)]
-	#[repr(u8)]
+	/// Sets the MAC address of the device to `mac`. Subsequent calls of
+	/// `get_mac_address` return the new address.
 	///
-	pub enum NetHdrFlag {
-		/// use csum_start, csum_offset
-		VIRTIO_NET_HDR_F_NEEDS_CSUM = 1,
-		/// csum is valid
-		VIRTIO_NET_HDR_F_DATA_VALID = 2,
-		/// reports number of coalesced TCP segments
-		VIRTIO_NET_HDR_F_RSC_INFO = 4,
+	/// Requires VIRTIO_NET_F_CTRL_MAC_ADDR, as the MAC address in the device configuration
+	/// is read-only. See Virtio specification v1.1. - 5.1.6.5.2
+	pub fn set_mac_address(&mut self, mac: [u8; 6]) -> Result<(), VirtioNetError> {
+		if !self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_NET_F_CTRL_MAC_ADDR)
+		{
+			return Err(VirtioNetError::FeatureNotNegotiated(
+				Features::VIRTIO_NET_F_CTRL_MAC_ADDR,
+			));
+		}
+
+		self.ctrl_vq.send_cmd(
+			CtrlClass::VIRTIO_NET_CTRL_MAC,
+			MacCmd::VIRTIO_NET_CTRL_MAC_ADDR_SET as u8,
+			&mac,
+		)?;
+		self.assigned_mac = Some(mac);
+
+		Ok(())
 	}
 
-	impl From<NetHdrFlag> for u8 {
-		fn from(val: NetHdrFlag) -> Self {
-			match val {
-				NetHdrFlag::VIRTIO_NET_HDR_F_NEEDS_CSUM => 1,
-				NetHdrFlag::VIRTIO_NET_HDR_F_DATA_VALID => 2,
-				NetHdrFlag::VIRTIO_NET_HDR_F_RSC_INFO => 4,
-			}
+	/// Adds the VLAN id `vid` to the filter table of the device.
+	///
+	/// Requires VIRTIO_NET_F_CTRL_VLAN.
+	pub fn add_vlan(&mut self, vid: u16) -> Result<(), VirtioNetError> {
+		self.send_vlan_cmd(VlanCmd::VIRTIO_NET_CTRL_VLAN_ADD, vid)?;
+		if !self.vlans.contains(&vid) {
+			self.vlans.push(vid);
 		}
+
+		Ok(())
 	}
 
-	impl BitOr for NetHdrFlag {
-		type Output = u8;
+	/// Removes the VLAN id `vid` from the filter table of the device.
+	///
+	/// Requires VIRTIO_NET_F_CTRL_VLAN.
+	pub fn del_vlan(&mut self, vid: u16) -> Result<(), VirtioNetError> {
+		self.send_vlan_cmd(VlanCmd::VIRTIO_NET_CTRL_VLAN_DEL, vid)?;
+		self.vlans.retain(|&active| active != vid);
 
-		fn bitor(self, rhs: Self) -> Self::Output {
-			u8::from(self) | u8::from(rhs)
-		}
+		Ok(())
 	}
 
-	impl BitOr<NetHdrFlag> for u8 {
-		type Output = u8;
-
-		fn bitor(self, rhs: NetHdrFlag) -> Self::Output {
-			self | u8::from(rhs)
-		}
+	/// Returns the VLAN ids, which have been added to the filter table of the device.
+	pub fn vlans(&self) -> &[u16] {
+		&self.vlans
 	}
 
-	impl BitOrAssign<NetHdrFlag> for u8 {
-		fn bitor_assign(&mut self, rhs: NetHdrFlag) {
-			*self |= u8::from(rhs);
+	/// See Virtio specification v1.1. - 5.1.6.5.3
+	fn send_vlan_cmd(&mut self, cmd: VlanCmd, vid: u16) -> Result<(), VirtioNetError> {
+		if !self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_NET_F_CTRL_VLAN)
+		{
+			return Err(VirtioNetError::FeatureNotNegotiated(
+				Features::VIRTIO_NET_F_CTRL_VLAN,
+			));
+		}
+		if vid >= 4096 {
+			return Err(VirtioNetError::InvalidVlanId(vid));
 		}
+
+		self.ctrl_vq.send_cmd(
+			CtrlClass::VIRTIO_NET_CTRL_VLAN,
+			cmd as u8,
+			&vid.to_le_bytes(),
+		)
 	}
 
-	impl BitAnd for NetHdrFlag {
-		type Output = u8;
+	/// Enables or disables the offloads of the device for received packets at runtime. See
+	/// Virtio specification v1.1. - 5.1.6.5.6.1
+	///
+	/// Requires VIRTIO_NET_F_CTRL_GUEST_OFFLOADS. Only offloads, whose feature has been
+	/// negotiated upon initialization, can be enabled. Disabling is always possible.
+	/// Receiving segments with ECN set (`ecn`) requires VIRTIO_NET_F_GUEST_ECN and `tso4`
+	/// or `tso6`, otherwise `VirtioNetError::IncompatibleFeatures` is returned.
+	pub fn set_guest_offloads(
+		&mut self,
+		csum: bool,
+		tso4: bool,
+		tso6: bool,
+		ecn: bool,
+		ufo: bool,
+	) -> Result<(), VirtioNetError> {
+		if !self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_NET_F_CTRL_GUEST_OFFLOADS)
+		{
+			return Err(VirtioNetError::FeatureNotNegotiated(
+				Features::VIRTIO_NET_F_CTRL_GUEST_OFFLOADS,
+			));
+		}
 
-		fn bitand(self, rhs: NetHdrFlag) -> Self::Output {
-			u8::from(self) & u8::from(rhs)
+		// The offloads are identified by the bits of their features.
+		let mut offloads = 0u64;
+		for (enable, feat) in [
+			(csum, Features::VIRTIO_NET_F_GUEST_CSUM),
+			(tso4, Features::VIRTIO_NET_F_GUEST_TSO4),
+			(tso6, Features::VIRTIO_NET_F_GUEST_TSO6),
+			(ecn, Features::VIRTIO_NET_F_GUEST_ECN),
+			(ufo, Features::VIRTIO_NET_F_GUEST_UFO),
+		] {
+			if !enable {
+				continue;
+			}
+			if !self.dev_cfg.features.is_feature(feat) {
+				return Err(VirtioNetError::FeatureNotNegotiated(feat));
+			}
+			offloads |= u64::from(feat);
 		}
+		if ecn && !tso4 && !tso6 {
+			return Err(VirtioNetError::IncompatibleFeatures(
+				Features::VIRTIO_NET_F_GUEST_ECN,
+				Features::VIRTIO_NET_F_GUEST_TSO4 | Features::VIRTIO_NET_F_GUEST_TSO6,
+			));
+		}
+
+		self.ctrl_vq.send_cmd(
+			CtrlClass::VIRTIO_NET_CTRL_GUEST_OFFLOADS,
+			GuestOffloadsCmd::VIRTIO_NET_CTRL_GUEST_OFFLOADS_SET as u8,
+			&offloads.to_le_bytes(),
+		)
 	}
 
-	impl BitAnd<NetHdrFlag> for u8 {
-		type Output = u8;
+	/// Configures the distribution of received packets to the receive queues by receive-side
+	/// scaling. See Virtio specification v1.2. - 5.1.6.5.7.1
+	///
+	/// Packets of the given `hash_types` (bit mask of `RSS_HASH_TYPE_*`) are hashed with `key`
+	/// and steered to the receive queue, whose index is stored at the hash masked to the length
+	/// of the `indirection` table. The table length must be a power of two. Requires
+	/// VIRTIO_NET_F_RSS. Key and table must not exceed the maximal sizes reported by the device.
+	///
+	/// The table may only refer to the active queue pairs, see
+	/// [set_active_queue_pairs](Self::set_active_queue_pairs). Afterwards the
+	/// [steering_mode](Self::steering_mode) is [SteeringMode::Rss].
+	pub fn configure_rss(
+		&mut self,
+		key: &[u8],
+		indirection: &[u16],
+		hash_types: u32,
+	) -> Result<(), VirtioNetError> {
+		if !self.dev_cfg.features.is_feature(Features::VIRTIO_NET_F_RSS) {
+			return Err(VirtioNetError::FeatureNotNegotiated(
+				Features::VIRTIO_NET_F_RSS,
+			));
+		}
 
-		fn bitand(self, rhs: NetHdrFlag) -> Self::Output {
-			self & u8::from(rhs)
+		let max_key = self.dev_cfg.rss_max_key_size();
+		if key.len() > usize::from(max_key) {
+			return Err(VirtioNetError::RssKeyTooLong(key.len(), max_key));
+		}
+		let max_table = self.dev_cfg.rss_max_indirection_table_length();
+		if !indirection.len().is_power_of_two() || indirection.len() > usize::from(max_table) {
+			return Err(VirtioNetError::InvalidRssTable(
+				indirection.len(),
+				max_table,
+			));
 		}
+		let unsupported = hash_types & !self.dev_cfg.supported_hash_types();
+		if unsupported != 0 {
+			return Err(VirtioNetError::UnsupportedHashTypes(unsupported));
+		}
+		// Queues behind the active pairs are not polled by the driver.
+		let pairs = self.active_queue_pairs();
+		if let Some(queue) = indirection.iter().find(|queue| **queue >= pairs) {
+			return Err(VirtioNetError::InvalidQueue(*queue));
+		}
+
+		let data = rss_config_bytes(hash_types, indirection, pairs, key);
+		self.ctrl_vq.send_cmd(
+			CtrlClass::VIRTIO_NET_CTRL_MQ,
+			MqCmd::VIRTIO_NET_CTRL_MQ_RSS_CONFIG as u8,
+			&data,
+		)?;
+		self.steering = SteeringMode::Rss;
+		Ok(())
 	}
 
-	impl BitAndAssign<NetHdrFlag> for u8 {
-		fn bitand_assign(&mut self, rhs: NetHdrFlag) {
-			*self &= u8::from(rhs);
-		}
+	/// Configures RSS to distribute packets of all supported hash types evenly over the active
+	/// queue pairs. See [configure_rss](Self::configure_rss).
+	///
+	/// Like all commands, the configuration must not be sent before DRIVER_OK has been set,
+	/// see [steering_init](Self::steering_init).
+	fn configure_default_rss(&mut self) -> Result<(), VirtioNetError> {
+		let indirection = default_indirection_table(
+			self.active_queue_pairs(),
+			self.dev_cfg.rss_max_indirection_table_length(),
+		);
+		let key_len = usize::from(self.dev_cfg.rss_max_key_size()).min(RSS_DEFAULT_KEY.len());
+		let hash_types = self.dev_cfg.supported_hash_types() & RSS_DEFAULT_HASH_TYPES;
+
+		self.configure_rss(&RSS_DEFAULT_KEY[..key_len], &indirection, hash_types)
 	}
 
-	/// Enum containing Virtios netword GSO types
+	/// Returns the number of descriptors of the send queue of the given queue pair, which are
+	/// not in use by the device. Allows to stop sending before [VirtioNetError::TxQueueFull]
+	/// is returned. Returns zero, if the queue does not exist.
 	///
-	/// See Virtio specification v1.1. - 5.1.6
-	#[allow(dead_code, non_camel_case_types)]
-	#[derive(Copy, Clone, Debug)]
-	#[repr(u8)]
-	pub enum NetHdrGSO {
-		/// not a GSO frame
-		VIRTIO_NET_HDR_GSO_NONE = 0,
-		/// GSO frame, IPv4 TCP (TSO)
-		VIRTIO_NET_HDR_GSO_TCPV4 = 1,
-		/// GSO frame, IPv4 UDP (UFO)
-		VIRTIO_NET_HDR_GSO_UDP = 3,
-		/// GSO frame, IPv6 TCP
-		VIRTIO_NET_HDR_GSO_TCPV6 = 4,
-		/// TCP has ECN set
-		VIRTIO_NET_HDR_GSO_ECN = 0x80,
+	/// Packets occupy a single descriptor, unless they are sent as scatter-gather list
+	/// without indirect descriptors.
+	pub fn tx_free_descriptors(&self, queue: u16) -> u16 {
+		self.send_vqs
+			.vqs
+			.get(usize::from(queue))
+			.map_or(0, |vq| vq.free_descriptors())
 	}
 
-	impl From<NetHdrGSO> for u8 {
-		fn from(val: NetHdrGSO) -> Self {
-			match val {
-				NetHdrGSO::VIRTIO_NET_HDR_GSO_NONE => 0,
-				NetHdrGSO::VIRTIO_NET_HDR_GSO_TCPV4 => 1,
-				NetHdrGSO::VIRTIO_NET_HDR_GSO_UDP => 3,
-				NetHdrGSO::VIRTIO_NET_HDR_GSO_TCPV6 => 4,
-				NetHdrGSO::VIRTIO_NET_HDR_GSO_ECN => 0x80,
+	/// Returns the maximal number of fragments of a packet sent via
+	/// [send_packet_sg](Self::send_packet_sg). Packets with more fragments are rejected with
+	/// [VirtioNetError::TooManySegments] and must be coalesced beforehand.
+	///
+	/// The device does not report a limit of its own. Descriptor chains must not exceed the
+	/// queue size and direct chains are additionally limited to [MAX_DIRECT_TX_CHAIN]
+	/// descriptors, as some hosts silently drop longer ones. With VIRTIO_F_RING_INDIRECT_DESC,
+	/// only the length of the indirect table is bounded by the queue size.
+	pub fn max_tx_segments(&self) -> u16 {
+		let queue_size = self
+			.send_vqs
+			.vqs
+			.iter()
+			.map(|vq| u16::from(vq.size()))
+			.min()
+			.unwrap_or(0);
+		let indirect = self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_F_RING_INDIRECT_DESC);
+
+		tx_segment_limit(indirect, queue_size)
+	}
+
+	/// Returns the number of receive buffers of the given queue pair, which have been filled
+	/// by the device and not yet been fetched via [receive_packet](Self::receive_packet). With
+	/// VIRTIO_NET_F_MRG_RXBUF a packet might span multiple buffers. Returns zero, if the queue
+	/// does not exist.
+	pub fn rx_available(&self, queue: u16) -> u16 {
+		let Some(vq) = self.recv_vqs.vqs.get(usize::from(queue)) else {
+			return 0;
+		};
+
+		let index = vq.index();
+		let polled = self
+			.recv_vqs
+			.poll_queue
+			.borrow()
+			.iter()
+			.filter(|transfer| transfer.vq_index() == index)
+			.count();
+
+		vq.pending_used()
+			.saturating_add(u16::try_from(polled).unwrap_or(u16::MAX))
+	}
+
+	/// Returns true, if the device acts as standby for a primary device, i.e. the networking
+	/// layer should prefer the primary device and fail over to this one. The driver does not
+	/// bond the devices itself. Requires VIRTIO_NET_F_STANDBY. See Virtio specification v1.2. - 5.1.3
+	pub fn is_standby(&self) -> bool {
+		self.has_feature(Features::VIRTIO_NET_F_STANDBY)
+	}
+
+	/// Returns the identifier of the failover group of a standby device, i.e. the MAC address,
+	/// which is shared with its primary device. Returns `None`, if the device is no standby.
+	pub fn failover_group(&self) -> Option<[u8; 6]> {
+		if self.is_standby() {
+			Some(self.get_mac_address())
+		} else {
+			None
+		}
+	}
+
+	/// Returns the number of queue pairs in use.
+	pub fn num_queues(&self) -> u16 {
+		self.num_vqs / 2
+	}
+
+	/// Returns the mechanism, which is used by the device to distribute received
+	/// packets over the receive queues. The mode is selected at the end of the
+	/// initialization and is [SteeringMode::Single] before.
+	pub fn steering_mode(&self) -> SteeringMode {
+		self.steering
+	}
+
+	/// Transmits a single packet, which is scattered over the given segments (e.g. protocol
+	/// headers and payload in distinct buffers).
+	///
+	/// The segments are NOT copied. Instead the driver places one descriptor per segment
+	/// (or per page of a segment) behind the VirtioNetHdr into the transmit queue. As the
+	/// segments are only borrowed, the function returns after the device has consumed the packet.
+	///
+	/// If the transmit queue is full, [VirtioNetError::TxQueueFull] is returned. If the device
+	/// does not consume the packet within [TX_BORROWED_TIMEOUT] microseconds, it is reset, so
+	/// it stops accessing the segments, and [VirtioNetError::Timeout] is returned. The device is
+	/// reinitialized afterwards like by [reinit](Self::reinit), hence the packets in flight are
+	/// lost.
+	pub fn send_iov(&mut self, segments: &[&[u8]]) -> Result<(), VirtioNetError> {
+		let len: usize = segments.iter().map(|seg| seg.len()).sum();
+		if len > self.dev_cfg.max_frame_len() {
+			return Err(VirtioNetError::PacketTooLarge(len));
+		}
+		// The segments are always chained directly in the first transmit queue.
+		let descriptors: usize = segments.iter().map(|seg| slice_descriptors(seg)).sum();
+		let queue_size = self
+			.send_vqs
+			.vqs
+			.first()
+			.map_or(0, |vq| u16::from(vq.size()));
+		let max_segments = tx_segment_limit(false, queue_size);
+		if descriptors > usize::from(max_segments) {
+			return Err(VirtioNetError::TooManySegments(descriptors, max_segments));
+		}
+
+		self.send_with_hdr(VirtioNetHdr::get_tx_hdr(), segments, len)
+	}
+
+	/// Transmits a single TCP packet, which is segmented by the device into frames carrying
+	/// at most `mss` bytes of payload (TCP segmentation offload).
+	///
+	/// `data` must contain the complete frame, starting with the ethernet header. `hdr_len` is
+	/// the length of the ethernet, IP and TCP headers, which are replicated by the device for
+	/// every segment. Requests are rejected, if
+	/// * `hdr_len` is shorter than the ethernet header or exceeds the frame,
+	/// * VIRTIO_NET_F_HOST_TSO4, respectively VIRTIO_NET_F_HOST_TSO6 has not been negotiated,
+	/// * `mss` is smaller than [MIN_TSO_MSS] or does not fit into the MTU together with the IP and TCP headers,
+	/// * the frame is small enough to be sent without segmentation.
+	///
+	/// With `ecn`, the device is told, that the TCP flow uses explicit congestion notification,
+	/// so the CWR flag is only set on the first segment. Requires VIRTIO_NET_F_HOST_ECN, which
+	/// in turn requires VIRTIO_NET_F_HOST_TSO4 or VIRTIO_NET_F_HOST_TSO6. Otherwise
+	/// `VirtioNetError::IncompatibleFeatures` is returned.
+	pub fn send_segmented(
+		&mut self,
+		data: &[u8],
+		hdr_len: u16,
+		mss: u16,
+		ecn: bool,
+	) -> Result<(), VirtioNetError> {
+		if ecn
+			&& !self
+				.dev_cfg
+				.features
+				.is_feature(Features::VIRTIO_NET_F_HOST_ECN)
+		{
+			return Err(VirtioNetError::IncompatibleFeatures(
+				Features::VIRTIO_NET_F_HOST_ECN,
+				Features::VIRTIO_NET_F_HOST_TSO4 | Features::VIRTIO_NET_F_HOST_TSO6,
+			));
+		}
+
+		if data.len() > MAX_GSO_LEN {
+			return Err(VirtioNetError::PacketTooLarge(data.len()));
+		}
+
+		let hdr_len_usize = usize::from(hdr_len);
+		if hdr_len_usize < ETH_HDR || hdr_len_usize > data.len() {
+			return Err(VirtioNetError::InvalidHdrLen(hdr_len));
+		}
+
+		// The MTU does not include the ethernet header.
+		let max_mss = usize::from(self.get_mtu()).saturating_sub(hdr_len_usize - ETH_HDR);
+		if mss < MIN_TSO_MSS || usize::from(mss) > max_mss {
+			return Err(VirtioNetError::InvalidMss(mss));
+		}
+		if data.len() <= hdr_len_usize + usize::from(mss) {
+			return Err(VirtioNetError::NoSegmentationNeeded(data.len()));
+		}
+
+		// Derive type of segmentation and start of the TCP header from the ethertype.
+		let (gso_type, feature, ip_hdr_len) = match u16::from_be_bytes([data[12], data[13]]) {
+			0x0800 => (
+				NetHdrGSO::VIRTIO_NET_HDR_GSO_TCPV4,
+				Features::VIRTIO_NET_F_HOST_TSO4,
+				usize::from(data[ETH_HDR] & 0x0f) * 4,
+			),
+			0x86dd => (
+				NetHdrGSO::VIRTIO_NET_HDR_GSO_TCPV6,
+				Features::VIRTIO_NET_F_HOST_TSO6,
+				40,
+			),
+			_ => return Err(VirtioNetError::GsoNotSupported),
+		};
+		if !self.dev_cfg.features.is_feature(feature) {
+			return Err(VirtioNetError::GsoNotSupported);
+		}
+
+		// Virtio specification v1.1. - 5.1.6.2.1: Segmentation requires the
+		// device to compute the checksums. The TCP checksum is located at offset 16.
+		let hdr = VirtioNetHdr {
+			flags: NetHdrFlag::VIRTIO_NET_HDR_F_NEEDS_CSUM.into(),
+			gso_type: gso_type_bits(gso_type, ecn),
+			hdr_len,
+			gso_size: mss,
+			csum_start: u16::try_from(ETH_HDR + ip_hdr_len).unwrap(),
+			csum_offset: 16,
+			num_buffers: 0,
+		};
+
+		self.send_with_hdr(hdr, &[data], data.len())
+	}
+
+	/// Places the given header and segments as a single packet into the first transmit
+	/// queue and waits until the device has consumed it.
+	fn send_with_hdr(
+		&mut self,
+		hdr: VirtioNetHdr,
+		segments: &[&[u8]],
+		len: usize,
+	) -> Result<(), VirtioNetError> {
+		// The header must be located in memory, which outlives the transfer.
+		let hdr = Box::new(hdr.to_bytes(self.dev_cfg.features));
+		let mut iov: Vec<&[u8]> = Vec::with_capacity(segments.len() + 1);
+		iov.push(&hdr[..VirtioNetHdr::hdr_size(self.dev_cfg.features)]);
+		iov.extend_from_slice(segments);
+
+		// Finished packets release their descriptors, before new ones are taken.
+		self.send_vqs.reclaim();
+		let vq = self
+			.send_vqs
+			.vqs
+			.first()
+			.ok_or(VirtioNetError::NoTxBuffAvail)?;
+		// The segments are borrowed until the transfer is finished below.
+		let transfer = match unsafe { vq.prep_transfer_from_slices(Rc::clone(vq), &iov) } {
+			Ok(tkn) => tkn.dispatch(false),
+			Err(VirtqError::NoDescrAvail) => {
+				self.stats[0].count_tx_error();
+				return Err(VirtioNetError::TxQueueFull);
 			}
+			Err(_) => {
+				self.stats[0].count_tx_error();
+				return Err(VirtioNetError::NoTxBuffAvail);
+			}
+		};
+
+		let clock = self.config.clock;
+		let start = clock();
+		while !transfer.poll() {
+			if clock() - start > TX_BORROWED_TIMEOUT {
+				// The device must not access the segments, once the caller regains them.
+				error!(
+					"Network device {:x} did not transmit a borrowed packet in time. Resetting the device.",
+					self.dev_cfg.dev_id
+				);
+				self.com_cfg.reset_dev();
+				transfer.close();
+				self.stats[0].count_tx_error();
+				if let Err(vnet_err) = self.reinit_unconditionally() {
+					error!(
+						"Network device {:x} could not be recovered: {}",
+						self.dev_cfg.dev_id, vnet_err
+					);
+				}
+				return Err(VirtioNetError::Timeout);
+			}
+			core::hint::spin_loop();
+			vq.poll();
 		}
+		transfer.close();
+		self.stats[0].count_tx(len);
+
+		Ok(())
 	}
 
-	impl BitOr for NetHdrGSO {
-		type Output = u8;
+	/// Transmits the given packet, which must start with the ethernet header.
+	///
+	/// The packet is copied behind a VirtioNetHdr without any offloads into a send buffer
+	/// and the device is notified. The function does not wait for the device to consume
+	/// the packet. If all descriptors of the transmit queue are in use,
+	/// [VirtioNetError::TxQueueFull] is returned instead of blocking. While the link is
+	/// down, [VirtioNetError::LinkDown] is returned. Both are transient, sending can be
+	/// retried later.
+	///
+	/// `queue` selects the transmit queue of the given queue pair (see
+	/// [num_queues](VirtioNetDriver::num_queues)), which allows to pin traffic to a core.
+	/// Without a queue, the first one is used.
+	pub fn send_packet(&mut self, data: &[u8], queue: Option<u16>) -> Result<(), VirtioNetError> {
+		let queue = queue.unwrap_or(0);
+		let buff_tkn = match self.prep_tx_buffer(data, queue) {
+			Ok(buff_tkn) => buff_tkn,
+			Err(vnet_err) => {
+				self.queue_counters(queue.into()).count_tx_error();
+				return Err(vnet_err);
+			}
+		};
 
-		fn bitor(self, rhs: Self) -> Self::Output {
-			u8::from(self) | u8::from(rhs)
+		buff_tkn
+			.provide()
+			.dispatch_await(Rc::clone(&self.send_vqs.poll_queue), false);
+		self.queue_counters(queue.into()).count_tx(data.len());
+
+		Ok(())
+	}
+
+	/// Sends a packet like [send_packet](Self::send_packet) on the first transmit queue. If the
+	/// queue is full, the buffers of finished packets are reclaimed and sending is retried, up
+	/// to `max_attempts` times in total.
+	///
+	/// Returns the number of reclaim passes, which were needed to send the packet. A high
+	/// number indicates, that the transmit queue is too small for the workload. Other errors,
+	/// e.g. [VirtioNetError::LinkDown], are not retried. If the queue is still full after the
+	/// last attempt, [VirtioNetError::TxQueueFull] is returned.
+	pub fn send_packet_retry(
+		&mut self,
+		data: &[u8],
+		max_attempts: u32,
+	) -> Result<u32, VirtioNetError> {
+		let mut passes = 0;
+		let buff_tkn = loop {
+			match self.prep_tx_buffer(data, 0) {
+				Ok(buff_tkn) => break buff_tkn,
+				Err(VirtioNetError::TxQueueFull) if passes + 1 < max_attempts => {
+					passes += 1;
+					if self.reclaim_tx_buffers() == 0 {
+						core::hint::spin_loop();
+					}
+				}
+				Err(vnet_err) => {
+					// Failed attempts, which are retried, are not counted as errors.
+					self.queue_counters(0).count_tx_error();
+					return Err(vnet_err);
+				}
+			}
+		};
+
+		buff_tkn
+			.provide()
+			.dispatch_await(Rc::clone(&self.send_vqs.poll_queue), false);
+		self.queue_counters(0).count_tx(data.len());
+
+		Ok(passes)
+	}
+
+	/// Transmits the given frames on the first transmit queue with a single notification of
+	/// the device. See [send_packet](Self::send_packet).
+	///
+	/// As many frames as fit into the queue are made available to the device at once. Returns
+	/// the number of enqueued frames, which is smaller than the number of frames, if the queue
+	/// ran full or a frame could not be sent. Sending the remainder can be retried. An error is
+	/// only returned, if not even the first frame could be enqueued.
+	pub fn send_batch(&mut self, frames: &[&[u8]]) -> Result<usize, VirtioNetError> {
+		let mut tkns = Vec::with_capacity(frames.len());
+		for frame in frames {
+			match self.prep_tx_buffer(frame, 0) {
+				Ok(buff_tkn) => tkns.push(buff_tkn.provide()),
+				Err(vnet_err) if tkns.is_empty() => {
+					self.queue_counters(0).count_tx_error();
+					return Err(vnet_err);
+				}
+				Err(_) => break,
+			}
+		}
+
+		let num = tkns.len();
+		if num == 0 {
+			return Ok(0);
+		}
+		Virtq::dispatch_batch_await(tkns, Rc::clone(&self.send_vqs.poll_queue), false);
+
+		let counters = self.queue_counters(0);
+		for frame in &frames[..num] {
+			counters.count_tx(frame.len());
 		}
+
+		Ok(num)
 	}
 
-	impl BitOr<NetHdrGSO> for u8 {
-		type Output = u8;
+	/// Sends a packet like [send_packet](Self::send_packet) on the first transmit queue and
+	/// waits until the device consumed it, at most for `timeout`.
+	///
+	/// Returns [VirtioNetError::Timeout], if the device did not consume the packet in time.
+	/// The buffer is released by the queue, once the device finishes it later.
+	pub fn send_packet_sync(
+		&mut self,
+		data: &[u8],
+		timeout: Duration,
+	) -> Result<(), VirtioNetError> {
+		let buff_tkn = match self.prep_tx_buffer(data, 0) {
+			Ok(buff_tkn) => buff_tkn,
+			Err(vnet_err) => {
+				self.queue_counters(0).count_tx_error();
+				return Err(vnet_err);
+			}
+		};
 
-		fn bitor(self, rhs: NetHdrGSO) -> Self::Output {
-			self | u8::from(rhs)
+		let transfer = buff_tkn.provide().dispatch(false);
+		self.queue_counters(0).count_tx(data.len());
+
+		// Timer ticks are counted in microseconds.
+		let timeout = u64::try_from(timeout.as_micros()).unwrap_or(u64::MAX);
+		let clock = self.config.clock;
+		let start = clock();
+		while !transfer.poll() {
+			if clock() - start > timeout {
+				transfer.close();
+				return Err(VirtioNetError::Timeout);
+			}
+			core::hint::spin_loop();
+			self.send_vqs.poll();
 		}
+		self.stamp_tx_completions(1);
+
+		// The buffer is reused for the next packets.
+		self.send_vqs.poll_queue.borrow_mut().push_back(transfer);
+		Ok(())
 	}
 
-	impl BitOrAssign<NetHdrGSO> for u8 {
-		fn bitor_assign(&mut self, rhs: NetHdrGSO) {
-			*self |= u8::from(rhs);
+	/// Returns a send buffer for a frame of `len` bytes, which can be filled in place and
+	/// sent without copying via [send_buffer](Self::send_buffer).
+	///
+	/// Fails with `VirtioNetError::PacketTooLarge`, if the frame exceeds the MTU, and with
+	/// `VirtioNetError::TxQueueFull`, if no buffer is available.
+	pub fn alloc_dma_buffer(&mut self, len: usize) -> Result<DmaBuffer, VirtioNetError> {
+		if len > self.dev_cfg.max_frame_len() {
+			return Err(VirtioNetError::PacketTooLarge(len));
 		}
+
+		let hdr_len = VirtioNetHdr::hdr_size(self.dev_cfg.features);
+		let (buff_tkn, _) = self
+			.send_vqs
+			.get_tkn(len + hdr_len)
+			.ok_or(VirtioNetError::TxQueueFull)?;
+
+		Ok(DmaBuffer { buff_tkn, hdr_len })
 	}
 
-	impl BitAnd for NetHdrGSO {
-		type Output = u8;
+	/// Sends the frame of the buffer without copying it on the first transmit queue.
+	///
+	/// The device reads the buffer until the returned handle reports completion. The frame
+	/// must neither be read nor written in the meantime, which is enforced by the handle
+	/// owning the buffer. No offloads are used for the frame.
+	pub fn send_buffer(&mut self, buf: DmaBuffer) -> Result<SendHandle, VirtioNetError> {
+		if !self.is_link_up() {
+			self.queue_counters(0).count_tx_error();
+			return Err(VirtioNetError::LinkDown);
+		}
 
-		fn bitand(self, rhs: NetHdrGSO) -> Self::Output {
-			u8::from(self) & u8::from(rhs)
+		let DmaBuffer {
+			mut buff_tkn,
+			hdr_len,
+		} = buf;
+		let (send_ptrs, _) = buff_tkn.raw_ptrs();
+		let (buff_ptr, _) = send_ptrs.unwrap()[0];
+		let hdr = VirtioNetHdr::get_tx_hdr();
+		unsafe {
+			core::ptr::copy_nonoverlapping(
+				hdr.to_bytes(self.dev_cfg.features).as_ptr(),
+				buff_ptr,
+				hdr_len,
+			);
 		}
+
+		let len = buff_tkn.len().0 - hdr_len;
+		let transfer = buff_tkn.provide().dispatch(false);
+		self.queue_counters(0).count_tx(len);
+
+		Ok(SendHandle {
+			transfer,
+			hdr_len,
+			timestamps: self.config.tx_timestamps,
+			completed_at: Cell::new(None),
+		})
 	}
 
-	impl BitAnd<NetHdrGSO> for u8 {
-		type Output = u8;
+	/// Sends a packet, which is split across the given fragments, on the transmit queue
+	/// of the given queue pair. See [send_packet](Self::send_packet).
+	///
+	/// The header and each fragment are placed in one descriptor. For more than
+	/// [INDIRECT_TX_THRESHOLD] fragments, the descriptors are placed in an indirect
+	/// descriptor table, if VIRTIO_F_RING_INDIRECT_DESC has been negotiated, so the packet
+	/// occupies a single descriptor of the queue. No offloads are used for such packets.
+	pub fn send_packet_sg(
+		&mut self,
+		frags: &[&[u8]],
+		queue: Option<u16>,
+	) -> Result<(), VirtioNetError> {
+		let queue = queue.unwrap_or(0);
+		let len = frags.iter().map(|frag| frag.len()).sum();
+		let buff_tkn = match self.prep_tx_sg_buffer(frags, len, queue) {
+			Ok(buff_tkn) => buff_tkn,
+			Err(vnet_err) => {
+				self.queue_counters(queue.into()).count_tx_error();
+				return Err(vnet_err);
+			}
+		};
 
-		fn bitand(self, rhs: NetHdrGSO) -> Self::Output {
-			self & u8::from(rhs)
+		buff_tkn
+			.provide()
+			.dispatch_await(Rc::clone(&self.send_vqs.poll_queue), false);
+		self.queue_counters(queue.into()).count_tx(len);
+
+		Ok(())
+	}
+
+	/// Copies a VirtioNetHdr and the given fragments into the descriptors of a new send buffer.
+	fn prep_tx_sg_buffer(
+		&mut self,
+		frags: &[&[u8]],
+		len: usize,
+		queue: u16,
+	) -> Result<BufferToken, VirtioNetError> {
+		if !self.is_link_up() {
+			return Err(VirtioNetError::LinkDown);
+		}
+		if len > self.dev_cfg.max_frame_len() {
+			return Err(VirtioNetError::PacketTooLarge(len));
+		}
+		if self.send_vqs.vqs.is_empty() {
+			return Err(VirtioNetError::NoTxBuffAvail);
+		}
+		if usize::from(queue) >= self.send_vqs.active().len() {
+			return Err(VirtioNetError::InvalidQueue(queue));
+		}
+
+		// Empty fragments can not be placed in a descriptor.
+		let frags: Vec<&[u8]> = frags
+			.iter()
+			.copied()
+			.filter(|frag| !frag.is_empty())
+			.collect();
+		let max_segments = self.max_tx_segments();
+		if frags.len() > usize::from(max_segments) {
+			return Err(VirtioNetError::TooManySegments(frags.len(), max_segments));
+		}
+		let hdr_len = VirtioNetHdr::hdr_size(self.dev_cfg.features);
+		let lens: Vec<Bytes> = core::iter::once(hdr_len)
+			.chain(frags.iter().map(|frag| frag.len()))
+			.map(|size| Bytes::new(size).unwrap())
+			.collect();
+		let indirect = frags.len() > INDIRECT_TX_THRESHOLD
+			&& self
+				.dev_cfg
+				.features
+				.is_feature(Features::VIRTIO_F_RING_INDIRECT_DESC);
+
+		let mut buff_tkn = self
+			.send_vqs
+			.get_sg_tkn(queue.into(), &lens, indirect)
+			.ok_or(VirtioNetError::TxQueueFull)?;
+
+		let (send_ptrs, _) = buff_tkn.raw_ptrs();
+		let send_ptrs = send_ptrs.unwrap();
+		let hdr = VirtioNetHdr::get_tx_hdr();
+		unsafe {
+			core::ptr::copy_nonoverlapping(
+				hdr.to_bytes(self.dev_cfg.features).as_ptr(),
+				send_ptrs[0].0,
+				hdr_len,
+			);
+			for (frag, (ptr, _)) in frags.iter().zip(send_ptrs[1..].iter()) {
+				core::ptr::copy_nonoverlapping(frag.as_ptr(), *ptr, frag.len());
+			}
 		}
+
+		Ok(buff_tkn)
 	}
 
-	impl BitAndAssign<NetHdrGSO> for u8 {
-		fn bitand_assign(&mut self, rhs: NetHdrGSO) {
-			*self &= u8::from(rhs);
+	/// Copies the given packet behind a VirtioNetHdr into a send buffer.
+	///
+	/// TCP packets exceeding the MTU are segmented by the device, if VIRTIO_NET_F_HOST_TSO4,
+	/// respectively VIRTIO_NET_F_HOST_TSO6 has been negotiated. See [max_tso_size](Self::max_tso_size).
+	fn prep_tx_buffer(&mut self, data: &[u8], queue: u16) -> Result<BufferToken, VirtioNetError> {
+		self.irq_storm_cooldown();
+		if !self.is_link_up() {
+			return Err(VirtioNetError::LinkDown);
+		}
+		let max_frame = self.dev_cfg.max_frame_len();
+		let tso = if data.len() > max_frame {
+			if data.len() > self.max_tso_size() {
+				return Err(VirtioNetError::PacketTooLarge(data.len()));
+			}
+			Some(
+				self.tso_params(data)
+					.ok_or(VirtioNetError::PacketTooLarge(data.len()))?,
+			)
+		} else {
+			None
+		};
+		if self.send_vqs.vqs.is_empty() {
+			return Err(VirtioNetError::NoTxBuffAvail);
+		}
+		if usize::from(queue) >= self.send_vqs.active().len() {
+			return Err(VirtioNetError::InvalidQueue(queue));
+		}
+
+		let hdr_len = VirtioNetHdr::hdr_size(self.dev_cfg.features);
+		let frame_len = tx_frame_len(data.len());
+		let mut buff_tkn = self
+			.send_vqs
+			.get_tkn_of(queue.into(), frame_len + hdr_len)
+			.ok_or(VirtioNetError::TxQueueFull)?;
+
+		let (send_ptrs, _) = buff_tkn.raw_ptrs();
+		// See TxQueues.add(), send buffers consist of a single descriptor.
+		let (buff_ptr, _) = send_ptrs.unwrap()[0];
+		// The frame is copied, hence padding does not touch the buffer of the caller.
+		let frame = unsafe { core::slice::from_raw_parts_mut(buff_ptr.add(hdr_len), frame_len) };
+		write_padded(frame, data);
+
+		let mut hdr = VirtioNetHdr::get_tx_hdr();
+		if let Some((gso_type, ip_hdr_len, tcp_hdr_len)) = tso {
+			// Segmentation requires the device to compute the checksums of all segments.
+			// Hence a checksum computed by the caller is replaced by the partial one.
+			let field = ETH_HDR + ip_hdr_len + 16;
+			frame[field..field + 2].copy_from_slice(&[0, 0]);
+			let (csum_start, csum_offset) =
+				prepare_tx_csum(frame).ok_or(VirtioNetError::GsoNotSupported)?;
+
+			let hdrs_len = ETH_HDR + ip_hdr_len + tcp_hdr_len;
+			hdr.flags = NetHdrFlag::VIRTIO_NET_HDR_F_NEEDS_CSUM.into();
+			hdr.gso_type = gso_type.into();
+			hdr.hdr_len = u16::try_from(hdrs_len).unwrap();
+			hdr.gso_size = u16::try_from(max_frame - hdrs_len).unwrap();
+			hdr.csum_start = csum_start;
+			hdr.csum_offset = csum_offset;
+		} else if self.supports_tx_checksum_offload() {
+			if let Some((csum_start, csum_offset)) = prepare_tx_csum(frame) {
+				hdr.flags = NetHdrFlag::VIRTIO_NET_HDR_F_NEEDS_CSUM.into();
+				hdr.csum_start = csum_start;
+				hdr.csum_offset = csum_offset;
+			}
+		}
+		unsafe {
+			core::ptr::copy_nonoverlapping(
+				hdr.to_bytes(self.dev_cfg.features).as_ptr(),
+				buff_ptr,
+				hdr_len,
+			);
 		}
+
+		Ok(buff_tkn)
 	}
 
-	/// Enum contains virtio's network device features and general features of Virtio.
+	/// Returns the maximal size of a frame accepted by [send_packet](Self::send_packet).
 	///
-	/// See Virtio specification v1.1. - 5.1.3
+	/// With VIRTIO_NET_F_HOST_TSO4 or VIRTIO_NET_F_HOST_TSO6, TCP packets of up to
+	/// [MAX_GSO_LEN] bytes are segmented by the device. Otherwise frames are limited
+	/// by the MTU.
+	pub fn max_tso_size(&self) -> usize {
+		let feats = self.dev_cfg.features;
+		if feats.is_feature(Features::VIRTIO_NET_F_HOST_TSO4)
+			|| feats.is_feature(Features::VIRTIO_NET_F_HOST_TSO6)
+		{
+			MAX_GSO_LEN
+		} else {
+			self.dev_cfg.max_frame_len()
+		}
+	}
+
+	/// Returns the type of segmentation as well as the length of the IP and the TCP header,
+	/// if the frame contains a TCP packet, which can be segmented by the device.
+	fn tso_params(&self, frame: &[u8]) -> Option<(NetHdrGSO, usize, usize)> {
+		if frame.len() < ETH_HDR + 1 {
+			return None;
+		}
+
+		let (gso_type, feature, ip_hdr_len, proto) =
+			match u16::from_be_bytes([frame[12], frame[13]]) {
+				0x0800 => (
+					NetHdrGSO::VIRTIO_NET_HDR_GSO_TCPV4,
+					Features::VIRTIO_NET_F_HOST_TSO4,
+					usize::from(frame[ETH_HDR] & 0x0f) * 4,
+					*frame.get(ETH_HDR + 9)?,
+				),
+				0x86dd => (
+					NetHdrGSO::VIRTIO_NET_HDR_GSO_TCPV6,
+					Features::VIRTIO_NET_F_HOST_TSO6,
+					40,
+					*frame.get(ETH_HDR + 6)?,
+				),
+				_ => return None,
+			};
+		if proto != 6 || !self.dev_cfg.features.is_feature(feature) {
+			return None;
+		}
+
+		// The length of the TCP header is given by the data offset in 32-bit words.
+		let tcp_hdr_len = usize::from(*frame.get(ETH_HDR + ip_hdr_len + 12)? >> 4) * 4;
+		if tcp_hdr_len < 20 || frame.len() < ETH_HDR + ip_hdr_len + tcp_hdr_len {
+			return None;
+		}
+
+		Some((gso_type, ip_hdr_len, tcp_hdr_len))
+	}
+
+	/// Returns true, if the device computes the checksums of transmitted TCP and UDP
+	/// packets (VIRTIO_NET_F_CSUM). The checksum fields of such packets must then be
+	/// left zero, the driver fills in the partial checksum of the pseudo header.
+	pub fn supports_tx_checksum_offload(&self) -> bool {
+		self.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_NET_F_CSUM)
+	}
+
+	/// Transmits the given packet and invokes `on_sent` as soon as the device has
+	/// marked the descriptor of the packet as used.
 	///
-	/// See Virtio specification v1.1. - 6
-	//
-	// WARN: In case the enum is changed, the static function of features `into_features(feat: u64) ->
-	// Option<Vec<Features>>` must also be adjusted to return a correct vector of features.
-	#[allow(dead_code, non_camel_case_types)]
-	#[derive(Copy, Clone, Debug)]
-	#[repr(u64)]
-	pub enum Features {
-		VIRTIO_NET_F_CSUM = 1 << 0,
-		VIRTIO_NET_F_GUEST_CSUM = 1 << 1,
-		VIRTIO_NET_F_CTRL_GUEST_OFFLOADS = 1 << 2,
-		VIRTIO_NET_F_MTU = 1 << 3,
-		VIRTIO_NET_F_MAC = 1 << 5,
-		VIRTIO_NET_F_GUEST_TSO4 = 1 << 7,
-		VIRTIO_NET_F_GUEST_TSO6 = 1 << 8,
-		VIRTIO_NET_F_GUEST_ECN = 1 << 9,
-		VIRTIO_NET_F_GUEST_UFO = 1 << 10,
-		VIRTIO_NET_F_HOST_TSO4 = 1 << 11,
-		VIRTIO_NET_F_HOST_TSO6 = 1 << 12,
-		VIRTIO_NET_F_HOST_ECN = 1 << 13,
-		VIRTIO_NET_F_HOST_UFO = 1 << 14,
-		VIRTIO_NET_F_MRG_RXBUF = 1 << 15,
-		VIRTIO_NET_F_STATUS = 1 << 16,
-		VIRTIO_NET_F_CTRL_VQ = 1 << 17,
-		VIRTIO_NET_F_CTRL_RX = 1 << 18,
-		VIRTIO_NET_F_CTRL_VLAN = 1 << 19,
-		VIRTIO_NET_F_GUEST_ANNOUNCE = 1 << 21,
-		VIRTIO_NET_F_MQ = 1 << 22,
-		VIRTIO_NET_F_CTRL_MAC_ADDR = 1 << 23,
-		VIRTIO_F_RING_INDIRECT_DESC = 1 << 28,
-		VIRTIO_F_RING_EVENT_IDX = 1 << 29,
-		VIRTIO_F_VERSION_1 = 1 << 32,
-		VIRTIO_F_ACCESS_PLATFORM = 1 << 33,
-		VIRTIO_F_RING_PACKED = 1 << 34,
-		VIRTIO_F_IN_ORDER = 1 << 35,
-		VIRTIO_F_ORDER_PLATFORM = 1 << 36,
-		VIRTIO_F_SR_IOV = 1 << 37,
-		VIRTIO_F_NOTIFICATION_DATA = 1 << 38,
-		VIRTIO_NET_F_GUEST_HDRLEN = 1 << 59,
-		VIRTIO_NET_F_RSC_EXT = 1 << 61,
-		VIRTIO_NET_F_STANDBY = 1 << 62,
-		// INTERNAL DOCUMENTATION TO KNOW WHICH FEATURES HAVE REQUIREMENTS
-		//
-		// 5.1.3.1 Feature bit requirements
-		// Some networking feature bits require other networking feature bits (see 2.2.1):
-		// VIRTIO_NET_F_GUEST_TSO4 Requires VIRTIO_NET_F_GUEST_CSUM.
-		// VIRTIO_NET_F_GUEST_TSO6 Requires VIRTIO_NET_F_GUEST_CSUM.
-		// VIRTIO_NET_F_GUEST_ECN Requires VIRTIO_NET_F_GUEST_TSO4orVIRTIO_NET_F_GUEST_TSO6.
-		// VIRTIO_NET_F_GUEST_UFO Requires VIRTIO_NET_F_GUEST_CSUM.
-		// VIRTIO_NET_F_HOST_TSO4 Requires VIRTIO_NET_F_CSUM.
-		// VIRTIO_NET_F_HOST_TSO6 Requires VIRTIO_NET_F_CSUM.
-		// VIRTIO_NET_F_HOST_ECN Requires VIRTIO_NET_F_HOST_TSO4 or VIRTIO_NET_F_HOST_TSO6.
-		// VIRTIO_NET_F_HOST_UFO Requires VIRTIO_NET_F_CSUM.
-		// VIRTIO_NET_F_CTRL_RX Requires VIRTIO_NET_F_CTRL_VQ.
-		// VIRTIO_NET_F_CTRL_VLAN Requires VIRTIO_NET_F_CTRL_VQ.
-		// VIRTIO_NET_F_GUEST_ANNOUNCE Requires VIRTIO_NET_F_CTRL_VQ.
-		// VIRTIO_NET_F_MQ Requires VIRTIO_NET_F_CTRL_VQ.
-		// VIRTIO_NET_F_CTRL_MAC_ADDR Requires VIRTIO_NET_F_CTRL_VQ.
-		// VIRTIO_NET_F_RSC_EXT Requires VIRTIO_NET_F_HOST_TSO4 or VIRTIO_NET_F_HOST_TSO6.
+	/// In contrast to [send_tx_buffer](NetworkInterface::send_tx_buffer) the completion of
+	/// each packet is tracked individually. Callbacks are invoked by
+	/// [process_tx_completions](VirtioNetDriver::process_tx_completions), which is also called
+	/// by the interrupt handler and upon each call of this function.
+	pub fn send_packet_notify(
+		&mut self,
+		data: &[u8],
+		on_sent: impl FnOnce() + Send + 'static,
+	) -> Result<(), VirtioNetError> {
+		self.process_tx_completions();
+		let buff_tkn = self.prep_tx_buffer(data, 0).map_err(|vnet_err| {
+			self.stats[0].count_tx_error();
+			vnet_err
+		})?;
+
+		let transfer = buff_tkn.provide().dispatch(false);
+		self.send_vqs
+			.notify_queue
+			.push((transfer, Box::new(on_sent)));
+		self.stats[0].count_tx(data.len());
+
+		Ok(())
 	}
 
-	impl From<Features> for u64 {
-		fn from(val: Features) -> Self {
-			match val {
-				Features::VIRTIO_NET_F_CSUM => 1 << 0,
-				Features::VIRTIO_NET_F_GUEST_CSUM => 1 << 1,
-				Features::VIRTIO_NET_F_CTRL_GUEST_OFFLOADS => 1 << 2,
-				Features::VIRTIO_NET_F_MTU => 1 << 3,
-				Features::VIRTIO_NET_F_MAC => 1 << 5,
-				Features::VIRTIO_NET_F_GUEST_TSO4 => 1 << 7,
-				Features::VIRTIO_NET_F_GUEST_TSO6 => 1 << 8,
-				Features::VIRTIO_NET_F_GUEST_ECN => 1 << 9,
-				Features::VIRTIO_NET_F_GUEST_UFO => 1 << 10,
-				Features::VIRTIO_NET_F_HOST_TSO4 => 1 << 11,
-				Features::VIRTIO_NET_F_HOST_TSO6 => 1 << 12,
-				Features::VIRTIO_NET_F_HOST_ECN => 1 << 13,
-				Features::VIRTIO_NET_F_HOST_UFO => 1 << 14,
-				Features::VIRTIO_NET_F_MRG_RXBUF => 1 << 15,
-				Features::VIRTIO_NET_F_STATUS => 1 << 16,
-				Features::VIRTIO_NET_F_CTRL_VQ => 1 << 17,
-				Features::VIRTIO_NET_F_CTRL_RX => 1 << 18,
-				Features::VIRTIO_NET_F_CTRL_VLAN => 1 << 19,
-				Features::VIRTIO_NET_F_GUEST_ANNOUNCE => 1 << 21,
-				Features::VIRTIO_NET_F_MQ => 1 << 22,
-				Features::VIRTIO_NET_F_CTRL_MAC_ADDR => 1 << 23,
-				Features::VIRTIO_F_RING_INDIRECT_DESC => 1 << 28,
-				Features::VIRTIO_F_RING_EVENT_IDX => 1 << 29,
-				Features::VIRTIO_F_VERSION_1 => 1 << 32,
-				Features::VIRTIO_F_ACCESS_PLATFORM => 1 << 33,
-				Features::VIRTIO_F_RING_PACKED => 1 << 34,
-				Features::VIRTIO_F_IN_ORDER => 1 << 35,
-				Features::VIRTIO_F_ORDER_PLATFORM => 1 << 36,
-				Features::VIRTIO_F_SR_IOV => 1 << 37,
-				Features::VIRTIO_F_NOTIFICATION_DATA => 1 << 38,
-				Features::VIRTIO_NET_F_GUEST_HDRLEN => 1 << 59,
-				Features::VIRTIO_NET_F_RSC_EXT => 1 << 61,
-				Features::VIRTIO_NET_F_STANDBY => 1 << 62,
+	/// Invokes the callbacks of all packets sent via
+	/// [send_packet_notify](VirtioNetDriver::send_packet_notify), which have
+	/// been transmitted by the device in the meantime.
+	///
+	/// Returns the number of invoked callbacks.
+	pub fn process_tx_completions(&mut self) -> usize {
+		self.irq_storm_cooldown();
+		let completed = self.send_vqs.complete_notified();
+		self.stamp_tx_completions(completed);
+		completed
+	}
+
+	/// Reclaims the send buffers of all packets, which have been transmitted by the device,
+	/// and returns the number of reclaimed buffers.
+	///
+	/// Buffers are reclaimed lazily while sending as well. Long-running senders can use the
+	/// function to check whether the device made progress.
+	pub fn reclaim_tx_buffers(&mut self) -> usize {
+		let reclaimed = self.send_vqs.reclaim();
+		self.stamp_tx_completions(reclaimed);
+		reclaimed
+	}
+
+	/// Returns the time in microseconds, at which the driver observed the completion of a
+	/// sent packet last, i.e. while sending synchronously via
+	/// [send_packet_sync](Self::send_packet_sync), processing completions or reclaiming
+	/// send buffers. Returns `None`, unless [VirtioNetConfig::tx_timestamps] is set.
+	pub fn last_tx_completion_time(&self) -> Option<u64> {
+		self.last_tx_completion
+	}
+
+	/// Records the current time as time of the last transmit completion, if packets have
+	/// been completed and timestamping is enabled.
+	fn stamp_tx_completions(&mut self, completed: usize) {
+		if self.config.tx_timestamps && completed > 0 {
+			self.last_tx_completion = Some(get_timer_ticks());
+		}
+	}
+
+	/// Returns the next received packet, starting with the ethernet header, or `None` if
+	/// no packet is pending. The function does not wait for packets.
+	///
+	/// The VirtioNetHdr is stripped and the used receive buffers are returned to the device.
+	/// If VIRTIO_NET_F_MRG_RXBUF is negotiated, a packet might span multiple buffers, whose
+	/// number is indicated by the `num_buffers` field of the header. Their content is
+	/// merged into the returned packet.
+	///
+	/// `queue` restricts receiving to the receive queue of the given queue pair (see
+	/// [num_queues](VirtioNetDriver::num_queues)). Without a queue, packets of all queues
+	/// are returned in the order they have been received.
+	///
+	/// The packet is returned in a buffer of the receive pool, which returns to the pool
+	/// once the packet is dropped.
+	pub fn receive_packet(&mut self, queue: Option<u16>) -> Option<PooledBuffer> {
+		self.receive_packet_meta(queue).map(|(packet, _)| packet)
+	}
+
+	/// Returns an iterator over the packets of all receive queues, which are available
+	/// now. Packets, which arrive during the iteration, are left for the next call.
+	///
+	/// Like [receive_packet](Self::receive_packet), each packet's buffers are returned to
+	/// the device, before the packet is yielded. Hence, the iterator may be dropped at
+	/// any point without losing buffers.
+	pub fn drain_rx(&mut self) -> RxDrain<'_> {
+		let remaining = self.rx_pending();
+		RxDrain {
+			driver: self,
+			remaining,
+		}
+	}
+
+	/// Returns the number of filled receive buffers of all active queues.
+	fn rx_pending(&self) -> usize {
+		(0..self.active_queue_pairs())
+			.map(|queue| usize::from(self.rx_available(queue)))
+			.sum()
+	}
+
+	/// Returns the next received packet together with its metadata. See
+	/// [receive_packet](Self::receive_packet).
+	///
+	/// If [VirtioNetConfig::guest_gso] is set and VIRTIO_NET_F_GUEST_TSO4, _TSO6 or _UFO has
+	/// been negotiated, the packet might consist of multiple segments, whose size is reported
+	/// via [RxMeta::gso]. Otherwise all packets are fully segmented and `gso` is `None`.
+	pub fn receive_packet_meta(&mut self, queue: Option<u16>) -> Option<(PooledBuffer, RxMeta)> {
+		self.next_packet(queue, false)
+	}
+
+	/// Returns the next received packet like [receive_packet](Self::receive_packet), but
+	/// without copying it, if the packet occupies a single receive buffer.
+	///
+	/// Instead, the receive buffer itself is lent to the caller. It is returned to the
+	/// device by the next call to one of the receive functions after the packet has been
+	/// dropped. Hence, the packet should be dropped soon, as the device is missing the
+	/// buffer meanwhile. Packets, which span multiple buffers, are copied into the
+	/// receive pool.
+	pub fn receive_packet_lent(&mut self, queue: Option<u16>) -> Option<PooledBuffer> {
+		self.next_packet(queue, true).map(|(packet, _)| packet)
+	}
+
+	/// Returns the next received packet. If `lend` is set, contiguous receive buffers are
+	/// lent instead of copied. See [receive_packet_lent](Self::receive_packet_lent).
+	fn next_packet(&mut self, queue: Option<u16>, lend: bool) -> Option<(PooledBuffer, RxMeta)> {
+		self.irq_storm_cooldown();
+		self.reclaim_rx_loans();
+
+		if self.rx_drop {
+			self.shed_rx();
+			return None;
+		}
+
+		let index = match queue {
+			Some(queue) => Some(self.recv_vqs.active().get(usize::from(queue))?.index()),
+			None => None,
+		};
+		let transfer = self.next_rx_transfer(index)?;
+		// All buffers of a merged packet belong to the same queue.
+		let index = transfer.vq_index();
+		let pair = Self::pair_of(index);
+		let mut pooled = if lend && Self::rx_contiguous(&transfer) {
+			self.lend_rx_transfer(transfer)
+		} else {
+			let mut pooled = self.rx_pool.get();
+			Self::rx_content(&transfer, pooled.as_vec_mut());
+			self.recycle_rx_buffer(transfer.reuse().unwrap());
+			pooled
+		};
+
+		let features = self.dev_cfg.features;
+		let hdr = match VirtioNetHdr::from_bytes(&pooled, features) {
+			Some(hdr) => hdr,
+			None => {
+				error!("Received buffer is smaller than the VirtioNetHdr. Dropping packet...");
+				self.queue_counters(pair).count_rx_error();
+				return None;
+			}
+		};
+
+		// Only with VIRTIO_NET_F_MRG_RXBUF the device may use more than one buffer.
+		let num_buffers = if features.is_feature(Features::VIRTIO_NET_F_MRG_RXBUF) {
+			hdr.num_buffers()
+		} else {
+			1
+		};
+		if num_buffers == 0 {
+			error!("Received packet claims to span zero buffers. Dropping packet...");
+			self.queue_counters(pair).count_rx_error();
+			return None;
+		}
+		let max_chain = self.max_rx_chain();
+		if num_buffers > max_chain {
+			error!(
+				"Received packet spans {} buffers, which exceeds the maximum of {}. Dropping packet...",
+				num_buffers, max_chain
+			);
+			self.drop_rx_buffers(index, num_buffers - 1);
+			self.queue_counters(pair).count_rx_error();
+			return None;
+		}
+		let meta = match hdr.rx_meta(RxQueues::guest_gso(&self.dev_cfg)) {
+			Ok(meta) => meta,
+			Err(gso_type) => {
+				error!(
+					"Received packet has unknown gso_type {:#x}. Dropping packet...",
+					gso_type
+				);
+				self.drop_rx_buffers(index, num_buffers - 1);
+				self.queue_counters(pair).count_rx_error();
+				return None;
+			}
+		};
+		pooled.strip_front(VirtioNetHdr::hdr_size(features));
+
+		// The device marks all buffers of a packet as used at once. Hence, they must already be
+		// available. See Virtio specification v1.1. - 5.1.6.4
+		for _ in 1..num_buffers {
+			match self.next_rx_transfer(Some(index)) {
+				Some(transfer) => {
+					Self::rx_content(&transfer, pooled.as_vec_mut());
+					self.recycle_rx_buffer(transfer.reuse().unwrap());
+				}
+				None => {
+					error!(
+						"Received packet spans {} buffers, but not all are available. Dropping packet...",
+						num_buffers
+					);
+					self.queue_counters(pair).count_rx_dropped();
+					return None;
+				}
+			}
+		}
+
+		// A buggy device must not hand malformed frames to the network stack. The buffers
+		// have already been recycled, hence the packet is simply dropped.
+		let max_len = RxQueues::max_frame_len(&self.dev_cfg);
+		if !rx_frame_len_valid(pooled.len(), max_len) {
+			error!(
+				"Received frame of {} bytes is out of the range [{}, {}]. Dropping packet...",
+				pooled.len(),
+				ETH_HDR,
+				max_len
+			);
+			self.queue_counters(pair).count_rx_error();
+			return None;
+		}
+
+		self.queue_counters(pair).count_rx(pooled.len());
+		Some((pooled, meta))
+	}
+
+	/// Returns true, if the device wrote the received data into a single descriptor.
+	fn rx_contiguous(transfer: &Transfer) -> bool {
+		matches!(transfer.as_slices(), Ok((_, Some(recv))) if recv.len() == 1)
+	}
+
+	/// Lends the receive buffer of `transfer`, which must be contiguous, to the holder of
+	/// the returned packet. See [reclaim_rx_loans](Self::reclaim_rx_loans).
+	fn lend_rx_transfer(&mut self, mut transfer: Transfer) -> PooledBuffer {
+		let (ptr, len) = match transfer.as_slices_mut() {
+			Ok((_, Some(mut recv))) => (recv[0].as_mut_ptr(), recv[0].len()),
+			_ => unreachable!("Only finished receive transfers are lent"),
+		};
+
+		let id = self.recv_vqs.next_loan;
+		self.recv_vqs.next_loan = id.wrapping_add(1);
+		self.recv_vqs.lent.push((
+			id,
+			RxLoan {
+				transfer,
+				stale: false,
+			},
+		));
+		// SAFETY: The memory belongs to the transfer, which is kept, until the id is
+		// returned by the pool, or leaked by RxQueues::release.
+		unsafe { self.rx_pool.lend(id, ptr, len) }
+	}
+
+	/// Returns all lent receive buffers, whose packets have been dropped, to the device.
+	fn reclaim_rx_loans(&mut self) {
+		for id in self.rx_pool.take_returned() {
+			// Loans, which have been leaked upon a reset, are unknown.
+			let pos = match self.recv_vqs.lent.iter().position(|(lent, _)| *lent == id) {
+				Some(pos) => pos,
+				None => continue,
+			};
+
+			let (_, loan) = self.recv_vqs.lent.swap_remove(pos);
+			if loan.stale {
+				loan.transfer.close();
+			} else {
+				self.recycle_rx_buffer(loan.transfer.reuse().unwrap());
+			}
+		}
+	}
+
+	/// Returns the following `count` buffers of a dropped packet of the queue with the given
+	/// index to the device. Buffers, which the device has not used yet, are not waited for.
+	fn drop_rx_buffers(&mut self, index: VqIndex, count: u16) {
+		for _ in 0..count {
+			match self.next_rx_transfer(Some(index)) {
+				Some(transfer) => self.recycle_rx_buffer(transfer.reuse().unwrap()),
+				None => break,
+			}
+		}
+	}
+
+	/// Returns the next finished receive transfer, optionally of the given queue only.
+	fn next_rx_transfer(&mut self, index: Option<VqIndex>) -> Option<Transfer> {
+		let transfer = match index {
+			Some(index) => self.recv_vqs.get_next_of(index)?,
+			None => self.recv_vqs.get_next()?,
+		};
+		match RxQueues::post_processing(transfer) {
+			Ok(trf) => Some(trf),
+			Err(vnet_err) => {
+				error!("Post processing failed. Err: {:?}", vnet_err);
+				self.stats[0].count_rx_error();
+				None
+			}
+		}
+	}
+
+	/// Appends the data written by the device into the receive buffer of the transfer to `packet`,
+	/// regardless of whether the buffer consists of a single or of multiple descriptors.
+	fn rx_content(transfer: &Transfer, packet: &mut Vec<u8>) {
+		let (_, recv_data) = transfer.as_slices().unwrap();
+		for slice in recv_data.unwrap_or_default() {
+			packet.extend_from_slice(slice);
+		}
+	}
+
+	/// Returns the maximal number of receive buffers a single received packet can span.
+	///
+	/// Without VIRTIO_NET_F_MRG_RXBUF every packet is placed inside a single buffer. Otherwise
+	/// the value is derived from the largest packet the device is allowed to send with the
+	/// negotiated MTU and offloading features and the size of the receive buffers. As all buffers
+	/// of a packet must be posted at the same time, the value is bounded by the queue size.
+	///
+	/// The value is the upper bound for merging receive buffers.
+	pub fn max_rx_chain(&self) -> u16 {
+		if !self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_NET_F_MRG_RXBUF)
+			|| self.recv_vqs.buf_len == 0
+		{
+			return 1;
+		}
+
+		let max_packet = if self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_NET_F_GUEST_TSO4)
+			| self
+				.dev_cfg
+				.features
+				.is_feature(Features::VIRTIO_NET_F_GUEST_TSO6)
+			| self
+				.dev_cfg
+				.features
+				.is_feature(Features::VIRTIO_NET_F_GUEST_UFO)
+		{
+			mem::size_of::<VirtioNetHdr>() + MAX_GSO_LEN
+		} else {
+			mem::size_of::<VirtioNetHdr>() + self.dev_cfg.max_frame_len()
+		};
+
+		let chain = (max_packet + self.recv_vqs.buf_len - 1) / self.recv_vqs.buf_len;
+		let queue_size = self
+			.recv_vqs
+			.vqs
+			.iter()
+			.map(|vq| u16::from(vq.size()))
+			.min()
+			.unwrap_or(1);
+
+		u16::try_from(chain)
+			.unwrap_or(u16::MAX)
+			.clamp(1, queue_size.max(1))
+	}
+
+	/// Returns the statistics collected since the last reset and resets all counters
+	/// to zero. Packets, which are processed concurrently, are either part of the returned
+	/// snapshot or of the next one.
+	pub fn snapshot_and_reset_stats(&mut self) -> NetStats {
+		let mut stats = NetStats::default();
+		for counters in &self.stats {
+			stats += counters.take();
+		}
+		stats
+	}
+
+	/// Returns the statistics of all queue pairs collected since the last reset.
+	pub fn stats(&self) -> NetStats {
+		let mut stats = NetStats::default();
+		for counters in &self.stats {
+			stats += counters.snapshot();
+		}
+		stats
+	}
+
+	/// Returns the statistics of the given queue pair collected since the last reset,
+	/// which allows to spot an imbalance between the queues.
+	pub fn queue_stats(&self, pair: u16) -> Option<NetStats> {
+		self.stats
+			.get(usize::from(pair))
+			.map(|counters| counters.snapshot())
+	}
+
+	/// Resets the statistics of all queue pairs to zero.
+	pub fn reset_stats(&mut self) {
+		for counters in &self.stats {
+			counters.take();
+		}
+	}
+
+	/// Returns the counters of the given queue pair. Counts of unknown pairs are
+	/// attributed to the first one.
+	fn queue_counters(&self, pair: usize) -> &NetCounters {
+		self.stats.get(pair).unwrap_or(&self.stats[0])
+	}
+
+	/// Returns the queue pair the virtqueue with the given index belongs to.
+	fn pair_of(index: VqIndex) -> usize {
+		usize::from(u16::from(index) / 2)
+	}
+
+	/// Returns the utilization of the buffers, into which received packets are copied.
+	/// Lent receive buffers are counted as in use.
+	pub fn rx_pool_usage(&self) -> PoolUsage {
+		self.rx_pool.usage()
+	}
+
+	/// Returns the configuration the driver has been initialized with.
+	pub fn config(&self) -> &VirtioNetConfig {
+		&self.config
+	}
+
+	/// Returns a received buffer either to the receive queues or parks it,
+	/// if [VirtioNetConfig::manual_refill] is set.
+	fn recycle_rx_buffer(&mut self, buff_tkn: BufferToken) {
+		if self.config.manual_refill {
+			self.recv_vqs.parked.push(buff_tkn);
+		} else {
+			self.repost_rx_buffer(buff_tkn);
+		}
+	}
+
+	/// Returns the receive buffer to the device, regardless of [VirtioNetConfig::manual_refill].
+	fn repost_rx_buffer(&mut self, buff_tkn: BufferToken) {
+		buff_tkn
+			.provide()
+			.dispatch_await(Rc::clone(&self.recv_vqs.poll_queue), false);
+	}
+
+	/// Enables or disables shedding of received packets.
+	///
+	/// While enabled, all received packets are dropped and their buffers are returned to
+	/// the device immediately, independent of [VirtioNetConfig::manual_refill]. Dropped
+	/// packets are counted in [NetStats::rx_shed]. Intended as a last resort, if the
+	/// system is overloaded.
+	pub fn set_rx_drop(&mut self, drop: bool) {
+		self.rx_drop = drop;
+	}
+
+	/// Drops all received packets and returns their buffers to the device.
+	fn shed_rx(&mut self) {
+		while let Some(transfer) = self.recv_vqs.get_next() {
+			let transfer = match RxQueues::post_processing(transfer) {
+				Ok(trf) => trf,
+				Err(_) => break,
+			};
+
+			let pair = Self::pair_of(transfer.vq_index());
+			self.repost_rx_buffer(transfer.reuse().unwrap());
+			self.queue_counters(pair).count_shed();
+		}
+	}
+
+	/// Returns one parked receive buffer to the device.
+	///
+	/// Only useful if [VirtioNetConfig::manual_refill] is set, as otherwise buffers are
+	/// never parked. Fails with `VirtioNetError::NoRxBuffAvail` if no buffer is parked.
+	pub fn post_rx_buffer(&mut self) -> Result<(), VirtioNetError> {
+		match self.recv_vqs.parked.pop() {
+			Some(buff_tkn) => {
+				buff_tkn
+					.provide()
+					.dispatch_await(Rc::clone(&self.recv_vqs.poll_queue), false);
+				Ok(())
+			}
+			None => Err(VirtioNetError::NoRxBuffAvail),
+		}
+	}
+
+	/// Returns the number of receive buffers, which are currently parked and
+	/// not available to the device.
+	pub fn parked_rx_buffers(&self) -> usize {
+		self.recv_vqs.parked.len()
+	}
+
+	/// Switches between interrupt driven operation (`enable`) and pure polling.
+	///
+	/// If disabled, the device is asked to suppress notifications for all queues, i.e. the
+	/// VIRTQ_AVAIL_F_NO_INTERRUPT flag for split queues, respectively the driver event
+	/// suppression structure for packed queues, is set. Notifications stay disabled until
+	/// they are enabled explicitly, regardless of [set_polling_mode](NetworkInterface::set_polling_mode)
+	/// and interrupt storm detection. If enabled, the state after initialization is restored,
+	/// with notifications for the receive and control queues. Notifications are enabled by default.
+	pub fn set_notif(&mut self, enable: bool) {
+		self.notif_enabled = enable;
+
+		if enable {
+			if self.polling_mode_counter == 0 {
+				self.enable_interrupts();
+			}
+			if let Some(vq) = self.ctrl_vq.vq.as_ref() {
+				vq.enable_notifs();
+			}
+		} else {
+			self.disable_interrupts();
+			self.send_vqs.disable_notifs();
+			if let Some(vq) = self.ctrl_vq.vq.as_ref() {
+				vq.disable_notifs();
+			}
+		}
+	}
+
+	pub fn disable_interrupts(&self) {
+		// For send and receive queues?
+		// Only for receive? Because send is off anyway?
+		self.recv_vqs.disable_notifs();
+	}
+
+	pub fn enable_interrupts(&self) {
+		// For send and receive queues?
+		// Only for receive? Because send is off anyway?
+		self.recv_vqs.enable_notifs();
+		// Queues, which are masked due to an interrupt storm, stay masked until their
+		// cooldown has passed.
+		for queue in 0..self.recv_vqs.active().len() {
+			if self.irq_storm.is_masked(queue) {
+				self.recv_vqs.disable_notifs_of(queue);
+			}
+		}
+	}
+
+	/// Returns true, if the device signals, that it must be reset (DEVICE_NEEDS_RESET).
+	pub fn needs_reset(&self) -> bool {
+		self.com_cfg.dev_status() & u8::from(device::Status::DEVICE_NEEDS_RESET) != 0
+	}
+
+	/// Recovers the device, if it signals DEVICE_NEEDS_RESET. Does nothing otherwise.
+	///
+	/// The device is reset and initialized again with the existing configuration structures.
+	/// Afterwards the MAC address, promiscuous mode, VLAN filters and the notification
+	/// setting are restored. All packets in flight, i.e. received but not yet fetched packets
+	/// and sent but not yet consumed packets, are lost. Their buffers are released after the
+	/// reset and callbacks of pending sends are not invoked.
+	///
+	/// If the initialization fails, the device is marked as failed. Settings, which the
+	/// device rejects, are logged and skipped, while the remaining ones are still restored.
+	/// The first rejection is returned afterwards.
+	pub fn reinit(&mut self) -> Result<(), VirtioNetError> {
+		if !self.needs_reset() {
+			return Ok(());
+		}
+		warn!(
+			"Network device {:x} needs a reset. Packets in flight are lost!",
+			self.dev_cfg.dev_id
+		);
+
+		self.reinit_unconditionally()
+	}
+
+	/// Resets and reinitializes the device like [reinit](Self::reinit), regardless of
+	/// DEVICE_NEEDS_RESET. Used as well, after the driver has reset the device itself.
+	fn reinit_unconditionally(&mut self) -> Result<(), VirtioNetError> {
+		// The device must not access the buffers any more, before they are released.
+		self.com_cfg.reset_dev();
+		self.recv_vqs.release();
+		self.send_vqs.release();
+		self.ctrl_vq = CtrlQueue::new(None);
+
+		let generated_mac = self.generated_mac;
+		let assigned_mac = self.assigned_mac.take();
+		let promiscuous = mem::take(&mut self.promiscuous);
+		let vlans = mem::take(&mut self.vlans);
+
+		if let Err(vnet_err) = self.init_dev() {
+			self.com_cfg.set_failed();
+			return Err(vnet_err);
+		}
+
+		// A generated MAC address must not change, as peers might have learned it.
+		if generated_mac != [0; 6] {
+			self.generated_mac = generated_mac;
+		}
+
+		let dev_id = self.dev_cfg.dev_id;
+		let mut first_err = None;
+		let mut restore = |setting: &str, result: Result<(), VirtioNetError>| {
+			if let Err(vnet_err) = result {
+				warn!(
+					"Network device {:x} rejected to restore the {}: {}",
+					dev_id, setting, vnet_err
+				);
+				first_err.get_or_insert(vnet_err);
+			}
+		};
+		if let Some(mac) = assigned_mac {
+			restore("MAC address", self.set_mac_address(mac));
+		}
+		if promiscuous {
+			restore("promiscuous mode", self.set_promiscuous(true));
+		}
+		for vid in vlans {
+			restore("VLAN filter", self.add_vlan(vid));
+		}
+		if !self.notif_enabled {
+			self.set_notif(false);
+		} else if self.polling_mode_counter > 0 {
+			self.disable_interrupts();
+		} else if self.irq_storm.any_masked() {
+			self.enable_interrupts();
+		}
+
+		match first_err {
+			Some(vnet_err) => Err(vnet_err),
+			None => {
+				info!("Network device {:x} has been reinitialized", dev_id);
+				Ok(())
+			}
+		}
+	}
+
+	/// Logs the event and hands it to [VirtioNetConfig::event_hook]. Failures are logged as
+	/// errors, all other events as information.
+	pub(super) fn emit(&self, event: NetEvent) {
+		match event {
+			NetEvent::InitFailed { .. } => {
+				error!("Network device {:x}: {}", self.dev_cfg.dev_id, event)
+			}
+			_ => info!("Network device {:x}: {}", self.dev_cfg.dev_id, event),
+		}
+
+		if let Some(hook) = self.config.event_hook {
+			hook(&event);
+		}
+	}
+
+	/// Initializes the device. See Virtio specification v1.1. - 3.1.1
+	///
+	/// A failure is reported as [NetEvent::InitFailed].
+	pub fn init_dev(&mut self) -> Result<(), VirtioNetError> {
+		let result = self.try_init_dev();
+		if let Err(err) = result {
+			self.emit(NetEvent::InitFailed { err });
+		}
+		result
+	}
+
+	/// Initializes the device in adherence to specification.
+	///
+	/// See Virtio specification v1.1. - 3.1.1.
+	///                      and v1.1. - 5.1.5
+	fn try_init_dev(&mut self) -> Result<(), VirtioNetError> {
+		// Reset
+		self.com_cfg.reset_dev();
+		self.emit(NetEvent::ResetDone);
+
+		// The reset removes the assignment of the configuration vector and of the vectors
+		// of the queue pairs.
+		#[cfg(feature = "pci")]
+		self.queue_affinity.clear();
+		#[cfg(feature = "pci")]
+		if self.interrupt_mode == InterruptMode::MsiX
+			&& !self
+				.com_cfg
+				.set_config_msix_vector(constants::MSIX_CFG_ENTRY)
+		{
+			warn!("Device did not accept MSI-X vector for configuration changes.");
+		}
+
+		// Indicate device, that OS noticed it
+		self.com_cfg.ack_dev();
+
+		// Indicate device, that driver is able to handle it
+		self.com_cfg.set_drv();
+
+		// Define minimal feature set
+		let min_feats: Vec<Features> = vec![Features::VIRTIO_F_VERSION_1];
+
+		let mut min_feat_set = FeatureSet::new(0);
+		min_feat_set.set_features(&min_feats);
+		let mut feats: Vec<Features> = min_feats;
+
+		// If wanted, push new features into feats here:
+		//
+		// Link state is reported. Otherwise the link is assumed to be up.
+		feats.push(Features::VIRTIO_NET_F_STATUS);
+		// MAC address of the device is used. Otherwise the driver creates one.
+		feats.push(Features::VIRTIO_NET_F_MAC);
+		// Indirect descriptors can be used
+		feats.push(Features::VIRTIO_F_RING_INDIRECT_DESC);
+		// MTU setting can be used
+		feats.push(Features::VIRTIO_NET_F_MTU);
+		// Packed Vq can be used, split Vqs are used otherwise
+		if !self.config.force_split {
+			feats.push(Features::VIRTIO_F_RING_PACKED);
+		}
+		// Control queue and coalescing of notifications can be used
+		feats.push(Features::VIRTIO_NET_F_CTRL_VQ);
+		// Multiple queue pairs can be used
+		feats.push(Features::VIRTIO_NET_F_MQ);
+		feats.push(Features::VIRTIO_NET_F_NOTF_COAL);
+		feats.push(Features::VIRTIO_NET_F_VQ_NOTF_COAL);
+		// Receive offloads can be toggled at runtime
+		feats.push(Features::VIRTIO_NET_F_CTRL_GUEST_OFFLOADS);
+		// Receive filtering can be controlled
+		feats.push(Features::VIRTIO_NET_F_CTRL_RX);
+		feats.push(Features::VIRTIO_NET_F_CTRL_VLAN);
+		// MAC address can be changed at runtime
+		feats.push(Features::VIRTIO_NET_F_CTRL_MAC_ADDR);
+		// Announcements are acknowledged upon configuration changes
+		feats.push(Features::VIRTIO_NET_F_GUEST_ANNOUNCE);
+		// Checksums of transmitted TCP and UDP packets can be computed by the device
+		feats.push(Features::VIRTIO_NET_F_CSUM);
+		// Received packets can be merged from multiple buffers
+		feats.push(Features::VIRTIO_NET_F_MRG_RXBUF);
+		// Large TCP packets can be segmented by the device
+		feats.push(Features::VIRTIO_NET_F_HOST_TSO4);
+		feats.push(Features::VIRTIO_NET_F_HOST_TSO6);
+		// Segmentation of TCP flows with explicit congestion notification
+		feats.push(Features::VIRTIO_NET_F_HOST_ECN);
+		// Speed and duplex mode of the link are reported
+		feats.push(Features::VIRTIO_NET_F_SPEED_DUPLEX);
+		// Received packets can be distributed by receive-side scaling
+		feats.push(Features::VIRTIO_NET_F_RSS);
+		// The device may act as standby of a primary device, see is_standby()
+		feats.push(Features::VIRTIO_NET_F_STANDBY);
+
+		// Unsegmented packets are handed up together with their segment size. The checksums
+		// of those are incomplete, see RxMeta.
+		if self.config.guest_gso {
+			feats.push(Features::VIRTIO_NET_F_GUEST_CSUM);
+			feats.push(Features::VIRTIO_NET_F_GUEST_TSO4);
+			feats.push(Features::VIRTIO_NET_F_GUEST_TSO6);
+			feats.push(Features::VIRTIO_NET_F_GUEST_ECN);
+			feats.push(Features::VIRTIO_NET_F_GUEST_UFO);
+		}
+
+		// Do not negotiate offloads for a direction, which is not used.
+		let direction = self.config.direction;
+		feats.retain(|feat| !direction.excludes(*feat));
+
+		// Optional features are only requested, if they are offered by the device.
+		// Aborts in case the device does not support min_feat_set.
+		let feats = self.select_features(min_feat_set, &feats)?;
+
+		// Kept in order to report the refused features, if the device does not accept them.
+		let offered = self.com_cfg.dev_features();
+		let feats = match self.negotiate_features(&feats) {
+			Ok(negotiated) => {
+				info!(
+					"Driver found a subset of features for virtio device {:x}. Features are: {:?}",
+					self.dev_cfg.dev_id, &negotiated
+				);
+				negotiated
+			}
+			Err(vnet_err) => {
+				match vnet_err {
+					VirtioNetError::FeatReqNotMet(feat_set) => {
+						error!("Network drivers feature set {:x} does not satisfy rules in section 5.1.3.1 of specification v1.1. Aborting!", u64::from(feat_set));
+					}
+					_ => {
+						error!(
+							"Wanted set of features is NOT supported by device. Set: {:?}",
+							feats
+						);
+					}
+				}
+				return Err(vnet_err);
+			}
+		};
+
+		// The device might reject an inconsistent set silently. Hence the final set is checked
+		// before it is acknowledged.
+		let mut feat_set = FeatureSet::new(0);
+		feat_set.set_features(&feats);
+		if let Err(vnet_err) = FeatureSet::validate_feature_dependencies(feat_set.into()) {
+			error!(
+				"Negotiated feature set {:x} of network device {:x} is inconsistent: {:?}",
+				u64::from(feat_set),
+				self.dev_cfg.dev_id,
+				vnet_err
+			);
+			return Err(vnet_err);
+		}
+
+		// Indicates the device, that the current feature set is final for the driver
+		// and will not be changed.
+		self.com_cfg.features_ok();
+
+		// Checks if the device has accepted final set. This finishes feature negotiation.
+		if self.com_cfg.check_features() {
+			// Set feature set in device config for future use. Features of a previous
+			// initialization are discarded.
+			self.dev_cfg.features = FeatureSet::new(0);
+			self.dev_cfg.features.set_features(&feats);
+			self.emit(NetEvent::FeaturesNegotiated {
+				mask: self.dev_cfg.features.into(),
+			});
+
+			if self.config.verify_features {
+				self.verify_drv_features();
+			}
+		} else {
+			// The device does not report the accepted features. But it might withdraw
+			// offered features, which it can not provide together with the requested ones.
+			let diff = FeatureDiff {
+				offered,
+				requested: feat_set.into(),
+				reported: self.com_cfg.dev_features(),
+			};
+			error!(
+				"Virtio network device {:x} did not accept the features. {}",
+				self.dev_cfg.dev_id, diff
+			);
+			return Err(VirtioNetError::FeaturesRejected(diff));
+		}
+
+		match self.dev_spec_init() {
+			Ok(_) => info!(
+				"Device specific initialization for Virtio network device {:x} finished",
+				self.dev_cfg.dev_id
+			),
+			Err(vnet_err) => return Err(vnet_err),
+		}
+		// At this point the device is "live"
+		self.com_cfg.drv_ok();
+
+		// The device may have failed upon accepting the driver, see Virtio specification v1.1. - 2.1.1
+		let status = self.com_cfg.dev_status();
+		if status
+			& (u8::from(device::Status::DEVICE_NEEDS_RESET) | u8::from(device::Status::FAILED))
+			!= 0
+		{
+			return Err(VirtioNetError::DeviceNotReady(status));
+		}
+
+		// The device processes commands of the control queue only, once it is live.
+		self.steering_init();
+
+		self.initial_link = self.link_state();
+		self.link = self.initial_link;
+		self.emit(self.link.into());
+
+		Ok(())
+	}
+
+	/// Reduces the wanted features to the ones offered by the device.
+	///
+	/// Optional features, which are not offered or whose dependencies are not offered, are
+	/// dropped and logged. Fails with `VirtioNetError::FailFeatureNeg`, if the device does not
+	/// offer all features of `min_feat_set`.
+	fn select_features(
+		&self,
+		min_feat_set: FeatureSet,
+		wanted_feats: &[Features],
+	) -> Result<Vec<Features>, VirtioNetError> {
+		let dev_feats = FeatureSet::new(self.com_cfg.dev_features());
+		if (min_feat_set & dev_feats) != min_feat_set {
+			error!(
+				"Device features set {:x}, does not satisfy minimal features {:x} needed. Aborting!",
+				u64::from(dev_feats),
+				u64::from(min_feat_set)
+			);
+			return Err(VirtioNetError::FailFeatureNeg(self.dev_cfg.dev_id));
+		}
+
+		let feats = offered_features(dev_feats, min_feat_set, wanted_feats)?;
+
+		let mut wanted_set = FeatureSet::new(0);
+		wanted_set.set_features(wanted_feats);
+		let mut feat_set = FeatureSet::new(0);
+		feat_set.set_features(&feats);
+		let dropped = FeatureSet::new(u64::from(wanted_set) & !u64::from(feat_set));
+		if let Some(dropped) = Features::from_set(dropped) {
+			info!(
+				"Network device {:x} does not support the optional features {:?}",
+				self.dev_cfg.dev_id, dropped
+			);
+		}
+
+		Ok(feats)
+	}
+
+	/// Reads back the driver features from the device and warns, if they differ from
+	/// the negotiated feature set. See [VirtioNetConfig::verify_features].
+	fn verify_drv_features(&mut self) {
+		let negotiated = u64::from(self.dev_cfg.features);
+
+		match self.com_cfg.drv_features() {
+			Some(accepted) if accepted != negotiated => warn!(
+				"Virtio network device {:x} reports driver features {:x}, but {:x} have been negotiated!",
+				self.dev_cfg.dev_id, accepted, negotiated
+			),
+			Some(_) => (),
+			None => info!(
+				"Driver features of virtio network device {:x} can not be read back.",
+				self.dev_cfg.dev_id
+			),
+		}
+	}
+
+	/// Negotiates a subset of features, understood and wanted by both the OS
+	/// and the device.
+	///
+	/// Features, whose dependencies are not part of the subset, are dropped. Returns
+	/// the features written to the device.
+	fn negotiate_features(
+		&mut self,
+		wanted_feats: &[Features],
+	) -> Result<Vec<Features>, VirtioNetError> {
+		let mut drv_feats = FeatureSet::new(0);
+
+		for feat in wanted_feats.iter() {
+			drv_feats |= *feat;
+		}
+
+		let missing = constants::missing_dependencies(drv_feats.into());
+		if !missing.is_empty() {
+			let satisfiable = constants::satisfiable_features(drv_feats.into());
+			warn!(
+				"Network device {:x}: dropping features {}, as their dependencies {} are missing",
+				self.dev_cfg.dev_id,
+				constants::feature_names(u64::from(drv_feats) & !satisfiable).join(", "),
+				missing
+					.iter()
+					.map(|feat| feat.name())
+					.collect::<Vec<_>>()
+					.join(" or ")
+			);
+			drv_feats = FeatureSet::new(satisfiable);
+		}
+		let feats: Vec<Features> = wanted_feats
+			.iter()
+			.copied()
+			.filter(|feat| drv_feats.is_feature(*feat))
+			.collect();
+
+		let dev_feats = FeatureSet::new(self.com_cfg.dev_features());
+
+		// Checks if the selected feature set is compatible with requirements for
+		// features according to Virtio spec. v1.1 - 5.1.3.1.
+		match FeatureSet::check_features(&feats) {
+			Ok(_) => info!(
+				"Feature set wanted by network driver is in conformance with specification: {}",
+				constants::feature_names(drv_feats.into()).join(", ")
+			),
+			Err(vnet_err) => return Err(vnet_err),
+		}
+
+		if (dev_feats & drv_feats) == drv_feats {
+			// If device supports subset of features write feature set to common config
+			self.com_cfg.set_drv_features(drv_feats.into());
+			Ok(feats)
+		} else {
+			Err(VirtioNetError::IncompFeatsSet(drv_feats, dev_feats))
+		}
+	}
+
+	/// Device Specific initialization according to Virtio specifictation v1.1. - 5.1.5
+	fn dev_spec_init(&mut self) -> Result<(), VirtioNetError> {
+		match self.virtqueue_init() {
+			Ok(_) => info!("Network driver successfully initialized virtqueues."),
+			Err(vnet_err) => return Err(vnet_err),
+		}
+
+		// Add a control if feature is negotiated
+		if self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_NET_F_CTRL_VQ)
+		{
+			let vq_type = if self
+				.dev_cfg
+				.features
+				.is_feature(Features::VIRTIO_F_RING_PACKED)
+			{
+				VqType::Packed
+			} else {
+				VqType::Split
+			};
+			let vq = self.create_vq(vq_type, self.ctrl_vq_index())?;
+			self.ctrl_vq = CtrlQueue::new(Some(Rc::new(vq))).with_clock(self.config.clock);
+
+			self.ctrl_vq.vq.as_ref().unwrap().enable_notifs();
+		}
+
+		// If device does not take care of MAC address, the driver has to create one
+		// See Virtio specification v1.1. - 5.1.5
+		if !self.dev_cfg.features.is_feature(Features::VIRTIO_NET_F_MAC) {
+			self.generated_mac = Self::generate_mac();
+			info!(
+				"Network device {:x} does not provide a MAC address. Using {:02x?}",
+				self.dev_cfg.dev_id, self.generated_mac
+			);
+		} else if self.device_mac().is_none() {
+			self.generated_mac = Self::generate_mac();
+			warn!(
+				"Network device {:x} reports the invalid MAC address {:02x?}. Using {:02x?}",
+				self.dev_cfg.dev_id,
+				self.dev_cfg.mac(),
+				self.generated_mac
+			);
+		}
+
+		Ok(())
+	}
+
+	/// Selects and configures the steering of received packets over the queue pairs.
+	///
+	/// RSS and plain multiqueue steering are mutually exclusive. Both are configured via the
+	/// control queue, which must not be used before DRIVER_OK has been set.
+	/// See Virtio specification v1.1. - 3.1.1
+	fn steering_init(&mut self) {
+		self.steering = SteeringMode::select(self.dev_cfg.features, self.num_vqs / 2);
+		if self.steering == SteeringMode::Rss {
+			// Without a configuration, the device would use the first queue pair only.
+			if let Err(vnet_err) = self.configure_default_rss() {
+				warn!(
+					"Network device {:x} did not accept the default RSS configuration: {:?}. Using plain multiqueue steering.",
+					self.dev_cfg.dev_id, vnet_err
+				);
+				self.steering = SteeringMode::MultiQueue;
+			}
+		}
+		if self.steering == SteeringMode::MultiQueue {
+			// The device only uses the first queue pair, until further pairs are enabled.
+			// See Virtio specification v1.1. - 5.1.6.5.5
+			let pairs = self.num_vqs / 2;
+			if let Err(vnet_err) = self.ctrl_vq.send_cmd(
+				CtrlClass::VIRTIO_NET_CTRL_MQ,
+				MqCmd::VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET as u8,
+				&pairs.to_le_bytes(),
+			) {
+				warn!(
+					"Network device {:x} did not enable {} queue pairs: {:?}. Using a single pair.",
+					self.dev_cfg.dev_id, pairs, vnet_err
+				);
+				self.shrink_queue_pairs(1);
+			}
+		}
+		info!(
+			"Network device {:x} uses steering mode {:?}",
+			self.dev_cfg.dev_id, self.steering
+		);
+	}
+
+	/// Returns the MAC address provided by the device, if VIRTIO_NET_F_MAC is negotiated
+	/// and the address can be used as unicast address.
+	///
+	/// The address is read with the [layout](DevCfgLayout) detected by the transport. Still,
+	/// it is validated, as a zero address usually indicates a device config, which is mapped
+	/// wrongly.
+	fn device_mac(&self) -> Option<[u8; 6]> {
+		if !self.dev_cfg.features.is_feature(Features::VIRTIO_NET_F_MAC) {
+			return None;
+		}
+
+		let mac = self.dev_cfg.mac();
+		is_unicast_mac(&mac).then_some(mac)
+	}
+
+	/// Returns a random, locally administered unicast MAC address.
+	fn generate_mac() -> [u8; 6] {
+		let mut mac = [0u8; 6];
+		if crate::entropy::read(&mut mac, crate::entropy::Flags::empty()) < 0 {
+			// Without a source of entropy, at least distinguish devices initialized at
+			// different points in time.
+			mac.copy_from_slice(&get_timer_ticks().to_le_bytes()[..6]);
+		}
+
+		// Clear the multicast bit and set the locally administered bit.
+		mac[0] = (mac[0] & !0x01) | 0x02;
+		mac
+	}
+
+	/// Returns the number of receive and transmit queues, which are set up for the device.
+	/// `dev_queues` is the total number of virtqueues of the device, if it is known.
+	fn num_data_vqs(dev_cfg: &NetDevCfg, dev_queues: Option<u16>) -> u16 {
+		if dev_cfg.features.is_feature(Features::VIRTIO_NET_F_MQ) {
+			usable_queue_pairs(dev_cfg.max_virtqueue_pairs(), dev_queues) * 2
+		} else {
+			// Minimal number of virtqueues defined in the standard v1.1. - 5.1.5 Step 1
+			2
+		}
+	}
+
+	/// Returns the index of the control queue, which follows the receive and transmit
+	/// queues of all pairs supported by the device, even if the driver uses less pairs.
+	/// A device reporting zero pairs is treated like a device with a single pair.
+	///
+	/// See Virtio specification v1.1. - 5.1.2
+	fn ctrl_vq_index(&self) -> u16 {
+		if self.dev_cfg.features.is_feature(Features::VIRTIO_NET_F_MQ) {
+			2 * self.dev_cfg.max_virtqueue_pairs().max(1)
+		} else {
+			2
+		}
+	}
+
+	/// Requests a size of `desired` entries for the virtqueue with the given index.
+	/// The size is clamped to the maximum of the device.
+	///
+	/// Returns the size, which is effectively used by the device.
+	fn negotiate_queue_size(&mut self, index: u16, desired: u16) -> Result<u16, VirtioNetError> {
+		// A size of zero indicates, that the queue is unavailable.
+		// See Virtio specification v1.1. - 4.1.4.3
+		let mut vq_handler = self
+			.com_cfg
+			.select_vq(index)
+			.ok_or(VirtioNetError::QueueUnavailable(index))?;
+		let size = vq_handler.set_vq_size(desired);
+		if size == 0 {
+			return Err(VirtioNetError::QueueUnavailable(index));
+		}
+
+		if size < desired {
+			debug!(
+				"Device limits virtqueue {} to {} entries instead of {}.",
+				index, size, desired
+			);
+		}
+
+		Ok(size)
+	}
+
+	/// Creates the virtqueue with the given index via the configured allocator.
+	/// The queue is created with the size granted by the device, which also determines
+	/// the number of receive buffers a receive queue is populated with.
+	fn create_vq(&mut self, vq_type: VqType, index: u16) -> Result<Virtq, VirtioNetError> {
+		let size = self.negotiate_queue_size(index, VIRTIO_MAX_QUEUE_SIZE)?;
+
+		Virtq::try_new_with_allocator(
+			&mut self.com_cfg,
+			&self.notif_cfg,
+			VqSize::from(size),
+			vq_type,
+			VqIndex::from(index),
+			self.dev_cfg.features.into(),
+			self.config.allocator,
+		)
+		.map_err(|vq_err| {
+			error!(
+				"Virtqueue {} of network device {:x} could not be created: {:?}",
+				index, self.dev_cfg.dev_id, vq_err
+			);
+			match vq_err {
+				VirtqError::NoMemAvail => VirtioNetError::QueueAllocFailed(index),
+				_ => VirtioNetError::VqCreation(index),
+			}
+		})
+	}
+
+	/// Returns the total number of virtqueues of the device, if the transport reports it.
+	fn dev_num_queues(&self) -> Option<u16> {
+		#[cfg(feature = "pci")]
+		{
+			Some(self.com_cfg.num_queues())
+		}
+		#[cfg(not(feature = "pci"))]
+		{
+			None
+		}
+	}
+
+	/// Initialize virtqueues via the queue interface and populates receiving queues
+	fn virtqueue_init(&mut self) -> Result<(), VirtioNetError> {
+		// The device specific configuration is not trusted blindly. The pair count is checked
+		// against num_queues of the ComCfg struct, which defines the maximal number of supported
+		// queues, including a possible control queue.
+		let dev_queues = self.dev_num_queues();
+		self.num_vqs = Self::num_data_vqs(&self.dev_cfg, dev_queues);
+		if self.dev_cfg.features.is_feature(Features::VIRTIO_NET_F_MQ) {
+			let max_pairs = self.dev_cfg.max_virtqueue_pairs();
+			if max_pairs == 0 || dev_queues.map_or(false, |num| max_pairs > num / 2) {
+				warn!(
+					"Network device {:x} reports {} queue pairs, which is outside of the supported range for {:?} virtqueues. Using {} pairs.",
+					self.dev_cfg.dev_id,
+					max_pairs,
+					dev_queues,
+					self.num_vqs / 2
+				);
+			} else if max_pairs > self.num_vqs / 2 {
+				info!(
+					"Network device {:x} supports {} queue pairs, of which the driver uses at most {}.",
+					self.dev_cfg.dev_id,
+					max_pairs,
+					MAX_NUM_VQ / 2
+				);
+			}
+		}
+
+		// The loop is running from 0 to num_vqs and the indexes are provided to the VqIndex::from function in this way
+		// in order to allow the indexes of the queues to be in a form of:
+		//
+		// index i for receiv queue
+		// index i+1 for send queue
+		//
+		// as it is wanted by the network network device.
+		// see Virtio specification v1.1. - 5.1.2
+		// Assure that we have always an even number of queues (i.e. pairs of queues).
+		assert_eq!(self.num_vqs % 2, 0);
+		self.stats
+			.resize_with(usize::from(self.num_vqs / 2).max(1), NetCounters::default);
+
+		let packed = self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_F_RING_PACKED);
+		let vq_type = || {
+			if packed {
+				VqType::Packed
+			} else {
+				VqType::Split
+			}
+		};
+
+		for i in 0..(self.num_vqs / 2) {
+			// Queues, which are not needed for the configured direction, are not created.
+			if self.config.direction.has_rx() {
+				let vq = self.create_vq(vq_type(), 2 * i)?;
+				// Interrupt for receiving packets is wanted
+				vq.enable_notifs();
+
+				self.recv_vqs.add(vq, &self.dev_cfg)?;
+			}
+
+			if self.config.direction.has_tx() {
+				let vq = self.create_vq(vq_type(), 2 * i + 1)?;
+				// Interrupt for comunicating that a sended packet left, is not needed
+				vq.disable_notifs();
+
+				self.send_vqs.add(vq, &self.dev_cfg)?;
+			}
+		}
+
+		if self.config.direction.has_rx() {
+			self.rx_pool = BufferPool::new(self.config.rx_pool_size, self.recv_vqs.buf_len);
+		}
+		self.emit(NetEvent::QueuesAllocated {
+			count: u16::try_from(self.recv_vqs.vqs.len() + self.send_vqs.vqs.len()).unwrap(),
+		});
+
+		Ok(())
+	}
+}
+
+pub mod constants {
+	use alloc::vec::Vec;
+	use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign};
+
+	pub use super::error::VirtioNetError;
+
+	// Configuration constants
+	/// Maximal number of receive and transmit queues used by the driver. Limits the
+	/// memory, which is allocated for receive buffers.
+	pub const MAX_NUM_VQ: u16 = 8;
+	/// Length of the window in microseconds, in which interrupts are counted
+	/// in order to detect interrupt storms.
+	pub const IRQ_STORM_WINDOW: u64 = 1_000_000;
+	/// MSI-X table entry used for configuration changes.
+	pub const MSIX_CFG_ENTRY: u16 = 0;
+	/// MSI-X table entry shared by all virtqueues.
+	pub const MSIX_QUEUE_ENTRY: u16 = 1;
+	/// MSI-X table entry of the first queue pair, whose affinity is set. The entries of
+	/// the following pairs follow it.
+	pub const MSIX_FIRST_PAIR_ENTRY: u16 = 2;
+	/// Bit of the ISR status, which indicates a used buffer notification of a virtqueue.
+	/// See Virtio specification v1.1. - 4.1.4.5
+	pub const ISR_QUEUE: u32 = 1 << 0;
+	/// Bit of the ISR status, which indicates a change of the device configuration.
+	pub const ISR_CFG_CHANGE: u32 = 1 << 1;
+	/// Time in microseconds, the device has to finish a command on the control queue.
+	pub const CTRL_CMD_TIMEOUT: u64 = 1_000_000;
+	/// Time in microseconds, the device has to transmit a packet, whose segments are
+	/// borrowed from the caller.
+	pub const TX_BORROWED_TIMEOUT: u64 = 1_000_000;
+	/// Time in microseconds, within which the frame of the loopback test must be received.
+	pub const LOOPBACK_TIMEOUT: u64 = 100_000;
+	/// Ether type of the loopback test frame, which is reserved for local experiments.
+	pub const LOOPBACK_ETHER_TYPE: u16 = 0x88b5;
+	/// Ether type and operation of the gratuitous ARP request announcing the guest.
+	pub const ARP_ETHER_TYPE: u16 = 0x0806;
+	pub const ARP_REQUEST: u8 = 1;
+	/// Ether type and operation of the RARP request announcing the guest, if its IPv4
+	/// address is unknown. See RFC 903
+	pub const RARP_ETHER_TYPE: u16 = 0x8035;
+	pub const RARP_REQUEST: u8 = 3;
+	/// Smallest segment size accepted for TCP segmentation offload.
+	pub const MIN_TSO_MSS: u16 = 88;
+	/// Number of fragments of a packet, above which the packet is sent via an
+	/// indirect descriptor table.
+	pub const INDIRECT_TX_THRESHOLD: usize = 2;
+	/// Maximal number of descriptors of a transmitted packet, which are chained directly in
+	/// the queue, including the one of the VirtioNetHdr.
+	pub const MAX_DIRECT_TX_CHAIN: u16 = 33;
+	/// Maximal length of a frame handed to the device for segmentation.
+	/// See Virtio specification v1.1. - 5.1.6.3.1
+	pub const MAX_GSO_LEN: usize = 65550;
+
+	// Hash types of receive-side scaling. See Virtio specification v1.2. - 5.1.6.4.3.1
+	pub const RSS_HASH_TYPE_IPV4: u32 = 1 << 0;
+	pub const RSS_HASH_TYPE_TCPV4: u32 = 1 << 1;
+	pub const RSS_HASH_TYPE_UDPV4: u32 = 1 << 2;
+	pub const RSS_HASH_TYPE_IPV6: u32 = 1 << 3;
+	pub const RSS_HASH_TYPE_TCPV6: u32 = 1 << 4;
+	pub const RSS_HASH_TYPE_UDPV6: u32 = 1 << 5;
+	pub const RSS_HASH_TYPE_IP_EX: u32 = 1 << 6;
+	pub const RSS_HASH_TYPE_TCP_EX: u32 = 1 << 7;
+	pub const RSS_HASH_TYPE_UDP_EX: u32 = 1 << 8;
+	/// Hash types of the RSS configuration used upon initialization, if supported by the device.
+	pub const RSS_DEFAULT_HASH_TYPES: u32 = RSS_HASH_TYPE_IPV4
+		| RSS_HASH_TYPE_TCPV4
+		| RSS_HASH_TYPE_UDPV4
+		| RSS_HASH_TYPE_IPV6
+		| RSS_HASH_TYPE_TCPV6
+		| RSS_HASH_TYPE_UDPV6;
+	/// Maximal length of the indirection table of the RSS configuration used upon initialization.
+	pub const RSS_DEFAULT_TABLE_LEN: u16 = 128;
+	/// Toeplitz hash key of the RSS configuration used upon initialization. It is the
+	/// well-known key of the Microsoft RSS verification suite, which is used by most drivers.
+	pub const RSS_DEFAULT_KEY: [u8; 40] = [
+		0x6d, 0x5a, 0x56, 0xda, 0x25, 0x5b, 0x0e, 0xc2, 0x41, 0x67, 0x25, 0x3d, 0x43, 0xa3, 0x8f,
+		0xb0, 0xd0, 0xca, 0x2b, 0xcb, 0xae, 0x7b, 0x30, 0xb4, 0x77, 0xcb, 0x2d, 0xa3, 0x80, 0x30,
+		0xf2, 0x0c, 0x6a, 0x42, 0xb7, 0x3b, 0xbe, 0xac, 0x01, 0xfa,
+	];
+
+	macro_rules! unknown_bits {
+		($($bit:literal)*) => {
+			[$(concat!("UNKNOWN(bit", $bit, ")")),*]
+		};
+	}
+
+	/// Names of feature bits, which are unknown to the driver, indexed by bit.
+	const UNKNOWN_BITS: [&str; 64] = unknown_bits!(
+		0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+		32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47 48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63
+	);
+
+	/// Returns the names of the feature bits set in `mask`, ordered by bit. Bits, which do
+	/// not belong to a known feature, are named `UNKNOWN(bitN)`.
+	pub fn feature_names(mask: u64) -> Vec<&'static str> {
+		(0..64)
+			.filter(|bit| mask & (1 << bit) != 0)
+			.map(|bit| {
+				Features::ALL
+					.iter()
+					.find(|feat| feat.bit() == bit)
+					.map_or(UNKNOWN_BITS[bit as usize], |feat| feat.name())
+			})
+			.collect()
+	}
+
+	/// Dependencies between features defined in Virtio specification v1.1. - 5.1.3.1
+	///
+	/// Each feature requires at least one of the features it is paired with.
+	pub const FEATURE_DEPS: &[(Features, &[Features])] = &[
+		(
+			Features::VIRTIO_NET_F_GUEST_TSO4,
+			&[Features::VIRTIO_NET_F_GUEST_CSUM],
+		),
+		(
+			Features::VIRTIO_NET_F_GUEST_TSO6,
+			&[Features::VIRTIO_NET_F_GUEST_CSUM],
+		),
+		(
+			Features::VIRTIO_NET_F_GUEST_ECN,
+			&[
+				Features::VIRTIO_NET_F_GUEST_TSO4,
+				Features::VIRTIO_NET_F_GUEST_TSO6,
+			],
+		),
+		(
+			Features::VIRTIO_NET_F_GUEST_UFO,
+			&[Features::VIRTIO_NET_F_GUEST_CSUM],
+		),
+		(
+			Features::VIRTIO_NET_F_HOST_TSO4,
+			&[Features::VIRTIO_NET_F_CSUM],
+		),
+		(
+			Features::VIRTIO_NET_F_HOST_TSO6,
+			&[Features::VIRTIO_NET_F_CSUM],
+		),
+		(
+			Features::VIRTIO_NET_F_HOST_ECN,
+			&[
+				Features::VIRTIO_NET_F_HOST_TSO4,
+				Features::VIRTIO_NET_F_HOST_TSO6,
+			],
+		),
+		(
+			Features::VIRTIO_NET_F_HOST_UFO,
+			&[Features::VIRTIO_NET_F_CSUM],
+		),
+		(
+			Features::VIRTIO_NET_F_CTRL_RX,
+			&[Features::VIRTIO_NET_F_CTRL_VQ],
+		),
+		(
+			Features::VIRTIO_NET_F_CTRL_VLAN,
+			&[Features::VIRTIO_NET_F_CTRL_VQ],
+		),
+		(
+			Features::VIRTIO_NET_F_GUEST_ANNOUNCE,
+			&[Features::VIRTIO_NET_F_CTRL_VQ],
+		),
+		(Features::VIRTIO_NET_F_MQ, &[Features::VIRTIO_NET_F_CTRL_VQ]),
+		(
+			Features::VIRTIO_NET_F_CTRL_MAC_ADDR,
+			&[Features::VIRTIO_NET_F_CTRL_VQ],
+		),
+		(
+			Features::VIRTIO_NET_F_NOTF_COAL,
+			&[Features::VIRTIO_NET_F_CTRL_VQ],
+		),
+		(
+			Features::VIRTIO_NET_F_VQ_NOTF_COAL,
+			&[Features::VIRTIO_NET_F_CTRL_VQ],
+		),
+		(
+			Features::VIRTIO_NET_F_RSS,
+			&[Features::VIRTIO_NET_F_CTRL_VQ],
+		),
+		(
+			Features::VIRTIO_NET_F_RSC_EXT,
+			&[
+				Features::VIRTIO_NET_F_HOST_TSO4,
+				Features::VIRTIO_NET_F_HOST_TSO6,
+			],
+		),
+	];
+
+	/// Returns the dependencies of the features set in `mask`, which are not set themselves.
+	/// For a feature with alternative dependencies, all alternatives are returned. Each
+	/// dependency is listed once.
+	pub fn missing_dependencies(mask: u64) -> Vec<Features> {
+		let mut missing: Vec<Features> = Vec::new();
+		for (feat, deps) in FEATURE_DEPS {
+			if mask & *feat == 0 || deps.iter().any(|dep| mask & *dep != 0) {
+				continue;
+			}
+			for dep in deps.iter() {
+				if !missing.iter().any(|known| known.bit() == dep.bit()) {
+					missing.push(*dep);
+				}
+			}
+		}
+		missing
+	}
+
+	/// Removes the features from `mask`, whose dependencies are not set. Features, which
+	/// depend on a removed feature, are removed as well.
+	pub fn satisfiable_features(mut mask: u64) -> u64 {
+		while !missing_dependencies(mask).is_empty() {
+			for (feat, deps) in FEATURE_DEPS {
+				if mask & *feat != 0 && deps.iter().all(|dep| mask & *dep == 0) {
+					mask &= !u64::from(*feat);
+				}
+			}
+		}
+		mask
+	}
+
+	/// Enum containing Virtios netword header flags
+	///
+	/// See Virtio specification v1.1. - 5.1.6
+	#[allow(dead_code, non_camel_case_types)]
+	#[derive(Copy, Clone, Debug)]
+	#[repr(u8)]
+	///
+	pub enum NetHdrFlag {
+		/// use csum_start, csum_offset
+		VIRTIO_NET_HDR_F_NEEDS_CSUM = 1,
+		/// csum is valid
+		VIRTIO_NET_HDR_F_DATA_VALID = 2,
+		/// reports number of coalesced TCP segments
+		VIRTIO_NET_HDR_F_RSC_INFO = 4,
+	}
+
+	impl From<NetHdrFlag> for u8 {
+		fn from(val: NetHdrFlag) -> Self {
+			match val {
+				NetHdrFlag::VIRTIO_NET_HDR_F_NEEDS_CSUM => 1,
+				NetHdrFlag::VIRTIO_NET_HDR_F_DATA_VALID => 2,
+				NetHdrFlag::VIRTIO_NET_HDR_F_RSC_INFO => 4,
+			}
+		}
+	}
+
+	impl BitOr for NetHdrFlag {
+		type Output = u8;
+
+		fn bitor(self, rhs: Self) -> Self::Output {
+			u8::from(self) | u8::from(rhs)
+		}
+	}
+
+	impl BitOr<NetHdrFlag> for u8 {
+		type Output = u8;
+
+		fn bitor(self, rhs: NetHdrFlag) -> Self::Output {
+			self | u8::from(rhs)
+		}
+	}
+
+	impl BitOrAssign<NetHdrFlag> for u8 {
+		fn bitor_assign(&mut self, rhs: NetHdrFlag) {
+			*self |= u8::from(rhs);
+		}
+	}
+
+	impl BitAnd for NetHdrFlag {
+		type Output = u8;
+
+		fn bitand(self, rhs: NetHdrFlag) -> Self::Output {
+			u8::from(self) & u8::from(rhs)
+		}
+	}
+
+	impl BitAnd<NetHdrFlag> for u8 {
+		type Output = u8;
+
+		fn bitand(self, rhs: NetHdrFlag) -> Self::Output {
+			self & u8::from(rhs)
+		}
+	}
+
+	impl BitAndAssign<NetHdrFlag> for u8 {
+		fn bitand_assign(&mut self, rhs: NetHdrFlag) {
+			*self &= u8::from(rhs);
+		}
+	}
+
+	/// Enum containing Virtios netword GSO types
+	///
+	/// See Virtio specification v1.1. - 5.1.6
+	#[allow(dead_code, non_camel_case_types)]
+	#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+	#[repr(u8)]
+	pub enum NetHdrGSO {
+		/// not a GSO frame
+		VIRTIO_NET_HDR_GSO_NONE = 0,
+		/// GSO frame, IPv4 TCP (TSO)
+		VIRTIO_NET_HDR_GSO_TCPV4 = 1,
+		/// GSO frame, IPv4 UDP (UFO)
+		VIRTIO_NET_HDR_GSO_UDP = 3,
+		/// GSO frame, IPv6 TCP
+		VIRTIO_NET_HDR_GSO_TCPV6 = 4,
+		/// TCP has ECN set
+		VIRTIO_NET_HDR_GSO_ECN = 0x80,
+	}
+
+	impl From<NetHdrGSO> for u8 {
+		fn from(val: NetHdrGSO) -> Self {
+			match val {
+				NetHdrGSO::VIRTIO_NET_HDR_GSO_NONE => 0,
+				NetHdrGSO::VIRTIO_NET_HDR_GSO_TCPV4 => 1,
+				NetHdrGSO::VIRTIO_NET_HDR_GSO_UDP => 3,
+				NetHdrGSO::VIRTIO_NET_HDR_GSO_TCPV6 => 4,
+				NetHdrGSO::VIRTIO_NET_HDR_GSO_ECN => 0x80,
+			}
+		}
+	}
+
+	impl BitOr for NetHdrGSO {
+		type Output = u8;
+
+		fn bitor(self, rhs: Self) -> Self::Output {
+			u8::from(self) | u8::from(rhs)
+		}
+	}
+
+	impl BitOr<NetHdrGSO> for u8 {
+		type Output = u8;
+
+		fn bitor(self, rhs: NetHdrGSO) -> Self::Output {
+			self | u8::from(rhs)
+		}
+	}
+
+	impl BitOrAssign<NetHdrGSO> for u8 {
+		fn bitor_assign(&mut self, rhs: NetHdrGSO) {
+			*self |= u8::from(rhs);
+		}
+	}
+
+	impl BitAnd for NetHdrGSO {
+		type Output = u8;
+
+		fn bitand(self, rhs: NetHdrGSO) -> Self::Output {
+			u8::from(self) & u8::from(rhs)
+		}
+	}
+
+	impl BitAnd<NetHdrGSO> for u8 {
+		type Output = u8;
+
+		fn bitand(self, rhs: NetHdrGSO) -> Self::Output {
+			self & u8::from(rhs)
+		}
+	}
+
+	impl BitAndAssign<NetHdrGSO> for u8 {
+		fn bitand_assign(&mut self, rhs: NetHdrGSO) {
+			*self &= u8::from(rhs);
+		}
+	}
+
+	/// Enum contains virtio's network device features and general features of Virtio.
+	///
+	/// See Virtio specification v1.1. - 5.1.3
+	///
+	/// See Virtio specification v1.1. - 6
+	//
+	// WARN: In case the enum is changed, the static function of features `into_features(feat: u64) ->
+	// Option<Vec<Features>>` must also be adjusted to return a correct vector of features.
+	#[allow(dead_code, non_camel_case_types)]
+	#[derive(Copy, Clone, Debug)]
+	#[repr(u64)]
+	pub enum Features {
+		VIRTIO_NET_F_CSUM = 1 << 0,
+		VIRTIO_NET_F_GUEST_CSUM = 1 << 1,
+		VIRTIO_NET_F_CTRL_GUEST_OFFLOADS = 1 << 2,
+		VIRTIO_NET_F_MTU = 1 << 3,
+		VIRTIO_NET_F_MAC = 1 << 5,
+		VIRTIO_NET_F_GUEST_TSO4 = 1 << 7,
+		VIRTIO_NET_F_GUEST_TSO6 = 1 << 8,
+		VIRTIO_NET_F_GUEST_ECN = 1 << 9,
+		VIRTIO_NET_F_GUEST_UFO = 1 << 10,
+		VIRTIO_NET_F_HOST_TSO4 = 1 << 11,
+		VIRTIO_NET_F_HOST_TSO6 = 1 << 12,
+		VIRTIO_NET_F_HOST_ECN = 1 << 13,
+		VIRTIO_NET_F_HOST_UFO = 1 << 14,
+		VIRTIO_NET_F_MRG_RXBUF = 1 << 15,
+		VIRTIO_NET_F_STATUS = 1 << 16,
+		VIRTIO_NET_F_CTRL_VQ = 1 << 17,
+		VIRTIO_NET_F_CTRL_RX = 1 << 18,
+		VIRTIO_NET_F_CTRL_VLAN = 1 << 19,
+		VIRTIO_NET_F_GUEST_ANNOUNCE = 1 << 21,
+		VIRTIO_NET_F_MQ = 1 << 22,
+		VIRTIO_NET_F_CTRL_MAC_ADDR = 1 << 23,
+		VIRTIO_F_RING_INDIRECT_DESC = 1 << 28,
+		VIRTIO_F_RING_EVENT_IDX = 1 << 29,
+		VIRTIO_F_VERSION_1 = 1 << 32,
+		VIRTIO_F_ACCESS_PLATFORM = 1 << 33,
+		VIRTIO_F_RING_PACKED = 1 << 34,
+		VIRTIO_F_IN_ORDER = 1 << 35,
+		VIRTIO_F_ORDER_PLATFORM = 1 << 36,
+		VIRTIO_F_SR_IOV = 1 << 37,
+		VIRTIO_F_NOTIFICATION_DATA = 1 << 38,
+		VIRTIO_NET_F_VQ_NOTF_COAL = 1 << 52,
+		VIRTIO_NET_F_NOTF_COAL = 1 << 53,
+		VIRTIO_NET_F_GUEST_HDRLEN = 1 << 59,
+		VIRTIO_NET_F_RSS = 1 << 60,
+		VIRTIO_NET_F_RSC_EXT = 1 << 61,
+		VIRTIO_NET_F_STANDBY = 1 << 62,
+		VIRTIO_NET_F_SPEED_DUPLEX = 1 << 63,
+		// INTERNAL DOCUMENTATION TO KNOW WHICH FEATURES HAVE REQUIREMENTS
+		//
+		// 5.1.3.1 Feature bit requirements
+		// Some networking feature bits require other networking feature bits (see 2.2.1):
+		// VIRTIO_NET_F_GUEST_TSO4 Requires VIRTIO_NET_F_GUEST_CSUM.
+		// VIRTIO_NET_F_GUEST_TSO6 Requires VIRTIO_NET_F_GUEST_CSUM.
+		// VIRTIO_NET_F_GUEST_ECN Requires VIRTIO_NET_F_GUEST_TSO4orVIRTIO_NET_F_GUEST_TSO6.
+		// VIRTIO_NET_F_GUEST_UFO Requires VIRTIO_NET_F_GUEST_CSUM.
+		// VIRTIO_NET_F_HOST_TSO4 Requires VIRTIO_NET_F_CSUM.
+		// VIRTIO_NET_F_HOST_TSO6 Requires VIRTIO_NET_F_CSUM.
+		// VIRTIO_NET_F_HOST_ECN Requires VIRTIO_NET_F_HOST_TSO4 or VIRTIO_NET_F_HOST_TSO6.
+		// VIRTIO_NET_F_HOST_UFO Requires VIRTIO_NET_F_CSUM.
+		// VIRTIO_NET_F_CTRL_RX Requires VIRTIO_NET_F_CTRL_VQ.
+		// VIRTIO_NET_F_CTRL_VLAN Requires VIRTIO_NET_F_CTRL_VQ.
+		// VIRTIO_NET_F_GUEST_ANNOUNCE Requires VIRTIO_NET_F_CTRL_VQ.
+		// VIRTIO_NET_F_MQ Requires VIRTIO_NET_F_CTRL_VQ.
+		// VIRTIO_NET_F_CTRL_MAC_ADDR Requires VIRTIO_NET_F_CTRL_VQ.
+		// VIRTIO_NET_F_RSC_EXT Requires VIRTIO_NET_F_HOST_TSO4 or VIRTIO_NET_F_HOST_TSO6.
+		// VIRTIO_NET_F_RSS Requires VIRTIO_NET_F_CTRL_VQ.
+		// VIRTIO_NET_F_NOTF_COAL Requires VIRTIO_NET_F_CTRL_VQ.
+		// VIRTIO_NET_F_VQ_NOTF_COAL Requires VIRTIO_NET_F_CTRL_VQ.
+	}
+
+	impl From<Features> for u64 {
+		fn from(val: Features) -> Self {
+			// The discriminants are the masks of the feature bits.
+			val as u64
+		}
+	}
+
+	impl BitOr for Features {
+		type Output = u64;
+
+		fn bitor(self, rhs: Self) -> Self::Output {
+			u64::from(self) | u64::from(rhs)
+		}
+	}
+
+	impl BitOr<Features> for u64 {
+		type Output = u64;
+
+		fn bitor(self, rhs: Features) -> Self::Output {
+			self | u64::from(rhs)
+		}
+	}
+
+	impl BitOrAssign<Features> for u64 {
+		fn bitor_assign(&mut self, rhs: Features) {
+			*self |= u64::from(rhs);
+		}
+	}
+
+	impl BitAnd for Features {
+		type Output = u64;
+
+		fn bitand(self, rhs: Features) -> Self::Output {
+			u64::from(self) & u64::from(rhs)
+		}
+	}
+
+	impl BitAnd<Features> for u64 {
+		type Output = u64;
+
+		fn bitand(self, rhs: Features) -> Self::Output {
+			self & u64::from(rhs)
+		}
+	}
+
+	impl BitAndAssign<Features> for u64 {
+		fn bitand_assign(&mut self, rhs: Features) {
+			*self &= u64::from(rhs);
+		}
+	}
+
+	impl core::fmt::Display for Features {
+		fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+			f.write_str(self.name())
+		}
+	}
+
+	impl Features {
+		/// Returns the name of the feature as given by the specification.
+		pub fn name(self) -> &'static str {
+			match self {
+				Features::VIRTIO_NET_F_CSUM => "VIRTIO_NET_F_CSUM",
+				Features::VIRTIO_NET_F_GUEST_CSUM => "VIRTIO_NET_F_GUEST_CSUM",
+				Features::VIRTIO_NET_F_CTRL_GUEST_OFFLOADS => "VIRTIO_NET_F_CTRL_GUEST_OFFLOADS",
+				Features::VIRTIO_NET_F_MTU => "VIRTIO_NET_F_MTU",
+				Features::VIRTIO_NET_F_MAC => "VIRTIO_NET_F_MAC",
+				Features::VIRTIO_NET_F_GUEST_TSO4 => "VIRTIO_NET_F_GUEST_TSO4",
+				Features::VIRTIO_NET_F_GUEST_TSO6 => "VIRTIO_NET_F_GUEST_TSO6",
+				Features::VIRTIO_NET_F_GUEST_ECN => "VIRTIO_NET_F_GUEST_ECN",
+				Features::VIRTIO_NET_F_GUEST_UFO => "VIRTIO_NET_F_GUEST_UFO",
+				Features::VIRTIO_NET_F_HOST_TSO4 => "VIRTIO_NET_F_HOST_TSO4",
+				Features::VIRTIO_NET_F_HOST_TSO6 => "VIRTIO_NET_F_HOST_TSO6",
+				Features::VIRTIO_NET_F_HOST_ECN => "VIRTIO_NET_F_HOST_ECN",
+				Features::VIRTIO_NET_F_HOST_UFO => "VIRTIO_NET_F_HOST_UFO",
+				Features::VIRTIO_NET_F_MRG_RXBUF => "VIRTIO_NET_F_MRG_RXBUF",
+				Features::VIRTIO_NET_F_STATUS => "VIRTIO_NET_F_STATUS",
+				Features::VIRTIO_NET_F_CTRL_VQ => "VIRTIO_NET_F_CTRL_VQ",
+				Features::VIRTIO_NET_F_CTRL_RX => "VIRTIO_NET_F_CTRL_RX",
+				Features::VIRTIO_NET_F_CTRL_VLAN => "VIRTIO_NET_F_CTRL_VLAN",
+				Features::VIRTIO_NET_F_GUEST_ANNOUNCE => "VIRTIO_NET_F_GUEST_ANNOUNCE",
+				Features::VIRTIO_NET_F_MQ => "VIRTIO_NET_F_MQ",
+				Features::VIRTIO_NET_F_CTRL_MAC_ADDR => "VIRTIO_NET_F_CTRL_MAC_ADDR",
+				Features::VIRTIO_F_RING_INDIRECT_DESC => "VIRTIO_F_RING_INDIRECT_DESC",
+				Features::VIRTIO_F_RING_EVENT_IDX => "VIRTIO_F_RING_EVENT_IDX",
+				Features::VIRTIO_F_VERSION_1 => "VIRTIO_F_VERSION_1",
+				Features::VIRTIO_F_ACCESS_PLATFORM => "VIRTIO_F_ACCESS_PLATFORM",
+				Features::VIRTIO_F_RING_PACKED => "VIRTIO_F_RING_PACKED",
+				Features::VIRTIO_F_IN_ORDER => "VIRTIO_F_IN_ORDER",
+				Features::VIRTIO_F_ORDER_PLATFORM => "VIRTIO_F_ORDER_PLATFORM",
+				Features::VIRTIO_F_SR_IOV => "VIRTIO_F_SR_IOV",
+				Features::VIRTIO_F_NOTIFICATION_DATA => "VIRTIO_F_NOTIFICATION_DATA",
+				Features::VIRTIO_NET_F_VQ_NOTF_COAL => "VIRTIO_NET_F_VQ_NOTF_COAL",
+				Features::VIRTIO_NET_F_NOTF_COAL => "VIRTIO_NET_F_NOTF_COAL",
+				Features::VIRTIO_NET_F_GUEST_HDRLEN => "VIRTIO_NET_F_GUEST_HDRLEN",
+				Features::VIRTIO_NET_F_RSS => "VIRTIO_NET_F_RSS",
+				Features::VIRTIO_NET_F_RSC_EXT => "VIRTIO_NET_F_RSC_EXT",
+				Features::VIRTIO_NET_F_STANDBY => "VIRTIO_NET_F_STANDBY",
+				Features::VIRTIO_NET_F_SPEED_DUPLEX => "VIRTIO_NET_F_SPEED_DUPLEX",
+			}
+		}
+
+		/// All features known to the driver, in the order of their feature bits.
+		pub const ALL: [Features; 37] = [
+			Features::VIRTIO_NET_F_CSUM,
+			Features::VIRTIO_NET_F_GUEST_CSUM,
+			Features::VIRTIO_NET_F_CTRL_GUEST_OFFLOADS,
+			Features::VIRTIO_NET_F_MTU,
+			Features::VIRTIO_NET_F_MAC,
+			Features::VIRTIO_NET_F_GUEST_TSO4,
+			Features::VIRTIO_NET_F_GUEST_TSO6,
+			Features::VIRTIO_NET_F_GUEST_ECN,
+			Features::VIRTIO_NET_F_GUEST_UFO,
+			Features::VIRTIO_NET_F_HOST_TSO4,
+			Features::VIRTIO_NET_F_HOST_TSO6,
+			Features::VIRTIO_NET_F_HOST_ECN,
+			Features::VIRTIO_NET_F_HOST_UFO,
+			Features::VIRTIO_NET_F_MRG_RXBUF,
+			Features::VIRTIO_NET_F_STATUS,
+			Features::VIRTIO_NET_F_CTRL_VQ,
+			Features::VIRTIO_NET_F_CTRL_RX,
+			Features::VIRTIO_NET_F_CTRL_VLAN,
+			Features::VIRTIO_NET_F_GUEST_ANNOUNCE,
+			Features::VIRTIO_NET_F_MQ,
+			Features::VIRTIO_NET_F_CTRL_MAC_ADDR,
+			Features::VIRTIO_F_RING_INDIRECT_DESC,
+			Features::VIRTIO_F_RING_EVENT_IDX,
+			Features::VIRTIO_F_VERSION_1,
+			Features::VIRTIO_F_ACCESS_PLATFORM,
+			Features::VIRTIO_F_RING_PACKED,
+			Features::VIRTIO_F_IN_ORDER,
+			Features::VIRTIO_F_ORDER_PLATFORM,
+			Features::VIRTIO_F_SR_IOV,
+			Features::VIRTIO_F_NOTIFICATION_DATA,
+			Features::VIRTIO_NET_F_VQ_NOTF_COAL,
+			Features::VIRTIO_NET_F_NOTF_COAL,
+			Features::VIRTIO_NET_F_GUEST_HDRLEN,
+			Features::VIRTIO_NET_F_RSS,
+			Features::VIRTIO_NET_F_RSC_EXT,
+			Features::VIRTIO_NET_F_STANDBY,
+			Features::VIRTIO_NET_F_SPEED_DUPLEX,
+		];
+
+		/// Returns the position of the feature bit.
+		pub fn bit(self) -> u32 {
+			u64::from(self).trailing_zeros()
+		}
+
+		/// Return a vector of [Features](Features) for a given input of a u64 representation.
+		/// Bits, which do not belong to a known feature, are ignored.
+		pub fn from_set(feat_set: FeatureSet) -> Option<Vec<Features>> {
+			let feats = u64::from(feat_set);
+			let vec_of_feats: Vec<Features> = Features::ALL
+				.iter()
+				.copied()
+				.filter(|feat| feats & u64::from(*feat) != 0)
+				.collect();
+
+			if vec_of_feats.is_empty() {
+				None
+			} else {
+				Some(vec_of_feats)
+			}
+		}
+	}
+
+	/// Enum contains virtio's network device status
+	/// indiacted in the status field of the device's
+	/// configuration structure.
+	///
+	/// See Virtio specification v1.1. - 5.1.4
+	#[allow(dead_code, non_camel_case_types)]
+	#[derive(Copy, Clone, Debug)]
+	#[repr(u16)]
+	pub enum Status {
+		VIRTIO_NET_S_LINK_UP = 1 << 0,
+		VIRTIO_NET_S_ANNOUNCE = 1 << 1,
+	}
+
+	impl From<Status> for u16 {
+		fn from(stat: Status) -> Self {
+			match stat {
+				Status::VIRTIO_NET_S_LINK_UP => 1,
+				Status::VIRTIO_NET_S_ANNOUNCE => 2,
+			}
+		}
+	}
+
+	/// FeatureSet is new type whicih holds features for virito network devices indicated by the virtio specification
+	/// v1.1. - 5.1.3. and all General Features defined in Virtio specification v1.1. - 6
+	/// wrapping a u64.
+	///
+	/// The main functionality of this type are functions implemented on it.
+	#[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Eq)]
+	pub struct FeatureSet(u64);
+
+	impl BitOr for FeatureSet {
+		type Output = FeatureSet;
+
+		fn bitor(self, rhs: Self) -> Self::Output {
+			FeatureSet(self.0 | rhs.0)
+		}
+	}
+
+	impl BitOr<FeatureSet> for u64 {
+		type Output = u64;
+
+		fn bitor(self, rhs: FeatureSet) -> Self::Output {
+			self | u64::from(rhs)
+		}
+	}
+
+	impl BitOrAssign<FeatureSet> for u64 {
+		fn bitor_assign(&mut self, rhs: FeatureSet) {
+			*self |= u64::from(rhs);
+		}
+	}
+
+	impl BitOrAssign<Features> for FeatureSet {
+		fn bitor_assign(&mut self, rhs: Features) {
+			self.0 = self.0 | u64::from(rhs);
+		}
+	}
+
+	impl BitAnd for FeatureSet {
+		type Output = FeatureSet;
+
+		fn bitand(self, rhs: FeatureSet) -> Self::Output {
+			FeatureSet(self.0 & rhs.0)
+		}
+	}
+
+	impl BitAnd<FeatureSet> for u64 {
+		type Output = u64;
+
+		fn bitand(self, rhs: FeatureSet) -> Self::Output {
+			self & u64::from(rhs)
+		}
+	}
+
+	impl BitAndAssign<FeatureSet> for u64 {
+		fn bitand_assign(&mut self, rhs: FeatureSet) {
+			*self &= u64::from(rhs);
+		}
+	}
+
+	impl From<FeatureSet> for u64 {
+		fn from(feature_set: FeatureSet) -> Self {
+			feature_set.0
+		}
+	}
+
+	impl FeatureSet {
+		/// Checks if a given set of features is compatible and adheres to the
+		/// specfification v1.1. - 5.1.3.1, i.e. [FEATURE_DEPS].
+		/// Upon an error returns the incompatible set of features by the
+		/// [FeatReqNotMet](super::error::VirtioNetError) error value, which
+		/// wraps the u64 indicating the feature set.
+		pub fn check_features(feats: &[Features]) -> Result<(), VirtioNetError> {
+			let feat_bits = feats.iter().fold(0u64, |mask, feat| mask | *feat);
+
+			match FeatureSet::validate_feature_dependencies(feat_bits) {
+				Ok(()) => Ok(()),
+				Err(_) => Err(VirtioNetError::FeatReqNotMet(FeatureSet(feat_bits))),
+			}
+		}
+
+		/// Checks the given feature bits against the dependencies between features defined
+		/// in Virtio specification v1.1. - 5.1.3.1
+		///
+		/// Returns [VirtioNetError::IncompatibleFeatures] for the first feature, which is set
+		/// without any of the features it depends on.
+		pub fn validate_feature_dependencies(feats: u64) -> Result<(), VirtioNetError> {
+			for (feat, deps) in FEATURE_DEPS {
+				let required = deps.iter().fold(0u64, |mask, dep| mask | *dep);
+				if feats & *feat != 0 && feats & required == 0 {
+					return Err(VirtioNetError::IncompatibleFeatures(*feat, required));
+				}
+			}
+
+			Ok(())
+		}
+
+		/// Checks if a given feature is set.
+		pub fn is_feature(self, feat: Features) -> bool {
+			self.0 & feat != 0
+		}
+
+		/// Sets features contained in feats to true.
+		///
+		/// WARN: Features should be checked before using this function via the [`FeatureSet::check_features`] function.
+		pub fn set_features(&mut self, feats: &[Features]) {
+			for feat in feats {
+				self.0 |= *feat;
+			}
+		}
+
+		/// Returns a new instance of (FeatureSet)[FeatureSet] with all features
+		/// initialized to false.
+		pub fn new(val: u64) -> Self {
+			FeatureSet(val)
+		}
+	}
+}
+
+/// Error module of virtios network driver. Containing the (VirtioNetError)[VirtioNetError]
+/// enum.
+pub mod error {
+	use core::fmt;
+
+	use super::constants::{feature_names, FeatureSet, Features};
+	/// Network drivers error enum.
+	#[derive(Debug, Copy, Clone)]
+	pub enum VirtioNetError {
+		General,
+		NoDevCfg(u16),
+		NoComCfg(u16),
+		NoIsrCfg(u16),
+		NoNotifCfg(u16),
+		FailFeatureNeg(u16),
+		/// Set of features does not adhere to the requirements of features
+		/// indicated by the specification
+		FeatReqNotMet(FeatureSet),
+		/// The first u64 contains the feature bits wanted by the driver.
+		/// but which are incompatible with the device feature set, second u64.
+		IncompFeatsSet(FeatureSet, FeatureSet),
+		/// Indicates that an operation for finished Transfers, was performed on
+		/// an ongoing transfer
+		ProcessOngoing,
+		/// No parked receive buffer is available, which could be posted to the device.
+		NoRxBuffAvail,
+		/// The transmit queue has no free descriptors left.
+		NoTxBuffAvail,
+		/// All descriptors of the transmit queue are in use by packets, which have not yet
+		/// been consumed by the device. Sending can be retried later.
+		TxQueueFull,
+		/// The packet of the given size in bytes exceeds the maximum size allowed by the device.
+		PacketTooLarge(usize),
+		/// The control queue has not been created, as VIRTIO_NET_F_CTRL_VQ is not negotiated.
+		NoCtrlQueue,
+		/// The device did not finish an operation in time.
+		Timeout,
+		/// The segment size is out of the range allowed for segmentation offload.
+		InvalidMss(u16),
+		/// The given length of the headers, which are replicated for every segment, is
+		/// shorter than the ethernet header or exceeds the packet.
+		InvalidHdrLen(u16),
+		/// The packet of the given size fits into a single segment and should be sent without segmentation.
+		NoSegmentationNeeded(usize),
+		/// The packet can not be segmented by the device, as the protocol is unknown or the
+		/// respective offloading feature has not been negotiated.
+		GsoNotSupported,
+		/// The device did not acknowledge the control command of the given class and command.
+		CtrlCmdFailed(u8, u8),
+		/// The given index does not refer to an existing data queue.
+		InvalidQueue(u16),
+		/// Per queue notification coalescing (VIRTIO_NET_F_VQ_NOTF_COAL) is not available, but
+		/// the global setting (VIRTIO_NET_F_NOTF_COAL) can be used.
+		OnlyGlobalCoalescing,
+		/// Notification coalescing has not been negotiated.
+		CoalescingNotSupported,
+		/// The virtqueue with the given index could not be created.
+		VqCreation(u16),
+		/// The memory for the rings of the virtqueue with the given index could not be allocated.
+		QueueAllocFailed(u16),
+		/// A buffer of the given size in bytes exceeds the maximal size of a descriptor.
+		BufferTooLarge(usize),
+		/// The feature is selected without any of the features it depends on, given as bit mask.
+		/// See Virtio specification v1.1. - 5.1.3.1
+		IncompatibleFeatures(Features, u64),
+		/// The given value is not a known class of control commands.
+		UnknownCtrlClass(u8),
+		/// The operation requires the given feature, which has not been negotiated.
+		FeatureNotNegotiated(Features),
+		/// The given VLAN id is out of the 12-bit range.
+		InvalidVlanId(u16),
+		/// The device reports a size of zero for the virtqueue with the given index,
+		/// i.e. the queue is unavailable.
+		QueueUnavailable(u16),
+		/// The device did not become ready after the initialization. Contains the
+		/// status of the device, e.g. with DEVICE_NEEDS_RESET or FAILED set.
+		DeviceNotReady(u8),
+		/// The link of the device is down, hence packets are not sent.
+		/// Sending can be retried after the link came up.
+		LinkDown,
+		/// The loopback test failed at the given stage.
+		LoopbackFailed(LoopbackStage),
+		/// The RSS key of the given length exceeds the maximal key size of the device.
+		RssKeyTooLong(usize, u8),
+		/// The RSS indirection table of the given length is not a power of two or exceeds the
+		/// maximal table length of the device.
+		InvalidRssTable(usize, u16),
+		/// The given hash types are not supported for RSS by the device.
+		UnsupportedHashTypes(u32),
+		/// The device did not accept the requested features upon FEATURES_OK.
+		FeaturesRejected(FeatureDiff),
+		/// The packet consists of more segments, first value, than the device accepts, second value.
+		/// See [max_tx_segments](super::VirtioNetDriver::max_tx_segments).
+		TooManySegments(usize, u16),
+		/// The device does not use MSI-X or its table has no entry for the queue pair.
+		MsixUnavailable(u16),
+		/// The processor does not exist.
+		InvalidCpu(u32),
+		/// The number of queue pairs to activate and the number of available pairs.
+		InvalidQueuePairs(u16, u16),
+		Unknown,
+	}
+
+	/// Stage of [VirtioNetDriver::loopback_test](super::VirtioNetDriver::loopback_test).
+	#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+	pub enum LoopbackStage {
+		/// The test frame could not be sent.
+		Send,
+		/// The test frame did not come back in time.
+		Receive,
+		/// A test frame came back, but its content differs from the sent one.
+		PayloadMismatch,
+	}
+
+	/// Features of a failed negotiation. See Virtio specification v1.1. - 3.1.1
+	#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+	pub struct FeatureDiff {
+		/// Features offered by the device.
+		pub offered: u64,
+		/// Features requested by the driver.
+		pub requested: u64,
+		/// Features offered by the device after FEATURES_OK.
+		pub reported: u64,
+	}
+
+	impl FeatureDiff {
+		/// Returns the requested features, which the device does not offer after FEATURES_OK.
+		/// Zero, if the device refused the combination of the requested features as a whole.
+		pub fn refused(&self) -> u64 {
+			self.requested & !self.reported
+		}
+	}
+
+	impl fmt::Display for FeatureDiff {
+		fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+			write!(
+				f,
+				"Offered {:#x}, requested {:#x}, offered after FEATURES_OK {:#x}",
+				self.offered, self.requested, self.reported
+			)?;
+			match self.refused() {
+				0 => write!(f, ", the combination has been refused"),
+				refused => write!(f, ", refused: {}", feature_names(refused).join(", ")),
+			}
+		}
+	}
+
+	impl fmt::Display for VirtioNetError {
+		fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+			match self {
+				VirtioNetError::General => write!(f, "Virtio network driver failed due to unknown reasons!"),
+				VirtioNetError::NoDevCfg(id) => write!(f, "Virtio network driver failed, for device {id:x}, due to a missing or malformed device config!"),
+				VirtioNetError::NoComCfg(id) => write!(f, "Virtio network driver failed, for device {id:x}, due to a missing or malformed common config!"),
+				VirtioNetError::NoIsrCfg(id) => write!(f, "Virtio network driver failed, for device {id:x}, due to a missing or malformed ISR status config!"),
+				VirtioNetError::NoNotifCfg(id) => write!(f, "Virtio network driver failed, for device {id:x}, due to a missing or malformed notification config!"),
+				VirtioNetError::FailFeatureNeg(id) => write!(f, "Virtio network driver failed, for device {id:x}, device did not acknowledge negotiated feature set!"),
+				VirtioNetError::FeatReqNotMet(feats) => write!(f, "Virtio network driver tried to set feature bit without setting dependency feature. Feat set: {:x}", u64::from(*feats)),
+				VirtioNetError::IncompFeatsSet(drv_feats, dev_feats) => write!(f, "Feature set: {:x} , is incompatible with the device features: {:x}", u64::from(*drv_feats), u64::from(*dev_feats)),
+				VirtioNetError::ProcessOngoing => write!(f, "Virtio network performed an unsuitable operation upon an ongoging transfer."),
+				VirtioNetError::NoRxBuffAvail => write!(f, "Virtio network driver has no parked receive buffer, which could be posted to the device."),
+				VirtioNetError::NoTxBuffAvail => write!(f, "Virtio network driver failed to send a packet, as the transmit queue is full."),
+				VirtioNetError::PacketTooLarge(len) => write!(f, "Virtio network driver failed to send a packet of {len} bytes, as it exceeds the maximum packet size."),
+				VirtioNetError::NoCtrlQueue => write!(f, "Virtio network driver failed to send a command, as the device has no control queue."),
+				VirtioNetError::Timeout => write!(f, "Virtio network device did not finish the operation in time."),
+				VirtioNetError::InvalidMss(mss) => write!(f, "Virtio network driver rejected segmentation with invalid segment size {mss}."),
+				VirtioNetError::InvalidHdrLen(len) => write!(f, "Virtio network driver rejected segmentation with invalid header length {len}."),
+				VirtioNetError::NoSegmentationNeeded(len) => write!(f, "Virtio network driver rejected segmentation of packet with size {len}, which fits into a single segment."),
+				VirtioNetError::GsoNotSupported => write!(f, "Virtio network device does not support segmentation of the packet."),
+				VirtioNetError::CtrlCmdFailed(class, cmd) => write!(f, "Virtio network device did not acknowledge control command {cmd} of class {class}."),
+				VirtioNetError::InvalidQueue(idx) => write!(f, "Virtio network driver has no data queue with index {idx}."),
+				VirtioNetError::OnlyGlobalCoalescing => write!(f, "Virtio network device supports only global notification coalescing. Use set_coalesce instead."),
+				VirtioNetError::CoalescingNotSupported => write!(f, "Virtio network device does not support notification coalescing."),
+				VirtioNetError::TxQueueFull => write!(f, "Virtio network driver's transmit queue is full."),
+				VirtioNetError::VqCreation(idx) => write!(f, "Virtio network driver failed to create virtqueue {idx}."),
+				VirtioNetError::QueueAllocFailed(idx) => write!(f, "Virtio network driver failed to allocate the memory of virtqueue {idx}."),
+				VirtioNetError::BufferTooLarge(len) => write!(f, "Virtio network driver can not place a buffer of {len} bytes in a descriptor."),
+				VirtioNetError::IncompatibleFeatures(feat, required) => write!(f, "Feature {feat:?} requires one of the features {required:x}, which are not selected."),
+				VirtioNetError::DeviceNotReady(status) => write!(f, "Virtio network device is not ready, its status is {status:#x}."),
+				VirtioNetError::LinkDown => write!(f, "Virtio network driver can not send packets, as the link is down."),
+				VirtioNetError::LoopbackFailed(stage) => write!(f, "Virtio network driver failed the loopback test at stage {stage:?}."),
+				VirtioNetError::RssKeyTooLong(len, max) => write!(f, "Virtio network driver rejected RSS key of {len} bytes, as the device supports at most {max} bytes."),
+				VirtioNetError::InvalidRssTable(len, max) => write!(f, "Virtio network driver rejected RSS indirection table of length {len}, which must be a power of two of at most {max}."),
+				VirtioNetError::UnsupportedHashTypes(types) => write!(f, "Virtio network device does not support the RSS hash types {types:#x}."),
+				VirtioNetError::FeaturesRejected(diff) => write!(f, "Virtio network device did not accept the requested features. {diff}"),
+				VirtioNetError::TooManySegments(num, max) => write!(f, "Virtio network driver rejected packet of {num} segments, as at most {max} segments are supported."),
+				VirtioNetError::InvalidQueuePairs(pairs, max) => write!(f, "Virtio network driver can not activate {pairs} queue pairs, as between 1 and {max} pairs are available."),
+				VirtioNetError::MsixUnavailable(pair) => write!(f, "Virtio network driver has no MSI-X vector for queue pair {pair}."),
+				VirtioNetError::InvalidCpu(cpu) => write!(f, "Virtio network driver can not deliver interrupts to the non existing processor {cpu}."),
+				VirtioNetError::UnknownCtrlClass(class) => write!(f, "Virtio network driver does not know the control command class {class}."),
+				VirtioNetError::FeatureNotNegotiated(feat) => write!(f, "Virtio network driver can not perform the operation, as {feat:?} has not been negotiated."),
+				VirtioNetError::InvalidVlanId(vid) => write!(f, "Virtio network driver rejected VLAN id {vid}, which exceeds 4095."),
+				VirtioNetError::QueueUnavailable(idx) => write!(f, "Virtio network device reports virtqueue {idx} as unavailable."),
+				VirtioNetError::Unknown => write!(f, "Virtio network driver failed due unknown reason!"),
+			}
+		}
+	}
+}
+
+/// Prepares the given ethernet frame for checksum offloading and returns the values of
+/// `csum_start` and `csum_offset`.
+///
+/// Only unfragmented TCP and UDP packets over IPv4 and IPv6 (without extension headers), whose
+/// checksum field is zero, are offloaded. For those, the checksum field is set to the sum of the
+/// pseudo header, which is completed by the device. All other frames are left untouched and
+/// `None` is returned. See Virtio specification v1.1. - 5.1.6.2
+fn prepare_tx_csum(frame: &mut [u8]) -> Option<(u16, u16)> {
+	if frame.len() < ETH_HDR {
+		return None;
+	}
+	let ip = &frame[ETH_HDR..];
+
+	// Source and destination address, protocol and length of the transport layer
+	let (addrs, proto, ip_hdr_len, l4_len) = match u16::from_be_bytes([frame[12], frame[13]]) {
+		0x0800 => {
+			if ip.len() < 20 || ip[0] >> 4 != 4 {
+				return None;
+			}
+			// Fragments can not be checksummed by the device.
+			if u16::from_be_bytes([ip[6], ip[7]]) & 0x3fff != 0 {
+				return None;
+			}
+			let ip_hdr_len = usize::from(ip[0] & 0x0f) * 4;
+			let total_len = usize::from(u16::from_be_bytes([ip[2], ip[3]]));
+			if ip_hdr_len < 20 || total_len < ip_hdr_len || total_len > ip.len() {
+				return None;
+			}
+			(&ip[12..20], ip[9], ip_hdr_len, total_len - ip_hdr_len)
+		}
+		0x86dd => {
+			if ip.len() < 40 || ip[0] >> 4 != 6 {
+				return None;
+			}
+			let payload_len = usize::from(u16::from_be_bytes([ip[4], ip[5]]));
+			if 40 + payload_len > ip.len() {
+				return None;
+			}
+			(&ip[8..40], ip[6], 40, payload_len)
+		}
+		_ => return None,
+	};
+
+	let csum_offset: usize = match proto {
+		6 => 16,
+		17 => 6,
+		_ => return None,
+	};
+	let csum_start = ETH_HDR + ip_hdr_len;
+	let field = csum_start + csum_offset;
+	if l4_len < csum_offset + 2 || frame[field] != 0 || frame[field + 1] != 0 {
+		return None;
+	}
+
+	// Ones' complement sum of the pseudo header. See RFC 793 and RFC 8200 - 8.1
+	let mut sum: u32 = addrs
+		.chunks(2)
+		.map(|word| u32::from(u16::from_be_bytes([word[0], word[1]])))
+		.sum();
+	sum += u32::from(proto);
+	sum += u32::try_from(l4_len).ok()?;
+	while sum > 0xffff {
+		sum = (sum & 0xffff) + (sum >> 16);
+	}
+	frame[field..field + 2].copy_from_slice(&(sum as u16).to_be_bytes());
+
+	Some((
+		u16::try_from(csum_start).unwrap(),
+		u16::try_from(csum_offset).unwrap(),
+	))
+}
+
+// The tests only rely on `core` and `alloc` and are hence usable with the
+// kernel's own test runner, as well as with the standard test harness.
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use alloc::string::ToString;
+	use alloc::vec;
+
+	use super::*;
+
+	#[test]
+	fn counters_are_cleared_by_take() {
+		let counters = NetCounters::default();
+		counters.count_tx(60);
+		counters.count_tx(1514);
+		counters.count_rx(42);
+
+		let stats = counters.take();
+		assert_eq!(stats.tx_packets, 2);
+		assert_eq!(stats.tx_bytes, 1574);
+		assert_eq!(stats.rx_packets, 1);
+		assert_eq!(stats.rx_bytes, 42);
+
+		assert_eq!(counters.take(), NetStats::default());
+	}
+
+	#[test]
+	fn tx_csum_is_prepared_for_tcp_and_udp() {
+		// Ethernet, IPv4 from 10.0.0.1 to 10.0.0.2 and an UDP header with 4 bytes of payload
+		let mut frame = vec![0u8; ETH_HDR + 20 + 8 + 4];
+		frame[12..14].copy_from_slice(&[0x08, 0x00]);
+		frame[14] = 0x45;
+		frame[16..18].copy_from_slice(&32u16.to_be_bytes());
+		frame[23] = 17;
+		frame[26..30].copy_from_slice(&[10, 0, 0, 1]);
+		frame[30..34].copy_from_slice(&[10, 0, 0, 2]);
+
+		assert_eq!(prepare_tx_csum(&mut frame), Some((34, 6)));
+		// 0x0a00 + 0x0001 + 0x0a00 + 0x0002 + 17 + 12
+		assert_eq!(frame[40..42], 0x1420u16.to_be_bytes());
+
+		// Complete checksums are not touched.
+		assert_eq!(prepare_tx_csum(&mut frame), None);
+		assert_eq!(frame[40..42], 0x1420u16.to_be_bytes());
+
+		// Neither are fragments nor other protocols.
+		frame[40..42].copy_from_slice(&[0, 0]);
+		frame[20] = 0x20;
+		assert_eq!(prepare_tx_csum(&mut frame), None);
+		frame[20] = 0;
+		frame[23] = 1;
+		assert_eq!(prepare_tx_csum(&mut frame), None);
+		frame[12..14].copy_from_slice(&[0x08, 0x06]);
+		assert_eq!(prepare_tx_csum(&mut frame), None);
+	}
+
+	#[test]
+	fn net_hdr_size_depends_on_features() {
+		let legacy = FeatureSet::new(0);
+		let modern = FeatureSet::new(Features::VIRTIO_F_VERSION_1.into());
+		let mrg = FeatureSet::new(Features::VIRTIO_NET_F_MRG_RXBUF.into());
+		assert_eq!(VirtioNetHdr::hdr_size(legacy), 10);
+		assert_eq!(VirtioNetHdr::hdr_size(modern), 12);
+		assert_eq!(VirtioNetHdr::hdr_size(mrg), 12);
+
+		let buf = [1, 0, 54, 0, 0xb4, 0x05, 34, 0, 16, 0, 3, 0];
+		let hdr = VirtioNetHdr::from_bytes(&buf, mrg).unwrap();
+		assert_eq!(hdr.num_buffers(), 3);
+		assert_eq!(hdr.gso_size, 1460);
+		assert_eq!(
+			VirtioNetHdr::from_bytes(&buf, legacy)
+				.unwrap()
+				.num_buffers(),
+			1
+		);
+		assert!(VirtioNetHdr::from_bytes(&buf[..11], mrg).is_none());
+	}
+
+	#[test]
+	fn queue_stats_are_aggregated() {
+		let first = NetCounters::default();
+		first.count_tx(60);
+		first.count_tx_error();
+		let second = NetCounters::default();
+		second.count_rx(42);
+		second.count_rx_dropped();
+
+		let mut stats = first.snapshot();
+		stats += second.snapshot();
+		assert_eq!(stats.tx_packets, 1);
+		assert_eq!(stats.tx_errors, 1);
+		assert_eq!(stats.rx_bytes, 42);
+		assert_eq!(stats.rx_dropped, 1);
+
+		// Taking a snapshot does not clear the counters.
+		assert_eq!(first.snapshot().tx_bytes, 60);
+	}
+
+	#[test]
+	fn status_is_only_read_if_negotiated() {
+		let status = FeatureSet::new(Features::VIRTIO_NET_F_STATUS.into());
+		let no_status = FeatureSet::new(Features::VIRTIO_F_VERSION_1.into());
+
+		let word = status_word(no_status, || panic!("status field must not be read"));
+		assert_eq!(link_state_of(word), LinkState::Up);
+		assert_eq!(word & u16::from(Status::VIRTIO_NET_S_ANNOUNCE), 0);
+
+		assert_eq!(link_state_of(status_word(status, || 0)), LinkState::Down);
+		assert_eq!(
+			link_state_of(status_word(status, || {
+				u16::from(Status::VIRTIO_NET_S_LINK_UP)
+			})),
+			LinkState::Up
+		);
+	}
+
+	#[test]
+	fn invalid_device_macs_are_rejected() {
+		assert!(is_unicast_mac(&[0x52, 0x54, 0, 0x12, 0x34, 0x56]));
+		// Locally administered addresses, like generated ones, are fine.
+		assert!(is_unicast_mac(&[0x02, 0, 0, 0, 0, 1]));
+
+		assert!(!is_unicast_mac(&[0; 6]));
+		assert!(!is_unicast_mac(&[0xff; 6]));
+		assert!(!is_unicast_mac(&[0x01, 0x00, 0x5e, 0, 0, 1]));
+	}
+
+	#[test]
+	fn runt_frames_are_padded() {
+		let data = [0xabu8; 20];
+		// Reused send buffers contain the previous frame.
+		let mut desc = vec![0xffu8; tx_frame_len(data.len())];
+		write_padded(&mut desc, &data);
+
+		assert_eq!(desc.len(), ETH_MIN_FRAME);
+		assert_eq!(desc[..20], data);
+		assert!(desc[20..].iter().all(|byte| *byte == 0));
+		assert_eq!(data, [0xab; 20]);
+
+		assert_eq!(tx_frame_len(ETH_MIN_FRAME), ETH_MIN_FRAME);
+		assert_eq!(tx_frame_len(1514), 1514);
+	}
+
+	#[test]
+	fn zero_queue_pairs_are_clamped() {
+		assert_eq!(usable_queue_pairs(0, None), 1);
+		assert_eq!(usable_queue_pairs(0, Some(9)), 1);
+		// Even a device without any queues gets a pair, whose creation fails later on.
+		assert_eq!(usable_queue_pairs(0, Some(0)), 1);
+		assert_eq!(usable_queue_pairs(4, Some(9)), 4);
+	}
+
+	#[test]
+	fn oversized_queue_pairs_are_clamped() {
+		// Two pairs plus a control queue.
+		assert_eq!(usable_queue_pairs(3, Some(5)), 2);
+		assert_eq!(usable_queue_pairs(0x8000, Some(4)), 2);
+		assert_eq!(usable_queue_pairs(1, Some(1)), 1);
+		assert_eq!(usable_queue_pairs(u16::MAX, None), MAX_NUM_VQ / 2);
+		assert_eq!(usable_queue_pairs(u16::MAX, Some(u16::MAX)), MAX_NUM_VQ / 2);
+	}
+
+	#[test]
+	fn irq_storms_are_handled_per_queue() {
+		let mut storm = IrqStorm::default();
+
+		assert!(!storm.record(1, 0, 2, 100));
+		assert!(!storm.record(1, 1, 2, 100));
+		assert!(!storm.record(0, 1, 2, 100));
+		assert!(storm.record(1, 2, 2, 100));
+		assert!(storm.is_masked(1));
+		assert!(!storm.is_masked(0));
+		// A masked queue does not raise interrupts and is not counted.
+		assert!(!storm.record(1, 3, 2, 100));
+
+		// The cooldown passes without any further interrupt.
+		assert!(storm.expire(101).is_empty());
+		assert_eq!(storm.expire(102), vec![1]);
+		assert!(!storm.any_masked());
+		assert!(!storm.record(1, 103, 2, 100));
+	}
+
+	#[test]
+	fn queue_pair_count_is_validated() {
+		assert!(check_queue_pairs(1, 4).is_ok());
+		assert!(check_queue_pairs(4, 4).is_ok());
+		assert!(matches!(
+			check_queue_pairs(0, 4),
+			Err(VirtioNetError::InvalidQueuePairs(0, 4))
+		));
+		assert!(matches!(
+			check_queue_pairs(5, 4),
+			Err(VirtioNetError::InvalidQueuePairs(5, 4))
+		));
+	}
+
+	#[test]
+	fn spurious_interrupts_are_counted() {
+		assert!(isr_is_spurious(0));
+		assert!(isr_is_spurious(1 << 2));
+		assert!(!isr_is_spurious(ISR_QUEUE));
+		assert!(!isr_is_spurious(ISR_CFG_CHANGE));
+
+		let counters = NetCounters::default();
+		counters.count_spurious_interrupt();
+		counters.count_spurious_interrupt();
+		let mut stats = counters.snapshot();
+		stats += counters.take();
+		assert_eq!(stats.spurious_interrupts, 4);
+		assert_eq!(counters.snapshot().spurious_interrupts, 0);
+	}
+
+	#[test]
+	fn steering_prefers_rss() {
+		let mut feats = FeatureSet::new(0);
+		feats.set_features(&[Features::VIRTIO_NET_F_CTRL_VQ, Features::VIRTIO_NET_F_MQ]);
+		assert_eq!(SteeringMode::select(feats, 1), SteeringMode::Single);
+		assert_eq!(SteeringMode::select(feats, 4), SteeringMode::MultiQueue);
+
+		feats.set_features(&[Features::VIRTIO_NET_F_RSS]);
+		assert_eq!(SteeringMode::select(feats, 4), SteeringMode::Rss);
+	}
+
+	#[test]
+	fn direction_excludes_offloads_of_other_direction() {
+		assert!(!Direction::Both.excludes(Features::VIRTIO_NET_F_HOST_TSO4));
+		assert!(!Direction::Both.excludes(Features::VIRTIO_NET_F_GUEST_TSO4));
+
+		assert!(Direction::RxOnly.excludes(Features::VIRTIO_NET_F_HOST_TSO4));
+		assert!(!Direction::RxOnly.excludes(Features::VIRTIO_NET_F_GUEST_TSO4));
+		assert!(Direction::TxOnly.excludes(Features::VIRTIO_NET_F_MRG_RXBUF));
+		assert!(!Direction::TxOnly.excludes(Features::VIRTIO_NET_F_CSUM));
+
+		// Features independent of the direction are always negotiated.
+		assert!(!Direction::RxOnly.excludes(Features::VIRTIO_NET_F_MAC));
+		assert!(!Direction::TxOnly.excludes(Features::VIRTIO_F_VERSION_1));
+	}
+
+	#[test]
+	fn feature_dependencies_are_validated() {
+		let guest_csum = u64::from(Features::VIRTIO_NET_F_GUEST_CSUM);
+		let guest_tso4 = u64::from(Features::VIRTIO_NET_F_GUEST_TSO4);
+
+		assert!(FeatureSet::validate_feature_dependencies(0).is_ok());
+		assert!(FeatureSet::validate_feature_dependencies(guest_csum | guest_tso4).is_ok());
+		match FeatureSet::validate_feature_dependencies(guest_tso4) {
+			Err(VirtioNetError::IncompatibleFeatures(feat, required)) => {
+				assert_eq!(u64::from(feat), guest_tso4);
+				assert_eq!(required, guest_csum);
+			}
+			_ => panic!("GUEST_TSO4 without GUEST_CSUM must be rejected"),
+		}
+	}
+
+	#[test]
+	fn unsatisfiable_features_are_dropped() {
+		let csum = u64::from(Features::VIRTIO_NET_F_CSUM);
+		let tso4 = u64::from(Features::VIRTIO_NET_F_HOST_TSO4);
+		let ecn = u64::from(Features::VIRTIO_NET_F_HOST_ECN);
+		let mrg = u64::from(Features::VIRTIO_NET_F_MRG_RXBUF);
+
+		let missing = constants::missing_dependencies(tso4 | mrg);
+		assert_eq!(
+			missing
+				.iter()
+				.map(|feat| u64::from(*feat))
+				.collect::<Vec<_>>(),
+			[csum]
+		);
+		assert_eq!(constants::satisfiable_features(tso4 | mrg), mrg);
+		assert_eq!(constants::satisfiable_features(csum | tso4), csum | tso4);
+
+		// ECN is dropped together with TSO4, on which it depends.
+		assert_eq!(constants::satisfiable_features(tso4 | ecn | mrg), mrg);
+		assert!(constants::missing_dependencies(csum | tso4 | ecn).is_empty());
+	}
+
+	#[test]
+	fn checked_features_follow_dependencies() {
+		assert!(FeatureSet::check_features(&[
+			Features::VIRTIO_NET_F_CSUM,
+			Features::VIRTIO_NET_F_HOST_TSO6,
+			Features::VIRTIO_NET_F_HOST_ECN,
+		])
+		.is_ok());
+		assert!(matches!(
+			FeatureSet::check_features(&[
+				Features::VIRTIO_NET_F_MQ,
+				Features::VIRTIO_NET_F_MRG_RXBUF
+			]),
+			Err(VirtioNetError::FeatReqNotMet(_))
+		));
+	}
+
+	#[test]
+	fn feature_masks_match_bits() {
+		let mut all = 0u64;
+		for feat in Features::ALL {
+			let mask = u64::from(feat);
+			assert_eq!(mask, 1 << feat.bit(), "{feat:?}");
+			assert_eq!(all & mask, 0, "{feat:?} is listed twice");
+			all |= mask;
+
+			let feats = Features::from_set(FeatureSet::new(mask)).unwrap();
+			assert_eq!(feats.len(), 1);
+			assert_eq!(u64::from(feats[0]), mask);
+		}
+		assert_eq!(
+			Features::from_set(FeatureSet::new(all)).unwrap().len(),
+			Features::ALL.len()
+		);
+	}
+
+	fn assert_send<T: Send>() {}
+
+	fn assert_send_sync<T: Send + Sync>() {}
+
+	#[test]
+	fn driver_is_send() {
+		assert_send::<VirtioNetDriver>();
+		assert_send_sync::<MmioRef<NetDevCfgRaw>>();
+	}
+
+	#[test]
+	fn unknown_speed_and_duplex_are_none() {
+		assert_eq!(decode_speed(10_000), Some(10_000));
+		assert_eq!(decode_speed(0xffff_ffff), None);
+		assert_eq!(decode_duplex(0x01), Some(true));
+		assert_eq!(decode_duplex(0x00), Some(false));
+		assert_eq!(decode_duplex(0xff), None);
+	}
+
+	static RX_HANDLED: AtomicU64 = AtomicU64::new(0);
+
+	fn count_rx(packet: &[u8]) {
+		RX_HANDLED.fetch_add(packet.len() as u64, AtomicOrdering::Relaxed);
+	}
+
+	#[test]
+	fn rx_handler_gets_all_completed_packets() {
+		// Packets, whose buffers have been marked as used by the device.
+		let mut used = vec![vec![0u8; 60], vec![0u8; 1514], vec![0u8; 64]];
+
+		assert_eq!(deliver_rx(count_rx, || used.pop()), 3);
+		assert_eq!(RX_HANDLED.load(AtomicOrdering::Relaxed), 60 + 1514 + 64);
+		assert!(used.is_empty());
+	}
+
+	#[test]
+	fn only_network_devices_are_supported() {
+		// Transitional and modern network device.
+		assert!(is_supported(0x1af4, 0x1000));
+		assert!(is_supported(0x1af4, 0x1041));
+		// Block device and foreign vendor.
+		assert!(!is_supported(0x1af4, 0x1001));
+		assert!(!is_supported(0x1af4, 0x1042));
+		assert!(!is_supported(0x8086, 0x1000));
+	}
+
+	#[test]
+	fn loopback_frame_is_addressed_to_itself() {
+		let mac = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+		let frame = loopback_frame(mac);
+
+		// Minimal ethernet frame without frame check sequence.
+		assert_eq!(frame.len(), 60);
+		assert_eq!(frame[..6], mac);
+		assert_eq!(frame[6..12], mac);
+		assert_eq!(frame[12..14], constants::LOOPBACK_ETHER_TYPE.to_be_bytes());
+	}
+
+	#[test]
+	fn events_are_formatted_consistently() {
+		assert_eq!(
+			NetEvent::FeaturesNegotiated {
+				mask: 0x1_0001_0000
+			}
+			.to_string(),
+			"Features 0x100010000 have been negotiated"
+		);
+		assert_eq!(
+			NetEvent::QueuesAllocated { count: 2 }.to_string(),
+			"2 data virtqueues have been allocated"
+		);
+		assert_eq!(
+			NetEvent::InitFailed {
+				err: VirtioNetError::LinkDown
+			}
+			.to_string(),
+			"Initialization failed: Virtio network driver can not send packets, as the link is down."
+		);
+		assert!(matches!(NetEvent::from(LinkState::Up), NetEvent::LinkUp));
+	}
+
+	#[test]
+	fn rx_buffers_hold_jumbo_frames() {
+		// MTU of 9000 bytes.
+		assert_eq!(rx_data_len(false, 9000 + ETH_HDR), 9014);
+		// Buffers never shrink below the size required for the default MTU.
+		assert_eq!(rx_data_len(false, 1500 + ETH_HDR), 1514);
+		assert_eq!(rx_data_len(false, 576 + ETH_HDR), 1514);
+		// Segmentation offloads require buffers for the largest segmented packet.
+		assert_eq!(rx_data_len(true, 9000 + ETH_HDR), MAX_GSO_LEN);
+	}
+
+	#[test]
+	fn ecn_is_signalled_in_gso_type() {
+		assert_eq!(
+			gso_type_bits(NetHdrGSO::VIRTIO_NET_HDR_GSO_TCPV4, false),
+			0x01
+		);
+		assert_eq!(
+			gso_type_bits(NetHdrGSO::VIRTIO_NET_HDR_GSO_TCPV4, true),
+			0x81
+		);
+		assert_eq!(
+			gso_type_bits(NetHdrGSO::VIRTIO_NET_HDR_GSO_TCPV6, true),
+			0x84
+		);
+	}
+
+	#[test]
+	fn rss_config_is_laid_out_as_specified() {
+		let data = rss_config_bytes(
+			constants::RSS_HASH_TYPE_TCPV4 | constants::RSS_HASH_TYPE_IPV4,
+			&[0, 1, 0, 1],
+			2,
+			&[0xaa, 0xbb, 0xcc],
+		);
+		assert_eq!(
+			data,
+			vec![
+				0x03, 0, 0, 0, // hash_types
+				0x03, 0, // indirection_table_mask
+				0, 0, // unclassified_queue
+				0, 0, 1, 0, 0, 0, 1, 0, // indirection_table
+				2, 0, // max_tx_vq
+				3, 0xaa, 0xbb, 0xcc, // hash key
+			]
+		);
+	}
+
+	#[test]
+	fn default_indirection_table_covers_all_pairs() {
+		assert_eq!(default_indirection_table(2, 4), [0, 1, 0, 1]);
+		// The length is rounded down to a power of two.
+		assert_eq!(default_indirection_table(3, 6), [0, 1, 2, 0]);
+		assert_eq!(default_indirection_table(4, 512).len(), 128);
+		assert!(default_indirection_table(4, 0).is_empty());
+	}
+
+	#[test]
+	fn runt_and_oversized_frames_are_rejected() {
+		let max_len = max_rx_frame_len(false, 1500 + ETH_HDR);
+		assert_eq!(max_len, 1518);
+
+		assert!(!rx_frame_len_valid(3, max_len));
+		assert!(!rx_frame_len_valid(ETH_HDR - 1, max_len));
+		assert!(rx_frame_len_valid(ETH_HDR, max_len));
+		assert!(rx_frame_len_valid(max_len, max_len));
+		assert!(!rx_frame_len_valid(max_len + 1, max_len));
+		assert!(!rx_frame_len_valid(9000, max_len));
+
+		// Unsegmented packets may be as large as the largest GSO frame.
+		assert!(rx_frame_len_valid(
+			9000,
+			max_rx_frame_len(true, 1500 + ETH_HDR)
+		));
+	}
+
+	#[test]
+	fn long_scatter_lists_require_indirect_descriptors() {
+		// 33 fragments behind the header fit into an indirect table ...
+		assert!(33 <= tx_segment_limit(true, 256));
+		// ... but exceed the limit of direct chains.
+		assert_eq!(tx_segment_limit(false, 256), 32);
+		assert!(33 > tx_segment_limit(false, 256));
+
+		// Both are bounded by the queue size.
+		assert_eq!(tx_segment_limit(true, 16), 15);
+		assert_eq!(tx_segment_limit(false, 16), 15);
+		assert_eq!(tx_segment_limit(false, 0), 0);
+	}
+
+	#[test]
+	fn slices_are_split_at_page_boundaries() {
+		let page_size = BasePageSize::SIZE as usize;
+		let buf = vec![0u8; 3 * page_size];
+		let start = page_size - buf.as_ptr() as usize % page_size;
+		let aligned = &buf[start..start + page_size];
+
+		assert_eq!(slice_descriptors(&[]), 0);
+		assert_eq!(slice_descriptors(aligned), 1);
+		assert_eq!(slice_descriptors(&buf[start + 1..start + page_size + 1]), 2);
+	}
+
+	#[test]
+	fn registered_wakers_are_replaced() {
+		use core::task::{RawWaker, RawWakerVTable};
+
+		static CLONED: AtomicU64 = AtomicU64::new(0);
+		static DROPPED: AtomicU64 = AtomicU64::new(0);
+		static VTABLE: RawWakerVTable = RawWakerVTable::new(
+			|data| {
+				CLONED.fetch_add(1, AtomicOrdering::Relaxed);
+				RawWaker::new(data, &VTABLE)
+			},
+			|_| {},
+			|_| {},
+			|_| {
+				DROPPED.fetch_add(1, AtomicOrdering::Relaxed);
+			},
+		);
+		let waker = |task: &'static u8| unsafe {
+			Waker::from_raw(RawWaker::new(task as *const u8 as *const (), &VTABLE))
+		};
+		let first = waker(&1);
+		let second = waker(&2);
+
+		let mut slot = None;
+		register_waker(&mut slot, &first);
+		register_waker(&mut slot, &first);
+		assert_eq!(CLONED.load(AtomicOrdering::Relaxed), 1);
+		assert!(slot.as_ref().unwrap().will_wake(&first));
+
+		register_waker(&mut slot, &second);
+		assert_eq!(CLONED.load(AtomicOrdering::Relaxed), 2);
+		// The waker of the first task has been released.
+		assert_eq!(DROPPED.load(AtomicOrdering::Relaxed), 1);
+		assert!(slot.as_ref().unwrap().will_wake(&second));
+	}
+
+	#[test]
+	fn feature_names_round_trip() {
+		let mask = Features::ALL.iter().fold(0u64, |mask, feat| mask | *feat);
+		let names = constants::feature_names(mask);
+		assert_eq!(names.len(), Features::ALL.len());
+		assert!(names.contains(&"VIRTIO_F_RING_PACKED"));
+		assert!(names.contains(&"VIRTIO_F_RING_INDIRECT_DESC"));
+		assert!(names.contains(&"VIRTIO_NET_F_GUEST_UFO"));
+
+		let round_trip = names.iter().fold(0u64, |mask, name| {
+			let feat = Features::ALL
+				.iter()
+				.find(|feat| feat.name() == *name)
+				.unwrap();
+			mask | *feat
+		});
+		assert_eq!(round_trip, mask);
+
+		assert_eq!(
+			constants::feature_names(u64::from(Features::VIRTIO_NET_F_MAC) | (1 << 4) | (1 << 50)),
+			vec!["UNKNOWN(bit4)", "VIRTIO_NET_F_MAC", "UNKNOWN(bit50)"]
+		);
+	}
+
+	#[test]
+	fn refused_features_are_named() {
+		let offered = Features::VIRTIO_NET_F_MAC | Features::VIRTIO_F_VERSION_1;
+		let requested = offered | Features::VIRTIO_F_RING_PACKED;
+
+		// The device withdrew a requested feature.
+		let diff = FeatureDiff {
+			offered: requested,
+			requested,
+			reported: offered,
+		};
+		assert_eq!(diff.refused(), u64::from(Features::VIRTIO_F_RING_PACKED));
+		assert!(diff.to_string().ends_with("refused: VIRTIO_F_RING_PACKED"));
+
+		let diff = FeatureDiff {
+			offered,
+			requested: offered,
+			reported: offered,
+		};
+		assert_eq!(diff.refused(), 0);
+		assert!(diff
+			.to_string()
+			.ends_with("the combination has been refused"));
+	}
+
+	#[test]
+	fn standby_is_requested_only_when_offered() {
+		let min_feats = FeatureSet::new(u64::from(Features::VIRTIO_F_VERSION_1));
+		let wanted = [
+			Features::VIRTIO_F_VERSION_1,
+			Features::VIRTIO_NET_F_MAC,
+			Features::VIRTIO_NET_F_STANDBY,
+		];
+
+		let offered = FeatureSet::new(Features::VIRTIO_F_VERSION_1 | Features::VIRTIO_NET_F_MAC);
+		let feats = offered_features(offered, min_feats, &wanted).unwrap();
+		assert!(!feats
+			.iter()
+			.any(|feat| u64::from(*feat) == u64::from(Features::VIRTIO_NET_F_STANDBY)));
+
+		let offered = offered | FeatureSet::new(u64::from(Features::VIRTIO_NET_F_STANDBY));
+		let feats = offered_features(offered, min_feats, &wanted).unwrap();
+		assert!(feats
+			.iter()
+			.any(|feat| u64::from(*feat) == u64::from(Features::VIRTIO_NET_F_STANDBY)));
+	}
+
+	#[test]
+	fn announcement_is_gratuitous_arp_if_address_is_known() {
+		let mac = [0x52, 0x54, 0, 0x12, 0x34, 0x56];
+
+		let arp = announce_frame(mac, Some([10, 0, 5, 3]));
+		assert_eq!(arp[..6], [0xff; 6]);
+		assert_eq!(arp[6..12], mac);
+		assert_eq!(arp[12..14], [0x08, 0x06]);
+		assert_eq!(arp[ETH_HDR + 6..ETH_HDR + 8], [0, 1]);
+		assert_eq!(arp[ETH_HDR + 8..ETH_HDR + 14], mac);
+		assert_eq!(arp[ETH_HDR + 14..ETH_HDR + 18], [10, 0, 5, 3]);
+		assert_eq!(arp[ETH_HDR + 18..ETH_HDR + 24], [0; 6]);
+		assert_eq!(arp[ETH_HDR + 24..], [10, 0, 5, 3]);
+
+		let rarp = announce_frame(mac, None);
+		assert_eq!(rarp[12..14], [0x80, 0x35]);
+		assert_eq!(rarp[ETH_HDR + 6..ETH_HDR + 8], [0, 3]);
+		assert_eq!(rarp[ETH_HDR + 14..ETH_HDR + 18], [0; 4]);
+		assert_eq!(rarp[ETH_HDR + 18..ETH_HDR + 24], mac);
+	}
+
+	#[test]
+	fn hdr_uses_byte_order_of_the_device() {
+		let mut hdr = VirtioNetHdr::get_tx_hdr();
+		hdr.hdr_len = 0x1234;
+		hdr.num_buffers = 2;
+
+		let modern = FeatureSet::new(u64::from(Features::VIRTIO_F_VERSION_1));
+		let bytes = hdr.to_bytes(modern);
+		assert_eq!(bytes[2..4], [0x34, 0x12]);
+		assert_eq!(bytes[10..12], [0x02, 0x00]);
+		let parsed = VirtioNetHdr::from_bytes(&bytes, modern).unwrap();
+		assert_eq!((parsed.hdr_len, parsed.num_buffers), (0x1234, 2));
+
+		// Legacy devices use the byte order of the guest, a big-endian value is hence
+		// decoded as stored by a big-endian guest.
+		let legacy = FeatureSet::new(u64::from(Features::VIRTIO_NET_F_MRG_RXBUF));
+		let bytes = hdr.to_bytes(legacy);
+		assert_eq!(bytes[2..4], 0x1234u16.to_ne_bytes());
+		let mut swapped = bytes;
+		swapped[2..4].copy_from_slice(&0x1234u16.to_be_bytes());
+		let parsed = VirtioNetHdr::from_bytes(&swapped, legacy).unwrap();
+		assert_eq!(parsed.hdr_len, u16::from_ne_bytes(0x1234u16.to_be_bytes()));
+		assert_eq!(
+			VirtioNetHdr::from_bytes(&bytes, legacy).unwrap().hdr_len,
+			0x1234
+		);
+	}
+
+	#[test]
+	fn gso_fields_are_interpreted_if_negotiated() {
+		let mut hdr = VirtioNetHdr::get_rx_hdr();
+		hdr.gso_type = NetHdrGSO::VIRTIO_NET_HDR_GSO_TCPV4 | NetHdrGSO::VIRTIO_NET_HDR_GSO_ECN;
+		hdr.gso_size = 1448;
+
+		let meta = hdr.rx_meta(true).unwrap();
+		let gso = meta.gso.unwrap();
+		assert_eq!(gso.kind, NetHdrGSO::VIRTIO_NET_HDR_GSO_TCPV4);
+		assert_eq!(gso.segment_size, 1448);
+		assert!(gso.ecn);
+
+		// Without negotiated offloads, all frames are regarded as fully segmented.
+		assert_eq!(hdr.rx_meta(false).unwrap().gso, None);
+
+		hdr.gso_type = NetHdrGSO::VIRTIO_NET_HDR_GSO_NONE.into();
+		assert_eq!(hdr.rx_meta(true).unwrap(), RxMeta::default());
+
+		hdr.gso_type = 2;
+		assert_eq!(hdr.rx_meta(true), Err(2));
+	}
+
+	/// Tests, which drive the driver against the simulated device of the PCI transport.
+	#[cfg(feature = "pci")]
+	mod driver {
+		use super::*;
+		use crate::drivers::net::virtio_pci::MockDev;
+		use crate::drivers::virtio::env::dma::MockArena;
+
+		fn feats(feats: &[Features]) -> u64 {
+			feats
+				.iter()
+				.fold(u64::from(Features::VIRTIO_F_VERSION_1), |acc, feat| {
+					acc | u64::from(*feat)
+				})
+		}
+
+		/// Returns a driver for the simulated device, whose queues are created. The
+		/// device specific initialization is skipped, as the device cannot process
+		/// commands of the control queue.
+		fn mock_driver(features: u64, dev: MockDev) -> VirtioNetDriver {
+			let mut driver = VirtioNetDriver::mock(features, dev);
+			driver.config.allocator = MockArena::leak(8 << 20);
+			// Storm detection reads the timer, which is unavailable on the host.
+			driver.config.irq_storm_threshold = 0;
+			driver.link = driver.link_state();
+			driver.initial_link = driver.link;
+			driver.virtqueue_init().unwrap();
+			driver
+		}
+
+		/// Lets the device receive `frame` on the receive queue of `pair`.
+		fn inject_rx(driver: &VirtioNetDriver, pair: usize, frame: &[u8]) {
+			inject_rx_hdr(driver, pair, 1, frame);
+		}
+
+		/// Places `frame` behind a VirtioNetHdr, which claims that the packet spans
+		/// `num_buffers` buffers, into the next buffer of the receive queue of `pair`.
+		fn inject_rx_hdr(driver: &VirtioNetDriver, pair: usize, num_buffers: u16, frame: &[u8]) {
+			let hdr_len = VirtioNetHdr::hdr_size(driver.dev_cfg.features);
+			let mut hdr = VirtioNetHdr::get_rx_hdr();
+			hdr.num_buffers = num_buffers;
+			let mut buf = hdr.to_bytes(driver.dev_cfg.features)[..hdr_len].to_vec();
+			buf.extend_from_slice(frame);
+			driver.recv_vqs.vqs[pair].mock_use(&buf).unwrap();
+		}
+
+		/// Lets the device transmit the next packet of the transmit queue of `pair` and
+		/// returns the frame behind the VirtioNetHdr.
+		fn take_tx(driver: &VirtioNetDriver, pair: usize) -> Option<Vec<u8>> {
+			let hdr_len = VirtioNetHdr::hdr_size(driver.dev_cfg.features);
+			let mut buf = driver.send_vqs.vqs[pair].mock_use(&[])?;
+			Some(buf.split_off(hdr_len))
+		}
+
+		fn frame(len: usize, fill: u8) -> Vec<u8> {
+			let mut frame = vec![fill; len];
+			frame[..6].copy_from_slice(&[0xff; 6]);
+			frame
+		}
+
+		/// Returns the feature word of a [MockDev], which offers `feats` and VIRTIO_F_VERSION_1.
+		/// The device mirrors the word into the high 32 bits, hence each feature is offered
+		/// along with the same bit of the other half.
+		fn offer(feats: &[Features]) -> u32 {
+			let feats = feats
+				.iter()
+				.fold(u64::from(Features::VIRTIO_F_VERSION_1), |acc, feat| {
+					acc | u64::from(*feat)
+				});
+			(feats | feats >> 32) as u32
+		}
+
+		thread_local! {
+			/// Driver, whose control queue is processed by [live_clock].
+			static LIVE_DRIVER: Cell<*const VirtioNetDriver> = Cell::new(core::ptr::null());
+		}
+
+		/// Clock of a simulated device, which acknowledges the commands on the control queue
+		/// of [LIVE_DRIVER], but only once DRIVER_OK is set. Every reading advances the time
+		/// by a quarter of the timeout of commands.
+		fn live_clock() -> u64 {
+			static NOW: AtomicU64 = AtomicU64::new(0);
+
+			LIVE_DRIVER.with(|driver| {
+				// SAFETY: The driver is boxed by live_driver and leaked.
+				if let Some(driver) = unsafe { driver.get().as_ref() } {
+					let live =
+						driver.com_cfg.dev_status() & u8::from(device::Status::DRIVER_OK) != 0;
+					if let (true, Some(vq)) = (live, driver.ctrl_vq.vq.as_ref()) {
+						while vq.mock_use(&[VIRTIO_NET_OK]).is_some() {}
+					}
+				}
+			});
+			NOW.fetch_add(CTRL_CMD_TIMEOUT / 4, AtomicOrdering::Relaxed)
+		}
+
+		/// Returns an uninitialized driver for `dev`, whose control queue is processed by
+		/// [live_clock] of the current thread.
+		fn live_driver(dev: MockDev) -> &'static mut VirtioNetDriver {
+			let driver = Box::leak(Box::new(VirtioNetDriver::mock(feats(&[]), dev)));
+			driver.config.allocator = MockArena::leak(8 << 20);
+			driver.config.irq_storm_threshold = 0;
+			driver.config.force_split = true;
+			driver.config.clock = live_clock;
+			LIVE_DRIVER.with(|live| live.set(&*driver));
+			driver
+		}
+
+		#[test]
+		fn packets_are_exchanged_with_the_device() {
+			let mut driver = mock_driver(feats(&[]), MockDev::with_pairs(1));
+
+			let sent = frame(100, 0x11);
+			driver.send_packet(&sent, None).unwrap();
+			assert_eq!(take_tx(&driver, 0).unwrap(), sent);
+
+			let received = frame(80, 0x22);
+			inject_rx(&driver, 0, &received);
+			assert_eq!(&*driver.receive_packet(None).unwrap(), &received[..]);
+			assert!(driver.receive_packet(None).is_none());
+		}
+
+		#[test]
+		fn timed_out_borrowed_send_recovers_the_device() {
+			static NOW: AtomicU64 = AtomicU64::new(0);
+			// Every reading of the clock passes the timeout of borrowed transfers.
+			fn clock() -> u64 {
+				NOW.fetch_add(TX_BORROWED_TIMEOUT, AtomicOrdering::Relaxed)
+			}
+
+			// The device does not offer a control queue, so the initialization does not
+			// wait for it.
+			let dev = MockDev {
+				features: offer(&[Features::VIRTIO_NET_F_MAC, Features::VIRTIO_NET_F_STATUS]),
+				..MockDev::with_pairs(1)
+			};
+			let mut driver = VirtioNetDriver::mock(feats(&[]), dev);
+			driver.config.allocator = MockArena::leak(8 << 20);
+			driver.config.irq_storm_threshold = 0;
+			driver.config.force_split = true;
+			driver.config.clock = clock;
+			driver.init_dev().unwrap();
+
+			// The device does not consume the packet.
+			let packet = frame(60, 0x33);
+			assert!(matches!(
+				driver.send_iov(&[&packet]),
+				Err(VirtioNetError::Timeout)
+			));
+
+			let sent = frame(100, 0x11);
+			driver.send_packet(&sent, None).unwrap();
+			assert_eq!(take_tx(&driver, 0).unwrap(), sent);
+
+			let received = frame(80, 0x22);
+			inject_rx(&driver, 0, &received);
+			assert_eq!(&*driver.receive_packet(None).unwrap(), &received[..]);
+		}
+
+		#[test]
+		fn lent_receive_buffers_return_to_the_device() {
+			let mut driver = mock_driver(feats(&[]), MockDev::with_pairs(1));
+
+			let received = frame(80, 0x22);
+			inject_rx(&driver, 0, &received);
+			let packet = driver.receive_rx_buffer().unwrap();
+			assert!(packet.is_lent());
+			assert_eq!(&*packet, &received[..]);
+			assert_eq!(driver.rx_pool_usage().in_use, 1);
+
+			drop(packet);
+			assert!(driver.receive_rx_buffer().is_err());
+			assert!(driver.recv_vqs.lent.is_empty());
+			assert_eq!(driver.rx_pool_usage().in_use, 0);
+
+			// Without returning the buffers, the device would run out of them.
+			let size = usize::from(u16::from(driver.recv_vqs.vqs[0].size()));
+			for i in 0..2 * size {
+				let received = frame(60, i as u8);
+				inject_rx(&driver, 0, &received);
+				assert_eq!(&*driver.receive_rx_buffer().unwrap(), &received[..]);
+			}
+		}
+
+		#[test]
+		fn overlong_buffer_chains_are_dropped() {
+			let mut driver = mock_driver(
+				feats(&[Features::VIRTIO_NET_F_MRG_RXBUF]),
+				MockDev::with_pairs(1),
+			);
+			let max_chain = driver.max_rx_chain();
+
+			inject_rx_hdr(&driver, 0, max_chain + 1, &frame(60, 0x77));
+			for _ in 0..max_chain {
+				driver.recv_vqs.vqs[0].mock_use(&[0x88; 60]).unwrap();
 			}
+			inject_rx(&driver, 0, &frame(60, 0x99));
+
+			assert!(driver.receive_packet(None).is_none());
+			assert_eq!(driver.stats().rx_errors, 1);
+			// The remaining buffers of the packet are not mistaken for a packet of their own.
+			assert_eq!(&*driver.receive_packet(None).unwrap(), &frame(60, 0x99)[..]);
+			assert!(driver.receive_packet(None).is_none());
 		}
-	}
 
-	impl BitOr for Features {
-		type Output = u64;
+		#[test]
+		fn unknown_gso_types_drop_all_buffers() {
+			let mut driver = mock_driver(
+				feats(&[
+					Features::VIRTIO_NET_F_MRG_RXBUF,
+					Features::VIRTIO_NET_F_GUEST_CSUM,
+					Features::VIRTIO_NET_F_GUEST_TSO4,
+				]),
+				MockDev::with_pairs(1),
+			);
 
-		fn bitor(self, rhs: Self) -> Self::Output {
-			u64::from(self) | u64::from(rhs)
+			let features = driver.dev_cfg.features;
+			let mut hdr = VirtioNetHdr::get_rx_hdr();
+			hdr.num_buffers = 2;
+			hdr.gso_type = 0x02;
+			let mut buf = hdr.to_bytes(features)[..VirtioNetHdr::hdr_size(features)].to_vec();
+			buf.extend_from_slice(&frame(60, 0x77));
+			driver.recv_vqs.vqs[0].mock_use(&buf).unwrap();
+			driver.recv_vqs.vqs[0].mock_use(&[0x88; 60]).unwrap();
+			inject_rx(&driver, 0, &frame(60, 0x99));
+
+			assert!(driver.receive_packet(None).is_none());
+			assert_eq!(driver.stats().rx_errors, 1);
+			assert_eq!(&*driver.receive_packet(None).unwrap(), &frame(60, 0x99)[..]);
 		}
-	}
 
-	impl BitOr<Features> for u64 {
-		type Output = u64;
+		#[test]
+		fn shed_packets_return_their_buffers() {
+			let mut driver = mock_driver(feats(&[]), MockDev::with_pairs(1));
+			driver.config.manual_refill = true;
+			let size = u16::from(driver.recv_vqs.vqs[0].size());
 
-		fn bitor(self, rhs: Features) -> Self::Output {
-			self | u64::from(rhs)
+			driver.set_rx_drop(true);
+			for _ in 0..size {
+				inject_rx(&driver, 0, &frame(60, 0x55));
+			}
+			assert!(driver.receive_packet(None).is_none());
+			assert_eq!(driver.stats().rx_shed, u64::from(size));
+			assert_eq!(driver.parked_rx_buffers(), 0);
+
+			// All buffers are available to the device again.
+			driver.set_rx_drop(false);
+			for _ in 0..size {
+				inject_rx(&driver, 0, &frame(60, 0x66));
+			}
+			for _ in 0..size {
+				assert_eq!(&*driver.receive_packet(None).unwrap(), &frame(60, 0x66)[..]);
+			}
 		}
-	}
 
-	impl BitOrAssign<Features> for u64 {
-		fn bitor_assign(&mut self, rhs: Features) {
-			*self |= u64::from(rhs);
+		#[test]
+		fn malformed_frames_are_dropped_and_counted() {
+			let mut driver = mock_driver(
+				feats(&[Features::VIRTIO_NET_F_MRG_RXBUF]),
+				MockDev::with_pairs(1),
+			);
+			let max_len = RxQueues::max_frame_len(&driver.dev_cfg);
+
+			inject_rx(&driver, 0, &[0xff; 3]);
+			assert!(driver.receive_packet(None).is_none());
+			assert_eq!(driver.stats().rx_errors, 1);
+
+			// The frame spans two buffers, which exceed the maximal length together.
+			inject_rx_hdr(&driver, 0, 2, &frame(max_len, 0x77));
+			driver.recv_vqs.vqs[0].mock_use(&[0x88; 60]).unwrap();
+			assert!(driver.receive_packet(None).is_none());
+			assert_eq!(driver.stats().rx_errors, 2);
+			assert_eq!(driver.stats().rx_packets, 0);
+
+			// The buffers of the dropped frames have been posted again.
+			let size = u16::from(driver.recv_vqs.vqs[0].size());
+			for _ in 0..size {
+				inject_rx(&driver, 0, &frame(60, 0x66));
+			}
+			for _ in 0..size {
+				assert_eq!(&*driver.receive_packet(None).unwrap(), &frame(60, 0x66)[..]);
+			}
+			assert_eq!(driver.stats().rx_packets, u64::from(size));
+			assert_eq!(driver.stats().rx_errors, 2);
 		}
-	}
 
-	impl BitAnd for Features {
-		type Output = u64;
+		static HANDLED_FRAMES: AtomicU64 = AtomicU64::new(0);
 
-		fn bitand(self, rhs: Features) -> Self::Output {
-			u64::from(self) & u64::from(rhs)
+		fn count_frame(frame: &[u8]) {
+			assert_eq!(frame, &frame_of_handler()[..]);
+			HANDLED_FRAMES.fetch_add(1, AtomicOrdering::Relaxed);
 		}
-	}
 
-	impl BitAnd<Features> for u64 {
-		type Output = u64;
+		fn frame_of_handler() -> Vec<u8> {
+			frame(60, 0x5a)
+		}
 
-		fn bitand(self, rhs: Features) -> Self::Output {
-			self & u64::from(rhs)
+		#[test]
+		fn queue_interrupts_deliver_packets_to_the_handler() {
+			let mut driver = mock_driver(feats(&[]), MockDev::with_pairs(1));
+			driver.set_rx_handler(Some(count_frame));
+
+			inject_rx(&driver, 0, &frame_of_handler());
+			inject_rx(&driver, 0, &frame_of_handler());
+			assert!(!driver.handle_queue_interrupt());
+			assert_eq!(HANDLED_FRAMES.load(AtomicOrdering::Relaxed), 2);
+			assert!(driver.receive_packet(None).is_none());
+
+			// Without a handler, packets are kept for receive_packet.
+			driver.set_rx_handler(None);
+			inject_rx(&driver, 0, &frame(60, 0x33));
+			assert!(driver.handle_queue_interrupt());
+			assert_eq!(HANDLED_FRAMES.load(AtomicOrdering::Relaxed), 2);
+			assert_eq!(&*driver.receive_packet(None).unwrap(), &frame(60, 0x33)[..]);
 		}
-	}
 
-	impl BitAndAssign<Features> for u64 {
-		fn bitand_assign(&mut self, rhs: Features) {
-			*self &= u64::from(rhs);
+		#[test]
+		fn queue_pairs_require_control_queue() {
+			let dev = MockDev {
+				num_queues: 4,
+				..MockDev::with_pairs(2)
+			};
+			let mut driver = mock_driver(feats(&[Features::VIRTIO_NET_F_MQ]), dev);
+			assert_eq!(driver.num_queues(), 2);
+
+			assert!(matches!(
+				driver.set_active_queue_pairs(1),
+				Err(VirtioNetError::NoCtrlQueue)
+			));
+			assert_eq!(driver.active_queue_pairs(), 2);
+
+			let sent = frame(60, 0x33);
+			driver.send_packet(&sent, Some(1)).unwrap();
+			assert_eq!(take_tx(&driver, 1).unwrap(), sent);
+			assert!(matches!(
+				driver.send_packet(&sent, Some(2)),
+				Err(VirtioNetError::InvalidQueue(2))
+			));
 		}
-	}
 
-	impl core::fmt::Display for Features {
-		fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-			match *self {
-				Features::VIRTIO_NET_F_CSUM => write!(f, "VIRTIO_NET_F_CSUM"),
-				Features::VIRTIO_NET_F_GUEST_CSUM => write!(f, "VIRTIO_NET_F_GUEST_CSUM"),
-				Features::VIRTIO_NET_F_CTRL_GUEST_OFFLOADS => {
-					write!(f, "VIRTIO_NET_F_CTRL_GUEST_OFFLOADS")
-				}
-				Features::VIRTIO_NET_F_MTU => write!(f, "VIRTIO_NET_F_MTU"),
-				Features::VIRTIO_NET_F_MAC => write!(f, "VIRTIO_NET_F_MAC"),
-				Features::VIRTIO_NET_F_GUEST_TSO4 => write!(f, "VIRTIO_NET_F_GUEST_TSO4"),
-				Features::VIRTIO_NET_F_GUEST_TSO6 => write!(f, "VIRTIO_NET_F_GUEST_TSO6"),
-				Features::VIRTIO_NET_F_GUEST_ECN => write!(f, "VIRTIO_NET_F_GUEST_ECN"),
-				Features::VIRTIO_NET_F_GUEST_UFO => write!(f, "VIRTIO_NET_FGUEST_UFO"),
-				Features::VIRTIO_NET_F_HOST_TSO4 => write!(f, "VIRTIO_NET_F_HOST_TSO4"),
-				Features::VIRTIO_NET_F_HOST_TSO6 => write!(f, "VIRTIO_NET_F_HOST_TSO6"),
-				Features::VIRTIO_NET_F_HOST_ECN => write!(f, "VIRTIO_NET_F_HOST_ECN"),
-				Features::VIRTIO_NET_F_HOST_UFO => write!(f, "VIRTIO_NET_F_HOST_UFO"),
-				Features::VIRTIO_NET_F_MRG_RXBUF => write!(f, "VIRTIO_NET_F_MRG_RXBUF"),
-				Features::VIRTIO_NET_F_STATUS => write!(f, "VIRTIO_NET_F_STATUS"),
-				Features::VIRTIO_NET_F_CTRL_VQ => write!(f, "VIRTIO_NET_F_CTRL_VQ"),
-				Features::VIRTIO_NET_F_CTRL_RX => write!(f, "VIRTIO_NET_F_CTRL_RX"),
-				Features::VIRTIO_NET_F_CTRL_VLAN => write!(f, "VIRTIO_NET_F_CTRL_VLAN"),
-				Features::VIRTIO_NET_F_GUEST_ANNOUNCE => write!(f, "VIRTIO_NET_F_GUEST_ANNOUNCE"),
-				Features::VIRTIO_NET_F_MQ => write!(f, "VIRTIO_NET_F_MQ"),
-				Features::VIRTIO_NET_F_CTRL_MAC_ADDR => write!(f, "VIRTIO_NET_F_CTRL_MAC_ADDR"),
-				Features::VIRTIO_F_RING_INDIRECT_DESC => write!(f, "VIRTIO_F_RING_INDIRECT_DESC"),
-				Features::VIRTIO_F_RING_EVENT_IDX => write!(f, "VIRTIO_F_RING_EVENT_IDX"),
-				Features::VIRTIO_F_VERSION_1 => write!(f, "VIRTIO_F_VERSION_1"),
-				Features::VIRTIO_F_ACCESS_PLATFORM => write!(f, "VIRTIO_F_ACCESS_PLATFORM"),
-				Features::VIRTIO_F_RING_PACKED => write!(f, "VIRTIO_F_RING_PACKED"),
-				Features::VIRTIO_F_IN_ORDER => write!(f, "VIRTIO_F_IN_ORDER"),
-				Features::VIRTIO_F_ORDER_PLATFORM => write!(f, "VIRTIO_F_ORDER_PLATFORM"),
-				Features::VIRTIO_F_SR_IOV => write!(f, "VIRTIO_F_SR_IOV"),
-				Features::VIRTIO_F_NOTIFICATION_DATA => write!(f, "VIRTIO_F_NOTIFICATION_DATA"),
-				Features::VIRTIO_NET_F_GUEST_HDRLEN => write!(f, "VIRTIO_NET_F_GUEST_HDRLEN"),
-				Features::VIRTIO_NET_F_RSC_EXT => write!(f, "VIRTIO_NET_F_RSC_EXT"),
-				Features::VIRTIO_NET_F_STANDBY => write!(f, "VIRTIO_NET_F_STANDBY"),
-			}
+		#[test]
+		fn link_status_is_only_read_if_negotiated() {
+			let dev = || MockDev {
+				status: 0,
+				..MockDev::with_pairs(1)
+			};
+
+			let mut driver = mock_driver(feats(&[]), dev());
+			assert_eq!(driver.link_state(), LinkState::Up);
+			assert!(driver.is_link_up());
+			driver.send_packet(&frame(60, 0), None).unwrap();
+
+			let mut driver = mock_driver(feats(&[Features::VIRTIO_NET_F_STATUS]), dev());
+			assert_eq!(driver.link_state(), LinkState::Down);
+			assert!(matches!(
+				driver.send_packet(&frame(60, 0), None),
+				Err(VirtioNetError::LinkDown)
+			));
+			assert!(take_tx(&driver, 0).is_none());
 		}
-	}
 
-	impl Features {
-		/// Return a vector of [Features](Features) for a given input of a u64 representation.
-		///
-		/// INFO: In case the FEATURES enum is changed, this function MUST also be adjusted to the new set!
-		//
-		// Really UGLY function, but currently the most convenienvt one to reduce the set of features for the driver easily!
-		pub fn from_set(feat_set: FeatureSet) -> Option<Vec<Features>> {
-			let mut vec_of_feats: Vec<Features> = Vec::new();
-			let feats = feat_set.0;
+		#[test]
+		fn send_handles_complete_without_notifying_sends() {
+			let mut driver = mock_driver(feats(&[]), MockDev::with_pairs(1));
+			let mut buf = driver.alloc_dma_buffer(60).unwrap();
+			buf.as_mut_slice().copy_from_slice(&frame(60, 0x21));
+			let handle = driver.send_buffer(buf).unwrap();
+			assert!(!handle.is_complete());
+
+			assert_eq!(take_tx(&driver, 0).unwrap(), frame(60, 0x21));
+			assert_eq!(driver.process_tx_completions(), 0);
+			assert!(handle.is_complete());
+			assert!(handle.into_buffer().is_ok());
+		}
 
-			if feats & (1 << 0) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_CSUM)
-			}
-			if feats & (1 << 1) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_GUEST_CSUM)
-			}
-			if feats & (1 << 2) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_CTRL_GUEST_OFFLOADS)
-			}
-			if feats & (1 << 3) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_MTU)
-			}
-			if feats & (1 << 5) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_MAC)
-			}
-			if feats & (1 << 7) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_GUEST_TSO4)
-			}
-			if feats & (1 << 8) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_GUEST_TSO6)
-			}
-			if feats & (1 << 9) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_GUEST_ECN)
-			}
-			if feats & (1 << 10) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_GUEST_UFO)
-			}
-			if feats & (1 << 11) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_HOST_TSO4)
-			}
-			if feats & (1 << 12) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_HOST_TSO6)
-			}
-			if feats & (1 << 13) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_HOST_ECN)
-			}
-			if feats & (1 << 14) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_HOST_UFO)
-			}
-			if feats & (1 << 15) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_MRG_RXBUF)
-			}
-			if feats & (1 << 16) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_STATUS)
-			}
-			if feats & (1 << 17) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_CTRL_VQ)
-			}
-			if feats & (1 << 18) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_CTRL_RX)
-			}
-			if feats & (1 << 19) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_CTRL_VLAN)
-			}
-			if feats & (1 << 21) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_GUEST_ANNOUNCE)
-			}
-			if feats & (1 << 22) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_MQ)
-			}
-			if feats & (1 << 23) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_CTRL_MAC_ADDR)
-			}
-			if feats & (1 << 28) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_RING_INDIRECT_DESC)
-			}
-			if feats & (1 << 29) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_RING_EVENT_IDX)
-			}
-			if feats & (1 << 32) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_VERSION_1)
-			}
-			if feats & (1 << 33) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_ACCESS_PLATFORM)
-			}
-			if feats & (1 << 34) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_RING_PACKED)
-			}
-			if feats & (1 << 35) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_IN_ORDER)
-			}
-			if feats & (1 << 36) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_ORDER_PLATFORM)
-			}
-			if feats & (1 << 37) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_SR_IOV)
-			}
-			if feats & (1 << 38) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_NOTIFICATION_DATA)
-			}
-			if feats & (1 << 59) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_GUEST_HDRLEN)
-			}
-			if feats & (1 << 61) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_RSC_EXT)
-			}
-			if feats & (1 << 62) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_STANDBY)
+		#[test]
+		fn runt_frames_are_padded_in_the_descriptor() {
+			let mut driver = mock_driver(feats(&[]), MockDev::with_pairs(1));
+			// Fill the buffer, which is used next, in order to detect a missing padding.
+			let tkn = driver.send_vqs.ready_queue.last_mut().unwrap();
+			let (send_ptrs, _) = tkn.raw_ptrs();
+			for (ptr, len) in send_ptrs.unwrap().iter() {
+				unsafe { ptr.write_bytes(0xee, *len) };
 			}
 
-			if vec_of_feats.is_empty() {
-				None
-			} else {
-				Some(vec_of_feats)
-			}
-		}
-	}
+			let sent = frame(20, 0x44);
+			driver.send_packet(&sent, None).unwrap();
+			assert_eq!(sent, frame(20, 0x44));
 
-	/// Enum contains virtio's network device status
-	/// indiacted in the status field of the device's
-	/// configuration structure.
-	///
-	/// See Virtio specification v1.1. - 5.1.4
-	#[allow(dead_code, non_camel_case_types)]
-	#[derive(Copy, Clone, Debug)]
-	#[repr(u16)]
-	pub enum Status {
-		VIRTIO_NET_S_LINK_UP = 1 << 0,
-		VIRTIO_NET_S_ANNOUNCE = 1 << 1,
-	}
+			let transmitted = take_tx(&driver, 0).unwrap();
+			assert_eq!(transmitted.len(), ETH_MIN_FRAME);
+			assert_eq!(transmitted[..20], sent[..]);
+			assert!(transmitted[20..].iter().all(|byte| *byte == 0));
+		}
 
-	impl From<Status> for u16 {
-		fn from(stat: Status) -> Self {
-			match stat {
-				Status::VIRTIO_NET_S_LINK_UP => 1,
-				Status::VIRTIO_NET_S_ANNOUNCE => 2,
+		#[test]
+		fn runt_frames_of_the_network_stack_are_padded() {
+			let mut driver = mock_driver(feats(&[]), MockDev::with_pairs(1));
+			let tkn = driver.send_vqs.ready_queue.last_mut().unwrap();
+			let (send_ptrs, _) = tkn.raw_ptrs();
+			for (ptr, len) in send_ptrs.unwrap().iter() {
+				unsafe { ptr.write_bytes(0xee, *len) };
 			}
+
+			let sent = frame(20, 0x44);
+			let (buf, handle) = driver.get_tx_buffer(sent.len()).unwrap();
+			unsafe { core::slice::from_raw_parts_mut(buf, sent.len()) }.copy_from_slice(&sent);
+			driver.send_tx_buffer(handle, sent.len()).unwrap();
+
+			let transmitted = take_tx(&driver, 0).unwrap();
+			assert_eq!(transmitted.len(), ETH_MIN_FRAME);
+			assert_eq!(transmitted[..20], sent[..]);
+			assert!(transmitted[20..].iter().all(|byte| *byte == 0));
+
+			// A frame, which is shorter than announced, is padded as well.
+			let (buf, handle) = driver.get_tx_buffer(100).unwrap();
+			unsafe { core::slice::from_raw_parts_mut(buf, 100) }.fill(0x55);
+			driver.send_tx_buffer(handle, 30).unwrap();
+
+			let transmitted = take_tx(&driver, 0).unwrap();
+			assert_eq!(transmitted.len(), ETH_MIN_FRAME);
+			assert!(transmitted[..30].iter().all(|byte| *byte == 0x55));
+			assert!(transmitted[30..].iter().all(|byte| *byte == 0));
 		}
-	}
 
-	/// FeatureSet is new type whicih holds features for virito network devices indicated by the virtio specification
-	/// v1.1. - 5.1.3. and all General Features defined in Virtio specification v1.1. - 6
-	/// wrapping a u64.
-	///
-	/// The main functionality of this type are functions implemented on it.
-	#[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Eq)]
-	pub struct FeatureSet(u64);
+		#[test]
+		fn invalid_device_macs_are_not_used() {
+			let mac = MockDev::with_pairs(1).mac;
+			let with_mac = feats(&[Features::VIRTIO_NET_F_MAC]);
 
-	impl BitOr for FeatureSet {
-		type Output = FeatureSet;
+			let driver = mock_driver(with_mac, MockDev::with_pairs(1));
+			assert_eq!(driver.device_mac(), Some(mac));
+			assert_eq!(driver.get_mac_address(), mac);
 
-		fn bitor(self, rhs: Self) -> Self::Output {
-			FeatureSet(self.0 | rhs.0)
-		}
-	}
+			let mut driver = mock_driver(with_mac, MockDev::with_pairs(1));
+			driver.dev_cfg.legacy = true;
+			assert_eq!(driver.device_mac(), Some(mac));
 
-	impl BitOr<FeatureSet> for u64 {
-		type Output = u64;
+			let driver = mock_driver(feats(&[]), MockDev::with_pairs(1));
+			assert_eq!(driver.device_mac(), None);
 
-		fn bitor(self, rhs: FeatureSet) -> Self::Output {
-			self | u64::from(rhs)
+			for invalid in [[0xff; 6], [0; 6], [0x01, 0, 0x5e, 0, 0, 1]] {
+				let dev = MockDev {
+					mac: invalid,
+					..MockDev::with_pairs(1)
+				};
+				let driver = mock_driver(with_mac, dev);
+				assert_eq!(driver.device_mac(), None);
+				assert_eq!(driver.get_mac_address(), driver.generated_mac);
+			}
 		}
-	}
 
-	impl BitOrAssign<FeatureSet> for u64 {
-		fn bitor_assign(&mut self, rhs: FeatureSet) {
-			*self |= u64::from(rhs);
+		#[test]
+		fn segmentation_rejects_invalid_header_length() {
+			let mut driver = mock_driver(
+				feats(&[Features::VIRTIO_NET_F_HOST_TSO4]),
+				MockDev::with_pairs(1),
+			);
+			let mut data = frame(4000, 0);
+			data[12..14].copy_from_slice(&[0x08, 0x00]);
+			data[ETH_HDR] = 0x45;
+
+			for hdr_len in [4, 4001] {
+				assert!(matches!(
+					driver.send_segmented(&data, hdr_len, 1000, false),
+					Err(VirtioNetError::InvalidHdrLen(len)) if len == hdr_len
+				));
+			}
+			assert!(take_tx(&driver, 0).is_none());
 		}
-	}
 
-	impl BitOrAssign<Features> for FeatureSet {
-		fn bitor_assign(&mut self, rhs: Features) {
-			self.0 = self.0 | u64::from(rhs);
+		#[test]
+		fn announcement_requests_are_answered() {
+			let dev = MockDev {
+				// VIRTIO_NET_S_LINK_UP | VIRTIO_NET_S_ANNOUNCE
+				status: 3,
+				..MockDev::with_pairs(1)
+			};
+			let mut driver = mock_driver(
+				feats(&[
+					Features::VIRTIO_NET_F_STATUS,
+					Features::VIRTIO_NET_F_GUEST_ANNOUNCE,
+				]),
+				dev,
+			);
+			driver.set_ipv4_addr([10, 0, 5, 3]);
+
+			driver.check_announce();
+			let frame = take_tx(&driver, 0).unwrap();
+			assert_eq!(frame.len(), ETH_MIN_FRAME);
+			assert_eq!(
+				frame[..ETH_HDR + 28],
+				announce_frame(driver.get_mac_address(), Some([10, 0, 5, 3]))
+			);
 		}
-	}
 
-	impl BitAnd for FeatureSet {
-		type Output = FeatureSet;
+		#[cfg(feature = "tcp")]
+		#[test]
+		fn smoltcp_tokens_follow_the_transmit_queue() {
+			use smoltcp::phy::{Checksum, Device};
+			use smoltcp::time::Instant;
 
-		fn bitand(self, rhs: FeatureSet) -> Self::Output {
-			FeatureSet(self.0 & rhs.0)
+			use crate::drivers::net::virtio_phy::VirtioNetDevice;
+
+			let mut driver = mock_driver(
+				feats(&[Features::VIRTIO_NET_F_GUEST_CSUM]),
+				MockDev::with_pairs(1),
+			);
+			let cap = VirtioNetDevice::new(&mut driver).capabilities();
+			assert!(matches!(cap.checksum.tcp, Checksum::Tx));
+
+			let size: u16 = driver.send_vqs.vqs[0].size().into();
+			for _ in 0..size {
+				driver.send_packet(&frame(60, 0), None).unwrap();
+			}
+			assert!(VirtioNetDevice::new(&mut driver)
+				.transmit(Instant::ZERO)
+				.is_none());
+
+			take_tx(&driver, 0).unwrap();
+			assert!(VirtioNetDevice::new(&mut driver)
+				.transmit(Instant::ZERO)
+				.is_some());
+
+			let mut driver = mock_driver(
+				feats(&[Features::VIRTIO_NET_F_CSUM]),
+				MockDev::with_pairs(1),
+			);
+			let cap = VirtioNetDevice::new(&mut driver).capabilities();
+			assert!(matches!(cap.checksum.udp, Checksum::Rx));
 		}
-	}
 
-	impl BitAnd<FeatureSet> for u64 {
-		type Output = u64;
+		#[cfg(feature = "tcp")]
+		#[test]
+		fn smoltcp_packets_are_written_into_send_buffers() {
+			use smoltcp::phy::{Device, TxToken};
+			use smoltcp::time::Instant;
+
+			use crate::drivers::net::virtio_phy::VirtioNetDevice;
+
+			let mut driver = mock_driver(feats(&[]), MockDev::with_pairs(1));
+			let sent = frame(60, 0x66);
+			VirtioNetDevice::new(&mut driver)
+				.transmit(Instant::ZERO)
+				.unwrap()
+				.consume(sent.len(), |buf| buf.copy_from_slice(&sent));
+			assert_eq!(take_tx(&driver, 0).unwrap(), sent);
+
+			// A buffer, which can not be sent, returns its descriptor to the queue.
+			let dev = MockDev {
+				status: 0,
+				..MockDev::with_pairs(1)
+			};
+			let mut driver = mock_driver(feats(&[Features::VIRTIO_NET_F_STATUS]), dev);
+			let free_ids = driver.send_vqs.vqs[0].debug_state().free_ids;
+			VirtioNetDevice::new(&mut driver)
+				.transmit(Instant::ZERO)
+				.unwrap()
+				.consume(60, |buf| buf.fill(0x66));
+			assert_eq!(driver.send_vqs.vqs[0].debug_state().free_ids, free_ids + 1);
+			assert!(take_tx(&driver, 0).is_none());
+		}
 
-		fn bitand(self, rhs: FeatureSet) -> Self::Output {
-			self & u64::from(rhs)
+		#[test]
+		fn queue_affinity_requires_msix() {
+			let mut driver = mock_driver(
+				feats(&[Features::VIRTIO_NET_F_MQ, Features::VIRTIO_NET_F_CTRL_VQ]),
+				MockDev::with_pairs(2),
+			);
+
+			assert!(matches!(
+				driver.set_queue_affinity(2, 0),
+				Err(VirtioNetError::InvalidQueue(2))
+			));
+			assert!(matches!(
+				driver.set_queue_affinity(0, 0),
+				Err(VirtioNetError::MsixUnavailable(_))
+			));
+			assert_eq!(driver.queue_affinity(0), None);
 		}
-	}
 
-	impl BitAndAssign<FeatureSet> for u64 {
-		fn bitand_assign(&mut self, rhs: FeatureSet) {
-			*self &= u64::from(rhs);
+		#[test]
+		fn fragmented_packets_are_bounded() {
+			// The queues hold more descriptors than a direct chain may use.
+			let dev = MockDev {
+				queue_size: 64,
+				..MockDev::with_pairs(1)
+			};
+			let mut driver = mock_driver(feats(&[]), dev);
+			let max_segments = driver.max_tx_segments();
+			assert_eq!(max_segments, MAX_DIRECT_TX_CHAIN - 1);
+
+			let packet = frame(usize::from(max_segments + 1) * 2, 0x44);
+			let frags: Vec<&[u8]> = packet.chunks(2).collect();
+
+			assert!(matches!(
+				driver.send_packet_sg(&frags, None),
+				Err(VirtioNetError::TooManySegments(33, 32))
+			));
+			assert_eq!(driver.stats().tx_errors, 1);
+			assert!(take_tx(&driver, 0).is_none());
+
+			// The fragments are sent, once they fit into the chain.
+			let frags = &frags[..usize::from(max_segments)];
+			driver.send_packet_sg(frags, None).unwrap();
+			assert_eq!(take_tx(&driver, 0).unwrap(), frags.concat());
 		}
-	}
 
-	impl From<FeatureSet> for u64 {
-		fn from(feature_set: FeatureSet) -> Self {
-			feature_set.0
+		#[test]
+		fn guest_ecn_requires_negotiation_and_tso() {
+			let mut driver = mock_driver(
+				feats(&[
+					Features::VIRTIO_NET_F_CTRL_GUEST_OFFLOADS,
+					Features::VIRTIO_NET_F_GUEST_CSUM,
+					Features::VIRTIO_NET_F_GUEST_TSO4,
+				]),
+				MockDev::with_pairs(1),
+			);
+
+			assert!(matches!(
+				driver.set_guest_offloads(true, true, false, true, false),
+				Err(VirtioNetError::FeatureNotNegotiated(
+					Features::VIRTIO_NET_F_GUEST_ECN
+				))
+			));
+			// Disabling ECN passes the validation and fails only for the missing queue.
+			assert!(matches!(
+				driver.set_guest_offloads(true, true, false, false, false),
+				Err(VirtioNetError::NoCtrlQueue)
+			));
+
+			let mut driver = mock_driver(
+				feats(&[
+					Features::VIRTIO_NET_F_CTRL_GUEST_OFFLOADS,
+					Features::VIRTIO_NET_F_GUEST_CSUM,
+					Features::VIRTIO_NET_F_GUEST_TSO4,
+					Features::VIRTIO_NET_F_GUEST_ECN,
+				]),
+				MockDev::with_pairs(1),
+			);
+			assert!(matches!(
+				driver.set_guest_offloads(true, false, false, true, false),
+				Err(VirtioNetError::IncompatibleFeatures(
+					Features::VIRTIO_NET_F_GUEST_ECN,
+					_
+				))
+			));
 		}
-	}
 
-	impl FeatureSet {
-		/// Checks if a given set of features is compatible and adheres to the
-		/// specfification v1.1. - 5.1.3.1
-		/// Upon an error returns the incompatible set of features by the
-		/// [FeatReqNotMet](super::error::VirtioNetError) error value, which
-		/// wraps the u64 indicating the feature set.
-		///
-		/// INFO: Iterates twice over the vector of features.
-		pub fn check_features(feats: &[Features]) -> Result<(), VirtioNetError> {
-			let mut feat_bits = 0u64;
+		#[test]
+		fn ctrl_queue_follows_all_device_queues() {
+			let ctrl = u64::from(Features::VIRTIO_NET_F_CTRL_VQ);
 
-			for feat in feats.iter() {
-				feat_bits |= *feat;
-			}
+			let driver = mock_driver(feats(&[]) | ctrl, MockDev::with_pairs(1));
+			assert_eq!(driver.ctrl_vq_index(), 2);
 
-			for feat in feats {
-				match feat {
-					Features::VIRTIO_NET_F_CSUM => continue,
-					Features::VIRTIO_NET_F_GUEST_CSUM => continue,
-					Features::VIRTIO_NET_F_CTRL_GUEST_OFFLOADS => continue,
-					Features::VIRTIO_NET_F_MTU => continue,
-					Features::VIRTIO_NET_F_MAC => continue,
-					Features::VIRTIO_NET_F_GUEST_TSO4 => {
-						if feat_bits & Features::VIRTIO_NET_F_GUEST_CSUM != 0 {
-							continue;
-						} else {
-							return Err(VirtioNetError::FeatReqNotMet(FeatureSet(feat_bits)));
-						}
-					}
-					Features::VIRTIO_NET_F_GUEST_TSO6 => {
-						if feat_bits & Features::VIRTIO_NET_F_GUEST_CSUM != 0 {
-							continue;
-						} else {
-							return Err(VirtioNetError::FeatReqNotMet(FeatureSet(feat_bits)));
-						}
-					}
-					Features::VIRTIO_NET_F_GUEST_ECN => {
-						if feat_bits
-							& (Features::VIRTIO_NET_F_GUEST_TSO4
-								| Features::VIRTIO_NET_F_GUEST_TSO6)
-							!= 0
-						{
-							continue;
-						} else {
-							return Err(VirtioNetError::FeatReqNotMet(FeatureSet(feat_bits)));
-						}
-					}
-					Features::VIRTIO_NET_F_GUEST_UFO => {
-						if feat_bits & Features::VIRTIO_NET_F_GUEST_CSUM != 0 {
-							continue;
-						} else {
-							return Err(VirtioNetError::FeatReqNotMet(FeatureSet(feat_bits)));
-						}
-					}
-					Features::VIRTIO_NET_F_HOST_TSO4 => {
-						if feat_bits & Features::VIRTIO_NET_F_CSUM != 0 {
-							continue;
-						} else {
-							return Err(VirtioNetError::FeatReqNotMet(FeatureSet(feat_bits)));
-						}
-					}
-					Features::VIRTIO_NET_F_HOST_TSO6 => {
-						if feat_bits & Features::VIRTIO_NET_F_CSUM != 0 {
-							continue;
-						} else {
-							return Err(VirtioNetError::FeatReqNotMet(FeatureSet(feat_bits)));
-						}
-					}
-					Features::VIRTIO_NET_F_HOST_ECN => {
-						if feat_bits
-							& (Features::VIRTIO_NET_F_HOST_TSO4 | Features::VIRTIO_NET_F_HOST_TSO6)
-							!= 0
-						{
-							continue;
-						} else {
-							return Err(VirtioNetError::FeatReqNotMet(FeatureSet(feat_bits)));
-						}
-					}
-					Features::VIRTIO_NET_F_HOST_UFO => {
-						if feat_bits & Features::VIRTIO_NET_F_CSUM != 0 {
-							continue;
-						} else {
-							return Err(VirtioNetError::FeatReqNotMet(FeatureSet(feat_bits)));
-						}
-					}
-					Features::VIRTIO_NET_F_MRG_RXBUF => continue,
-					Features::VIRTIO_NET_F_STATUS => continue,
-					Features::VIRTIO_NET_F_CTRL_VQ => continue,
-					Features::VIRTIO_NET_F_CTRL_RX => {
-						if feat_bits & Features::VIRTIO_NET_F_CTRL_VQ != 0 {
-							continue;
-						} else {
-							return Err(VirtioNetError::FeatReqNotMet(FeatureSet(feat_bits)));
-						}
-					}
-					Features::VIRTIO_NET_F_CTRL_VLAN => {
-						if feat_bits & Features::VIRTIO_NET_F_CTRL_VQ != 0 {
-							continue;
-						} else {
-							return Err(VirtioNetError::FeatReqNotMet(FeatureSet(feat_bits)));
-						}
-					}
-					Features::VIRTIO_NET_F_GUEST_ANNOUNCE => {
-						if feat_bits & Features::VIRTIO_NET_F_CTRL_VQ != 0 {
-							continue;
-						} else {
-							return Err(VirtioNetError::FeatReqNotMet(FeatureSet(feat_bits)));
-						}
-					}
-					Features::VIRTIO_NET_F_MQ => {
-						if feat_bits & Features::VIRTIO_NET_F_CTRL_VQ != 0 {
-							continue;
-						} else {
-							return Err(VirtioNetError::FeatReqNotMet(FeatureSet(feat_bits)));
-						}
-					}
-					Features::VIRTIO_NET_F_CTRL_MAC_ADDR => {
-						if feat_bits & Features::VIRTIO_NET_F_CTRL_VQ != 0 {
-							continue;
-						} else {
-							return Err(VirtioNetError::FeatReqNotMet(FeatureSet(feat_bits)));
-						}
-					}
-					Features::VIRTIO_NET_F_GUEST_HDRLEN => continue,
-					Features::VIRTIO_NET_F_RSC_EXT => {
-						if feat_bits
-							& (Features::VIRTIO_NET_F_HOST_TSO4 | Features::VIRTIO_NET_F_HOST_TSO6)
-							!= 0
-						{
-							continue;
-						} else {
-							return Err(VirtioNetError::FeatReqNotMet(FeatureSet(feat_bits)));
-						}
-					}
-					Features::VIRTIO_NET_F_STANDBY => continue,
-					Features::VIRTIO_F_RING_INDIRECT_DESC => continue,
-					Features::VIRTIO_F_RING_EVENT_IDX => continue,
-					Features::VIRTIO_F_VERSION_1 => continue,
-					Features::VIRTIO_F_ACCESS_PLATFORM => continue,
-					Features::VIRTIO_F_RING_PACKED => continue,
-					Features::VIRTIO_F_IN_ORDER => continue,
-					Features::VIRTIO_F_ORDER_PLATFORM => continue,
-					Features::VIRTIO_F_SR_IOV => continue,
-					Features::VIRTIO_F_NOTIFICATION_DATA => continue,
-				}
-			}
+			// The driver uses less pairs than the device supports.
+			let dev = MockDev::with_pairs(8);
+			let driver = mock_driver(feats(&[Features::VIRTIO_NET_F_MQ]) | ctrl, dev);
+			assert_eq!(driver.num_queues(), MAX_NUM_VQ / 2);
+			assert_eq!(driver.ctrl_vq_index(), 16);
+		}
 
-			Ok(())
+		#[test]
+		fn exhausted_queue_memory_is_reported() {
+			let mut driver = VirtioNetDriver::mock(feats(&[]), MockDev::with_pairs(1));
+			// Too small for the rings of the first queue.
+			driver.config.allocator = MockArena::leak(64);
+			driver.config.irq_storm_threshold = 0;
+
+			assert!(matches!(
+				driver.virtqueue_init(),
+				Err(VirtioNetError::QueueAllocFailed(0))
+			));
 		}
 
-		/// Checks if a given feature is set.
-		pub fn is_feature(self, feat: Features) -> bool {
-			self.0 & feat != 0
+		#[test]
+		fn exhausted_buffer_memory_is_reported() {
+			// Sufficient for the rings of a queue, but not for its buffers.
+			let arena = 5 * 4096;
+
+			let mut driver = VirtioNetDriver::mock(feats(&[]), MockDev::with_pairs(1));
+			driver.config.allocator = MockArena::leak(arena);
+			driver.config.irq_storm_threshold = 0;
+			assert!(matches!(
+				driver.virtqueue_init(),
+				Err(VirtioNetError::QueueAllocFailed(0))
+			));
+
+			let mut driver = VirtioNetDriver::mock(feats(&[]), MockDev::with_pairs(1));
+			driver.config.allocator = MockArena::leak(arena);
+			driver.config.irq_storm_threshold = 0;
+			driver.config.direction = Direction::TxOnly;
+			assert!(matches!(
+				driver.virtqueue_init(),
+				Err(VirtioNetError::QueueAllocFailed(1))
+			));
 		}
 
-		/// Sets features contained in feats to true.
-		///
-		/// WARN: Features should be checked before using this function via the [`FeatureSet::check_features`] function.
-		pub fn set_features(&mut self, feats: &[Features]) {
-			for feat in feats {
-				self.0 |= *feat;
-			}
+		#[test]
+		fn rejected_rss_falls_back_to_plain_multiqueue() {
+			let mut driver = VirtioNetDriver::mock(
+				feats(&[
+					Features::VIRTIO_NET_F_MQ,
+					Features::VIRTIO_NET_F_RSS,
+					Features::VIRTIO_NET_F_MAC,
+				]),
+				MockDev::with_pairs(2),
+			);
+			driver.config.allocator = MockArena::leak(8 << 20);
+			driver.config.irq_storm_threshold = 0;
+
+			// The device reports no indirection table and there is no control queue.
+			driver.dev_spec_init().unwrap();
+			driver.steering_init();
+			assert_eq!(driver.steering_mode(), SteeringMode::Single);
+			assert_eq!(driver.active_queue_pairs(), 1);
 		}
 
-		/// Returns a new instance of (FeatureSet)[FeatureSet] with all features
-		/// initialized to false.
-		pub fn new(val: u64) -> Self {
-			FeatureSet(val)
+		#[test]
+		fn queue_pairs_are_enabled_once_the_device_is_live() {
+			let dev = MockDev {
+				features: offer(&[
+					Features::VIRTIO_NET_F_MAC,
+					Features::VIRTIO_NET_F_CTRL_VQ,
+					Features::VIRTIO_NET_F_MQ,
+				]),
+				..MockDev::with_pairs(2)
+			};
+			let driver = live_driver(dev);
+			driver.init_dev().unwrap();
+
+			assert_eq!(driver.steering_mode(), SteeringMode::MultiQueue);
+			assert_eq!(driver.active_queue_pairs(), 2);
+			assert!(driver.ctrl_vq.is_healthy());
 		}
-	}
-}
 
-/// Error module of virtios network driver. Containing the (VirtioNetError)[VirtioNetError]
-/// enum.
-pub mod error {
-	use super::constants::FeatureSet;
-	/// Network drivers error enum.
-	#[derive(Debug, Copy, Clone)]
-	pub enum VirtioNetError {
-		General,
-		NoDevCfg(u16),
-		NoComCfg(u16),
-		NoIsrCfg(u16),
-		NoNotifCfg(u16),
-		FailFeatureNeg(u16),
-		/// Set of features does not adhere to the requirements of features
-		/// indicated by the specification
-		FeatReqNotMet(FeatureSet),
-		/// The first u64 contains the feature bits wanted by the driver.
-		/// but which are incompatible with the device feature set, second u64.
-		IncompFeatsSet(FeatureSet, FeatureSet),
-		/// Indicates that an operation for finished Transfers, was performed on
-		/// an ongoing transfer
-		ProcessOngoing,
-		Unknown,
+		#[test]
+		fn default_rss_is_configured_once_the_device_is_live() {
+			let dev = MockDev {
+				features: offer(&[
+					Features::VIRTIO_NET_F_MAC,
+					Features::VIRTIO_NET_F_CTRL_VQ,
+					Features::VIRTIO_NET_F_MQ,
+					Features::VIRTIO_NET_F_RSS,
+				]),
+				rss_max_key_size: 40,
+				rss_max_indirection_table_length: 128,
+				supported_hash_types: RSS_DEFAULT_HASH_TYPES,
+				..MockDev::with_pairs(2)
+			};
+			let driver = live_driver(dev);
+			driver.init_dev().unwrap();
+
+			assert_eq!(driver.steering_mode(), SteeringMode::Rss);
+			assert_eq!(driver.active_queue_pairs(), 2);
+			assert!(driver.ctrl_vq.is_healthy());
+		}
+
+		#[test]
+		fn steering_is_selected_once_the_device_is_live() {
+			// RSS can not be configured without an indirection table, so plain multiqueue
+			// steering is used instead.
+			let dev = MockDev {
+				features: offer(&[
+					Features::VIRTIO_NET_F_MAC,
+					Features::VIRTIO_NET_F_CTRL_VQ,
+					Features::VIRTIO_NET_F_MQ,
+					Features::VIRTIO_NET_F_RSS,
+				]),
+				..MockDev::with_pairs(2)
+			};
+			let driver = live_driver(dev);
+			assert_eq!(driver.steering_mode(), SteeringMode::Single);
+			driver.init_dev().unwrap();
+			assert_eq!(driver.steering_mode(), SteeringMode::MultiQueue);
+			assert_eq!(driver.active_queue_pairs(), 2);
+
+			// The steering is selected and configured again after a reset.
+			driver.reinit_unconditionally().unwrap();
+			assert_eq!(driver.steering_mode(), SteeringMode::MultiQueue);
+			assert_eq!(driver.active_queue_pairs(), 2);
+			assert!(driver.ctrl_vq.is_healthy());
+		}
+
+		#[test]
+		fn device_queue_pairs_are_bounded() {
+			let mq = feats(&[Features::VIRTIO_NET_F_MQ]);
+
+			let dev = MockDev {
+				max_virtqueue_pairs: 0,
+				..MockDev::with_pairs(1)
+			};
+			let driver = mock_driver(mq, dev);
+			assert_eq!(driver.num_queues(), 1);
+			assert_eq!(driver.recv_vqs.vqs.len(), 1);
+			assert_eq!(driver.send_vqs.vqs.len(), 1);
+			assert_eq!(driver.ctrl_vq_index(), 2);
+
+			let dev = MockDev {
+				max_virtqueue_pairs: 64,
+				num_queues: 5,
+				..MockDev::with_pairs(1)
+			};
+			let driver = mock_driver(mq, dev);
+			assert_eq!(driver.num_queues(), 2);
+			assert_eq!(driver.recv_vqs.vqs.len(), 2);
+		}
 	}
 }
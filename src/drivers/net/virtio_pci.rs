@@ -2,19 +2,34 @@
 //!
 //! The module contains ...
 
+#[cfg(all(test, not(target_os = "none")))]
+use alloc::boxed::Box;
 use alloc::collections::VecDeque;
 use alloc::rc::Rc;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::cell::RefCell;
+use core::{mem, ptr};
 
 use crate::arch::kernel::pci::PciAdapter;
-use crate::drivers::net::virtio_net::constants::FeatureSet;
-use crate::drivers::net::virtio_net::{CtrlQueue, NetDevCfg, RxQueues, TxQueues, VirtioNetDriver};
+use crate::drivers::net::buffer_pool::BufferPool;
+use crate::drivers::net::virtio_net::constants::{self, FeatureSet, Features};
+use crate::drivers::net::virtio_net::{
+	self, CtrlQueue, DevCfgLayout, InterruptMode, IrqStorm, LinkState, NetCounters, NetDevCfg,
+	RxQueues, SteeringMode, TxQueues, VirtioNetConfig, VirtioNetDriver,
+};
+use crate::drivers::virtio::env;
+use crate::drivers::virtio::env::memory::{MemLen, MmioRef};
 use crate::drivers::virtio::error::{self, VirtioError};
-use crate::drivers::virtio::transport::pci;
-use crate::drivers::virtio::transport::pci::{PciCap, UniCapsColl};
+use crate::drivers::virtio::transport::pci::{
+	BarInfo, ComCfg, IsrStatus, MsixCap, NotifCfg, PciCap, ShMem, ShMemCfg, UniCapsColl,
+};
+use crate::drivers::virtio::transport::{pci, VirtioTransport};
 use crate::drivers::virtio::virtqueue::Virtq;
 
+/// Register of the PCI configuration space, holding the revision id in its lowest byte.
+const PCI_CLASS_REVISION_REGISTER: u32 = 0x08;
+
 /// Virtio's network device configuration structure.
 /// See specification v1.1. - 5.1.4
 ///
@@ -28,60 +43,182 @@ pub struct NetDevCfgRaw {
 	max_virtqueue_pairs: u16,
 	// Indicates the maximum MTU driver should use. Only valid if VIRTIONET_F_MTU is set.
 	mtu: u16,
+	// Speed of the link in units of 1 MBit/s. Only valid if VIRTIO_NET_F_SPEED_DUPLEX is set.
+	speed: u32,
+	// Duplex mode of the link. Only valid if VIRTIO_NET_F_SPEED_DUPLEX is set.
+	duplex: u8,
+	// Maximum length of the RSS hash key in bytes. Only valid if VIRTIO_NET_F_RSS is set.
+	rss_max_key_size: u8,
+	// Maximum number of entries of the RSS indirection table. Only valid if VIRTIO_NET_F_RSS is set.
+	rss_max_indirection_table_length: u16,
+	// Bit mask of the supported hash types. Only valid if VIRTIO_NET_F_RSS is set.
+	supported_hash_types: u32,
 }
 
+/// Length of the device configuration up to and including the mtu field. Devices are
+/// only required to provide the fields of the features they offer.
+const NET_DEV_CFG_MIN_LEN: usize = 12;
+
+/// Accessors of the device configuration. Multi-byte fields are returned in the byte
+/// order of the device, see [NetDevCfg](crate::drivers::net::virtio_net::NetDevCfg)
+/// for decoded values.
 impl NetDevCfgRaw {
 	pub fn get_mtu(&self) -> u16 {
-		self.mtu
+		unsafe { ptr::read_volatile(&self.mtu) }
 	}
 
 	pub fn get_mac(&self) -> [u8; 6] {
-		self.mac
+		// Read byte by byte, as the structure resides in device memory.
+		let mut mac = [0u8; 6];
+		let mut src = self.mac.iter();
+		mac.fill_with(|| unsafe { ptr::read_volatile(src.next().unwrap()) });
+		mac
 	}
 
 	pub fn get_status(&self) -> u16 {
-		self.status
+		unsafe { ptr::read_volatile(&self.status) }
 	}
 
 	pub fn get_max_virtqueue_pairs(&self) -> u16 {
-		self.max_virtqueue_pairs
+		unsafe { ptr::read_volatile(&self.max_virtqueue_pairs) }
+	}
+
+	pub fn get_speed(&self) -> u32 {
+		unsafe { ptr::read_volatile(&self.speed) }
+	}
+
+	pub fn get_duplex(&self) -> u8 {
+		unsafe { ptr::read_volatile(&self.duplex) }
+	}
+
+	pub fn get_rss_max_key_size(&self) -> u8 {
+		unsafe { ptr::read_volatile(&self.rss_max_key_size) }
+	}
+
+	pub fn get_rss_max_indirection_table_length(&self) -> u16 {
+		unsafe { ptr::read_volatile(&self.rss_max_indirection_table_length) }
+	}
+
+	pub fn get_supported_hash_types(&self) -> u32 {
+		unsafe { ptr::read_volatile(&self.supported_hash_types) }
 	}
 }
 
 // Backend-dependent interface for Virtio network driver
 impl VirtioNetDriver {
-	fn map_cfg(cap: &PciCap) -> Option<NetDevCfg> {
-		/*
-		if cap.bar_len() <  u64::from(cap.len() + cap.offset()) {
-			error!("Network config of device {:x}, does not fit into memory specified by bar!",
-				cap.dev_id(),
+	/// Maps the device configuration of `cap`. The layout of the mac field is detected,
+	/// if the device is `transitional`, see [detect_layout](Self::detect_layout).
+	fn map_cfg(cap: &PciCap, transitional: bool) -> Option<NetDevCfg> {
+		// Drivers MAY do this check. See Virtio specification v1.1. - 4.1.4.1
+		if !Self::fits_dev_cfg(cap.len()) {
+			error!(
+				"Network config of device {:x} is too small for the structure specified by the standard!",
+				cap.dev_id()
 			);
-			return None
+			return None;
 		}
 
-		// Drivers MAY do this check. See Virtio specification v1.1. - 4.1.4.1
-		if cap.len() < MemLen::from(mem::size_of::<NetDevCfg>()*8) {
-			error!("Network config from device {:x}, does not represent actual structure specified by the standard!", cap.dev_id());
-			return None
+		// The speed and duplex fields only exist with VIRTIO_NET_F_SPEED_DUPLEX.
+		let dev_cfg: &'static NetDevCfgRaw =
+			match pci::map_dev_cfg_with_len::<NetDevCfgRaw>(cap, NET_DEV_CFG_MIN_LEN) {
+				Some(cfg) => cfg,
+				None => return None,
+			};
+
+		let mut net_dev_cfg = NetDevCfg {
+			// SAFETY: The device configuration lies inside a mapped BAR of the device.
+			raw: unsafe { MmioRef::new(dev_cfg) },
+			dev_id: cap.dev_id(),
+			legacy: false,
+			layout: DevCfgLayout::Modern,
+			features: FeatureSet::new(0),
+		};
+		Self::detect_layout(&mut net_dev_cfg, cap.len(), transitional);
+
+		Some(net_dev_cfg)
+	}
+
+	/// Sets the layout of the device configuration `cfg`, whose capability spans `len` bytes.
+	///
+	/// Some transitional devices place the mac field behind the header of the legacy
+	/// interface, see [DevCfgLayout::Transitional]. This layout is used, if only the shifted
+	/// field holds a unicast address. Modern devices always use the layout of the
+	/// specification.
+	fn detect_layout(cfg: &mut NetDevCfg, len: MemLen, transitional: bool) {
+		cfg.layout = DevCfgLayout::Modern;
+		let shifted_end = DevCfgLayout::Transitional.mac_offset() + 6;
+		if !transitional
+			|| len < MemLen::from(shifted_end)
+			|| virtio_net::is_unicast_mac(&cfg.mac())
+		{
+			return;
 		}
 
-		let virt_addr_raw = cap.bar_addr() + cap.offset();
+		cfg.layout = DevCfgLayout::Transitional;
+		if virtio_net::is_unicast_mac(&cfg.mac()) {
+			info!(
+				"Network device {:x} places its MAC address behind the legacy header.",
+				cfg.dev_id
+			);
+		} else {
+			cfg.layout = DevCfgLayout::Modern;
+		}
+	}
 
-		// Create mutable reference to the PCI structure in PCI memory
-		let dev_cfg: &mut NetDevCfgRaw = unsafe {
-			&mut *(usize::from(virt_addr_raw) as *mut NetDevCfgRaw)
-		};
-		*/
-		let dev_cfg: &'static NetDevCfgRaw = match pci::map_dev_cfg::<NetDevCfgRaw>(cap) {
-			Some(cfg) => cfg,
-			None => return None,
-		};
+	/// Returns true, if the device is a transitional device with revision 0, which
+	/// may expose its configuration in the byte order of the guest.
+	/// See Virtio specification v1.1. - 4.1.2.1
+	fn is_legacy(adapter: &PciAdapter) -> bool {
+		let revision = env::pci::read_config(adapter, PCI_CLASS_REVISION_REGISTER) & 0xff;
+		Self::is_legacy_id(adapter.device_id, revision)
+	}
 
-		Some(NetDevCfg {
-			raw: dev_cfg,
-			dev_id: cap.dev_id(),
-			features: FeatureSet::new(0),
-		})
+	fn is_legacy_id(device_id: u16, revision: u32) -> bool {
+		Self::is_transitional_id(device_id) && revision == 0
+	}
+
+	/// Returns true, if the device id belongs to a transitional device, which supports the
+	/// legacy interface besides the modern one. See Virtio specification v1.1. - 4.1.2.1
+	fn is_transitional_id(device_id: u16) -> bool {
+		(0x1000..0x1040).contains(&device_id)
+	}
+
+	/// Returns true, if a capability of length `len` bytes can hold the network
+	/// device configuration structure.
+	fn fits_dev_cfg(len: MemLen) -> bool {
+		len >= MemLen::from(NET_DEV_CFG_MIN_LEN)
+	}
+
+	/// Returns the location of each configuration structure (common, notification,
+	/// ISR status, device specific and shared memory) the driver uses, as resolved
+	/// from the device's capabilities.
+	pub fn bar_layout(&self) -> Vec<BarInfo> {
+		self.bar_layout.clone()
+	}
+
+	/// Checks the mapped device configuration for values, which are allowed by the
+	/// specification but are implausible for a network device. As these do most likely
+	/// stem from a wrongly parsed capability list, the driver only warns about them.
+	///
+	/// Must be called after feature negotiation, as fields are only valid if the
+	/// respective features are negotiated.
+	fn check_dev_cfg(&self) {
+		if self.dev_cfg.features.is_feature(Features::VIRTIO_NET_F_MQ)
+			&& self.dev_cfg.max_virtqueue_pairs() > 0x8000
+		{
+			warn!(
+				"Network device {:x} reports {} virtqueue pairs, which exceeds the maximum of 0x8000. Device config might be mapped wrongly!",
+				self.dev_cfg.dev_id,
+				self.dev_cfg.max_virtqueue_pairs()
+			);
+		}
+
+		if self.dev_cfg.features.is_feature(Features::VIRTIO_NET_F_MTU) && self.dev_cfg.mtu() == 0 {
+			warn!(
+				"Network device {:x} negotiated VIRTIO_NET_F_MTU, but reports a MTU of zero. Device config might be mapped wrongly!",
+				self.dev_cfg.dev_id
+			);
+		}
 	}
 
 	/// Instantiates a new (VirtioNetDriver)[VirtioNetDriver] struct, by checking the available
@@ -90,45 +227,85 @@ impl VirtioNetDriver {
 		mut caps_coll: UniCapsColl,
 		adapter: &PciAdapter,
 	) -> Result<Self, error::VirtioNetError> {
-		let com_cfg = match caps_coll.get_com_cfg() {
-			Some(com_cfg) => com_cfg,
-			None => {
-				error!("No common config. Aborting!");
-				return Err(error::VirtioNetError::NoComCfg(adapter.device_id));
-			}
-		};
+		// Each list of the collection is consumed at most once. As every call removes the
+		// returned capability, unusable capabilities can not stall the discovery.
+		let com_cfg = caps_coll.take_com_cfg();
+		let isr_stat = caps_coll.take_isr_cfg();
+		let notif_cfg = caps_coll.take_notif_cfg();
+		// The highest prioritized device config, which can be mapped, is used.
+		let transitional = Self::is_transitional_id(adapter.device_id);
+		let dev_cfg = core::iter::from_fn(|| caps_coll.get_dev_cfg()).find_map(|cfg| {
+			VirtioNetDriver::map_cfg(&cfg, transitional).map(|dev_cfg| (dev_cfg, cfg.bar_info()))
+		});
 
-		let isr_stat = match caps_coll.get_isr_cfg() {
-			Some(isr_stat) => isr_stat,
-			None => {
-				error!("No ISR status config. Aborting!");
-				return Err(error::VirtioNetError::NoIsrCfg(adapter.device_id));
-			}
+		let Some(com_cfg) = com_cfg else {
+			error!("No common config. Aborting!");
+			return Err(error::VirtioNetError::NoComCfg(adapter.device_id));
 		};
-
-		let notif_cfg = match caps_coll.get_notif_cfg() {
-			Some(notif_cfg) => notif_cfg,
-			None => {
-				error!("No notif config. Aborting!");
-				return Err(error::VirtioNetError::NoNotifCfg(adapter.device_id));
-			}
+		let Some(isr_stat) = isr_stat else {
+			error!("No ISR status config. Aborting!");
+			return Err(error::VirtioNetError::NoIsrCfg(adapter.device_id));
 		};
+		let Some(notif_cfg) = notif_cfg else {
+			error!("No notif config. Aborting!");
+			return Err(error::VirtioNetError::NoNotifCfg(adapter.device_id));
+		};
+		let Some((mut dev_cfg, dev_bar_info)) = dev_cfg else {
+			error!("No dev config. Aborting!");
+			return Err(error::VirtioNetError::NoDevCfg(adapter.device_id));
+		};
+		dev_cfg.legacy = Self::is_legacy(adapter);
 
-		let dev_cfg = loop {
-			match caps_coll.get_dev_cfg() {
-				Some(cfg) => {
-					if let Some(dev_cfg) = VirtioNetDriver::map_cfg(&cfg) {
-						break dev_cfg;
-					}
-				}
-				None => {
-					error!("No dev config. Aborting!");
-					return Err(error::VirtioNetError::NoDevCfg(adapter.device_id));
-				}
+		let mut bar_layout = vec![
+			com_cfg.bar_info(),
+			notif_cfg.bar_info(),
+			isr_stat.bar_info(),
+			dev_bar_info,
+		];
+
+		let mut sh_mem: Vec<ShMemCfg> = Vec::new();
+		while let Some(sh_mem_cfg) = caps_coll.get_sh_mem_cfg() {
+			if sh_mem.iter().any(|cfg| cfg.id() == sh_mem_cfg.id()) {
+				warn!(
+					"Multiple shared memory regions with id {}. Ignoring all but the first!",
+					sh_mem_cfg.id()
+				);
+				continue;
 			}
-		};
 
-		Ok(VirtioNetDriver {
+			// The network driver does not use any region yet.
+			info!(
+				"Shared memory region with id {} of length {:#x} is present, but unused.",
+				sh_mem_cfg.id(),
+				sh_mem_cfg.sh_mem().len()
+			);
+			bar_layout.push(sh_mem_cfg.bar_info());
+			sh_mem.push(sh_mem_cfg);
+		}
+
+		Ok(VirtioNetDriver::with_cfgs(
+			dev_cfg,
+			com_cfg,
+			isr_stat,
+			notif_cfg,
+			adapter.irq,
+			bar_layout,
+			sh_mem,
+		))
+	}
+
+	/// Returns a driver for the given configuration structures, whose queues are not
+	/// yet created.
+	fn with_cfgs(
+		dev_cfg: NetDevCfg,
+		com_cfg: ComCfg,
+		isr_stat: IsrStatus,
+		notif_cfg: NotifCfg,
+		irq: u8,
+		bar_layout: Vec<BarInfo>,
+		sh_mem: Vec<ShMemCfg>,
+	) -> Self {
+		VirtioNetDriver {
 			dev_cfg,
 			com_cfg,
 			isr_stat,
@@ -147,9 +324,32 @@ impl VirtioNetDriver {
 				false,
 			),
 			num_vqs: 0,
-			irq: adapter.irq,
+			steering: SteeringMode::Single,
+			initial_link: LinkState::Down,
+			link: LinkState::Down,
+			irq,
+			interrupt_mode: InterruptMode::Legacy,
 			polling_mode_counter: 0,
-		})
+			irq_storm: IrqStorm::default(),
+			stats: vec![NetCounters::default()],
+			rx_drop: false,
+			notif_enabled: true,
+			generated_mac: [0; 6],
+			assigned_mac: None,
+			ipv4_addr: None,
+			vlans: Vec::new(),
+			promiscuous: false,
+			rx_handler: None,
+			rx_wakers: Vec::new(),
+			last_tx_completion: None,
+			rx_pool: BufferPool::new(0, 0),
+			bar_layout,
+			sh_mem,
+			msix: None,
+			msix_cfg_irq: None,
+			queue_affinity: Vec::new(),
+			config: VirtioNetConfig::default(),
+		}
 	}
 
 	/// Initializes virtio network device by mapping configuration layout to
@@ -161,6 +361,140 @@ impl VirtioNetDriver {
 	/// Returns a driver instance of
 	/// [VirtioNetDriver](structs.virtionetdriver.html) or an [VirtioError](enums.virtioerror.html).
 	pub fn init(adapter: &PciAdapter) -> Result<VirtioNetDriver, VirtioError> {
+		VirtioNetDriver::init_with_config(adapter, VirtioNetConfig::default())
+	}
+
+	/// Returns the shared memory region with the given id, if the device provides one.
+	/// See Virtio specification v1.1. - 4.1.4.7
+	pub fn shared_mem(&self, id: u8) -> Option<&ShMem> {
+		self.sh_mem
+			.iter()
+			.find(|cfg| cfg.id() == id)
+			.map(|cfg| cfg.sh_mem())
+	}
+
+	/// Switches the device to MSI-X, if it provides an MSI-X table with at least
+	/// two entries. Virtqueue interrupts are delivered on the interrupt line of the
+	/// adapter, configuration changes on a newly allocated line, see
+	/// [msix_cfg_irq](Self::msix_cfg_irq). Otherwise the legacy interrupt line is kept.
+	fn setup_msix(&mut self, adapter: &PciAdapter) {
+		let msix = match pci::read_msix_cap(adapter) {
+			Some(msix) if msix.table_size() >= 2 => msix,
+			_ => {
+				debug!("MSI-X is not available, using legacy interrupts.");
+				return;
+			}
+		};
+
+		let cfg_irq = match crate::arch::kernel::interrupts::allocate_irq() {
+			Some(irq) => irq,
+			None => {
+				warn!("No interrupt line left for configuration changes, using legacy interrupts.");
+				return;
+			}
+		};
+		if !msix.set_entry(constants::MSIX_CFG_ENTRY, cfg_irq)
+			|| !msix.set_entry(constants::MSIX_QUEUE_ENTRY, adapter.irq)
+		{
+			warn!("Unable to program MSI-X table, using legacy interrupts.");
+			return;
+		}
+
+		msix.enable(adapter);
+		self.com_cfg
+			.set_queue_msix_vector(constants::MSIX_QUEUE_ENTRY);
+		self.interrupt_mode = InterruptMode::MsiX;
+		self.msix = Some((msix, adapter.clone()));
+		self.msix_cfg_irq = Some(cfg_irq);
+		info!("Network device uses MSI-X interrupts.");
+	}
+
+	/// Returns the interrupt line, on which configuration changes are signalled, if the
+	/// device uses MSI-X. Otherwise they are signalled on the line of the adapter.
+	pub fn msix_cfg_irq(&self) -> Option<u8> {
+		self.msix_cfg_irq
+	}
+
+	/// Returns the MSI-X table entry of the given queue pair, if the table has room for it.
+	fn pair_msix_entry(pair: u16, table_size: u16) -> Option<u16> {
+		let entry = constants::MSIX_FIRST_PAIR_ENTRY.checked_add(pair)?;
+		(entry < table_size).then_some(entry)
+	}
+
+	/// Delivers the interrupts of the receive and the transmit queue of the given pair to the
+	/// processor `cpu`, so both are handled on the same core. Requires MSI-X, as the pair gets
+	/// a table entry of its own.
+	///
+	/// The driver does not migrate the processing of the queues itself. The scheduler can
+	/// retrieve the mapping via [queue_affinity](Self::queue_affinity). A reset of the device
+	/// removes all affinities.
+	pub fn set_queue_affinity(
+		&mut self,
+		queue_pair: u16,
+		cpu: u32,
+	) -> Result<(), virtio_net::error::VirtioNetError> {
+		use virtio_net::error::VirtioNetError;
+
+		if queue_pair >= self.num_queues() {
+			return Err(VirtioNetError::InvalidQueue(queue_pair));
+		}
+		let Some((msix, adapter)) = self.msix.as_ref() else {
+			return Err(VirtioNetError::MsixUnavailable(queue_pair));
+		};
+		let entry = Self::pair_msix_entry(queue_pair, msix.table_size())
+			.ok_or(VirtioNetError::MsixUnavailable(queue_pair))?;
+		let apic_id =
+			crate::arch::kernel::apic::local_apic_id(cpu).ok_or(VirtioNetError::InvalidCpu(cpu))?;
+
+		if !msix.set_entry_to(entry, adapter.irq, apic_id) {
+			return Err(VirtioNetError::MsixUnavailable(queue_pair));
+		}
+		for index in [2 * queue_pair, 2 * queue_pair + 1] {
+			if !self.com_cfg.set_vq_msix_vector(index, entry) {
+				warn!(
+					"Network device {:x} did not accept MSI-X vector {} for virtqueue {}.",
+					self.dev_cfg.dev_id, entry, index
+				);
+				return Err(VirtioNetError::MsixUnavailable(queue_pair));
+			}
+		}
+
+		let pair = usize::from(queue_pair);
+		if self.queue_affinity.len() <= pair {
+			self.queue_affinity.resize(pair + 1, None);
+		}
+		self.queue_affinity[pair] = Some(cpu);
+		info!(
+			"Interrupts of queue pair {} of network device {:x} are delivered to core {}",
+			queue_pair, self.dev_cfg.dev_id, cpu
+		);
+		Ok(())
+	}
+
+	/// Returns the processor, to which the interrupts of the given queue pair are delivered,
+	/// if it has been set via [set_queue_affinity](Self::set_queue_affinity).
+	pub fn queue_affinity(&self, queue_pair: u16) -> Option<u32> {
+		self.queue_affinity
+			.get(usize::from(queue_pair))
+			.copied()
+			.flatten()
+	}
+
+	/// Initializes the virtio network device like [VirtioNetDriver::init], but uses the
+	/// given driver configuration instead of the default one.
+	pub fn init_with_config(
+		adapter: &PciAdapter,
+		config: VirtioNetConfig,
+	) -> Result<VirtioNetDriver, VirtioError> {
+		// Catch cases, where the driver is handed a device, which is not a network device.
+		if !virtio_net::is_supported(adapter.vendor_id, adapter.device_id) {
+			error!(
+				"Device {:#x}:{:#x} is not a virtio network device. Aborting!",
+				adapter.vendor_id, adapter.device_id
+			);
+			return Err(VirtioError::DevNotSupported(adapter.device_id));
+		}
+
 		let mut drv = match pci::map_caps(adapter) {
 			Ok(caps) => match VirtioNetDriver::new(caps, adapter) {
 				Ok(driver) => driver,
@@ -170,10 +504,12 @@ impl VirtioNetDriver {
 				}
 			},
 			Err(pci_error) => {
-				error!("Mapping capabilities failed. Aborting!");
+				error!("Mapping capabilities failed: {:?}. Aborting!", pci_error);
 				return Err(VirtioError::FromPci(pci_error));
 			}
 		};
+		drv.config = config;
+		drv.setup_msix(adapter);
 
 		match drv.init_dev() {
 			Ok(_) => info!(
@@ -186,6 +522,8 @@ impl VirtioNetDriver {
 			}
 		}
 
+		drv.check_dev_cfg();
+
 		if drv.is_link_up() {
 			info!("Virtio-net link is up after initialization.")
 		} else {
@@ -195,3 +533,208 @@ impl VirtioNetDriver {
 		Ok(drv)
 	}
 }
+
+/// Device configuration of a simulated device for host tests, see [VirtioNetDriver::mock].
+#[cfg(all(test, not(target_os = "none")))]
+pub(super) struct MockDev {
+	pub(super) mac: [u8; 6],
+	pub(super) status: u16,
+	pub(super) max_virtqueue_pairs: u16,
+	/// Number of virtqueues reported via the common configuration.
+	pub(super) num_queues: u16,
+	/// Number of entries of each queue.
+	pub(super) queue_size: u16,
+	/// Offered features. The device reports the same word for the low and the high half
+	/// of the feature bits, see [pci::mock::com_cfg].
+	pub(super) features: u32,
+	pub(super) rss_max_key_size: u8,
+	pub(super) rss_max_indirection_table_length: u16,
+	pub(super) supported_hash_types: u32,
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+impl MockDev {
+	/// Returns a device with `pairs` queue pairs and a control queue of 16 entries each,
+	/// whose link is up.
+	pub(super) fn with_pairs(pairs: u16) -> Self {
+		MockDev {
+			mac: [0x52, 0x54, 0, 0x12, 0x34, 0x56],
+			// VIRTIO_NET_S_LINK_UP
+			status: 1,
+			max_virtqueue_pairs: pairs,
+			num_queues: 2 * pairs + 1,
+			queue_size: 16,
+			features: 0,
+			rss_max_key_size: 0,
+			rss_max_indirection_table_length: 0,
+			supported_hash_types: 0,
+		}
+	}
+}
+
+/// Simulated device for host tests.
+#[cfg(all(test, not(target_os = "none")))]
+impl VirtioNetDriver {
+	/// Returns a driver for the simulated device `dev`, which negotiated `features` and
+	/// reports an MTU of 1500 bytes.
+	///
+	/// The configuration structures are placed in leaked heap memory, see [pci::mock].
+	/// The driver is not initialized and no queues are created yet.
+	pub(super) fn mock(features: u64, dev: MockDev) -> Self {
+		let raw = Box::leak(Box::new(NetDevCfgRaw {
+			mac: dev.mac,
+			status: dev.status.to_le(),
+			max_virtqueue_pairs: dev.max_virtqueue_pairs.to_le(),
+			mtu: 1500u16.to_le(),
+			speed: 0,
+			duplex: 0,
+			rss_max_key_size: dev.rss_max_key_size,
+			rss_max_indirection_table_length: dev.rss_max_indirection_table_length.to_le(),
+			supported_hash_types: dev.supported_hash_types.to_le(),
+		}));
+		let dev_cfg = NetDevCfg {
+			// SAFETY: The structure is leaked, hence it is never freed.
+			raw: unsafe { MmioRef::new(raw) },
+			dev_id: 0,
+			legacy: false,
+			layout: DevCfgLayout::Modern,
+			features: FeatureSet::new(features),
+		};
+
+		VirtioNetDriver::with_cfgs(
+			dev_cfg,
+			pci::mock::com_cfg(dev.num_queues, dev.queue_size, dev.features),
+			pci::mock::isr_status(0),
+			pci::mock::notif_cfg(),
+			0,
+			Vec::new(),
+			Vec::new(),
+		)
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn dev_cfg_size_is_checked_in_bytes() {
+		assert_eq!(mem::size_of::<NetDevCfgRaw>(), 24);
+		assert!(VirtioNetDriver::fits_dev_cfg(MemLen::from(12usize)));
+		assert!(!VirtioNetDriver::fits_dev_cfg(MemLen::from(11usize)));
+	}
+
+	/// Returns the device configuration, whose memory holds the given bytes. At least the
+	/// bytes of [NetDevCfgRaw] have to be given.
+	fn dev_cfg_of<const N: usize>(bytes: [u8; N], legacy: bool) -> NetDevCfg {
+		// The memory must be aligned like the structure.
+		let words = Box::leak(vec![0u32; (N + 3) / 4].into_boxed_slice());
+		for (word, chunk) in words.iter_mut().zip(bytes.chunks(4)) {
+			*word = u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+		}
+		let raw = unsafe { &*(words.as_ptr() as *const NetDevCfgRaw) };
+
+		NetDevCfg {
+			raw: unsafe { MmioRef::new(raw) },
+			dev_id: 0,
+			legacy,
+			layout: DevCfgLayout::Modern,
+			features: FeatureSet::new(0),
+		}
+	}
+
+	/// Returns the bytes of a device configuration with mac, status 1, max_virtqueue_pairs 4,
+	/// mtu 1500 and speed 10000, whose fields are encoded via `encode16` and `encode32`.
+	fn dev_cfg_bytes(encode16: fn(u16) -> [u8; 2], encode32: fn(u32) -> [u8; 4]) -> [u8; 24] {
+		let mut bytes = [0u8; 24];
+		bytes[..6].copy_from_slice(&[0x52, 0x54, 0, 0x12, 0x34, 0x56]);
+		bytes[6..8].copy_from_slice(&encode16(1));
+		bytes[8..10].copy_from_slice(&encode16(4));
+		bytes[10..12].copy_from_slice(&encode16(1500));
+		bytes[12..16].copy_from_slice(&encode32(10000));
+		bytes
+	}
+
+	#[test]
+	fn dev_cfg_is_decoded_in_byte_order_of_the_device() {
+		// Modern devices store all fields in little-endian.
+		let cfg = dev_cfg_of(dev_cfg_bytes(u16::to_le_bytes, u32::to_le_bytes), false);
+		assert_eq!(cfg.mac(), [0x52, 0x54, 0, 0x12, 0x34, 0x56]);
+		assert_eq!(cfg.status(), 1);
+		assert_eq!(cfg.max_virtqueue_pairs(), 4);
+		assert_eq!(cfg.mtu(), 1500);
+		assert_eq!(cfg.speed(), 10000);
+
+		// Values stored in big-endian are not mistaken for little-endian ones.
+		let cfg = dev_cfg_of(dev_cfg_bytes(u16::to_be_bytes, u32::to_be_bytes), false);
+		assert_ne!(cfg.mtu(), 1500);
+		assert_ne!(cfg.speed(), 10000);
+
+		// Legacy devices store the fields in the byte order of the guest, hence byte swapped
+		// values are decoded as stored by a guest of the other byte order.
+		let cfg = dev_cfg_of(dev_cfg_bytes(u16::to_ne_bytes, u32::to_ne_bytes), true);
+		assert_eq!(cfg.status(), 1);
+		assert_eq!(cfg.mtu(), 1500);
+		assert_eq!(cfg.speed(), 10000);
+		let swapped = |val: u16| val.swap_bytes().to_ne_bytes();
+		let cfg = dev_cfg_of(dev_cfg_bytes(swapped, u32::to_ne_bytes), true);
+		assert_eq!(cfg.mtu(), 1500u16.swap_bytes());
+	}
+
+	#[test]
+	fn queue_pairs_get_separate_msix_entries() {
+		// The entries of the configuration and the shared queue vector are skipped.
+		assert_eq!(VirtioNetDriver::pair_msix_entry(0, 4), Some(2));
+		assert_eq!(VirtioNetDriver::pair_msix_entry(1, 4), Some(3));
+		assert_eq!(VirtioNetDriver::pair_msix_entry(2, 4), None);
+		assert_eq!(VirtioNetDriver::pair_msix_entry(u16::MAX, u16::MAX), None);
+	}
+
+	#[test]
+	fn legacy_devices_are_detected() {
+		assert!(VirtioNetDriver::is_legacy_id(0x1000, 0));
+		assert!(!VirtioNetDriver::is_legacy_id(0x1000, 1));
+		assert!(!VirtioNetDriver::is_legacy_id(0x1041, 1));
+
+		assert!(VirtioNetDriver::is_transitional_id(0x1000));
+		assert!(!VirtioNetDriver::is_transitional_id(0x1041));
+	}
+
+	#[test]
+	fn mac_is_read_with_the_layout_of_the_device() {
+		let mac = [0x52, 0x54, 0, 0x12, 0x34, 0x56];
+		let len = MemLen::from(32usize);
+		let mut modern = [0u8; 32];
+		modern[..6].copy_from_slice(&mac);
+		let mut shifted = [0u8; 32];
+		shifted[20..26].copy_from_slice(&mac);
+
+		for transitional in [false, true] {
+			let mut cfg = dev_cfg_of(modern, false);
+			VirtioNetDriver::detect_layout(&mut cfg, len, transitional);
+			assert_eq!(cfg.layout, DevCfgLayout::Modern);
+			assert_eq!(cfg.mac(), mac);
+		}
+
+		let mut cfg = dev_cfg_of(shifted, false);
+		VirtioNetDriver::detect_layout(&mut cfg, len, true);
+		assert_eq!(cfg.layout, DevCfgLayout::Transitional);
+		assert_eq!(cfg.mac(), mac);
+
+		// Modern devices always use the specified layout.
+		let mut cfg = dev_cfg_of(shifted, false);
+		VirtioNetDriver::detect_layout(&mut cfg, len, false);
+		assert_eq!(cfg.layout, DevCfgLayout::Modern);
+		assert_eq!(cfg.mac(), [0; 6]);
+
+		// The shifted field must lie inside the capability.
+		let mut cfg = dev_cfg_of(shifted, false);
+		VirtioNetDriver::detect_layout(&mut cfg, MemLen::from(24usize), true);
+		assert_eq!(cfg.layout, DevCfgLayout::Modern);
+
+		// Without a valid address at either offset, the specified layout is kept.
+		let mut cfg = dev_cfg_of([0xffu8; 32], false);
+		VirtioNetDriver::detect_layout(&mut cfg, len, true);
+		assert_eq!(cfg.layout, DevCfgLayout::Modern);
+	}
+}
@@ -0,0 +1,136 @@
+//! Adapter, which allows smoltcp to use a virtio network device directly.
+//!
+//! The tokens own the packet buffers, hence no buffer of the virtqueues is lost,
+//! if a consume closure panics or fails.
+
+use alloc::vec;
+
+use smoltcp::phy::{self, Checksum, Device, DeviceCapabilities, Medium};
+use smoltcp::time::Instant;
+
+use crate::drivers::net::buffer_pool::PooledBuffer;
+use crate::drivers::net::virtio_net::constants::Features;
+use crate::drivers::net::virtio_net::{VirtioNetDriver, ETH_HDR};
+use crate::drivers::net::NetworkInterface;
+
+/// Implements [smoltcp::phy::Device] on top of a [VirtioNetDriver].
+pub struct VirtioNetDevice<'a> {
+	driver: &'a mut VirtioNetDriver,
+}
+
+impl<'a> VirtioNetDevice<'a> {
+	pub fn new(driver: &'a mut VirtioNetDriver) -> Self {
+		Self { driver }
+	}
+
+	/// Returns the checksum capability, depending on whether the device verifies
+	/// received checksums and computes transmitted checksums.
+	fn checksum(rx_offloaded: bool, tx_offloaded: bool) -> Checksum {
+		match (rx_offloaded, tx_offloaded) {
+			(true, true) => Checksum::None,
+			(true, false) => Checksum::Tx,
+			(false, true) => Checksum::Rx,
+			(false, false) => Checksum::Both,
+		}
+	}
+}
+
+impl<'a> Device for VirtioNetDevice<'a> {
+	type RxToken<'b>
+		= RxToken
+	where
+		Self: 'b;
+	type TxToken<'b>
+		= TxToken<'b>
+	where
+		Self: 'b;
+
+	fn capabilities(&self) -> DeviceCapabilities {
+		// Both directions are negotiated independently. VIRTIO_NET_F_GUEST_CSUM allows the
+		// device to skip the checksums of received packets, VIRTIO_NET_F_CSUM asks it
+		// to compute the checksums of transmitted packets.
+		let rx_csum = self.driver.has_feature(Features::VIRTIO_NET_F_GUEST_CSUM);
+		let tx_csum = self.driver.has_feature(Features::VIRTIO_NET_F_CSUM);
+
+		let mut cap = DeviceCapabilities::default();
+		cap.medium = Medium::Ethernet;
+		// smoltcp includes the ethernet header into the MTU.
+		cap.max_transmission_unit = usize::from(self.driver.get_mtu()) + ETH_HDR;
+		// The device does not compute checksums of IP headers.
+		cap.checksum.tcp = Self::checksum(rx_csum, tx_csum);
+		cap.checksum.udp = Self::checksum(rx_csum, tx_csum);
+		cap
+	}
+
+	fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+		let buffer = self.driver.receive_packet_lent(None)?;
+
+		Some((
+			RxToken { buffer },
+			TxToken {
+				driver: self.driver,
+			},
+		))
+	}
+
+	/// Returns a token only, if the first transmit queue has room for a packet. Otherwise,
+	/// smoltcp keeps the packet and retries with the next poll.
+	fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+		if self.driver.tx_free_descriptors(0) == 0 {
+			self.driver.reclaim_tx_buffers();
+			if self.driver.tx_free_descriptors(0) == 0 {
+				return None;
+			}
+		}
+
+		Some(TxToken {
+			driver: self.driver,
+		})
+	}
+}
+
+#[doc(hidden)]
+pub struct RxToken {
+	buffer: PooledBuffer,
+}
+
+impl phy::RxToken for RxToken {
+	fn consume<R, F>(mut self, f: F) -> R
+	where
+		F: FnOnce(&mut [u8]) -> R,
+	{
+		f(&mut self.buffer[..])
+	}
+}
+
+#[doc(hidden)]
+pub struct TxToken<'a> {
+	driver: &'a mut VirtioNetDriver,
+}
+
+impl<'a> phy::TxToken for TxToken<'a> {
+	fn consume<R, F>(self, len: usize, f: F) -> R
+	where
+		F: FnOnce(&mut [u8]) -> R,
+	{
+		// The packet is written into a send buffer of the driver directly.
+		let mut buffer = match self.driver.alloc_dma_buffer(len) {
+			Ok(buffer) => buffer,
+			Err(vnet_err) => {
+				warn!("Unable to send packet: {:?}", vnet_err);
+				// The packet is dropped, but smoltcp expects it to be written nevertheless.
+				return f(&mut vec![0u8; len][..]);
+			}
+		};
+		let result = f(buffer.as_mut_slice());
+
+		// A buffer, which is not sent, is dropped and thereby returned to the queue. The
+		// handle of a sent buffer is dropped as well, so the queue frees the buffer, once
+		// the device has consumed it.
+		if let Err(vnet_err) = self.driver.send_buffer(buffer) {
+			warn!("Unable to send packet: {:?}", vnet_err);
+		}
+
+		result
+	}
+}
@@ -5,7 +5,43 @@
 //! ensure only a single location needs changes, in cases where the underlying kernel code is changed
 
 pub mod memory {
-	use core::ops::Add;
+	use core::ops::{Add, Deref};
+	use core::ptr::NonNull;
+
+	/// A reference to a configuration structure in device memory.
+	///
+	/// # Safety invariants
+	///
+	/// * The structure stays mapped and valid for as long as the device is present,
+	///   which is mandatory for the drivers to work.
+	/// * The fields of the structure are only accessed via volatile reads and writes,
+	///   as the device may change them at any time.
+	/// * The device memory is not tied to a core, hence the reference may be moved
+	///   to and shared with other cores.
+	pub struct MmioRef<T: 'static>(NonNull<T>);
+
+	impl<T> MmioRef<T> {
+		/// # Safety
+		///
+		/// `raw` must reference device memory upholding the invariants of [MmioRef].
+		pub unsafe fn new(raw: &'static T) -> Self {
+			MmioRef(NonNull::from(raw))
+		}
+	}
+
+	impl<T> Deref for MmioRef<T> {
+		type Target = T;
+
+		fn deref(&self) -> &T {
+			// SAFETY: The structure stays mapped, see the invariants of MmioRef.
+			unsafe { self.0.as_ref() }
+		}
+	}
+
+	// SAFETY: The referenced memory belongs to the device and not to a core. All
+	// accesses are volatile, see the invariants of MmioRef.
+	unsafe impl<T> Send for MmioRef<T> {}
+	unsafe impl<T> Sync for MmioRef<T> {}
 
 	/// A newtype representing a memory offset which can be used to be added to [PhyMemAddr](PhyMemAddr) or
 	/// to [VirtMemAddr](VirtMemAddr).
@@ -169,6 +205,109 @@ pub mod memory {
 	}
 }
 
+/// This module abstracts the allocation of memory, which is shared between
+/// driver and device (e.g. virtqueue rings and buffers).
+pub mod dma {
+	#[cfg(all(test, not(target_os = "none")))]
+	use alloc::boxed::Box;
+	#[cfg(all(test, not(target_os = "none")))]
+	use core::cell::Cell;
+
+	use align_address::Align;
+
+	use crate::arch::mm::paging::{BasePageSize, PageSize};
+	use crate::arch::mm::{paging, PhysAddr, VirtAddr};
+
+	/// Backend used by the virtio drivers to allocate DMA-able memory.
+	///
+	/// Memory areas returned by an allocator MUST be physically contiguous, as
+	/// they are handed to the device via their physical address.
+	pub trait DmaAllocator: core::fmt::Debug {
+		/// Allocates a physically contiguous memory area of at least `size` bytes, which is
+		/// aligned to `align` bytes. Returns the virtual and the physical start address of the
+		/// area or None, if the request can not be fulfilled.
+		fn alloc_contiguous(&self, size: usize, align: usize) -> Option<(VirtAddr, PhysAddr)>;
+
+		/// Frees a memory area, which has been allocated via `alloc_contiguous` with the
+		/// same size.
+		fn free(&self, virt: VirtAddr, size: usize);
+	}
+
+	/// Default [DmaAllocator], which uses the memory management of the kernel.
+	///
+	/// Memory is allocated in multiples of the page size, hence alignments up to the
+	/// page size are supported.
+	#[derive(Debug, Default, Copy, Clone)]
+	pub struct KernelAllocator;
+
+	impl DmaAllocator for KernelAllocator {
+		fn alloc_contiguous(&self, size: usize, align: usize) -> Option<(VirtAddr, PhysAddr)> {
+			if size == 0 || !align.is_power_of_two() || align > BasePageSize::SIZE as usize {
+				return None;
+			}
+
+			let virt = crate::mm::allocate(size.align_up(BasePageSize::SIZE as usize), true);
+			let phys = paging::virt_to_phys(virt);
+
+			// Assert area does not cross a non-contiguous physical page barrier
+			let end_virt = virt.0 as usize + (size - 1);
+			assert_eq!(
+				paging::virt_to_phys(VirtAddr::from(end_virt)),
+				phys + (size - 1)
+			);
+
+			Some((virt, phys))
+		}
+
+		fn free(&self, virt: VirtAddr, size: usize) {
+			crate::mm::deallocate(virt, size.align_up(BasePageSize::SIZE as usize));
+		}
+	}
+
+	/// Bump allocator on top of a leaked heap area for host tests. Physical addresses
+	/// are identical to virtual ones, hence the test can access memory handed to the
+	/// device directly.
+	#[cfg(all(test, not(target_os = "none")))]
+	#[derive(Debug)]
+	pub(crate) struct MockArena {
+		pub(crate) base: usize,
+		len: usize,
+		next: Cell<usize>,
+		/// Number of bytes, which have been freed.
+		pub(crate) freed: Cell<usize>,
+	}
+
+	#[cfg(all(test, not(target_os = "none")))]
+	impl MockArena {
+		pub(crate) fn leak(len: usize) -> &'static MockArena {
+			let mem = Box::leak(vec![0u8; len].into_boxed_slice());
+			Box::leak(Box::new(MockArena {
+				base: mem.as_ptr() as usize,
+				len,
+				next: Cell::new(0),
+				freed: Cell::new(0),
+			}))
+		}
+	}
+
+	#[cfg(all(test, not(target_os = "none")))]
+	impl DmaAllocator for MockArena {
+		fn alloc_contiguous(&self, size: usize, align: usize) -> Option<(VirtAddr, PhysAddr)> {
+			let start = (self.base + self.next.get()).align_up(align);
+			if start + size > self.base + self.len {
+				return None;
+			}
+			self.next.set(start + size - self.base);
+
+			Some((VirtAddr::from(start), PhysAddr::from(start)))
+		}
+
+		fn free(&self, _virt: VirtAddr, size: usize) {
+			self.freed.set(self.freed.get() + size);
+		}
+	}
+}
+
 /// This module is used as a single entry point from Virtio code into
 /// other parts of the kernel.
 ///
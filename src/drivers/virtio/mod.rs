@@ -37,21 +37,13 @@ pub mod error {
                     PciError::NoBar(id ) => write!(f, "Driver failed to initialize device with id: {id:#x}. Reason: No BAR's found."), 
                     PciError::NoCapPtr(id) => write!(f, "Driver failed to initialize device with id: {id:#x}. Reason: No Capabilities pointer found."),
                     PciError::BadCapPtr(id) => write!(f, "Driver failed to initialize device with id: {id:#x}. Reason: Malformed Capabilities pointer."),
+                    PciError::TruncatedCapList(id) => write!(f, "Driver failed to initialize device with id: {id:#x}. Reason: Capabilities list exceeds the configuration space."),
+                    PciError::CapListLoop(id) => write!(f, "Driver failed to initialize device with id: {id:#x}. Reason: Capabilities list does not terminate."),
                     PciError::NoVirtioCaps(id) => write!(f, "Driver failed to initialize device with id: {id:#x}. Reason: No Virtio capabilities were found."),
+                    PciError::NoComCfg(id) => write!(f, "Driver failed to initialize device with id: {id:#x}. Reason: No common configuration structure was found."),
                 },
                 VirtioError::DevNotSupported(id) => write!(f, "Device with id {id:#x} not supported."),
-                VirtioError::NetDriver(net_error) => match net_error {
-                    VirtioNetError::General => write!(f, "Virtio network driver failed due to unknown reasons!"),
-                    VirtioNetError::NoDevCfg(id) => write!(f, "Virtio network driver failed, for device {id:x}, due to a missing or malformed device config!"),
-                    VirtioNetError::NoComCfg(id) =>  write!(f, "Virtio network driver failed, for device {id:x}, due to a missing or malformed common config!"),
-                    VirtioNetError::NoIsrCfg(id) =>  write!(f, "Virtio network driver failed, for device {id:x}, due to a missing or malformed ISR status config!"),
-                    VirtioNetError::NoNotifCfg(id) =>  write!(f, "Virtio network driver failed, for device {id:x}, due to a missing or malformed notification config!"),
-                    VirtioNetError::FailFeatureNeg(id) => write!(f, "Virtio network driver failed, for device {id:x}, device did not acknowledge negotiated feature set!"),
-                    VirtioNetError::FeatReqNotMet(feats) => write!(f, "Virtio network driver tried to set feature bit without setting dependency feature. Feat set: {:x}", u64::from(*feats)),
-                    VirtioNetError::IncompFeatsSet(drv_feats, dev_feats) => write!(f, "Feature set: {:x} , is incompatible with the device features: {:x}", u64::from(*drv_feats), u64::from(*dev_feats)),
-                    VirtioNetError::ProcessOngoing => write!(f, "Virtio network performed an unsuitable operation upon an ongoging transfer."),
-					VirtioNetError::Unknown => write!(f, "Virtio network driver failed due unknown reason!"),
-                },
+                VirtioError::NetDriver(net_error) => write!(f, "{net_error}"),
 				#[cfg(feature = "pci")]
 				VirtioError::FsDriver(fs_error) => match fs_error {
 					VirtioFsError::NoDevCfg(id) => write!(f, "Virtio filesystem driver failed, for device {id:x}, due to a missing or malformed device config!"),
@@ -9,6 +9,7 @@ use core::result::Result;
 use core::sync::atomic::{fence, Ordering};
 use core::u8;
 
+use crate::arch::kernel::mmio::MAGIC_VALUE;
 use crate::arch::mm::PhysAddr;
 use crate::arch::x86_64::kernel::interrupts::*;
 use crate::drivers::error::DriverError;
@@ -16,6 +17,7 @@ use crate::drivers::net::network_irqhandler;
 use crate::drivers::net::virtio_net::VirtioNetDriver;
 use crate::drivers::virtio::device;
 use crate::drivers::virtio::error::VirtioError;
+use crate::drivers::virtio::transport::VirtioTransport;
 
 /// Virtio device ID's
 /// See Virtio specification v1.1. - 5
@@ -233,6 +235,13 @@ impl ComCfg {
 		self.com_cfg.set_drv_features(feats);
 	}
 
+	/// Reading back the driver features is not possible, as the DriverFeatures
+	/// register of MMIO devices is write-only. Hence always returns None.
+	/// See Virtio specification v1.1. - 4.2.2
+	pub fn drv_features(&mut self) -> Option<u64> {
+		None
+	}
+
 	pub fn print_information(&mut self) {
 		self.com_cfg.print_information();
 	}
@@ -315,24 +324,14 @@ impl IsrStatus {
 		IsrStatus { raw }
 	}
 
-	pub fn is_interrupt(&self) -> bool {
-		unsafe {
-			let status = read_volatile(&self.raw.interrupt_status);
-			status & 0x1 == 0x1
-		}
-	}
-
-	pub fn is_cfg_change(&self) -> bool {
-		unsafe {
-			let status = read_volatile(&self.raw.interrupt_status);
-			status & 0x2 == 0x2
-		}
-	}
-
-	pub fn acknowledge(&mut self) {
+	/// Reads the interrupt status and acknowledges exactly the read events, so an
+	/// event raised in between stays pending. Returns the status bitmap.
+	/// See Virtio specification v1.1. - 4.2.2
+	pub fn read_and_acknowledge(&mut self) -> u32 {
 		unsafe {
 			let status = read_volatile(&self.raw.interrupt_status);
 			write_volatile(&mut self.raw.interrupt_ack, status);
+			status
 		}
 	}
 }
@@ -343,6 +342,83 @@ struct IsrStatusRaw {
 	interrupt_ack: u32,
 }
 
+/// Transport of a virtio device, whose register block is mapped into memory.
+/// See Virtio specification v1.1. - 4.2
+pub struct MmioTransport {
+	dev_id: u16,
+	device_type: DevId,
+	/// Address of the ConfigGeneration register, which is followed by the device
+	/// specific configuration.
+	dev_cfg: usize,
+	com_cfg: Option<ComCfg>,
+	notif_cfg: Option<NotifCfg>,
+	isr_stat: Option<IsrStatus>,
+}
+
+impl MmioTransport {
+	/// Checks, that `registers` belong to a virtio device, which provides the non-legacy
+	/// interface, and splits them into the configuration structures of the device.
+	///
+	/// As MMIO devices do not provide an id of their own, the device ID register, i.e. the
+	/// type of the device, is used like the device id of a PCI device.
+	pub fn new(registers: &'static mut MmioRegisterLayout) -> Result<Self, VirtioError> {
+		let device_type = registers.get_device_id();
+		let dev_id = u16::try_from(u32::from(device_type)).unwrap();
+
+		if registers.get_magic_value() != MAGIC_VALUE {
+			error!("Register block is not a virtio MMIO device!");
+			return Err(VirtioError::DevNotSupported(dev_id));
+		}
+		if registers.get_version() != 2 {
+			error!("Legacy interface isn't supported!");
+			return Err(VirtioError::DevNotSupported(dev_id));
+		}
+
+		let dev_cfg = registers as *const _ as usize + 0xFC;
+		let isr_stat = IsrStatus::new(registers);
+		let notif_cfg = NotifCfg::new(registers);
+
+		Ok(MmioTransport {
+			dev_id,
+			device_type,
+			dev_cfg,
+			com_cfg: Some(ComCfg::new(registers, 1)),
+			notif_cfg: Some(notif_cfg),
+			isr_stat: Some(isr_stat),
+		})
+	}
+
+	/// Returns the id of the device, which is logged by the drivers.
+	pub fn dev_id(&self) -> u16 {
+		self.dev_id
+	}
+
+	/// Returns the type of the device.
+	pub fn device_type(&self) -> DevId {
+		self.device_type
+	}
+
+	/// Returns the address of the device specific configuration, including the
+	/// preceding ConfigGeneration register.
+	pub fn dev_cfg_addr(&self) -> usize {
+		self.dev_cfg
+	}
+}
+
+impl VirtioTransport for MmioTransport {
+	fn take_com_cfg(&mut self) -> Option<ComCfg> {
+		self.com_cfg.take()
+	}
+
+	fn take_notif_cfg(&mut self) -> Option<NotifCfg> {
+		self.notif_cfg.take()
+	}
+
+	fn take_isr_cfg(&mut self) -> Option<IsrStatus> {
+		self.isr_stat.take()
+	}
+}
+
 pub enum VirtioDriver {
 	Network(VirtioNetDriver),
 }
@@ -351,19 +427,13 @@ pub fn init_device(
 	registers: &'static mut MmioRegisterLayout,
 	irq_no: u32,
 ) -> Result<VirtioDriver, DriverError> {
-	let dev_id: u16 = 0;
-
-	if registers.version == 0x1 {
-		error!("Legacy interface isn't supported!");
-		return Err(DriverError::InitVirtioDevFail(
-			VirtioError::DevNotSupported(dev_id),
-		));
-	}
+	let transport = MmioTransport::new(registers)?;
+	let dev_id = transport.dev_id();
 
 	// Verify the device-ID to find the network card
-	match registers.device_id {
+	match transport.device_type() {
 		DevId::VIRTIO_DEV_ID_NET => {
-			match VirtioNetDriver::init(dev_id, registers, irq_no) {
+			match VirtioNetDriver::init(transport, irq_no) {
 				Ok(virt_net_drv) => {
 					info!("Virtio network driver initialized.");
 					// Install interrupt handler
@@ -381,7 +451,7 @@ pub fn init_device(
 		_ => {
 			error!(
 				"Device with id {:?} is currently not supported!",
-				registers.device_id
+				transport.device_type()
 			);
 			// Return Driver error inidacting device is not supported
 			Err(DriverError::InitVirtioDevFail(
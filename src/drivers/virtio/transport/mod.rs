@@ -1,10 +1,33 @@
 //! A module containing virtios transport mechanisms.
 //!
-//! The module contains only PCI specific transport mechanism.
-//! Other mechanisms (MMIO and Channel I/O) are currently not
-//! supported.
+//! The module contains the PCI and the MMIO transport mechanism, of which
+//! one is built, depending on the `pci` feature. Channel I/O is currently
+//! not supported.
 
 #[cfg(not(feature = "pci"))]
 pub mod mmio;
 #[cfg(feature = "pci")]
 pub mod pci;
+
+#[cfg(not(feature = "pci"))]
+use self::mmio::{ComCfg, IsrStatus, NotifCfg};
+#[cfg(feature = "pci")]
+use self::pci::{ComCfg, IsrStatus, NotifCfg};
+
+/// Configuration structures of a virtio device, which are provided by its transport.
+///
+/// A driver takes the common configuration, the notification configuration and the
+/// ISR status of its device from the transport. Afterwards the device is accessed
+/// through these structures only, hence feature negotiation and the device specific
+/// initialization do not depend on the transport. See Virtio specification v1.1. - 4
+///
+/// Each structure is removed by the respective function, which returns `None` once the
+/// transport does not provide another one.
+pub trait VirtioTransport {
+	/// Returns the common configuration structure with the highest priority.
+	fn take_com_cfg(&mut self) -> Option<ComCfg>;
+	/// Returns the notification structure with the highest priority.
+	fn take_notif_cfg(&mut self) -> Option<NotifCfg>;
+	/// Returns the ISR status structure with the highest priority.
+	fn take_isr_cfg(&mut self) -> Option<IsrStatus>;
+}
@@ -5,18 +5,20 @@
 
 use alloc::vec::Vec;
 use core::mem;
+use core::ptr::{read_volatile, write_volatile};
 use core::result::Result;
 
 use crate::arch::kernel::pci::error::PciError;
 use crate::arch::kernel::pci::PciAdapter;
-use crate::arch::mm::PhysAddr;
+use crate::arch::mm::{PhysAddr, VirtAddr};
 use crate::arch::x86_64::kernel::interrupts::*;
 use crate::drivers::error::DriverError;
 use crate::drivers::fs::virtio_fs::VirtioFsDriver;
-use crate::drivers::net::network_irqhandler;
-use crate::drivers::net::virtio_net::VirtioNetDriver;
+use crate::drivers::net::virtio_net::{self, VirtioNetDriver};
+use crate::drivers::net::{network_cfg_irqhandler, network_irqhandler};
 use crate::drivers::virtio::env::memory::{MemLen, MemOff, VirtMemAddr};
 use crate::drivers::virtio::error::VirtioError;
+use crate::drivers::virtio::transport::VirtioTransport;
 use crate::drivers::virtio::{device, env};
 
 /// Virtio device ID's
@@ -84,7 +86,7 @@ impl From<u16> for DevId {
 // implementation, in order catch all cases correctly,
 // as this function uses the catch-all "_" case!
 #[allow(dead_code, non_camel_case_types, clippy::upper_case_acronyms)]
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(u8)]
 pub enum CfgType {
 	INVALID = 0,
@@ -138,6 +140,15 @@ pub struct Origin {
 /// Maps a given device specific pci configuration structure and
 /// returns a static reference to it.
 pub fn map_dev_cfg<T>(cap: &PciCap) -> Option<&'static mut T> {
+	map_dev_cfg_with_len(cap, mem::size_of::<T>())
+}
+
+/// Maps a given device specific pci configuration structure like [map_dev_cfg], but only
+/// requires the capability to provide `min_len` bytes of the structure. This allows
+/// structures, whose trailing fields only exist with certain features.
+///
+/// The caller must not access fields beyond the length of the capability.
+pub fn map_dev_cfg_with_len<T>(cap: &PciCap, min_len: usize) -> Option<&'static mut T> {
 	if cap.cfg_type != CfgType::VIRTIO_PCI_CAP_DEVICE_CFG {
 		error!("Capability of device config has wrong id. Mapping not possible...");
 		return None;
@@ -152,19 +163,37 @@ pub fn map_dev_cfg<T>(cap: &PciCap) -> Option<&'static mut T> {
 	}
 
 	// Drivers MAY do this check. See Virtio specification v1.1. - 4.1.4.1
-	if cap.len() < MemLen::from(mem::size_of::<T>()) {
+	if cap.len() < MemLen::from(min_len) {
 		error!("Device specific config from device {:x}, does not represent actual structure specified by the standard!", cap.dev_id());
 		return None;
 	}
 
 	let virt_addr_raw: VirtMemAddr = cap.bar_addr() + cap.offset();
+	let Some(ptr) = cfg_ptr::<T>(usize::from(virt_addr_raw)) else {
+		error!(
+			"Device config of device {:x} is located at invalid address {:#x}. BAR might not be assigned!",
+			cap.dev_id(),
+			usize::from(virt_addr_raw)
+		);
+		return None;
+	};
 
 	// Create mutable reference to the PCI structure in PCI memory
-	let dev_cfg: &'static mut T = unsafe { &mut *(usize::from(virt_addr_raw) as *mut T) };
+	let dev_cfg: &'static mut T = unsafe { &mut *ptr };
 
 	Some(dev_cfg)
 }
 
+/// Returns `addr` as pointer to `T`, if it is neither null nor misaligned for `T`. A null
+/// address indicates a BAR, to which the firmware did not assign memory.
+fn cfg_ptr<T>(addr: usize) -> Option<*mut T> {
+	if addr == 0 || addr % mem::align_of::<T>() != 0 {
+		None
+	} else {
+		Some(addr as *mut T)
+	}
+}
+
 /// Virtio's PCI capabilities structure.
 /// See Virtio specification v.1.1 - 4.1.4
 ///
@@ -210,6 +239,32 @@ impl PciCap {
 	pub fn dev_id(&self) -> u16 {
 		self.origin.dev_id
 	}
+
+	/// Returns where the capability's structure is located.
+	pub fn bar_info(&self) -> BarInfo {
+		BarInfo {
+			cfg_type: self.cfg_type,
+			bar: self.bar.index,
+			bar_addr: self.bar.mem_addr,
+			offset: self.offset,
+			length: self.length,
+		}
+	}
+}
+
+/// Location of a configuration structure inside a BAR, as resolved by the driver
+/// from the respective capability. Intended for diagnostics.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BarInfo {
+	pub cfg_type: CfgType,
+	/// Index of the BAR holding the structure.
+	pub bar: u8,
+	/// Address the BAR is mapped to.
+	pub bar_addr: VirtMemAddr,
+	/// Offset of the structure inside the BAR.
+	pub offset: MemOff,
+	/// Length of the structure.
+	pub length: MemLen,
 }
 
 /// Virtio's PCI capabilities structure.
@@ -369,6 +424,27 @@ impl UniCapsColl {
 	pub fn get_notif_cfg(&mut self) -> Option<NotifCfg> {
 		self.notif_cfg_list.pop()
 	}
+
+	/// Returns the highest prioritized shared memory structure.
+	///
+	/// INFO: This function removes the Capability and returns ownership.
+	pub fn get_sh_mem_cfg(&mut self) -> Option<ShMemCfg> {
+		self.sh_mem_cfg_list.pop()
+	}
+}
+
+impl VirtioTransport for UniCapsColl {
+	fn take_com_cfg(&mut self) -> Option<ComCfg> {
+		self.get_com_cfg()
+	}
+
+	fn take_notif_cfg(&mut self) -> Option<NotifCfg> {
+		self.get_notif_cfg()
+	}
+
+	fn take_isr_cfg(&mut self) -> Option<IsrStatus> {
+		self.get_isr_cfg()
+	}
 }
 
 /// Wraps a [ComCfgRaw](structs.comcfgraw.html) in order to preserve
@@ -382,18 +458,31 @@ pub struct ComCfg {
 	com_cfg: &'static mut ComCfgRaw,
 	/// Preferences of the device for this config. From 1 (highest) to 2^7-1 (lowest)
 	rank: u8,
+	bar_info: BarInfo,
+	/// MSI-X table entry, which is assigned to virtqueues upon their activation.
+	queue_vector: u16,
 }
 
 // Private interface of ComCfg
 impl ComCfg {
-	fn new(raw: &'static mut ComCfgRaw, rank: u8) -> Self {
-		ComCfg { com_cfg: raw, rank }
+	fn new(raw: &'static mut ComCfgRaw, rank: u8, bar_info: BarInfo) -> Self {
+		ComCfg {
+			com_cfg: raw,
+			rank,
+			bar_info,
+			queue_vector: VIRTIO_MSI_NO_VECTOR,
+		}
 	}
 }
 
+/// Value of the MSI-X vector fields, which indicates that no interrupt is signalled.
+/// See Virtio specification v1.1. - 4.1.5.1.2
+pub const VIRTIO_MSI_NO_VECTOR: u16 = 0xffff;
+
 pub struct VqCfgHandler<'a> {
 	vq_index: u16,
 	raw: &'a mut ComCfgRaw,
+	vector: u16,
 }
 
 impl<'a> VqCfgHandler<'a> {
@@ -431,14 +520,33 @@ impl<'a> VqCfgHandler<'a> {
 		self.raw.queue_notify_off
 	}
 
+	/// Enables the queue. If an MSI-X table entry has been set via
+	/// [ComCfg::set_queue_msix_vector], it is assigned to the queue beforehand.
 	pub fn enable_queue(&mut self) {
 		self.raw.queue_select = self.vq_index;
+		if self.vector != VIRTIO_MSI_NO_VECTOR {
+			unsafe {
+				write_volatile(&mut self.raw.queue_msix_vector, self.vector);
+				// The device indicates a failed allocation of the vector with NO_VECTOR.
+				if read_volatile(&self.raw.queue_msix_vector) != self.vector {
+					warn!(
+						"Device did not accept MSI-X vector {} for virtqueue {}.",
+						self.vector, self.vq_index
+					);
+				}
+			}
+		}
 		self.raw.queue_enable = 1;
 	}
 }
 
 // Public Interface of ComCfg
 impl ComCfg {
+	/// Returns where the structure is located.
+	pub fn bar_info(&self) -> BarInfo {
+		self.bar_info
+	}
+
 	/// Select a queue via an index. If queue does NOT exist returns `None`, else
 	/// returns `Some(VqCfgHandler)`.
 	///
@@ -452,6 +560,7 @@ impl ComCfg {
 			Some(VqCfgHandler {
 				vq_index: index,
 				raw: self.com_cfg,
+				vector: self.queue_vector,
 			})
 		}
 	}
@@ -461,6 +570,41 @@ impl ComCfg {
 		self.com_cfg.device_status
 	}
 
+	/// Returns the maximal number of virtqueues supported by the device.
+	pub fn num_queues(&self) -> u16 {
+		self.com_cfg.num_queues
+	}
+
+	/// Assigns the MSI-X table entry `vector` to device configuration changes.
+	/// Returns false, if the device could not allocate the vector.
+	///
+	/// As a reset of the device removes the assignment, it must be made after the reset.
+	/// See Virtio specification v1.1. - 4.1.5.1.2
+	pub fn set_config_msix_vector(&mut self, vector: u16) -> bool {
+		unsafe {
+			write_volatile(&mut self.com_cfg.config_msix_vector, vector);
+			read_volatile(&self.com_cfg.config_msix_vector) == vector
+		}
+	}
+
+	/// Sets the MSI-X table entry, which is assigned to all virtqueues enabled afterwards.
+	pub fn set_queue_msix_vector(&mut self, vector: u16) {
+		self.queue_vector = vector;
+	}
+
+	/// Assigns the MSI-X table entry `vector` to the already enabled virtqueue with the
+	/// given index. Returns false, if the queue does not exist or the device could not
+	/// allocate the vector.
+	pub fn set_vq_msix_vector(&mut self, index: u16, vector: u16) -> bool {
+		match self.select_vq(index) {
+			Some(vq_handler) => unsafe {
+				write_volatile(&mut vq_handler.raw.queue_msix_vector, vector);
+				read_volatile(&vq_handler.raw.queue_msix_vector) == vector
+			},
+			None => false,
+		}
+	}
+
 	/// Resets the device status field to zero.
 	pub fn reset_dev(&mut self) {
 		self.com_cfg.device_status = 0;
@@ -511,22 +655,29 @@ impl ComCfg {
 	}
 
 	/// Returns the features offered by the device. Coded in a 64bit value.
+	///
+	/// The accesses must be volatile, as the device changes the content of the
+	/// `device_feature` field depending on the `device_feature_select` field. Otherwise
+	/// the compiler is free to merge both reads, which results in a loss of the high feature bits
+	/// (e.g. VIRTIO_F_VERSION_1).
 	pub fn dev_features(&mut self) -> u64 {
-		// Indicate device to show high 32 bits in device_feature field.
-		// See Virtio specification v1.1. - 4.1.4.3
-		self.com_cfg.device_feature_select = 1;
+		unsafe {
+			// Indicate device to show high 32 bits in device_feature field.
+			// See Virtio specification v1.1. - 4.1.4.3
+			write_volatile(&mut self.com_cfg.device_feature_select, 1u32);
 
-		// read high 32 bits of device features
-		let mut dev_feat = u64::from(self.com_cfg.device_feature) << 32;
+			// read high 32 bits of device features
+			let mut dev_feat = u64::from(read_volatile(&self.com_cfg.device_feature)) << 32;
 
-		// Indicate device to show low 32 bits in device_feature field.
-		// See Virtio specification v1.1. - 4.1.4.3
-		self.com_cfg.device_feature_select = 0;
+			// Indicate device to show low 32 bits in device_feature field.
+			// See Virtio specification v1.1. - 4.1.4.3
+			write_volatile(&mut self.com_cfg.device_feature_select, 0u32);
 
-		// read low 32 bits of device features
-		dev_feat |= u64::from(self.com_cfg.device_feature);
+			// read low 32 bits of device features
+			dev_feat |= u64::from(read_volatile(&self.com_cfg.device_feature));
 
-		dev_feat
+			dev_feat
+		}
 	}
 
 	/// Write selected features into driver_select field.
@@ -534,19 +685,44 @@ impl ComCfg {
 		let high: u32 = (feats >> 32) as u32;
 		let low: u32 = feats as u32;
 
-		// Indicate to device that driver_features field shows low 32 bits.
-		// See Virtio specification v1.1. - 4.1.4.3
-		self.com_cfg.driver_feature_select = 0;
+		// Volatile accesses are needed, as otherwise the compiler is free to drop the
+		// first write to the `driver_feature` field. See `dev_features()`.
+		unsafe {
+			// Indicate to device that driver_features field shows low 32 bits.
+			// See Virtio specification v1.1. - 4.1.4.3
+			write_volatile(&mut self.com_cfg.driver_feature_select, 0u32);
+
+			// write low 32 bits of device features
+			write_volatile(&mut self.com_cfg.driver_feature, low);
 
-		// write low 32 bits of device features
-		self.com_cfg.driver_feature = low;
+			// Indicate to device that driver_features field shows high 32 bits.
+			// See Virtio specification v1.1. - 4.1.4.3
+			write_volatile(&mut self.com_cfg.driver_feature_select, 1u32);
 
-		// Indicate to device that driver_features field shows high 32 bits.
-		// See Virtio specification v1.1. - 4.1.4.3
-		self.com_cfg.driver_feature_select = 1;
+			// write high 32 bits of device features
+			write_volatile(&mut self.com_cfg.driver_feature, high);
+		}
+	}
+
+	/// Reads back the features written via `set_drv_features()` from the driver_feature field.
+	pub fn drv_features(&mut self) -> Option<u64> {
+		unsafe {
+			// Indicate device to show high 32 bits in driver_feature field.
+			// See Virtio specification v1.1. - 4.1.4.3
+			write_volatile(&mut self.com_cfg.driver_feature_select, 1u32);
+
+			// read high 32 bits of driver features
+			let mut drv_feat = u64::from(read_volatile(&self.com_cfg.driver_feature)) << 32;
+
+			// Indicate device to show low 32 bits in driver_feature field.
+			// See Virtio specification v1.1. - 4.1.4.3
+			write_volatile(&mut self.com_cfg.driver_feature_select, 0u32);
+
+			// read low 32 bits of driver features
+			drv_feat |= u64::from(read_volatile(&self.com_cfg.driver_feature));
 
-		// write high 32 bits of device features
-		self.com_cfg.driver_feature = high;
+			Some(drv_feat)
+		}
 	}
 }
 
@@ -619,6 +795,7 @@ pub struct NotifCfg {
 	rank: u8,
 	/// defines the maximum size of the notification space, starting from base_addr.
 	length: MemLen,
+	bar_info: BarInfo,
 }
 
 impl NotifCfg {
@@ -654,9 +831,15 @@ impl NotifCfg {
 			notify_off_multiplier,
 			rank: cap.id,
 			length: cap.length,
+			bar_info: cap.bar_info(),
 		})
 	}
 
+	/// Returns where the structure is located.
+	pub fn bar_info(&self) -> BarInfo {
+		self.bar_info
+	}
+
 	/// Returns base address of notification area as an usize
 	pub fn base(&self) -> usize {
 		usize::from(self.base_addr)
@@ -731,26 +914,29 @@ pub struct IsrStatus {
 	isr_stat: &'static mut IsrStatusRaw,
 	/// Preferences of the device for this config. From 1 (highest) to 2^7-1 (lowest)
 	rank: u8,
+	bar_info: BarInfo,
 }
 
 impl IsrStatus {
-	fn new(raw: &'static mut IsrStatusRaw, rank: u8) -> Self {
+	fn new(raw: &'static mut IsrStatusRaw, rank: u8, bar_info: BarInfo) -> Self {
 		IsrStatus {
 			isr_stat: raw,
 			rank,
+			bar_info,
 		}
 	}
 
-	pub fn is_interrupt(&self) -> bool {
-		self.isr_stat.flags & 1 << 0 == 1
+	/// Returns where the structure is located.
+	pub fn bar_info(&self) -> BarInfo {
+		self.bar_info
 	}
 
-	pub fn is_cfg_change(&self) -> bool {
-		self.isr_stat.flags & 1 << 1 == 1 << 1
-	}
-
-	pub fn acknowledge(&mut self) {
-		// nothing to do
+	/// Reads the ISR status and returns its bitmap, see [IsrStatusRaw].
+	///
+	/// The device clears the status upon reading, hence it must be read exactly once
+	/// per interrupt. Otherwise an event raised in between is lost.
+	pub fn read_and_acknowledge(&mut self) -> u32 {
+		u32::from(unsafe { read_volatile(&self.isr_stat.flags) })
 	}
 }
 
@@ -797,18 +983,6 @@ impl IsrStatusRaw {
 
 		Some(isr_stat_raw)
 	}
-
-	// returns true if second bit, from left is 1.
-	// read DOES reset flag
-	fn cfg_event() -> bool {
-		unimplemented!();
-	}
-
-	// returns true if first bit, from left is 1.
-	// read DOES reset flag
-	fn vqueue_event() -> bool {
-		unimplemented!();
-	}
 }
 
 /// PCI configuration access structure of Virtio PCI devices.
@@ -865,45 +1039,40 @@ pub struct ShMemCfg {
 	/// Shared memory regions are identified via an ID
 	/// See Virtio specification v1.1. - 4.1.4.7
 	id: u8,
+	bar_info: BarInfo,
 }
 
 impl ShMemCfg {
 	fn new(cap: &PciCap) -> Option<Self> {
-		if cap.bar.length < u64::from(cap.length + cap.offset) {
-			error!("Shared memory config of with id {} of device {:x}, does not fit into memory specified by bar {:x}!", 
-                cap.id,
-                cap.origin.dev_id,
-                 cap.bar.index
-            );
-			return None;
-		}
-
 		// Read the PciCap64 fields after the PciCap structure to get the right offset and length
+		let cap64_ptr = cap.origin.cfg_ptr + u32::try_from(mem::size_of::<PciCapRaw>()).unwrap();
+		let offset_high = env::pci::read_cfg_no_adapter(cap.origin.bus, cap.origin.dev, cap64_ptr);
+		let length_high =
+			env::pci::read_cfg_no_adapter(cap.origin.bus, cap.origin.dev, cap64_ptr + 4);
+
+		// Create 64 bit offset and length from high and low 32 bit values
+		let offset = (u64::from(offset_high) << 32) | u64::from(cap.origin.cap_struct.offset);
+		let length = (u64::from(length_high) << 32) | u64::from(cap.origin.cap_struct.length);
+
+		match offset.checked_add(length) {
+			Some(end) if end <= cap.bar.length => (),
+			_ => {
+				error!(
+					"Shared memory config with id {} of device {:x}, does not fit into memory specified by bar {:x}!",
+					cap.id, cap.origin.dev_id, cap.bar.index
+				);
+				return None;
+			}
+		}
 
-		// Assumes the cap_len is a multiple of 8
-		// This read MIGHT be slow, as it does NOT ensure 32 bit alignment.
-		let offset_high = env::pci::read_cfg_no_adapter(
-			cap.origin.bus,
-			cap.origin.bus,
-			cap.origin.cfg_ptr + u32::from(cap.origin.cap_struct.cap_len),
-		);
-
-		// Create 64 bit offset from high and low 32 bit values
-		let offset =
-			MemOff::from((u64::from(offset_high) << 32) ^ u64::from(cap.origin.cap_struct.offset));
-
-		// Assumes the cap_len is a multiple of 8
-		// This read MIGHT be slow, as it does NOT ensure 32 bit alignment.
-		let length_high = env::pci::read_cfg_no_adapter(
-			cap.origin.bus,
-			cap.origin.bus,
-			cap.origin.cfg_ptr + u32::from(cap.origin.cap_struct.cap_len + 4),
-		);
-
-		// Create 64 bit length from high and low 32 bit values
-		let length =
-			MemLen::from((u64::from(length_high) << 32) ^ u64::from(cap.origin.cap_struct.length));
+		// Currently in place in order to ensure a safe cast below
+		// "len: length as usize"
+		// In order to remove this assert a safe conversion from
+		// kernel PciBar struct into usize must be made
+		assert!(mem::size_of::<usize>() == 8);
 
+		let offset = MemOff::from(offset);
+		let length = MemLen::from(length);
 		let virt_addr_raw = cap.bar.mem_addr + offset;
 		let raw_ptr = usize::from(virt_addr_raw) as *mut u8;
 
@@ -914,28 +1083,42 @@ impl ShMemCfg {
 			}
 		};
 
-		// Currently in place in order to ensure a safe cast below
-		// "len: cap.bar.length as usize"
-		// In order to remove this assert a safe conversion from
-		// kernel PciBar struct into usize must be made
-		assert!(mem::size_of::<usize>() == 8);
-
 		Some(ShMemCfg {
 			mem_addr: virt_addr_raw,
-			length: cap.length,
+			length,
 			sh_mem: ShMem {
 				ptr: raw_ptr,
-				len: cap.bar.length as usize,
+				len: usize::from(length),
 			},
 			id: cap.id,
+			bar_info: BarInfo {
+				offset,
+				length,
+				..cap.bar_info()
+			},
 		})
 	}
+
+	/// Returns the id of the shared memory region.
+	pub fn id(&self) -> u8 {
+		self.id
+	}
+
+	/// Returns the shared memory region.
+	pub fn sh_mem(&self) -> &ShMem {
+		&self.sh_mem
+	}
+
+	/// Returns where the structure is located.
+	pub fn bar_info(&self) -> BarInfo {
+		self.bar_info
+	}
 }
 
 /// Defines a shared memory locate at location ptr with a length of len.
 /// The shared memories Drop implementation does not dealloc the memory
 /// behind the pointer but sets it to zero, to prevent leakage of data.
-struct ShMem {
+pub struct ShMem {
 	ptr: *mut u8,
 	len: usize,
 }
@@ -986,6 +1169,13 @@ impl PciBar {
 	}
 }
 
+/// Size of the configuration space of a conventional PCI device.
+const PCI_CFG_SPACE_SIZE: u32 = 0x100;
+
+/// Number of capabilities, which fit at most into the configuration space behind
+/// the header, as each one is dword aligned.
+const MAX_CAPS: usize = ((PCI_CFG_SPACE_SIZE - 0x40) / 4) as usize;
+
 /// Reads a raw capability struct [PciCapRaw](structs.PcicapRaw.html) out of a PCI device's configuration space.
 fn read_cap_raw(adapter: &PciAdapter, register: u32) -> PciCapRaw {
 	let mut quadruple_word: [u8; 16] = [0; 16];
@@ -1001,18 +1191,86 @@ fn read_cap_raw(adapter: &PciAdapter, register: u32) -> PciCapRaw {
 		quadruple_word[i..i + 4].copy_from_slice(&word);
 	}
 
-	PciCapRaw {
-		cap_vndr: quadruple_word[0],
-		cap_next: quadruple_word[1],
-		cap_len: quadruple_word[2],
-		cfg_type: quadruple_word[3],
-		bar_index: quadruple_word[4],
-		id: quadruple_word[5],
-		// Unwrapping is okay here, as transformed array slice is always 2 * u8 long and initialized
-		padding: quadruple_word[6..8].try_into().unwrap(),
-		// Unwrapping is okay here, as transformed array slice is always 4 * u8 long and initialized
-		offset: u32::from_le_bytes(quadruple_word[8..12].try_into().unwrap()),
-		length: u32::from_le_bytes(quadruple_word[12..16].try_into().unwrap()),
+	PciCapRaw::from_bytes(&quadruple_word)
+}
+
+impl PciCapRaw {
+	/// Interprets the little endian bytes of a capability.
+	fn from_bytes(quadruple_word: &[u8; 16]) -> Self {
+		PciCapRaw {
+			cap_vndr: quadruple_word[0],
+			cap_next: quadruple_word[1],
+			cap_len: quadruple_word[2],
+			cfg_type: quadruple_word[3],
+			bar_index: quadruple_word[4],
+			id: quadruple_word[5],
+			// Unwrapping is okay here, as transformed array slice is always 2 * u8 long and initialized
+			padding: quadruple_word[6..8].try_into().unwrap(),
+			// Unwrapping is okay here, as transformed array slice is always 4 * u8 long and initialized
+			offset: u32::from_le_bytes(quadruple_word[8..12].try_into().unwrap()),
+			length: u32::from_le_bytes(quadruple_word[12..16].try_into().unwrap()),
+		}
+	}
+}
+
+/// Walks the capabilities list starting at `ptr` and returns every capability together
+/// with the register it is located at. `read` returns the capability at a given register.
+///
+/// Fails, if a pointer of the list points into the configuration header, a capability lies
+/// partly outside of the configuration space or the list does not terminate.
+fn walk_caps(
+	device_id: u16,
+	ptr: u32,
+	mut read: impl FnMut(u32) -> PciCapRaw,
+) -> Result<Vec<(u32, PciCapRaw)>, PciError> {
+	let mut caps = Vec::new();
+	let mut next_ptr = ptr;
+	loop {
+		// Checks if pointer is well formed and does not point into config header space
+		if next_ptr < 0x40u32 {
+			error!(
+				"Capability pointer {:#x} of device {:x} points into the configuration header!",
+				next_ptr, device_id
+			);
+			return Err(PciError::BadCapPtr(device_id));
+		}
+		// Each capability starts with at least the vendor field and next pointer.
+		if next_ptr + 4 > PCI_CFG_SPACE_SIZE {
+			error!(
+				"Capability pointer {:#x} of device {:x} exceeds the configuration space!",
+				next_ptr, device_id
+			);
+			return Err(PciError::TruncatedCapList(device_id));
+		}
+		if caps.len() == MAX_CAPS {
+			error!(
+				"Capabilities list of device {:x} does not terminate!",
+				device_id
+			);
+			return Err(PciError::CapListLoop(device_id));
+		}
+
+		let cap_raw = read(next_ptr);
+
+		// Virtio specification v1.1. - 4.1.4 defines virtio specific capability
+		// with virtio vendor id = 0x09
+		let cap_size = mem::size_of::<PciCapRaw>() as u32;
+		if cap_raw.cap_vndr == 0x09u8
+			&& (u32::from(cap_raw.cap_len) < cap_size || next_ptr + cap_size > PCI_CFG_SPACE_SIZE)
+		{
+			error!(
+				"Virtio capability at {:#x} of device {:x} is truncated!",
+				next_ptr, device_id
+			);
+			return Err(PciError::TruncatedCapList(device_id));
+		}
+
+		let cap_ptr = next_ptr;
+		next_ptr = u32::from(cap_raw.cap_next);
+		caps.push((cap_ptr, cap_raw));
+		if next_ptr == 0u32 {
+			return Ok(caps);
+		}
 	}
 }
 
@@ -1024,37 +1282,27 @@ fn read_cap_raw(adapter: &PciAdapter, register: u32) -> PciCapRaw {
 fn read_caps(adapter: &PciAdapter, bars: Vec<PciBar>) -> Result<Vec<PciCap>, PciError> {
 	let ptr: u32 = dev_caps_ptr(adapter);
 
-	// Checks if pointer is well formed and does not point into config header space
-	let mut next_ptr = if ptr >= 0x40u32 {
-		ptr
-	} else {
-		return Err(PciError::BadCapPtr(adapter.device_id));
-	};
-
-	let mut cap_list: Vec<PciCap> = Vec::new();
-	// Loop through capabilities list via next pointer
-	'cap_list: while next_ptr != 0u32 {
+	let raw_caps = walk_caps(adapter.device_id, ptr, |register| {
 		// read into raw capabilities structure
 		//
 		// Devices configuration space must be read twice
 		// and only returns correct values if both reads
 		// return equal values.
 		// For clarity see Virtio specification v1.1. - 2.4.1
-		let mut before = read_cap_raw(adapter, next_ptr);
-		let mut cap_raw = read_cap_raw(adapter, next_ptr);
+		let mut before = read_cap_raw(adapter, register);
+		let mut cap_raw = read_cap_raw(adapter, register);
 
 		while before != cap_raw {
-			before = read_cap_raw(adapter, next_ptr);
-			cap_raw = read_cap_raw(adapter, next_ptr);
+			before = read_cap_raw(adapter, register);
+			cap_raw = read_cap_raw(adapter, register);
 		}
+		cap_raw
+	})?;
 
+	let mut cap_list: Vec<PciCap> = Vec::new();
+	'cap_list: for (cap_ptr, cap_raw) in raw_caps {
 		let mut iter = bars.iter();
 
-		// Set next pointer for next iteration of `caplist.
-		next_ptr = u32::from(cap_raw.cap_next);
-
-		// Virtio specification v1.1. - 4.1.4 defines virtio specific capability
-		// with virtio vendor id = 0x09
 		match cap_raw.cap_vndr {
 			0x09u8 => {
 				let cap_bar: PciBar = loop {
@@ -1082,7 +1330,7 @@ fn read_caps(adapter: &PciAdapter, bars: Vec<PciBar>) -> Result<Vec<PciCap>, Pci
 					offset: MemOff::from(cap_raw.offset),
 					length: MemLen::from(cap_raw.length),
 					origin: Origin {
-						cfg_ptr: next_ptr,
+						cfg_ptr: cap_ptr,
 						dev: adapter.device,
 						bus: adapter.bus,
 						dev_id: adapter.device_id,
@@ -1119,6 +1367,102 @@ fn dev_status(adapter: &PciAdapter) -> u32 {
 	stat_com_reg >> 16
 }
 
+/// MSI-X capability of a PCI device.
+/// See PCI Local Bus Specification 3.0 - 6.8.2
+#[derive(Clone, Copy, Debug)]
+pub struct MsixCap {
+	/// Position of the capability in the configuration space
+	cap_ptr: u32,
+	/// Number of entries of the MSI-X table
+	table_size: u16,
+	/// Offset of the MSI-X table inside the BAR
+	table_offset: u32,
+	/// Address and length of the BAR, which contains the MSI-X table. The BAR is
+	/// mapped once upon reading the capability.
+	bar_addr: VirtAddr,
+	bar_len: usize,
+}
+
+impl MsixCap {
+	/// Returns the number of entries of the MSI-X table.
+	pub fn table_size(&self) -> u16 {
+		self.table_size
+	}
+
+	/// Programs the table entry `entry` to deliver interrupt `irq` to the boot processor
+	/// and unmasks the entry. Returns false, if the entry does not exist or lies outside
+	/// of the BAR.
+	pub fn set_entry(&self, entry: u16, irq: u8) -> bool {
+		self.set_entry_to(entry, irq, 0)
+	}
+
+	/// Programs the table entry `entry` like [set_entry](Self::set_entry), but delivers
+	/// the interrupt to the processor with the local APIC id `apic_id`.
+	pub fn set_entry_to(&self, entry: u16, irq: u8, apic_id: u8) -> bool {
+		if entry >= self.table_size {
+			return false;
+		}
+		// Each entry consists of the message address (low and high), the message data
+		// and the vector control word.
+		let offset = usize::try_from(self.table_offset).unwrap() + 16 * usize::from(entry);
+		if offset + 16 > self.bar_len {
+			return false;
+		}
+
+		let entry_ptr = (self.bar_addr.as_u64() as usize + offset) as *mut u32;
+		unsafe {
+			// The destination APIC id is encoded in bits 12 to 19 of the message address.
+			write_volatile(entry_ptr, 0xfee0_0000 | u32::from(apic_id) << 12);
+			write_volatile(entry_ptr.add(1), 0);
+			write_volatile(entry_ptr.add(2), 32 + u32::from(irq));
+			write_volatile(entry_ptr.add(3), 0);
+		}
+
+		true
+	}
+
+	/// Enables MSI-X for the device. Afterwards the device signals interrupts via the
+	/// MSI-X table instead of the legacy interrupt line.
+	pub fn enable(&self, adapter: &PciAdapter) {
+		let reg = env::pci::read_config(adapter, self.cap_ptr);
+		// MSI-X Enable is bit 15 of the message control word in the upper half.
+		env::pci::write_config(adapter, self.cap_ptr, reg | (1 << 31));
+	}
+}
+
+/// Returns the MSI-X capability of the device, if it provides one. The BAR containing the
+/// MSI-X table is mapped, hence None is also returned, if the mapping fails.
+pub fn read_msix_cap(adapter: &PciAdapter) -> Option<MsixCap> {
+	if no_cap_list(adapter) {
+		return None;
+	}
+
+	let mut next_ptr = dev_caps_ptr(adapter);
+	// Capabilities are located behind the header and are at least 4 bytes long.
+	for _ in 0..48 {
+		if next_ptr < 0x40 {
+			return None;
+		}
+
+		let header = env::pci::read_config(adapter, next_ptr);
+		if header & 0xff == constants::PCI_CAP_ID_MSIX {
+			let table = env::pci::read_config(adapter, next_ptr + 4);
+			let table_bar = u8::try_from(table & 0x7).unwrap();
+			let (bar_addr, bar_len) = adapter.memory_map_bar(table_bar, true)?;
+			return Some(MsixCap {
+				cap_ptr: next_ptr,
+				table_size: u16::try_from((header >> 16) & 0x7ff).unwrap() + 1,
+				table_offset: table & !0x7,
+				bar_addr,
+				bar_len,
+			});
+		}
+		next_ptr = (header >> 8) & u32::from(constants::Masks::PCI_MASK_CAPLIST_POINTER);
+	}
+
+	None
+}
+
 /// Wrapper function to get a devices capabilities list pointer, which represents
 /// an offset starting from the header of the device's configuration space.
 fn dev_caps_ptr(adapter: &PciAdapter) -> u32 {
@@ -1143,10 +1487,13 @@ fn no_cap_list(adapter: &PciAdapter) -> bool {
 /// Checks if minimal set of capabilities is present.
 ///
 /// INFO: Currently only checks if at least one common config struct has been found and mapped.
-fn check_caps(caps: UniCapsColl) -> Result<UniCapsColl, PciError> {
+fn check_caps(device_id: u16, caps: UniCapsColl) -> Result<UniCapsColl, PciError> {
 	if caps.com_cfg_list.is_empty() {
-		error!("Device with unknown id, does not have a common config structure!");
-		return Err(PciError::General(0));
+		error!(
+			"Device {:x} does not have a common config structure!",
+			device_id
+		);
+		return Err(PciError::NoComCfg(device_id));
 	}
 
 	Ok(caps)
@@ -1176,7 +1523,7 @@ pub fn map_caps(adapter: &PciAdapter) -> Result<UniCapsColl, PciError> {
 	for pci_cap in cap_list {
 		match pci_cap.cfg_type {
 			CfgType::VIRTIO_PCI_CAP_COMMON_CFG => match ComCfgRaw::map(&pci_cap) {
-				Some(cap) => caps.add_cfg_common(ComCfg::new(cap, pci_cap.id)),
+				Some(cap) => caps.add_cfg_common(ComCfg::new(cap, pci_cap.id, pci_cap.bar_info())),
 				None => error!(
 					"Common config capability with id {}, of device {:x}, could not be mapped!",
 					pci_cap.id, adapter.device_id
@@ -1190,7 +1537,9 @@ pub fn map_caps(adapter: &PciAdapter) -> Result<UniCapsColl, PciError> {
 				),
 			},
 			CfgType::VIRTIO_PCI_CAP_ISR_CFG => match IsrStatusRaw::map(&pci_cap) {
-				Some(isr_stat) => caps.add_cfg_isr(IsrStatus::new(isr_stat, pci_cap.id)),
+				Some(isr_stat) => {
+					caps.add_cfg_isr(IsrStatus::new(isr_stat, pci_cap.id, pci_cap.bar_info()))
+				}
 				None => error!(
 					"ISR status config capability with id {}, of device {:x} could not be used!",
 					pci_cap.id, adapter.device_id
@@ -1212,7 +1561,7 @@ pub fn map_caps(adapter: &PciAdapter) -> Result<UniCapsColl, PciError> {
 		}
 	}
 
-	check_caps(caps)
+	check_caps(adapter.device_id, caps)
 }
 
 /// Checks existing drivers for support of given device. Upon match, provides
@@ -1220,6 +1569,23 @@ pub fn map_caps(adapter: &PciAdapter) -> Result<UniCapsColl, PciError> {
 /// list of the given device.
 pub fn init_device(adapter: &PciAdapter) -> Result<VirtioDriver, DriverError> {
 	let virt_drv = match DevId::from(adapter.device_id) {
+		DevId::VIRTIO_TRANS_DEV_ID_NET | DevId::VIRTIO_DEV_ID_NET
+			if virtio_net::is_supported(adapter.vendor_id, adapter.device_id) =>
+		{
+			match VirtioNetDriver::init(adapter) {
+				Ok(virt_net_drv) => {
+					info!("Virtio network driver initialized.");
+					Ok(VirtioDriver::Network(virt_net_drv))
+				}
+				Err(virtio_error) => {
+					error!(
+						"Virtio networkd driver could not be initialized with device: {:x}",
+						adapter.device_id
+					);
+					Err(DriverError::InitVirtioDevFail(virtio_error))
+				}
+			}
+		}
 		DevId::VIRTIO_TRANS_DEV_ID_NET
 		| DevId::VIRTIO_TRANS_DEV_ID_BLK
 		| DevId::VIRTIO_TRANS_DEV_ID_MEM_BALL
@@ -1237,19 +1603,6 @@ pub fn init_device(adapter: &PciAdapter) -> Result<VirtioDriver, DriverError> {
 				VirtioError::DevNotSupported(adapter.device_id),
 			))
 		}
-		DevId::VIRTIO_DEV_ID_NET => match VirtioNetDriver::init(adapter) {
-			Ok(virt_net_drv) => {
-				info!("Virtio network driver initialized.");
-				Ok(VirtioDriver::Network(virt_net_drv))
-			}
-			Err(virtio_error) => {
-				error!(
-					"Virtio networkd driver could not be initialized with device: {:x}",
-					adapter.device_id
-				);
-				Err(DriverError::InitVirtioDevFail(virtio_error))
-			}
-		},
 		DevId::VIRTIO_DEV_ID_FS => {
 			// TODO: check subclass
 			// TODO: proper error handling on driver creation fail
@@ -1283,12 +1636,18 @@ pub fn init_device(adapter: &PciAdapter) -> Result<VirtioDriver, DriverError> {
 	match virt_drv {
 		Ok(drv) => {
 			match &drv {
-				VirtioDriver::Network(_) => {
+				VirtioDriver::Network(net_drv) => {
 					info!("Install virtio interrupt handler at line {}", adapter.irq);
 					// Install interrupt handler
 					irq_install_handler(adapter.irq as u32, network_irqhandler as usize);
 					add_irq_name(adapter.irq as u32, "virtio_net");
 
+					// With MSI-X, configuration changes are signalled separately.
+					if let Some(irq) = net_drv.msix_cfg_irq() {
+						irq_install_handler(irq.into(), network_cfg_irqhandler as usize);
+						add_irq_name(irq.into(), "virtio_net_cfg");
+					}
+
 					Ok(drv)
 				}
 				VirtioDriver::FileSystem(_) => Ok(drv),
@@ -1312,6 +1671,7 @@ pub mod constants {
 	pub const PCI_CONFIG_ADDRESS_ENABLE: u32 = 1 << 31;
 	pub const PCI_CONFIG_DATA_PORT: u16 = 0xCFC;
 	pub const PCI_CAP_ID_VNDR_VIRTIO: u32 = 0x09;
+	pub const PCI_CAP_ID_MSIX: u32 = 0x11;
 	pub const PCI_MASK_IS_DEV_BUS_MASTER: u32 = 0x0000_0004u32;
 
 	/// PCI registers offset inside header,
@@ -1374,3 +1734,166 @@ pub mod constants {
 		}
 	}
 }
+
+/// Configuration structures of a simulated device for host tests. They are placed in
+/// leaked heap memory, which the tests access like the device does.
+#[cfg(all(test, not(target_os = "none")))]
+pub(crate) mod mock {
+	use alloc::boxed::Box;
+
+	use super::*;
+
+	fn bar_info(cfg_type: CfgType, addr: usize, length: usize) -> BarInfo {
+		BarInfo {
+			cfg_type,
+			bar: 0,
+			bar_addr: VirtMemAddr::from(addr),
+			offset: MemOff::from(0u32),
+			length: MemLen::from(length),
+		}
+	}
+
+	/// Returns a common configuration, which reports `num_queues` queues. All queues
+	/// share the `queue_size` field, hence they are all created with `queue_size` entries.
+	///
+	/// The simulated device ignores the feature select fields, hence `features` is offered
+	/// for the low as well as for the high 32 bits.
+	pub(crate) fn com_cfg(num_queues: u16, queue_size: u16, features: u32) -> ComCfg {
+		let raw = Box::leak(Box::new(ComCfgRaw {
+			device_feature_select: 0,
+			device_feature: features,
+			driver_feature_select: 0,
+			driver_feature: 0,
+			config_msix_vector: VIRTIO_MSI_NO_VECTOR,
+			num_queues,
+			device_status: 0,
+			config_generation: 0,
+			queue_select: 0,
+			queue_size,
+			queue_msix_vector: VIRTIO_MSI_NO_VECTOR,
+			queue_enable: 0,
+			queue_notify_off: 0,
+			queue_desc: 0,
+			queue_driver: 0,
+			queue_device: 0,
+		}));
+		let addr = &*raw as *const ComCfgRaw as usize;
+
+		ComCfg::new(
+			raw,
+			1,
+			bar_info(
+				CfgType::VIRTIO_PCI_CAP_COMMON_CFG,
+				addr,
+				mem::size_of::<ComCfgRaw>(),
+			),
+		)
+	}
+
+	/// Returns a notification configuration, whose notifications of all queues are
+	/// written into the same heap area.
+	pub(crate) fn notif_cfg() -> NotifCfg {
+		let area = Box::leak(Box::new([0u64; 4]));
+		let addr = area.as_ptr() as usize;
+		let length = mem::size_of_val(area);
+
+		NotifCfg {
+			base_addr: VirtMemAddr::from(addr),
+			notify_off_multiplier: 4,
+			rank: 1,
+			length: MemLen::from(length),
+			bar_info: bar_info(CfgType::VIRTIO_PCI_CAP_NOTIFY_CFG, addr, length),
+		}
+	}
+
+	/// Returns an ISR status, which signals `flags` upon every read.
+	pub(crate) fn isr_status(flags: u8) -> IsrStatus {
+		let raw = Box::leak(Box::new(IsrStatusRaw { flags }));
+		let addr = &*raw as *const IsrStatusRaw as usize;
+
+		IsrStatus::new(raw, 1, bar_info(CfgType::VIRTIO_PCI_CAP_ISR_CFG, addr, 1))
+	}
+
+	/// Changes the flags signalled by the ISR status.
+	pub(crate) fn set_isr_status(isr_stat: &mut IsrStatus, flags: u8) {
+		unsafe { write_volatile(&mut isr_stat.isr_stat.flags, flags) };
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn invalid_cfg_addresses_are_rejected() {
+		// BAR without assigned memory.
+		assert!(cfg_ptr::<u32>(0).is_none());
+		// Offset, which is not aligned for the structure.
+		assert!(cfg_ptr::<u32>(0xfebd_1002).is_none());
+		assert!(cfg_ptr::<u8>(0xfebd_1002).is_some());
+		assert_eq!(
+			cfg_ptr::<u32>(0xfebd_1004).map(|ptr| ptr as usize),
+			Some(0xfebd_1004)
+		);
+	}
+
+	/// Returns a reader of capabilities in the configuration space `cfg`.
+	fn cfg_reader(cfg: &[u8; 256]) -> impl FnMut(u32) -> PciCapRaw + '_ {
+		|register| {
+			let mut bytes = [0u8; 16];
+			for (i, byte) in bytes.iter_mut().enumerate() {
+				*byte = cfg.get(register as usize + i).copied().unwrap_or(0);
+			}
+			PciCapRaw::from_bytes(&bytes)
+		}
+	}
+
+	#[test]
+	fn truncated_cap_list_is_distinguished() {
+		let mut cfg = [0u8; 256];
+		// MSI-X capability followed by a virtio capability
+		cfg[0x40..0x42].copy_from_slice(&[0x11, 0x50]);
+		cfg[0x50..0x53].copy_from_slice(&[0x09, 0x00, 0x10]);
+
+		let caps = walk_caps(0x1041, 0x40, cfg_reader(&cfg)).unwrap();
+		assert_eq!(
+			caps.iter().map(|(ptr, _)| *ptr).collect::<Vec<_>>(),
+			[0x40, 0x50]
+		);
+
+		// The virtio capability does not fit into the configuration space.
+		cfg[0x41] = 0xf8;
+		cfg[0xf8..0xfb].copy_from_slice(&[0x09, 0x00, 0x10]);
+		assert!(matches!(
+			walk_caps(0x1041, 0x40, cfg_reader(&cfg)),
+			Err(PciError::TruncatedCapList(0x1041))
+		));
+
+		// The virtio capability is shorter than its structure.
+		cfg[0x41] = 0x50;
+		cfg[0x52] = 0x08;
+		assert!(matches!(
+			walk_caps(0x1041, 0x40, cfg_reader(&cfg)),
+			Err(PciError::TruncatedCapList(0x1041))
+		));
+
+		// The next pointer leads back into the header.
+		cfg[0x41] = 0x20;
+		assert!(matches!(
+			walk_caps(0x1041, 0x40, cfg_reader(&cfg)),
+			Err(PciError::BadCapPtr(0x1041))
+		));
+
+		// The list points to itself.
+		cfg[0x41] = 0x40;
+		assert!(matches!(
+			walk_caps(0x1041, 0x40, cfg_reader(&cfg)),
+			Err(PciError::CapListLoop(0x1041))
+		));
+
+		assert!(matches!(
+			walk_caps(0x1041, 0x10, cfg_reader(&cfg)),
+			Err(PciError::BadCapPtr(0x1041))
+		));
+	}
+}
@@ -24,14 +24,16 @@ use align_address::Align;
 use zerocopy::AsBytes;
 
 use self::error::{BufferError, VirtqError};
+use self::packed::error::VqPackedError;
 use self::packed::PackedVq;
 use self::split::SplitVq;
+use super::env::dma::{DmaAllocator, KernelAllocator};
 #[cfg(not(feature = "pci"))]
 use super::transport::mmio::{ComCfg, NotifCfg};
 #[cfg(feature = "pci")]
 use super::transport::pci::{ComCfg, NotifCfg};
 use crate::arch::mm::paging::{BasePageSize, PageSize};
-use crate::arch::mm::{paging, VirtAddr};
+use crate::arch::mm::{paging, PhysAddr, VirtAddr};
 
 /// A u16 newtype. If instantiated via ``VqIndex::from(T)``, the newtype is ensured to be
 /// smaller-equal to `min(u16::MAX , T::MAX)`.
@@ -174,11 +176,28 @@ impl Virtq {
 	}
 
 	/// Frees memory regions gained access to via `Transfer.ret_raw()`.
+	///
+	/// Only valid for queues, which use the [KernelAllocator].
 	pub fn free_raw(ptr: *mut u8, len: usize) {
 		crate::mm::deallocate(VirtAddr::from(ptr as usize), len);
 	}
 }
 
+// Device side of Virtq for host tests
+#[cfg(all(test, not(target_os = "none")))]
+impl Virtq {
+	/// Acts as the device: takes the next available buffer, writes `data` into it and
+	/// returns the data of its readable descriptors. See [SplitVq::mock_use].
+	///
+	/// Only split queues are simulated, packed queues always return None.
+	pub(crate) fn mock_use(&self, data: &[u8]) -> Option<Vec<u8>> {
+		match self {
+			Virtq::Packed(_) => None,
+			Virtq::Split(vq) => vq.mock_use(data),
+		}
+	}
+}
+
 // Public interface of Virtq
 impl Virtq {
 	/// Enables interrupts for this virtqueue upon receiving a transfer
@@ -344,6 +363,8 @@ impl Virtq {
 	/// Upon creation the virtqueue is "registered" at the device via the `ComCfg` struct.
 	///
 	/// Be aware, that devices define a maximum number of queues and a maximal size they can handle.
+	///
+	/// The memory of the queue is allocated via the [KernelAllocator].
 	pub fn new(
 		com_cfg: &mut ComCfg,
 		notif_cfg: &NotifCfg,
@@ -352,15 +373,73 @@ impl Virtq {
 		index: VqIndex,
 		feats: u64,
 	) -> Self {
+		Virtq::new_with_allocator(
+			com_cfg,
+			notif_cfg,
+			size,
+			vq_type,
+			index,
+			feats,
+			&KernelAllocator,
+		)
+	}
+
+	/// Creates a new Virtq like `Virtq::new()`, but allocates the rings and all
+	/// buffers of the queue via the given [DmaAllocator].
+	pub fn new_with_allocator(
+		com_cfg: &mut ComCfg,
+		notif_cfg: &NotifCfg,
+		size: VqSize,
+		vq_type: VqType,
+		index: VqIndex,
+		feats: u64,
+		allocator: &'static dyn DmaAllocator,
+	) -> Self {
+		match Virtq::try_new_with_allocator(
+			com_cfg, notif_cfg, size, vq_type, index, feats, allocator,
+		) {
+			Ok(vq) => vq,
+			Err(_vq_error) => panic!("Currently panics if queue fails to be created"),
+		}
+	}
+
+	/// Creates a new Virtq like `Virtq::new_with_allocator()`, but returns an error
+	/// instead of panicking, if the queue can not be created.
+	///
+	/// Fails with `VirtqError::QueueNotExisting` if the device does not know the queue and
+	/// with `VirtqError::NoMemAvail` if the rings could not be allocated.
+	pub fn try_new_with_allocator(
+		com_cfg: &mut ComCfg,
+		notif_cfg: &NotifCfg,
+		size: VqSize,
+		vq_type: VqType,
+		index: VqIndex,
+		feats: u64,
+		allocator: &'static dyn DmaAllocator,
+	) -> Result<Self, VirtqError> {
 		match vq_type {
-			VqType::Packed => match PackedVq::new(com_cfg, notif_cfg, size, index, feats) {
-				Ok(packed_vq) => Virtq::Packed(packed_vq),
-				Err(_vq_error) => panic!("Currently panics if queue fails to be created"),
-			},
-			VqType::Split => match SplitVq::new(com_cfg, notif_cfg, size, index, feats) {
-				Ok(split_vq) => Virtq::Split(split_vq),
-				Err(_vq_error) => panic!("Currently panics if queue fails to be created"),
-			},
+			VqType::Packed => {
+				match PackedVq::new(com_cfg, notif_cfg, size, index, feats, allocator) {
+					Ok(packed_vq) => Ok(Virtq::Packed(packed_vq)),
+					Err(VqPackedError::QueueNotExisting(idx)) => {
+						Err(VirtqError::QueueNotExisting(idx))
+					}
+					Err(VqPackedError::General) => Err(VirtqError::NoMemAvail),
+					Err(_vq_error) => Err(VirtqError::General),
+				}
+			}
+			VqType::Split => {
+				SplitVq::new(com_cfg, notif_cfg, size, index, feats, allocator).map(Virtq::Split)
+			}
+		}
+	}
+
+	/// Returns the number of bytes `Virtq::new()` allocates for the rings of a queue of the
+	/// given type with `size` entries, without allocating anything.
+	pub fn ring_mem_len(vq_type: &VqType, size: u16) -> usize {
+		match vq_type {
+			VqType::Packed => PackedVq::ring_mem_len(size),
+			VqType::Split => SplitVq::ring_mem_len(size),
 		}
 	}
 
@@ -373,6 +452,45 @@ impl Virtq {
 		}
 	}
 
+	/// Returns the number of descriptors, which are not owned by the device, i.e. which can
+	/// be made available without waiting for the device.
+	pub fn free_descriptors(&self) -> u16 {
+		match self {
+			Virtq::Packed(vq) => vq.free_descriptors(),
+			Virtq::Split(vq) => vq.free_descriptors(),
+		}
+	}
+
+	/// Returns the number of buffers, which have been used by the device, but which
+	/// have not yet been polled.
+	pub fn pending_used(&self) -> u16 {
+		match self {
+			Virtq::Packed(vq) => vq.pending_used(),
+			Virtq::Split(vq) => vq.pending_used(),
+		}
+	}
+
+	/// Returns the raw content of the first `count` descriptors of the queue's descriptor
+	/// table (split queues), respectively descriptor ring (packed queues).
+	///
+	/// `count` is bounded by the size of the queue. The descriptors are read with volatile
+	/// accesses, as the device might write to them concurrently. Intended for debugging only.
+	pub fn dump_descriptors(&self, count: usize) -> Vec<DescInfo> {
+		match self {
+			Virtq::Packed(vq) => vq.dump_descriptors(count),
+			Virtq::Split(vq) => vq.dump_descriptors(count),
+		}
+	}
+
+	/// Returns the state of the queue's ring and descriptor bookkeeping, e.g. in order to
+	/// inspect a queue, which does not make progress. Intended for debugging only.
+	pub fn debug_state(&self) -> QueueDebug {
+		match self {
+			Virtq::Packed(vq) => vq.debug_state(),
+			Virtq::Split(vq) => vq.debug_state(),
+		}
+	}
+
 	// Returns the index (ID) of a Virtqueue.
 	pub fn index(&self) -> VqIndex {
 		match self {
@@ -455,6 +573,35 @@ impl Virtq {
 		}
 	}
 
+	/// Provides the calley with a TransferToken, which presents the given list of slices
+	/// as a single send buffer to the device. No data is copied.
+	///
+	/// **INFO:**
+	/// * Each slice results in at least one descriptor in the queue. Slices crossing a page
+	/// boundary are split into one descriptor per page. Empty slices are skipped.
+	/// * Data behind the slices will NOT be deallocated.
+	/// * Transfers created from this TransferToken can NOT be reused.
+	///
+	/// **Reasons for Failure:**
+	/// * Queue does not have enough descriptors left.
+	/// * All slices are empty.
+	///
+	/// # Safety
+	///
+	/// The calley MUST ensure, that the memory behind the slices remains valid and unchanged
+	/// until the transfer is finished. I.e. the returned token must be dispatched and the resulting
+	/// [Transfer](Transfer) must be finished, before the slices are released.
+	pub unsafe fn prep_transfer_from_slices(
+		&self,
+		rc_self: Rc<Virtq>,
+		send: &[&[u8]],
+	) -> Result<TransferToken, VirtqError> {
+		match self {
+			Virtq::Packed(vq) => vq.prep_transfer_from_slices(rc_self, send),
+			Virtq::Split(vq) => vq.prep_transfer_from_slices(rc_self, send),
+		}
+	}
+
 	/// Provides the calley with empty buffers as specified via the `send` and `recv` function parameters, (see [BuffSpec](BuffSpec)), in form of
 	/// a [BufferToken](BufferToken).
 	/// Fails upon multiple circumstances.
@@ -604,6 +751,12 @@ impl Drop for Transfer {
 
 // Public Interface of Transfer
 impl Transfer {
+	/// Returns the index of the virtqueue, the transfer has been dispatched to.
+	pub fn vq_index(&self) -> VqIndex {
+		// Unwrapping is okay here, as Transfers must hold a TransferToken
+		self.transfer_tkn.as_ref().unwrap().get_vq().index()
+	}
+
 	/// Used to poll the current state of the transfer.
 	/// * true = Transfer is finished and can be closed, reused or return data
 	/// * false = Transfer is ongoing
@@ -1983,6 +2136,8 @@ struct MemDescr {
 	/// Defines the length of the controlled memory area
 	/// starting a `ptr: *mut u8`. Never Changes.
 	_mem_len: usize,
+	/// Physical address of `ptr`, which is handed to the device.
+	phys: PhysAddr,
 	/// If `id == None` this is an untracked memory descriptor
 	/// * Meaining: The descriptor does NOT count as a descriptor
 	/// taken from the [MemPool](MemPool).
@@ -2046,6 +2201,7 @@ impl MemDescr {
 	fn no_dealloc_clone(&self) -> Self {
 		MemDescr {
 			ptr: self.ptr,
+			phys: self.phys,
 			len: self.len,
 			_init_len: self.len(),
 			_mem_len: self._mem_len,
@@ -2079,10 +2235,114 @@ impl Drop for MemDescr {
 		match self.dealloc {
 			Dealloc::Not => (),
 			Dealloc::AsSlice => unsafe { drop(Vec::from_raw_parts(self.ptr, self._mem_len, 0)) },
-			Dealloc::AsPage => {
-				crate::mm::deallocate(VirtAddr::from(self.ptr as usize), self._mem_len)
+			Dealloc::AsPage => self
+				.pool
+				.allocator
+				.free(VirtAddr::from(self.ptr as usize), self._mem_len),
+		}
+	}
+}
+
+/// Raw content of a single descriptor of a virtqueue. See `Virtq.dump_descriptors()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DescInfo {
+	/// Physical address of the referred to buffer.
+	pub addr: u64,
+	/// Length of the buffer in bytes.
+	pub len: u32,
+	/// Raw descriptor flags. See [DescrFlags].
+	pub flags: u16,
+	/// For split queues, the index of the next descriptor in the chain.
+	/// For packed queues, the buffer id of the descriptor.
+	pub next: u16,
+}
+
+/// State of a virtqueue. See `Virtq.debug_state()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueueDebug {
+	/// Index of the queue.
+	pub index: u16,
+	/// Number of entries of the ring.
+	pub size: u16,
+	/// Indices of the ring, which depend on its layout.
+	pub ring: RingDebug,
+	/// Number of descriptors, which are made available to the device and not yet polled.
+	pub outstanding: u16,
+	/// Number of descriptor ids, which are not in use.
+	pub free_ids: usize,
+	/// The next descriptor ids to be handed out, at most [QueueDebug::MAX_FREE_HEADS].
+	pub free_heads: Vec<u16>,
+}
+
+impl QueueDebug {
+	/// Maximal number of listed ids of free descriptors.
+	pub const MAX_FREE_HEADS: usize = 8;
+}
+
+/// Indices of a virtqueue's ring. See [QueueDebug].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RingDebug {
+	Split {
+		/// Index of the next entry the driver writes to the available ring.
+		avail_idx: u16,
+		/// Index of the next entry the device writes to the used ring.
+		used_idx: u16,
+		/// Index of the next entry the driver reads from the used ring.
+		last_used_idx: u16,
+	},
+	Packed {
+		/// Position, at which the driver makes the next descriptor available.
+		write_index: u16,
+		/// Position, at which the driver expects the next used descriptor.
+		poll_index: u16,
+		/// Wrap counter of the driver.
+		drv_wrap: bool,
+		/// Wrap counter expected of the device.
+		dev_wrap: bool,
+	},
+}
+
+impl core::fmt::Display for QueueDebug {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		writeln!(f, "{:<14}{}", "queue", self.index)?;
+		writeln!(f, "{:<14}{}", "size", self.size)?;
+		match self.ring {
+			RingDebug::Split {
+				avail_idx,
+				used_idx,
+				last_used_idx,
+			} => {
+				writeln!(f, "{:<14}split", "layout")?;
+				writeln!(f, "{:<14}{}", "avail_idx", avail_idx)?;
+				writeln!(f, "{:<14}{}", "used_idx", used_idx)?;
+				writeln!(f, "{:<14}{}", "last_used_idx", last_used_idx)?;
+			}
+			RingDebug::Packed {
+				write_index,
+				poll_index,
+				drv_wrap,
+				dev_wrap,
+			} => {
+				writeln!(f, "{:<14}packed", "layout")?;
+				writeln!(f, "{:<14}{}", "write_index", write_index)?;
+				writeln!(f, "{:<14}{}", "poll_index", poll_index)?;
+				writeln!(f, "{:<14}{}", "drv_wrap", u8::from(drv_wrap))?;
+				writeln!(f, "{:<14}{}", "dev_wrap", u8::from(dev_wrap))?;
 			}
 		}
+		writeln!(f, "{:<14}{}", "outstanding", self.outstanding)?;
+		write!(f, "{:<14}{}", "free_ids", self.free_ids)?;
+		if !self.free_heads.is_empty() {
+			write!(f, " (next:")?;
+			for id in &self.free_heads {
+				write!(f, " {id}")?;
+			}
+			if self.free_ids > self.free_heads.len() {
+				write!(f, " ...")?;
+			}
+			write!(f, ")")?;
+		}
+		Ok(())
 	}
 }
 
@@ -2144,6 +2404,9 @@ enum Dealloc {
 struct MemPool {
 	pool: RefCell<Vec<MemDescrId>>,
 	limit: u16,
+	/// Backend, which provides the memory of descriptors pulled via
+	/// `fn pull()` and `fn pull_untracked()`.
+	allocator: &'static dyn DmaAllocator,
 }
 
 impl MemPool {
@@ -2152,8 +2415,21 @@ impl MemPool {
 		self.pool.borrow_mut().push(id);
 	}
 
+	/// Returns the number of free ids and the first ids, which will be handed out next.
+	fn free_ids(&self) -> (usize, Vec<u16>) {
+		let pool = self.pool.borrow();
+		// Ids are taken from the end of the pool.
+		let heads = pool
+			.iter()
+			.rev()
+			.take(QueueDebug::MAX_FREE_HEADS)
+			.map(|id| id.0)
+			.collect();
+		(pool.len(), heads)
+	}
+
 	/// Returns a new instance, with a pool of the specified size.
-	fn new(size: u16) -> MemPool {
+	fn new(size: u16, allocator: &'static dyn DmaAllocator) -> MemPool {
 		// Not really safe "as usize". But the minimum usize on rust is currently
 		// usize = 16bit. So it should work, as long as this does not change changes.
 		// Thus asserting here, to catch this change!
@@ -2168,6 +2444,7 @@ impl MemPool {
 		MemPool {
 			pool: RefCell::new(id_vec),
 			limit: size,
+			allocator,
 		}
 	}
 
@@ -2195,6 +2472,7 @@ impl MemPool {
 		let end_phy = paging::virt_to_phys(VirtAddr::from(end_virt));
 
 		assert_eq!(end_phy, end_phy_calc);
+		let phys = paging::virt_to_phys(VirtAddr::from(start_virt));
 
 		let desc_id = match self.pool.borrow_mut().pop() {
 			Some(id) => id,
@@ -2203,6 +2481,7 @@ impl MemPool {
 
 		Ok(MemDescr {
 			ptr: (&slice[0] as *const u8) as *mut u8,
+			phys,
 			len: slice.len(),
 			_init_len: slice.len(),
 			_mem_len: slice.len(),
@@ -2240,6 +2519,7 @@ impl MemPool {
 
 		MemDescr {
 			ptr: (&slice[0] as *const u8) as *mut u8,
+			phys: paging::virt_to_phys(VirtAddr::from(start_virt)),
 			len: slice.len(),
 			_init_len: slice.len(),
 			_mem_len: slice.len(),
@@ -2269,20 +2549,22 @@ impl MemPool {
 
 		let len = bytes.0;
 
-		// Allocate heap memory via a vec, leak and cast
+		// The allocator guarantees the memory area to be physically contiguous.
 		let _mem_len = len.align_up(BasePageSize::SIZE as usize);
-		let ptr = (crate::mm::allocate(_mem_len, true).0 as *const u8) as *mut u8;
-
-		// Assert descriptor does not cross a page barrier
-		let start_virt = ptr as usize;
-		let end_virt = start_virt + (len - 1);
-		let end_phy_calc = paging::virt_to_phys(VirtAddr::from(start_virt)) + (len - 1);
-		let end_phy = paging::virt_to_phys(VirtAddr::from(end_virt));
-
-		assert_eq!(end_phy, end_phy_calc);
+		let (ptr, phys) = match self
+			.allocator
+			.alloc_contiguous(_mem_len, BasePageSize::SIZE as usize)
+		{
+			Some((virt, phys)) => (virt.0 as *mut u8, phys),
+			None => {
+				self.ret_id(id);
+				return Err(VirtqError::NoMemAvail);
+			}
+		};
 
 		Ok(MemDescr {
 			ptr,
+			phys,
 			len,
 			_init_len: len,
 			_mem_len,
@@ -2301,31 +2583,80 @@ impl MemPool {
 	///   * First MemPool.pull -> MemDesc with id = 3
 	///   * Second MemPool.pull -> MemDesc with id = 100
 	///   * Third MemPool.pull -> MemDesc with id = 2,
-	fn pull_untracked(&self, rc_self: Rc<MemPool>, bytes: Bytes) -> MemDescr {
+	///
+	/// Returns None, if the allocator of the pool is not able to provide the memory.
+	fn pull_untracked(&self, rc_self: Rc<MemPool>, bytes: Bytes) -> Option<MemDescr> {
 		let len = bytes.0;
 
-		// Allocate heap memory via a vec, leak and cast
+		// The allocator guarantees the memory area to be physically contiguous.
 		let _mem_len = len.align_up(BasePageSize::SIZE as usize);
-		let ptr = (crate::mm::allocate(_mem_len, true).0 as *const u8) as *mut u8;
-
-		// Assert descriptor does not cross a page barrier
-		let start_virt = ptr as usize;
-		let end_virt = start_virt + (len - 1);
-		let end_phy_calc = paging::virt_to_phys(VirtAddr::from(start_virt)) + (len - 1);
-		let end_phy = paging::virt_to_phys(VirtAddr::from(end_virt));
+		let (virt, phys) = self
+			.allocator
+			.alloc_contiguous(_mem_len, BasePageSize::SIZE as usize)?;
 
-		assert_eq!(end_phy, end_phy_calc);
-
-		MemDescr {
-			ptr,
+		Some(MemDescr {
+			ptr: virt.0 as *mut u8,
+			phys,
 			len,
 			_init_len: len,
 			_mem_len,
 			id: None,
 			dealloc: Dealloc::AsPage,
 			pool: rc_self,
+		})
+	}
+}
+
+/// Creates a TransferToken with a single send buffer, which refers to the given slices.
+/// Slices are split at page boundaries, as `MemPool.pull_from_raw()` does not allow descriptors to cross
+/// those.
+///
+/// See `Virtq.prep_transfer_from_slices()` for details.
+fn transfer_from_slices(
+	pool: &Rc<MemPool>,
+	master: Rc<Virtq>,
+	send: &[&[u8]],
+) -> Result<TransferToken, VirtqError> {
+	let mut desc_lst: Vec<MemDescr> = Vec::with_capacity(send.len());
+	let mut len = 0usize;
+
+	for slice in send {
+		let mut rest: &[u8] = slice;
+
+		while !rest.is_empty() {
+			let offset = (rest.as_ptr() as usize) % (BasePageSize::SIZE as usize);
+			let chunk_len = core::cmp::min(rest.len(), BasePageSize::SIZE as usize - offset);
+
+			match pool.pull_from_raw(Rc::clone(pool), &rest[..chunk_len]) {
+				Ok(desc) => desc_lst.push(desc),
+				Err(vq_err) => return Err(vq_err),
+			}
+
+			len += chunk_len;
+			rest = &rest[chunk_len..];
 		}
 	}
+
+	if desc_lst.is_empty() {
+		return Err(VirtqError::BufferNotSpecified);
+	}
+
+	Ok(TransferToken {
+		state: TransferState::Ready,
+		buff_tkn: Some(BufferToken {
+			send_buff: Some(Buffer::Multiple {
+				desc_lst: desc_lst.into_boxed_slice(),
+				len,
+				next_write: 0,
+			}),
+			recv_buff: None,
+			vq: master,
+			ret_send: false,
+			ret_recv: false,
+			reusable: false,
+		}),
+		await_queue: None,
+	})
 }
 
 /// Specifies the type of buffer and amount of memory chunks that buffer does consist of wanted.
@@ -2372,6 +2703,27 @@ pub enum BuffSpec<'a> {
 	Indirect(&'a [Bytes]),
 }
 
+impl<'a> BuffSpec<'a> {
+	/// Returns the number of bytes a buffer created from this spec occupies, including
+	/// the indirect descriptor table if any.
+	///
+	/// Every descriptor's memory is allocated page aligned, see `MemPool::pull()`.
+	pub fn mem_len(&self) -> usize {
+		// Descriptors of packed and split queues are both 16 bytes large.
+		const DESCR_SIZE: usize = 16;
+
+		let align = |len: usize| len.align_up(BasePageSize::SIZE as usize);
+		match self {
+			BuffSpec::Single(size) => align(size.0),
+			BuffSpec::Multiple(sizes) => sizes.iter().map(|size| align(size.0)).sum(),
+			BuffSpec::Indirect(sizes) => {
+				sizes.iter().map(|size| align(size.0)).sum::<usize>()
+					+ align(DESCR_SIZE * sizes.len())
+			}
+		}
+	}
+}
+
 /// Ensures `T` is pinned at the same memory location.
 /// This allows to refer to structures via raw pointers.
 ///
@@ -2583,6 +2935,9 @@ pub mod error {
 		/// "unsend" `TransferToken` to the queue (see Docs for details)
 		/// or the device needs to process available descriptors in the queue.
 		NoDescrAvail,
+		/// Signals, that the allocator of the queue could not provide
+		/// memory for a buffer.
+		NoMemAvail,
 		/// Indicates that a [BuffSpec](super.BuffSpec) does have the right size
 		/// for a given structure. Returns the structures size in bytes.
 		///
@@ -2624,6 +2979,7 @@ pub mod error {
                 VirtqError::BufferNotSpecified => write!(f, "Virtq detected creation of Token, without a BuffSpec"),
                 VirtqError::QueueNotExisting(_) => write!(f, "Virtq does not exist and can not be used!"),
                 VirtqError::NoDescrAvail => write!(f, "Virtqs memory pool is exhausted!"),
+                VirtqError::NoMemAvail => write!(f, "Virtqs allocator could not provide memory!"),
                 VirtqError::BufferSizeWrong(_) => write!(f, "Specified Buffer is to small for write!"),
                 VirtqError::NoReuseBuffer => write!(f, "Buffer can not be reused!"),
                 VirtqError::OngoingTransfer(_) => write!(f, "Transfer is ongoging and can not be used currently!"),
@@ -2633,3 +2989,104 @@ pub mod error {
 		}
 	}
 }
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+	use crate::drivers::virtio::env::dma::MockArena;
+
+	#[test]
+	fn pull_uses_allocator() {
+		let arena = MockArena::leak(4 * BasePageSize::SIZE as usize);
+		let pool = Rc::new(MemPool::new(2, arena));
+
+		let desc = pool
+			.pull(Rc::clone(&pool), Bytes::new(100).unwrap())
+			.unwrap();
+		assert_eq!(desc.len(), 100);
+		assert!(desc.raw_ptr() as usize >= arena.base);
+		assert_eq!(desc.phys, PhysAddr::from(desc.raw_ptr() as usize));
+		assert_eq!(pool.pool.borrow().len(), 1);
+
+		drop(desc);
+		assert_eq!(arena.freed.get(), BasePageSize::SIZE as usize);
+		assert_eq!(pool.pool.borrow().len(), 2);
+	}
+
+	#[test]
+	fn pull_returns_id_if_allocator_is_exhausted() {
+		// Independent of the alignment of the arena, only a single page fits into it.
+		let arena = MockArena::leak(2 * BasePageSize::SIZE as usize - 1);
+		let pool = Rc::new(MemPool::new(2, arena));
+
+		let bytes = Bytes::new(BasePageSize::SIZE as usize).unwrap();
+		let _desc = pool.pull(Rc::clone(&pool), bytes).unwrap();
+		assert!(matches!(
+			pool.pull(Rc::clone(&pool), bytes),
+			Err(VirtqError::NoMemAvail)
+		));
+		assert_eq!(pool.pool.borrow().len(), 1);
+	}
+
+	#[test]
+	fn pull_untracked_fails_if_allocator_is_exhausted() {
+		let arena = MockArena::leak(2 * BasePageSize::SIZE as usize - 1);
+		let pool = Rc::new(MemPool::new(2, arena));
+
+		let bytes = Bytes::new(BasePageSize::SIZE as usize).unwrap();
+		let _desc = pool.pull_untracked(Rc::clone(&pool), bytes).unwrap();
+		assert!(pool.pull_untracked(Rc::clone(&pool), bytes).is_none());
+		assert_eq!(pool.pool.borrow().len(), 2);
+	}
+
+	#[test]
+	fn mem_len_matches_pulled_memory() {
+		let page = BasePageSize::SIZE as usize;
+		let sizes = [Bytes::new(12).unwrap(), Bytes::new(1514).unwrap()];
+
+		assert_eq!(BuffSpec::Single(Bytes::new(1526).unwrap()).mem_len(), page);
+		assert_eq!(BuffSpec::Multiple(&sizes).mem_len(), 2 * page);
+		// The indirect descriptor table occupies a page of its own.
+		assert_eq!(BuffSpec::Indirect(&sizes).mem_len(), 3 * page);
+	}
+
+	#[test]
+	fn split_ring_mem_len() {
+		let page = BasePageSize::SIZE as usize;
+
+		// 256 * 16 bytes of descriptors, 6 + 2 * 256 bytes available and 6 + 8 * 256 bytes used ring.
+		assert_eq!(Virtq::ring_mem_len(&VqType::Split, 256), 3 * page);
+		assert_eq!(
+			Virtq::ring_mem_len(&VqType::Split, 1024),
+			4 * page + page + 3 * page
+		);
+	}
+
+	#[test]
+	fn queue_state_is_printed_as_table() {
+		let state = QueueDebug {
+			index: 1,
+			size: 256,
+			ring: RingDebug::Split {
+				avail_idx: 12,
+				used_idx: 10,
+				last_used_idx: 9,
+			},
+			outstanding: 3,
+			free_ids: 253,
+			free_heads: vec![4, 5],
+		};
+
+		assert_eq!(
+			alloc::format!("{state}"),
+			"queue         1\n\
+			 size          256\n\
+			 layout        split\n\
+			 avail_idx     12\n\
+			 used_idx      10\n\
+			 last_used_idx 9\n\
+			 outstanding   3\n\
+			 free_ids      253 (next: 4 5 ...)"
+		);
+	}
+}
@@ -13,6 +13,7 @@ use core::sync::atomic::{fence, Ordering};
 use align_address::Align;
 
 use self::error::VqPackedError;
+use super::super::env::dma::DmaAllocator;
 use super::super::features::Features;
 #[cfg(not(feature = "pci"))]
 use super::super::transport::mmio::{ComCfg, NotifCfg, NotifCtrl};
@@ -20,11 +21,12 @@ use super::super::transport::mmio::{ComCfg, NotifCfg, NotifCtrl};
 use super::super::transport::pci::{ComCfg, NotifCfg, NotifCtrl};
 use super::error::VirtqError;
 use super::{
-	AsSliceU8, BuffSpec, Buffer, BufferToken, Bytes, DescrFlags, MemDescr, MemPool, Pinned,
-	Transfer, TransferState, TransferToken, Virtq, VqIndex, VqSize,
+	transfer_from_slices, AsSliceU8, BuffSpec, Buffer, BufferToken, Bytes, DescInfo, DescrFlags,
+	MemDescr, MemPool, Pinned, QueueDebug, RingDebug, Transfer, TransferState, TransferToken,
+	Virtq, VqIndex, VqSize,
 };
 use crate::arch::mm::paging::{BasePageSize, PageSize};
-use crate::arch::mm::{paging, VirtAddr};
+use crate::arch::mm::{PhysAddr, VirtAddr};
 
 /// A newtype of bool used for convenience in context with
 /// packed queues wrap counter.
@@ -79,6 +81,18 @@ impl WrapCount {
 			0
 		}
 	}
+
+	/// Returns true, if the descriptor with the given flags has been marked as used by the
+	/// device, while the device wrap counter matched this one.
+	///
+	/// A descriptor is used, if its avail and used flags are equal and the used flag
+	/// equals the wrap counter. Checking the used flag alone is wrong, once the
+	/// ring has wrapped around. See Virtio specification v1.1. - 2.7.1
+	fn is_used(&self, flags: u16) -> bool {
+		let avail = flags & DescrFlags::VIRTQ_DESC_F_AVAIL != 0;
+		let used = flags & DescrFlags::VIRTQ_DESC_F_USED != 0;
+		avail == used && used == self.0
+	}
 }
 
 /// Structure which allows to control raw ring and operate easily on it
@@ -104,13 +118,20 @@ struct DescriptorRing {
 }
 
 impl DescriptorRing {
-	fn new(size: u16) -> Self {
+	/// Returns the page aligned size of a ring with `size` entries.
+	fn mem_len(size: u16) -> usize {
+		(usize::from(size) * core::mem::size_of::<Descriptor>())
+			.align_up(BasePageSize::SIZE as usize)
+	}
+
+	/// Allocates a new ring of the given size via the allocator and returns it
+	/// together with its physical address.
+	fn new(size: u16, allocator: &'static dyn DmaAllocator) -> Option<(Self, PhysAddr)> {
+		let mem_len = Self::mem_len(size);
 		let size = usize::try_from(size).unwrap();
 
-		// Allocate heap memory via a vec, leak and cast
-		let _mem_len =
-			(size * core::mem::size_of::<Descriptor>()).align_up(BasePageSize::SIZE as usize);
-		let ptr = (crate::mm::allocate(_mem_len, true).0 as *const Descriptor) as *mut Descriptor;
+		let (virt, phys) = allocator.alloc_contiguous(mem_len, BasePageSize::SIZE as usize)?;
+		let ptr = virt.0 as *mut Descriptor;
 
 		let ring: &'static mut [Descriptor] = unsafe { core::slice::from_raw_parts_mut(ptr, size) };
 
@@ -119,15 +140,18 @@ impl DescriptorRing {
 		// and do not care about the first element being unused.
 		let tkn_ref_ring = vec![ptr::null_mut(); size + 1].into_boxed_slice();
 
-		DescriptorRing {
-			ring,
-			tkn_ref_ring,
-			write_index: 0,
-			capacity: size,
-			poll_index: 0,
-			drv_wc: WrapCount::new(),
-			dev_wc: WrapCount::new(),
-		}
+		Some((
+			DescriptorRing {
+				ring,
+				tkn_ref_ring,
+				write_index: 0,
+				capacity: size,
+				poll_index: 0,
+				drv_wc: WrapCount::new(),
+				dev_wc: WrapCount::new(),
+			},
+			phys,
+		))
 	}
 
 	/// Polls poll index and sets states of eventually used TransferTokens to finished.
@@ -435,6 +459,31 @@ impl DescriptorRing {
 		self.ring.as_ptr() as usize
 	}
 
+	/// Returns the offset and wrap counter of the descriptor, which is made available next.
+	fn next_avail(&self) -> (usize, u8) {
+		(self.write_index, self.drv_wc.0 as u8)
+	}
+
+	/// Returns the number of buffers, which have been used by the device, but not yet polled.
+	fn pending_used(&self) -> usize {
+		let in_flight = self.ring.len() - self.capacity;
+		count_used(
+			&*self.ring,
+			self.poll_index,
+			self.dev_wc,
+			in_flight,
+			|buff_id| {
+				let raw_tkn = self.tkn_ref_ring[usize::from(buff_id)];
+				if raw_tkn.is_null() {
+					1
+				} else {
+					// SAFETY: Tokens referenced by the ring are in flight and hence alive.
+					unsafe { (*raw_tkn).buff_tkn.as_ref().unwrap().num_consuming_descr() }
+				}
+			},
+		)
+	}
+
 	/// Returns an initialized write controller in order
 	/// to write the queue correctly.
 	fn get_write_ctrler(&mut self) -> WriteCtrl<'_> {
@@ -461,6 +510,40 @@ impl DescriptorRing {
 	}
 }
 
+/// Counts the buffers marked as used by the device, starting at the descriptor `pos`, at which
+/// the device wrap counter `wc` is expected. At most `in_flight` descriptors are inspected, as
+/// the flags of all other descriptors are stale. `chain_len` returns the number of descriptors
+/// of the buffer with the given id, which are skipped by the device.
+fn count_used(
+	ring: &[Descriptor],
+	mut pos: usize,
+	mut wc: WrapCount,
+	in_flight: usize,
+	chain_len: impl Fn(u16) -> usize,
+) -> usize {
+	let mut used = 0;
+	let mut walked = 0;
+
+	while walked < in_flight {
+		let desc = unsafe { ptr::read_volatile(&ring[pos]) };
+		if !wc.is_used(desc.flags) {
+			break;
+		}
+
+		let len = chain_len(desc.buff_id).max(1);
+		for _ in 0..len {
+			if pos + 1 == ring.len() {
+				wc.wrap();
+			}
+			pos = (pos + 1) % ring.len();
+		}
+		walked += len;
+		used += 1;
+	}
+
+	used
+}
+
 struct ReadCtrl<'a> {
 	/// Poll index of the ring at init of ReadCtrl
 	position: usize,
@@ -474,17 +557,27 @@ impl<'a> ReadCtrl<'a> {
 	/// updating the queue and returns the respective TransferToken.
 	fn poll_next(&mut self) -> Option<*mut TransferToken> {
 		// Check if descriptor has been marked used.
-		if self.desc_ring.ring[self.position].flags & WrapCount::flag_mask()
-			== self.desc_ring.dev_wc.as_flags_used()
+		if self
+			.desc_ring
+			.dev_wc
+			.is_used(self.desc_ring.ring[self.position].flags)
 		{
-			let tkn = unsafe {
-				let buff_id = usize::from(self.desc_ring.ring[self.position].buff_id);
-				let raw_tkn = self.desc_ring.tkn_ref_ring[buff_id];
+			// The buffer id is written by the device. As the number of descriptors of an unknown
+			// buffer is unknown too, the ring can not be advanced and is not polled any further.
+			let buff_id = usize::from(self.desc_ring.ring[self.position].buff_id);
+			let raw_tkn = match self.desc_ring.tkn_ref_ring.get_mut(buff_id) {
 				// unset the reference in the reference ring for security!
-				self.desc_ring.tkn_ref_ring[buff_id] = ptr::null_mut();
-				assert!(!raw_tkn.is_null());
-				&mut *raw_tkn
+				Some(raw_tkn) if !raw_tkn.is_null() => core::mem::replace(raw_tkn, ptr::null_mut()),
+				_ => {
+					error!(
+						"Device returned unknown buffer id {}. Stopping to poll the queue.",
+						buff_id
+					);
+					return None;
+				}
 			};
+			// SAFETY: Tokens referenced by the ring are in flight and hence alive.
+			let tkn = unsafe { &mut *raw_tkn };
 
 			let (send_buff, recv_buff) = {
 				let BufferToken {
@@ -579,6 +672,8 @@ impl<'a> ReadCtrl<'a> {
 					desc_iter.next().unwrap();
 				}
 
+				// The device must not report more bytes than the buffer holds.
+				write_len = write_len.min(u32::try_from(recv_buff.len()).unwrap_or(u32::MAX));
 				recv_buff.restr_len(usize::try_from(write_len).unwrap());
 
 				for desc in recv_buff.as_mut_slice() {
@@ -631,6 +726,8 @@ impl<'a> ReadCtrl<'a> {
 
 				let mut desc_iter = desc_slice.iter_mut();
 
+				// The device must not report more bytes than the buffer holds.
+				write_len = write_len.min(u32::try_from(recv_buff.len()).unwrap_or(u32::MAX));
 				recv_buff.restr_len(usize::try_from(write_len).unwrap());
 
 				for desc in recv_buff.as_mut_slice() {
@@ -675,7 +772,8 @@ impl<'a> ReadCtrl<'a> {
 	/// The given buffer must NEVER be an indirect buffer.
 	fn update_recv(&mut self, recv_buff_spec: (&mut Buffer, u32)) {
 		let (recv_buff, write_len) = recv_buff_spec;
-		let mut write_len = usize::try_from(write_len).unwrap();
+		// The device must not report more bytes than the buffer holds.
+		let mut write_len = usize::try_from(write_len).unwrap().min(recv_buff.len());
 
 		recv_buff.restr_len(write_len);
 
@@ -774,7 +872,7 @@ impl<'a> WriteCtrl<'a> {
 		// descriptor.
 		if self.start == self.position {
 			let desc_ref = &mut self.desc_ring.ring[self.position];
-			desc_ref.address = paging::virt_to_phys(VirtAddr::from(mem_desc.ptr as u64)).into();
+			desc_ref.address = mem_desc.phys.into();
 			desc_ref.len = mem_desc.len as u32;
 			desc_ref.buff_id = mem_desc.id.as_ref().unwrap().0;
 			// Remove possibly set avail and used flags
@@ -785,7 +883,7 @@ impl<'a> WriteCtrl<'a> {
 			self.incrmt();
 		} else {
 			let mut desc_ref = &mut self.desc_ring.ring[self.position];
-			desc_ref.address = paging::virt_to_phys(VirtAddr::from(mem_desc.ptr as u64)).into();
+			desc_ref.address = mem_desc.phys.into();
 			desc_ref.len = mem_desc.len as u32;
 			desc_ref.buff_id = self.buff_id;
 			// Remove possibly set avail and used flags and then set avail and used
@@ -891,6 +989,14 @@ struct EventSuppr {
 	flags: u16,
 }
 
+/// Notifications are enabled.
+const RING_EVENT_FLAGS_ENABLE: u16 = 0x0;
+/// Notifications are disabled.
+const RING_EVENT_FLAGS_DISABLE: u16 = 0x1;
+/// Notifications are enabled for a specific descriptor.
+/// Only valid if VIRTIO_F_RING_EVENT_IDX has been negotiated.
+const RING_EVENT_FLAGS_DESC: u16 = 0x2;
+
 /// A newtype in order to implement the correct functionality upon
 /// the `EventSuppr` structure for driver notifications settings.
 /// The Driver Event Suppression structure is read-only by the device
@@ -921,27 +1027,28 @@ impl EventSuppr {
 }
 
 impl DrvNotif {
-	/// Enables notifications by setting the LSB.
+	/// Enables notifications by the device.
 	/// See Virito specification v1.1. - 2.7.10
 	fn enable_notif(&mut self) {
-		self.raw.flags = 0;
+		unsafe { ptr::write_volatile(&mut self.raw.flags, RING_EVENT_FLAGS_ENABLE) }
 	}
 
-	/// Disables notifications by unsetting the LSB.
+	/// Disables notifications by the device.
 	/// See Virtio specification v1.1. - 2.7.10
 	fn disable_notif(&mut self) {
-		self.raw.flags = 0;
+		unsafe { ptr::write_volatile(&mut self.raw.flags, RING_EVENT_FLAGS_DISABLE) }
 	}
 
 	/// Enables a notification by the device for a specific descriptor.
 	fn enable_specific(&mut self, at_offset: u16, at_wrap: u8) {
 		// Check if VIRTIO_F_RING_EVENT_IDX has been negotiated
 		if self.f_notif_idx {
-			self.raw.flags |= 1 << 1;
-			// Reset event fields
-			self.raw.event = 0;
-			self.raw.event = at_offset;
-			self.raw.event |= (at_wrap as u16) << 15;
+			// The position must be valid, before the device reads the flags.
+			unsafe {
+				ptr::write_volatile(&mut self.raw.event, at_offset | u16::from(at_wrap) << 15);
+				fence(Ordering::SeqCst);
+				ptr::write_volatile(&mut self.raw.flags, RING_EVENT_FLAGS_DESC);
+			}
 		}
 	}
 }
@@ -952,26 +1059,31 @@ impl DevNotif {
 		self.f_notif_idx = true;
 	}
 
-	/// Reads notification bit (i.e. LSB) and returns value.
-	/// If notifications are enabled returns true, else false.
-	fn is_notif(&self) -> bool {
-		self.raw.flags & (1 << 0) == 0
-	}
-
-	fn is_notif_specfic(&self, next_off: usize, next_wrap: u8) -> bool {
-		if self.f_notif_idx {
-			if self.raw.flags & 1 << 1 == 2 {
-				// as u16 is okay for usize, as size of queue is restricted to 2^15
-				// it is also okay to just loose the upper 8 bits, as we only check the LSB in second clause.
-				let desc_event_off = self.raw.event & !(1 << 15);
-				let desc_event_wrap = (self.raw.event >> 15) as u8;
-
-				desc_event_off == next_off as u16 && desc_event_wrap == next_wrap
-			} else {
-				false
+	/// Returns true, if the device wants to be notified about the descriptors from `start`
+	/// up to, but excluding `end`, each given as offset and wrap counter. For notifications
+	/// of a specific descriptor, the descriptor must be within this range.
+	///
+	/// The structure is written by the device at any time, hence it is read volatile.
+	fn needs_notif(&self, start: (usize, u8), end: (usize, u8), queue_size: usize) -> bool {
+		let flags = unsafe { ptr::read_volatile(&self.raw.flags) } & 0x3;
+		match flags {
+			RING_EVENT_FLAGS_ENABLE => true,
+			RING_EVENT_FLAGS_DESC if self.f_notif_idx => {
+				let event = unsafe { ptr::read_volatile(&self.raw.event) };
+				let event_off = usize::from(event & !(1 << 15));
+				let event_wrap = (event >> 15) as u8;
+
+				// Positions are counted over two rounds of the ring, as the wrap counter
+				// distinguishes them.
+				let pos = |(off, wrap): (usize, u8)| off + if wrap == 1 { 0 } else { queue_size };
+				let round = 2 * queue_size;
+				let dist = |from: usize, to: usize| (to + round - from) % round;
+
+				// A batch filling the whole ring ends at its start.
+				let len = dist(pos(start), pos(end));
+				len == 0 || dist(pos(start), pos((event_off, event_wrap))) < len
 			}
-		} else {
-			false
+			_ => false,
 		}
 	}
 }
@@ -1018,6 +1130,41 @@ impl PackedVq {
 		self.drv_event.borrow_mut().disable_notif();
 	}
 
+	/// Notifies the device about the buffers made available starting at descriptor
+	/// `next_off` with wrap counter `next_wrap`, unless the device suppresses the
+	/// notification via its event suppression structure.
+	/// See Virtio specification v1.1. - 2.7.10 and 2.7.21
+	fn notify_if_wanted(&self, next_off: usize, next_wrap: u8) {
+		// The device must see the available descriptors before its suppression
+		// structure is read.
+		fence(Ordering::SeqCst);
+		let end = self.descr_ring.borrow().next_avail();
+		if !self
+			.dev_event
+			.needs_notif((next_off, next_wrap), end, self.size.0.into())
+		{
+			return;
+		}
+
+		let index = self.index.0.to_le_bytes();
+		let mut index = index.iter();
+		// Even on 64bit systems this is fine, as we have a queue_size < 2^15!
+		let det_notif_data: u16 = (next_off as u16) >> 1;
+		let flags = (det_notif_data | (u16::from(next_wrap) << 15)).to_le_bytes();
+		let mut flags = flags.iter();
+		let mut notif_data: [u8; 4] = [0, 0, 0, 0];
+
+		for (i, byte) in notif_data.iter_mut().enumerate() {
+			if i < 2 {
+				*byte = *index.next().unwrap();
+			} else {
+				*byte = *flags.next().unwrap();
+			}
+		}
+
+		self.notif_ctrl.notify_dev(&notif_data)
+	}
+
 	/// This function does check if early dropped TransferTokens are finished
 	/// and removes them if this is the case.
 	pub fn clean_up(&self) {
@@ -1053,25 +1200,7 @@ impl PackedVq {
 				.enable_specific(next_off as u16, next_wrap);
 		}
 
-		if self.dev_event.is_notif() | self.dev_event.is_notif_specfic(next_off, next_wrap) {
-			let index = self.index.0.to_le_bytes();
-			let mut index = index.iter();
-			// Even on 64bit systems this is fine, as we have a queue_size < 2^15!
-			let det_notif_data: u16 = (next_off as u16) >> 1;
-			let flags = (det_notif_data | (u16::from(next_wrap) << 15)).to_le_bytes();
-			let mut flags = flags.iter();
-			let mut notif_data: [u8; 4] = [0, 0, 0, 0];
-
-			for (i, byte) in notif_data.iter_mut().enumerate() {
-				if i < 2 {
-					*byte = *index.next().unwrap();
-				} else {
-					*byte = *flags.next().unwrap();
-				}
-			}
-
-			self.notif_ctrl.notify_dev(&notif_data)
-		}
+		self.notify_if_wanted(next_off, next_wrap);
 
 		let mut transfer_lst = Vec::with_capacity(pin_tkn_lst.len());
 
@@ -1118,25 +1247,7 @@ impl PackedVq {
 				.enable_specific(next_off as u16, next_wrap);
 		}
 
-		if self.dev_event.is_notif() {
-			let index = self.index.0.to_le_bytes();
-			let mut index = index.iter();
-			// Even on 64bit systems this is fine, as we have a queue_size < 2^15!
-			let det_notif_data: u16 = (next_off as u16) >> 1;
-			let flags = (det_notif_data | (u16::from(next_wrap) << 15)).to_le_bytes();
-			let mut flags = flags.iter();
-			let mut notif_data: [u8; 4] = [0, 0, 0, 0];
-
-			for (i, byte) in notif_data.iter_mut().enumerate() {
-				if i < 2 {
-					*byte = *index.next().unwrap();
-				} else {
-					*byte = *flags.next().unwrap();
-				}
-			}
-
-			self.notif_ctrl.notify_dev(&notif_data)
-		}
+		self.notify_if_wanted(next_off, next_wrap);
 
 		for pinned in pin_tkn_lst {
 			// Prevent TransferToken from being dropped
@@ -1160,25 +1271,7 @@ impl PackedVq {
 				.enable_specific(next_off as u16, next_wrap);
 		}
 
-		if self.dev_event.is_notif() {
-			let index = self.index.0.to_le_bytes();
-			let mut index = index.iter();
-			// Even on 64bit systems this is fine, as we have a queue_size < 2^15!
-			let det_notif_data: u16 = (next_off as u16) >> 1;
-			let flags = (det_notif_data | (u16::from(next_wrap) << 15)).to_le_bytes();
-			let mut flags = flags.iter();
-			let mut notif_data: [u8; 4] = [0, 0, 0, 0];
-
-			for (i, byte) in notif_data.iter_mut().enumerate() {
-				if i < 2 {
-					*byte = *index.next().unwrap();
-				} else {
-					*byte = *flags.next().unwrap();
-				}
-			}
-
-			self.notif_ctrl.notify_dev(&notif_data)
-		}
+		self.notify_if_wanted(next_off, next_wrap);
 
 		Transfer {
 			transfer_tkn: Some(pin_tkn),
@@ -1208,6 +1301,17 @@ impl PackedVq {
 		self.index
 	}
 
+	/// Returns the page aligned size of one event suppression area.
+	fn event_mem_len() -> usize {
+		core::mem::size_of::<EventSuppr>().align_up(BasePageSize::SIZE as usize)
+	}
+
+	/// Returns the number of bytes `PackedVq::new()` allocates for the descriptor ring and
+	/// the event suppression areas of a queue with `size` entries.
+	pub fn ring_mem_len(size: u16) -> usize {
+		DescriptorRing::mem_len(size) + 2 * Self::event_mem_len()
+	}
+
 	/// See `Virtq::new()` documentation
 	pub fn new(
 		com_cfg: &mut ComCfg,
@@ -1215,6 +1319,7 @@ impl PackedVq {
 		size: VqSize,
 		index: VqIndex,
 		feats: u64,
+		allocator: &'static dyn DmaAllocator,
 	) -> Result<Self, VqPackedError> {
 		// Currently we do not have support for in order use.
 		// This steems from the fact, that the packedVq ReadCtrl currently is not
@@ -1246,22 +1351,38 @@ impl PackedVq {
 			vq_handler.set_vq_size(size.0)
 		};
 
-		let descr_ring = RefCell::new(DescriptorRing::new(vq_size));
-		// Allocate heap memory via a vec, leak and cast
-		let _mem_len = core::mem::size_of::<EventSuppr>().align_up(BasePageSize::SIZE as usize);
+		let (descr_ring, ring_phys) =
+			DescriptorRing::new(vq_size, allocator).ok_or(VqPackedError::General)?;
+		let ring_virt = VirtAddr::from(descr_ring.raw_addr());
+		let ring_len = DescriptorRing::mem_len(vq_size);
+		let descr_ring = RefCell::new(descr_ring);
+
+		let event_len = Self::event_mem_len();
+		let (drv_event_virt, drv_event_phys) =
+			match allocator.alloc_contiguous(event_len, BasePageSize::SIZE as usize) {
+				Some(area) => area,
+				None => {
+					allocator.free(ring_virt, ring_len);
+					return Err(VqPackedError::General);
+				}
+			};
+		let (dev_event_virt, dev_event_phys) =
+			match allocator.alloc_contiguous(event_len, BasePageSize::SIZE as usize) {
+				Some(area) => area,
+				None => {
+					allocator.free(ring_virt, ring_len);
+					allocator.free(drv_event_virt, event_len);
+					return Err(VqPackedError::General);
+				}
+			};
 
-		let drv_event_ptr =
-			(crate::mm::allocate(_mem_len, true).0 as *const EventSuppr) as *mut EventSuppr;
-		let dev_event_ptr =
-			(crate::mm::allocate(_mem_len, true).0 as *const EventSuppr) as *mut EventSuppr;
+		let drv_event_ptr = drv_event_virt.0 as *mut EventSuppr;
+		let dev_event_ptr = dev_event_virt.0 as *mut EventSuppr;
 
 		// Provide memory areas of the queues data structures to the device
-		vq_handler.set_ring_addr(paging::virt_to_phys(VirtAddr::from(
-			descr_ring.borrow().raw_addr() as u64,
-		)));
-		// As usize is safe here, as the *mut EventSuppr raw pointer is a thin pointer of size usize
-		vq_handler.set_drv_ctrl_addr(paging::virt_to_phys(VirtAddr::from(drv_event_ptr as u64)));
-		vq_handler.set_dev_ctrl_addr(paging::virt_to_phys(VirtAddr::from(dev_event_ptr as u64)));
+		vq_handler.set_ring_addr(ring_phys);
+		vq_handler.set_drv_ctrl_addr(drv_event_phys);
+		vq_handler.set_dev_ctrl_addr(dev_event_phys);
 
 		let drv_event: &'static mut EventSuppr = unsafe { &mut *(drv_event_ptr) };
 
@@ -1292,7 +1413,7 @@ impl PackedVq {
 		}
 
 		// Initialize new memory pool.
-		let mem_pool = Rc::new(MemPool::new(vq_size));
+		let mem_pool = Rc::new(MemPool::new(vq_size, allocator));
 
 		// Initialize an empty vector for future dropped transfers
 		let dropped: RefCell<Vec<Pinned<TransferToken>>> = RefCell::new(Vec::new());
@@ -1313,6 +1434,15 @@ impl PackedVq {
 		})
 	}
 
+	/// See `Virtq.prep_transfer_from_slices()` documentation.
+	pub fn prep_transfer_from_slices(
+		&self,
+		master: Rc<Virtq>,
+		send: &[&[u8]],
+	) -> Result<TransferToken, VirtqError> {
+		transfer_from_slices(&self.mem_pool, master, send)
+	}
+
 	/// See `Virtq.prep_transfer_from_raw()` documentation.
 	pub fn prep_transfer_from_raw<T: AsSliceU8 + 'static, K: AsSliceU8 + 'static>(
 		&self,
@@ -1976,7 +2106,8 @@ impl PackedVq {
 							// control descriptor, the actual list is untracked
 							desc_lst.push(
 								self.mem_pool
-									.pull_untracked(Rc::clone(&self.mem_pool), *size),
+									.pull_untracked(Rc::clone(&self.mem_pool), *size)
+									.ok_or(VirtqError::NoMemAvail)?,
 							);
 							len += usize::from(*size);
 						}
@@ -2064,7 +2195,8 @@ impl PackedVq {
 							// control descriptor, the actual list is untracked
 							desc_lst.push(
 								self.mem_pool
-									.pull_untracked(Rc::clone(&self.mem_pool), *size),
+									.pull_untracked(Rc::clone(&self.mem_pool), *size)
+									.ok_or(VirtqError::NoMemAvail)?,
 							);
 							len += usize::from(*size);
 						}
@@ -2256,7 +2388,8 @@ impl PackedVq {
 							// control descriptor, the actual list is untracked
 							send_desc_lst.push(
 								self.mem_pool
-									.pull_untracked(Rc::clone(&self.mem_pool), *size),
+									.pull_untracked(Rc::clone(&self.mem_pool), *size)
+									.ok_or(VirtqError::NoMemAvail)?,
 							);
 							send_len += usize::from(*size);
 						}
@@ -2270,7 +2403,8 @@ impl PackedVq {
 							// control descriptor, the actual list is untracked
 							recv_desc_lst.push(
 								self.mem_pool
-									.pull_untracked(Rc::clone(&self.mem_pool), *size),
+									.pull_untracked(Rc::clone(&self.mem_pool), *size)
+									.ok_or(VirtqError::NoMemAvail)?,
 							);
 							recv_len += usize::from(*size);
 						}
@@ -2316,6 +2450,55 @@ impl PackedVq {
 	pub fn size(&self) -> VqSize {
 		self.size
 	}
+
+	/// See `Virtq.free_descriptors()` documentation
+	pub fn free_descriptors(&self) -> u16 {
+		u16::try_from(self.descr_ring.borrow().capacity).unwrap()
+	}
+
+	/// See `Virtq.pending_used()` documentation
+	pub fn pending_used(&self) -> u16 {
+		u16::try_from(self.descr_ring.borrow().pending_used()).unwrap()
+	}
+
+	/// See `Virtq.debug_state()` documentation
+	pub fn debug_state(&self) -> QueueDebug {
+		let ring = self.descr_ring.borrow();
+		let (free_ids, free_heads) = self.mem_pool.free_ids();
+
+		QueueDebug {
+			index: self.index.0,
+			size: self.size.0,
+			ring: RingDebug::Packed {
+				write_index: u16::try_from(ring.write_index).unwrap(),
+				poll_index: u16::try_from(ring.poll_index).unwrap(),
+				drv_wrap: ring.drv_wc.0,
+				dev_wrap: ring.dev_wc.0,
+			},
+			outstanding: u16::try_from(ring.ring.len() - ring.capacity).unwrap(),
+			free_ids,
+			free_heads,
+		}
+	}
+
+	/// See `Virtq.dump_descriptors()` documentation
+	pub fn dump_descriptors(&self, count: usize) -> Vec<DescInfo> {
+		let ring = self.descr_ring.borrow();
+
+		ring.ring
+			.iter()
+			.take(count)
+			.map(|desc| {
+				let desc = unsafe { ptr::read_volatile(desc) };
+				DescInfo {
+					addr: desc.address,
+					len: desc.len,
+					flags: desc.flags,
+					next: desc.buff_id,
+				}
+			})
+			.collect()
+	}
 }
 
 // Private Interface for PackedVq
@@ -2360,7 +2543,7 @@ impl PackedVq {
 			(None, Some(recv_desc_lst)) => {
 				for desc in recv_desc_lst {
 					desc_slice[crtl_desc_iter] = Descriptor::new(
-						paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
+						desc.phys.into(),
 						desc.len as u32,
 						0,
 						DescrFlags::VIRTQ_DESC_F_WRITE.into(),
@@ -2373,12 +2556,8 @@ impl PackedVq {
 			// Only sending descriptors
 			(Some(send_desc_lst), None) => {
 				for desc in send_desc_lst {
-					desc_slice[crtl_desc_iter] = Descriptor::new(
-						paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
-						desc.len as u32,
-						0,
-						0,
-					);
+					desc_slice[crtl_desc_iter] =
+						Descriptor::new(desc.phys.into(), desc.len as u32, 0, 0);
 
 					crtl_desc_iter += 1;
 				}
@@ -2387,19 +2566,15 @@ impl PackedVq {
 			(Some(send_desc_lst), Some(recv_desc_lst)) => {
 				// Send descriptors ALWAYS before receiving ones.
 				for desc in send_desc_lst {
-					desc_slice[crtl_desc_iter] = Descriptor::new(
-						paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
-						desc.len as u32,
-						0,
-						0,
-					);
+					desc_slice[crtl_desc_iter] =
+						Descriptor::new(desc.phys.into(), desc.len as u32, 0, 0);
 
 					crtl_desc_iter += 1;
 				}
 
 				for desc in recv_desc_lst {
 					desc_slice[crtl_desc_iter] = Descriptor::new(
-						paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
+						desc.phys.into(),
 						desc.len as u32,
 						0,
 						DescrFlags::VIRTQ_DESC_F_WRITE.into(),
@@ -2422,3 +2597,142 @@ pub mod error {
 		FeatNotSupported(u64),
 	}
 }
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	/// Counts the notifications of the device, while a burst of single descriptor
+	/// packets is made available in a ring of `size` descriptors.
+	fn count_notifs(dev_event: &DevNotif, size: usize, packets: usize) -> usize {
+		let mut pos = (0, 1);
+		let mut notifs = 0;
+		for _ in 0..packets {
+			let next = if pos.0 + 1 == size {
+				(0, pos.1 ^ 1)
+			} else {
+				(pos.0 + 1, pos.1)
+			};
+			if dev_event.needs_notif(pos, next, size) {
+				notifs += 1;
+			}
+			pos = next;
+		}
+		notifs
+	}
+
+	#[test]
+	fn event_suppression_reduces_notifications() {
+		let mut dev_event = DevNotif {
+			f_notif_idx: true,
+			raw: Box::leak(Box::new(EventSuppr::new())),
+		};
+
+		dev_event.raw.flags = RING_EVENT_FLAGS_ENABLE;
+		assert_eq!(count_notifs(&dev_event, 256, 1000), 1000);
+
+		dev_event.raw.flags = RING_EVENT_FLAGS_DISABLE;
+		assert_eq!(count_notifs(&dev_event, 256, 1000), 0);
+
+		// Descriptor 10 with wrap counter 1 is made available by packets 10 and 522.
+		dev_event.raw.flags = RING_EVENT_FLAGS_DESC;
+		dev_event.raw.event = 10 | 1 << 15;
+		assert_eq!(count_notifs(&dev_event, 256, 1000), 2);
+
+		// Without VIRTIO_F_RING_EVENT_IDX, the descriptor specific mode is not valid.
+		dev_event.f_notif_idx = false;
+		assert_eq!(count_notifs(&dev_event, 256, 1000), 0);
+	}
+
+	#[test]
+	fn event_descriptor_within_batch_is_notified() {
+		let dev_event = DevNotif {
+			f_notif_idx: true,
+			raw: Box::leak(Box::new(EventSuppr {
+				event: 2,
+				flags: RING_EVENT_FLAGS_DESC,
+			})),
+		};
+
+		// Descriptor 2 of the second round lies in a batch wrapping around the ring.
+		assert!(dev_event.needs_notif((14, 1), (4, 0), 16));
+		assert!(!dev_event.needs_notif((3, 0), (6, 0), 16));
+	}
+
+	fn ring_with_flags(flags: &[u16]) -> &'static [Descriptor] {
+		let ring: Vec<Descriptor> = flags
+			.iter()
+			.map(|flags| Descriptor::new(0, 0, 1, *flags))
+			.collect();
+		Vec::leak(ring)
+	}
+
+	#[test]
+	fn used_buffers_are_counted_at_ring_boundaries() {
+		let used_wc0 = WrapCount(false).as_flags_used();
+		let used_wc1 = WrapCount(true).as_flags_used();
+		let avail_wc0 = WrapCount(false).as_flags_avail();
+
+		// Fresh descriptors look used for the wrap counter 0, but nothing is in flight.
+		let ring = ring_with_flags(&[0; 4]);
+		assert_eq!(count_used(ring, 0, WrapCount(false), 0, |_| 1), 0);
+
+		// A full ring, whose used descriptors wrap around the end of the ring.
+		let ring = ring_with_flags(&[used_wc0, used_wc0, used_wc1, used_wc1]);
+		assert_eq!(count_used(ring, 2, WrapCount(true), 4, |_| 1), 4);
+
+		// The device has only used the descriptors before the wrap around.
+		let ring = ring_with_flags(&[avail_wc0, avail_wc0, used_wc1, used_wc1]);
+		assert_eq!(count_used(ring, 2, WrapCount(true), 4, |_| 1), 2);
+
+		// A chain of two descriptors counts as a single buffer.
+		let ring = ring_with_flags(&[used_wc1, 0, used_wc1, avail_wc0]);
+		assert_eq!(count_used(ring, 0, WrapCount(true), 4, |_| 2), 2);
+	}
+
+	#[test]
+	fn used_descriptors_are_detected_across_wraps() {
+		const SIZE: usize = 4;
+		let mut ring = [0u16; SIZE];
+		let mut drv_wc = WrapCount::new();
+		// Wrap counter of the device and the one expected by the driver.
+		let mut dev_wc = WrapCount::new();
+		let mut poll_wc = WrapCount::new();
+		let (mut write, mut dev, mut poll) = (0, 0, 0);
+
+		// Fresh descriptors are not used.
+		assert!(ring.iter().all(|flags| !poll_wc.is_used(*flags)));
+
+		for _ in 0..2 {
+			// The driver fills the whole ring.
+			for _ in 0..SIZE {
+				ring[write] = (ring[write] & !WrapCount::flag_mask()) | drv_wc.as_flags_avail();
+				if write + 1 == SIZE {
+					drv_wc.wrap();
+				}
+				write = (write + 1) % SIZE;
+			}
+			assert!(ring.iter().all(|flags| !poll_wc.is_used(*flags)));
+
+			// The device uses one descriptor after the other, each of which is detected
+			// exactly once by the driver.
+			for _ in 0..SIZE {
+				assert!(!poll_wc.is_used(ring[poll]));
+				ring[dev] = dev_wc.as_flags_used();
+				if dev + 1 == SIZE {
+					dev_wc.wrap();
+				}
+				dev = (dev + 1) % SIZE;
+
+				assert!(poll_wc.is_used(ring[poll]));
+				if poll + 1 == SIZE {
+					poll_wc.wrap();
+				}
+				poll = (poll + 1) % SIZE;
+			}
+
+			// After the wrap, descriptors of the previous round are stale.
+			assert!(ring.iter().all(|flags| !poll_wc.is_used(*flags)));
+		}
+	}
+}
@@ -12,17 +12,18 @@ use core::sync::atomic::{fence, Ordering};
 
 use align_address::Align;
 
+use super::super::env::dma::DmaAllocator;
 #[cfg(not(feature = "pci"))]
 use super::super::transport::mmio::{ComCfg, NotifCfg, NotifCtrl};
 #[cfg(feature = "pci")]
 use super::super::transport::pci::{ComCfg, NotifCfg, NotifCtrl};
 use super::error::VirtqError;
 use super::{
-	AsSliceU8, BuffSpec, Buffer, BufferToken, Bytes, DescrFlags, MemDescr, MemPool, Pinned,
-	Transfer, TransferState, TransferToken, Virtq, VqIndex, VqSize,
+	transfer_from_slices, AsSliceU8, BuffSpec, Buffer, BufferToken, Bytes, DescInfo, DescrFlags,
+	MemDescr, MemPool, Pinned, QueueDebug, RingDebug, Transfer, TransferState, TransferToken,
+	Virtq, VqIndex, VqSize,
 };
 use crate::arch::mm::paging::{BasePageSize, PageSize};
-use crate::arch::mm::{paging, VirtAddr};
 
 #[repr(C)]
 #[derive(Copy, Clone)]
@@ -71,6 +72,8 @@ struct UsedElem {
 
 struct DescrRing {
 	read_idx: u16,
+	/// Number of descriptors, which have been made available, but not yet polled.
+	in_flight: usize,
 	descr_table: DescrTable,
 	ref_ring: Box<[*mut TransferToken]>,
 	avail_ring: AvailRing,
@@ -79,6 +82,34 @@ struct DescrRing {
 
 impl DescrRing {
 	fn push(&mut self, tkn: TransferToken) -> (Pinned<TransferToken>, u16, u16) {
+		let pin = self.write(tkn, 0);
+
+		fence(Ordering::SeqCst);
+		*self.avail_ring.index = self.avail_ring.index.wrapping_add(1);
+
+		(pin, 0, 0)
+	}
+
+	/// Places the buffers of all tokens into the queue, but makes them available to the
+	/// device at once by updating the index of the available ring only once.
+	fn push_batch(&mut self, tkns: Vec<TransferToken>) -> (Vec<Pinned<TransferToken>>, u16, u16) {
+		let num = u16::try_from(tkns.len()).unwrap();
+		let pins = tkns
+			.into_iter()
+			.enumerate()
+			.map(|(offset, tkn)| self.write(tkn, offset as u16))
+			.collect();
+
+		fence(Ordering::SeqCst);
+		*self.avail_ring.index = self.avail_ring.index.wrapping_add(num);
+
+		(pins, 0, 0)
+	}
+
+	/// Writes the descriptors of the token into the descriptor table and its head into the
+	/// available ring, `offset` entries behind the current index. The entry is not made
+	/// available to the device.
+	fn write(&mut self, tkn: TransferToken, offset: u16) -> Pinned<TransferToken> {
 		let pin = Pinned::pin(tkn);
 
 		let mut desc_lst = Vec::new();
@@ -115,6 +146,7 @@ impl DescrRing {
 		}
 
 		let mut len = pin.buff_tkn.as_ref().unwrap().num_consuming_descr();
+		self.in_flight += len;
 
 		assert!(!desc_lst.is_empty());
 		// Minus 1, comes from  the fact that ids run from one to 255 and not from 0 to 254 for u8::MAX sized pool
@@ -133,14 +165,14 @@ impl DescrRing {
 				assert!(len == 1);
 				if is_write {
 					Descriptor::new(
-						paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
+						desc.phys.into(),
 						desc.len as u32,
 						DescrFlags::VIRTQ_DESC_F_INDIRECT | DescrFlags::VIRTQ_DESC_F_WRITE,
 						0,
 					)
 				} else {
 					Descriptor::new(
-						paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
+						desc.phys.into(),
 						desc.len as u32,
 						DescrFlags::VIRTQ_DESC_F_INDIRECT.into(),
 						0,
@@ -154,14 +186,14 @@ impl DescrRing {
 
 				if is_write {
 					Descriptor::new(
-						paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
+						desc.phys.into(),
 						desc.len as u32,
 						DescrFlags::VIRTQ_DESC_F_WRITE | DescrFlags::VIRTQ_DESC_F_NEXT,
 						next_index,
 					)
 				} else {
 					Descriptor::new(
-						paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
+						desc.phys.into(),
 						desc.len as u32,
 						DescrFlags::VIRTQ_DESC_F_NEXT.into(),
 						next_index,
@@ -169,18 +201,13 @@ impl DescrRing {
 				}
 			} else if is_write {
 				Descriptor::new(
-					paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
+					desc.phys.into(),
 					desc.len as u32,
 					DescrFlags::VIRTQ_DESC_F_WRITE.into(),
 					0,
 				)
 			} else {
-				Descriptor::new(
-					paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
-					desc.len as u32,
-					0,
-					0,
-				)
+				Descriptor::new(desc.phys.into(), desc.len as u32, 0, 0)
 			};
 
 			self.descr_table.raw[write_indx] = descriptor;
@@ -190,13 +217,10 @@ impl DescrRing {
 		}
 
 		self.ref_ring[index] = pin.raw_addr();
-		self.avail_ring.ring[*self.avail_ring.index as usize % self.avail_ring.ring.len()] =
-			index as u16;
-
-		fence(Ordering::SeqCst);
-		*self.avail_ring.index = self.avail_ring.index.wrapping_add(1);
+		let avail_idx = self.avail_ring.index.wrapping_add(offset);
+		self.avail_ring.ring[avail_idx as usize % self.avail_ring.ring.len()] = index as u16;
 
-		(pin, 0, 0)
+		pin
 	}
 
 	fn poll(&mut self) {
@@ -204,13 +228,39 @@ impl DescrRing {
 			let cur_ring_index = self.read_idx as usize % self.used_ring.ring.len();
 			let used_elem = self.used_ring.ring[cur_ring_index];
 
-			let tkn = unsafe { &mut *(self.ref_ring[used_elem.id as usize]) };
-
-			if tkn.buff_tkn.as_ref().unwrap().recv_buff.as_ref().is_some() {
+			// The id is written by the device. An id, which is out of range or does not belong
+			// to a descriptor chain in flight, is skipped instead of dereferenced.
+			let raw_tkn = match self.ref_ring.get_mut(used_elem.id as usize) {
+				Some(raw_tkn) if !raw_tkn.is_null() => core::mem::replace(raw_tkn, ptr::null_mut()),
+				_ => {
+					error!(
+						"Device returned unknown descriptor id {}. Skipping used element.",
+						used_elem.id
+					);
+					self.read_idx = self.read_idx.wrapping_add(1);
+					continue;
+				}
+			};
+			// SAFETY: Tokens referenced by the ring are in flight and hence alive.
+			let tkn = unsafe { &mut *raw_tkn };
+			self.in_flight = self
+				.in_flight
+				.saturating_sub(tkn.buff_tkn.as_ref().unwrap().num_consuming_descr());
+
+			if let Some(recv_buff) = tkn.buff_tkn.as_ref().unwrap().recv_buff.as_ref() {
+				// The device must not report more bytes than the buffer holds.
+				let capacity = recv_buff.len();
+				let written = usize::try_from(used_elem.len).unwrap();
+				if written > capacity {
+					error!(
+						"Device reported {} written bytes for a buffer of {} bytes. Truncating.",
+						written, capacity
+					);
+				}
 				tkn.buff_tkn
 					.as_mut()
 					.unwrap()
-					.restr_size(None, Some(used_elem.len as usize))
+					.restr_size(None, Some(written.min(capacity)))
 					.unwrap();
 			}
 			match tkn.await_queue {
@@ -229,6 +279,12 @@ impl DescrRing {
 		}
 	}
 
+	/// Returns the number of buffers, which have been used by the device, but not yet polled.
+	fn pending_used(&self) -> u16 {
+		let used_idx = unsafe { ptr::read_volatile(&*self.used_ring.index) };
+		pending_used(self.read_idx, used_idx)
+	}
+
 	fn drv_enable_notif(&mut self) {
 		*self.avail_ring.flags = 0;
 	}
@@ -242,6 +298,82 @@ impl DescrRing {
 	}
 }
 
+/// Device side of the ring for host tests.
+#[cfg(all(test, not(target_os = "none")))]
+impl DescrRing {
+	/// Takes the next available descriptor chain, copies `data` into its writable
+	/// descriptors and marks the chain as used, like a device processing buffers in order.
+	///
+	/// Returns the content of the readable descriptors or None, if no chain is available.
+	/// Descriptor addresses are accessed as virtual addresses, hence the memory of the
+	/// queue must be allocated via an identity mapping allocator.
+	fn mock_use(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+		let used_idx = *self.used_ring.index;
+		if used_idx == *self.avail_ring.index {
+			return None;
+		}
+		let size = self.used_ring.ring.len();
+		let head = self.avail_ring.ring[usize::from(used_idx) % size];
+
+		let has_next = |desc: &Descriptor| desc.flags & DescrFlags::VIRTQ_DESC_F_NEXT != 0;
+		let mut chain = Vec::new();
+		let mut next = Some(head);
+		while let Some(i) = next {
+			let desc = self.descr_table.raw[usize::from(i)];
+			next = has_next(&desc).then_some(desc.next);
+
+			if desc.flags & DescrFlags::VIRTQ_DESC_F_INDIRECT != 0 {
+				// SAFETY: The table is owned by the buffer, which is in flight.
+				let table = unsafe {
+					core::slice::from_raw_parts(
+						desc.address as usize as *const Descriptor,
+						desc.len as usize / core::mem::size_of::<Descriptor>(),
+					)
+				};
+				let mut next = Some(0);
+				while let Some(i) = next {
+					let desc = table[usize::from(i)];
+					next = has_next(&desc).then_some(desc.next);
+					chain.push(desc);
+				}
+			} else {
+				chain.push(desc);
+			}
+		}
+
+		let mut read = Vec::new();
+		let mut written = 0;
+		for desc in chain {
+			// SAFETY: The memory is owned by the buffer, which is in flight.
+			let buf = unsafe {
+				core::slice::from_raw_parts_mut(desc.address as usize as *mut u8, desc.len as usize)
+			};
+			if desc.flags & DescrFlags::VIRTQ_DESC_F_WRITE != 0 {
+				let len = buf.len().min(data.len() - written);
+				buf[..len].copy_from_slice(&data[written..written + len]);
+				written += len;
+			} else {
+				read.extend_from_slice(buf);
+			}
+		}
+
+		self.used_ring.ring[usize::from(used_idx) % size] = UsedElem {
+			id: u32::from(head),
+			len: u32::try_from(written).unwrap(),
+		};
+		fence(Ordering::SeqCst);
+		*self.used_ring.index = used_idx.wrapping_add(1);
+
+		Some(read)
+	}
+}
+
+/// Returns the number of used ring entries between the driver's read index and the
+/// device's used index. Both indices are free running and wrap around at `u16::MAX`.
+fn pending_used(read_idx: u16, used_idx: u16) -> u16 {
+	used_idx.wrapping_sub(read_idx)
+}
+
 /// Virtio's split virtqueue structure
 pub struct SplitVq {
 	ring: RefCell<DescrRing>,
@@ -287,8 +419,24 @@ impl SplitVq {
 	/// The `notif` parameter indicates if the driver wants to have a notification for this specific
 	/// transfer. This is only for performance optimization. As it is NOT ensured, that the device sees the
 	/// updated notification flags before finishing transfers!
-	pub fn dispatch_batch(&self, _tkns: Vec<TransferToken>, _notif: bool) -> Vec<Transfer> {
-		unimplemented!();
+	pub fn dispatch_batch(&self, tkns: Vec<TransferToken>, notif: bool) -> Vec<Transfer> {
+		// Zero transfers are not allowed
+		assert!(!tkns.is_empty());
+
+		if notif {
+			self.request_notif();
+		}
+
+		let (pin_tkn_lst, next_off, next_wrap) = self.ring.borrow_mut().push_batch(tkns);
+
+		self.notify_if_wanted(next_off, next_wrap);
+
+		pin_tkn_lst
+			.into_iter()
+			.map(|pinned| Transfer {
+				transfer_tkn: Some(pinned),
+			})
+			.collect()
 	}
 
 	/// Dispatches a batch of TransferTokens. The Transfers will be placed in to the `await_queue`
@@ -305,11 +453,32 @@ impl SplitVq {
 	/// Tokens to get a reference to the provided await_queue, where they will be placed upon finish.
 	pub fn dispatch_batch_await(
 		&self,
-		_tkns: Vec<TransferToken>,
-		_await_queue: Rc<RefCell<VecDeque<Transfer>>>,
-		_notif: bool,
+		mut tkns: Vec<TransferToken>,
+		await_queue: Rc<RefCell<VecDeque<Transfer>>>,
+		notif: bool,
 	) {
-		unimplemented!()
+		// Zero transfers are not allowed
+		assert!(!tkns.is_empty());
+
+		// We have to iterate here too, in order to ensure, tokens are placed into the await_queue
+		for tkn in tkns.iter_mut() {
+			tkn.await_queue = Some(Rc::clone(&await_queue));
+		}
+
+		if notif {
+			self.request_notif();
+		}
+
+		let (pin_tkn_lst, next_off, next_wrap) = self.ring.borrow_mut().push_batch(tkns);
+
+		self.notify_if_wanted(next_off, next_wrap);
+
+		for pinned in pin_tkn_lst {
+			// Prevent TransferToken from being dropped
+			// I.e. do NOT run the custom constructor which will
+			// deallocate memory.
+			pinned.into_raw();
+		}
 	}
 
 	/// See `Virtq.prep_transfer()` documentation.
@@ -318,14 +487,31 @@ impl SplitVq {
 	/// transfer. This is only for performance optimization. As it is NOT ensured, that the device sees the
 	/// updated notification flags before finishing transfers!
 	pub fn dispatch(&self, tkn: TransferToken, notif: bool) -> Transfer {
+		if notif {
+			self.request_notif();
+		}
+
 		let (pin_tkn, next_off, next_wrap) = self.ring.borrow_mut().push(tkn);
 
-		if notif {
-			// TODO: Check whether the splitvirtquue has notifications for specific descriptors
-			// I believe it does not.
-			unimplemented!();
+		self.notify_if_wanted(next_off, next_wrap);
+
+		Transfer {
+			transfer_tkn: Some(pin_tkn),
 		}
+	}
+
+	/// Asks the device for a notification, once the dispatched buffers have been used.
+	///
+	/// Split virtqueues do not support notifications for specific descriptors. Hence
+	/// notifications are enabled for all used buffers of the queue, until they are disabled
+	/// again via [disable_notifs](Self::disable_notifs). Must be called before the buffers are
+	/// made available, so the device sees the flag when it uses them.
+	fn request_notif(&self) {
+		self.ring.borrow_mut().drv_enable_notif();
+	}
 
+	/// Notifies the device about new available buffers, unless it suppressed notifications.
+	fn notify_if_wanted(&self, next_off: u16, next_wrap: u16) {
 		if self.ring.borrow().dev_is_notif() {
 			let index = self.index.0.to_le_bytes();
 			let mut index = index.iter();
@@ -345,10 +531,6 @@ impl SplitVq {
 
 			self.notif_ctrl.notify_dev(&notif_data)
 		}
-
-		Transfer {
-			transfer_tkn: Some(pin_tkn),
-		}
 	}
 
 	/// The packed virtqueue handles early dropped transfers by moving the respective tokens into
@@ -374,6 +556,25 @@ impl SplitVq {
 		self.index
 	}
 
+	/// Returns the page aligned sizes of the descriptor table, the available ring and the
+	/// used ring of a queue with `size` entries.
+	///
+	/// See Virtio specification v1.1. - 2.6
+	fn ring_mem_lens(size: u16) -> [usize; 3] {
+		let size = usize::from(size);
+		[
+			(size * core::mem::size_of::<Descriptor>()).align_up(BasePageSize::SIZE as usize),
+			(6 + size * 2).align_up(BasePageSize::SIZE as usize),
+			(6 + size * 8).align_up(BasePageSize::SIZE as usize),
+		]
+	}
+
+	/// Returns the number of bytes `SplitVq::new()` allocates for the rings of a queue
+	/// with `size` entries.
+	pub fn ring_mem_len(size: u16) -> usize {
+		Self::ring_mem_lens(size).iter().sum()
+	}
+
 	/// See `Virtq::new()` documentation
 	pub fn new(
 		com_cfg: &mut ComCfg,
@@ -381,29 +582,45 @@ impl SplitVq {
 		size: VqSize,
 		index: VqIndex,
 		_feats: u64,
-	) -> Result<Self, ()> {
+		allocator: &'static dyn DmaAllocator,
+	) -> Result<Self, VirtqError> {
 		// Get a handler to the queues configuration area.
 		let mut vq_handler = match com_cfg.select_vq(index.into()) {
 			Some(handler) => handler,
-			None => return Err(()),
+			None => return Err(VirtqError::QueueNotExisting(index.into())),
 		};
 
 		let size = vq_handler.set_vq_size(size.0);
+		let [table_len, avail_len, used_len] = Self::ring_mem_lens(size);
 
-		// Allocate heap memory via a vec, leak and cast
-		let _mem_len = (size as usize * core::mem::size_of::<Descriptor>())
-			.align_up(BasePageSize::SIZE as usize);
-		let table_raw =
-			(crate::mm::allocate(_mem_len, true).0 as *const Descriptor) as *mut Descriptor;
+		let (table_virt, table_phys) = allocator
+			.alloc_contiguous(table_len, BasePageSize::SIZE as usize)
+			.ok_or(VirtqError::NoMemAvail)?;
+		let table_raw = table_virt.0 as *mut Descriptor;
 
 		let descr_table = DescrTable {
 			raw: unsafe { core::slice::from_raw_parts_mut(table_raw, size as usize) },
 		};
 
-		let _mem_len = (6 + (size as usize * 2)).align_up(BasePageSize::SIZE as usize);
-		let avail_raw = (crate::mm::allocate(_mem_len, true).0 as *const u8) as *mut u8;
-		let _mem_len = (6 + (size as usize * 8)).align_up(BasePageSize::SIZE as usize);
-		let used_raw = (crate::mm::allocate(_mem_len, true).0 as *const u8) as *mut u8;
+		let (avail_virt, avail_phys) =
+			match allocator.alloc_contiguous(avail_len, BasePageSize::SIZE as usize) {
+				Some(area) => area,
+				None => {
+					allocator.free(table_virt, table_len);
+					return Err(VirtqError::NoMemAvail);
+				}
+			};
+		let avail_raw = avail_virt.0 as *mut u8;
+		let (used_virt, used_phys) =
+			match allocator.alloc_contiguous(used_len, BasePageSize::SIZE as usize) {
+				Some(area) => area,
+				None => {
+					allocator.free(table_virt, table_len);
+					allocator.free(avail_virt, avail_len);
+					return Err(VirtqError::NoMemAvail);
+				}
+			};
+		let used_raw = used_virt.0 as *mut u8;
 
 		let avail_ring = unsafe {
 			AvailRing {
@@ -442,13 +659,13 @@ impl SplitVq {
 		}
 
 		// Provide memory areas of the queues data structures to the device
-		vq_handler.set_ring_addr(paging::virt_to_phys(VirtAddr::from(table_raw as u64)));
-		// As usize is safe here, as the *mut EventSuppr raw pointer is a thin pointer of size usize
-		vq_handler.set_drv_ctrl_addr(paging::virt_to_phys(VirtAddr::from(avail_raw as u64)));
-		vq_handler.set_dev_ctrl_addr(paging::virt_to_phys(VirtAddr::from(used_raw as u64)));
+		vq_handler.set_ring_addr(table_phys);
+		vq_handler.set_drv_ctrl_addr(avail_phys);
+		vq_handler.set_dev_ctrl_addr(used_phys);
 
 		let descr_ring = DescrRing {
 			read_idx: 0,
+			in_flight: 0,
 			ref_ring: vec![ptr::null_mut(); size as usize].into_boxed_slice(),
 			descr_table,
 			avail_ring,
@@ -462,7 +679,7 @@ impl SplitVq {
 		);
 
 		// Initialize new memory pool.
-		let mem_pool = Rc::new(MemPool::new(size));
+		let mem_pool = Rc::new(MemPool::new(size, allocator));
 
 		// Initialize an empty vector for future dropped transfers
 		let dropped: RefCell<Vec<Pinned<TransferToken>>> = RefCell::new(Vec::new());
@@ -481,6 +698,15 @@ impl SplitVq {
 		})
 	}
 
+	/// See `Virtq.prep_transfer_from_slices()` documentation.
+	pub fn prep_transfer_from_slices(
+		&self,
+		master: Rc<Virtq>,
+		send: &[&[u8]],
+	) -> Result<TransferToken, VirtqError> {
+		transfer_from_slices(&self.mem_pool, master, send)
+	}
+
 	/// See `Virtq.prep_transfer_from_raw()` documentation.
 	pub fn prep_transfer_from_raw<T: AsSliceU8 + 'static, K: AsSliceU8 + 'static>(
 		&self,
@@ -1144,7 +1370,8 @@ impl SplitVq {
 							// control descriptor, the actual list is untracked
 							desc_lst.push(
 								self.mem_pool
-									.pull_untracked(Rc::clone(&self.mem_pool), *size),
+									.pull_untracked(Rc::clone(&self.mem_pool), *size)
+									.ok_or(VirtqError::NoMemAvail)?,
 							);
 							len += usize::from(*size);
 						}
@@ -1232,7 +1459,8 @@ impl SplitVq {
 							// control descriptor, the actual list is untracked
 							desc_lst.push(
 								self.mem_pool
-									.pull_untracked(Rc::clone(&self.mem_pool), *size),
+									.pull_untracked(Rc::clone(&self.mem_pool), *size)
+									.ok_or(VirtqError::NoMemAvail)?,
 							);
 							len += usize::from(*size);
 						}
@@ -1424,7 +1652,8 @@ impl SplitVq {
 							// control descriptor, the actual list is untracked
 							send_desc_lst.push(
 								self.mem_pool
-									.pull_untracked(Rc::clone(&self.mem_pool), *size),
+									.pull_untracked(Rc::clone(&self.mem_pool), *size)
+									.ok_or(VirtqError::NoMemAvail)?,
 							);
 							send_len += usize::from(*size);
 						}
@@ -1438,7 +1667,8 @@ impl SplitVq {
 							// control descriptor, the actual list is untracked
 							recv_desc_lst.push(
 								self.mem_pool
-									.pull_untracked(Rc::clone(&self.mem_pool), *size),
+									.pull_untracked(Rc::clone(&self.mem_pool), *size)
+									.ok_or(VirtqError::NoMemAvail)?,
 							);
 							recv_len += usize::from(*size);
 						}
@@ -1484,6 +1714,56 @@ impl SplitVq {
 	pub fn size(&self) -> VqSize {
 		self.size
 	}
+
+	/// See `Virtq.free_descriptors()` documentation
+	pub fn free_descriptors(&self) -> u16 {
+		let in_flight = self.ring.borrow().in_flight;
+		u16::from(self.size).saturating_sub(u16::try_from(in_flight).unwrap_or(u16::MAX))
+	}
+
+	/// See `Virtq.pending_used()` documentation
+	pub fn pending_used(&self) -> u16 {
+		self.ring.borrow().pending_used()
+	}
+
+	/// See `Virtq.debug_state()` documentation
+	pub fn debug_state(&self) -> QueueDebug {
+		let ring = self.ring.borrow();
+		let (free_ids, free_heads) = self.mem_pool.free_ids();
+
+		QueueDebug {
+			index: self.index.0,
+			size: self.size.0,
+			ring: RingDebug::Split {
+				avail_idx: unsafe { ptr::read_volatile(&*ring.avail_ring.index) },
+				used_idx: unsafe { ptr::read_volatile(&*ring.used_ring.index) },
+				last_used_idx: ring.read_idx,
+			},
+			outstanding: u16::try_from(ring.in_flight).unwrap_or(u16::MAX),
+			free_ids,
+			free_heads,
+		}
+	}
+
+	/// See `Virtq.dump_descriptors()` documentation
+	pub fn dump_descriptors(&self, count: usize) -> Vec<DescInfo> {
+		let ring = self.ring.borrow();
+
+		ring.descr_table
+			.raw
+			.iter()
+			.take(count)
+			.map(|desc| {
+				let desc = unsafe { ptr::read_volatile(desc) };
+				DescInfo {
+					addr: desc.address,
+					len: desc.len,
+					flags: desc.flags,
+					next: desc.next,
+				}
+			})
+			.collect()
+	}
 }
 
 // Private Interface for PackedVq
@@ -1530,14 +1810,14 @@ impl SplitVq {
 				for desc in recv_desc_lst {
 					desc_slice[crtl_desc_iter] = if desc_lst_len > 1 {
 						Descriptor::new(
-							paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
+							desc.phys.into(),
 							desc.len as u32,
 							DescrFlags::VIRTQ_DESC_F_WRITE | DescrFlags::VIRTQ_DESC_F_NEXT,
 							(crtl_desc_iter + 1) as u16,
 						)
 					} else {
 						Descriptor::new(
-							paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
+							desc.phys.into(),
 							desc.len as u32,
 							DescrFlags::VIRTQ_DESC_F_WRITE.into(),
 							0,
@@ -1554,18 +1834,13 @@ impl SplitVq {
 				for desc in send_desc_lst {
 					desc_slice[crtl_desc_iter] = if desc_lst_len > 1 {
 						Descriptor::new(
-							paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
+							desc.phys.into(),
 							desc.len as u32,
 							DescrFlags::VIRTQ_DESC_F_NEXT.into(),
 							(crtl_desc_iter + 1) as u16,
 						)
 					} else {
-						Descriptor::new(
-							paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
-							desc.len as u32,
-							0,
-							0,
-						)
+						Descriptor::new(desc.phys.into(), desc.len as u32, 0, 0)
 					};
 
 					desc_lst_len -= 1;
@@ -1578,18 +1853,13 @@ impl SplitVq {
 				for desc in send_desc_lst {
 					desc_slice[crtl_desc_iter] = if desc_lst_len > 1 {
 						Descriptor::new(
-							paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
+							desc.phys.into(),
 							desc.len as u32,
 							DescrFlags::VIRTQ_DESC_F_NEXT.into(),
 							(crtl_desc_iter + 1) as u16,
 						)
 					} else {
-						Descriptor::new(
-							paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
-							desc.len as u32,
-							0,
-							0,
-						)
+						Descriptor::new(desc.phys.into(), desc.len as u32, 0, 0)
 					};
 
 					desc_lst_len -= 1;
@@ -1599,14 +1869,14 @@ impl SplitVq {
 				for desc in recv_desc_lst {
 					desc_slice[crtl_desc_iter] = if desc_lst_len > 1 {
 						Descriptor::new(
-							paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
+							desc.phys.into(),
 							desc.len as u32,
 							DescrFlags::VIRTQ_DESC_F_WRITE | DescrFlags::VIRTQ_DESC_F_NEXT,
 							(crtl_desc_iter + 1) as u16,
 						)
 					} else {
 						Descriptor::new(
-							paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
+							desc.phys.into(),
 							desc.len as u32,
 							DescrFlags::VIRTQ_DESC_F_WRITE.into(),
 							0,
@@ -1622,3 +1892,28 @@ impl SplitVq {
 		}
 	}
 }
+
+#[cfg(all(test, not(target_os = "none")))]
+impl SplitVq {
+	/// Acts as the device of the queue. See [DescrRing::mock_use].
+	pub(crate) fn mock_use(&self, data: &[u8]) -> Option<Vec<u8>> {
+		self.ring.borrow_mut().mock_use(data)
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn pending_used_handles_empty_full_and_wrap() {
+		// Empty ring.
+		assert_eq!(pending_used(5, 5), 0);
+		assert_eq!(pending_used(u16::MAX, u16::MAX), 0);
+		// All entries of a full ring of 256 entries are used.
+		assert_eq!(pending_used(0, 256), 256);
+		assert_eq!(pending_used(u16::MAX - 127, 128), 256);
+		// The used index wrapped around, the read index not yet.
+		assert_eq!(pending_used(u16::MAX, 1), 2);
+	}
+}
@@ -1,5 +1,4 @@
 use alloc::boxed::Box;
-use alloc::vec::Vec;
 use core::slice;
 #[cfg(not(feature = "dhcpv4"))]
 use core::str::FromStr;
@@ -18,6 +17,7 @@ use crate::arch;
 use crate::arch::kernel::mmio as hardware;
 #[cfg(feature = "pci")]
 use crate::arch::kernel::pci as hardware;
+use crate::drivers::net::buffer_pool::PooledBuffer;
 #[cfg(not(feature = "dhcpv4"))]
 use crate::env;
 use crate::net::{NetworkInterface, NetworkState};
@@ -33,6 +33,14 @@ impl HermitNet {
 	pub(crate) const fn new(mtu: u16) -> Self {
 		Self { mtu }
 	}
+
+	/// Passes the IPv4 address of the interface to the driver, see
+	/// [NetworkInterface::set_ipv4_addr](crate::drivers::net::NetworkInterface::set_ipv4_addr).
+	pub(crate) fn set_ipv4_addr(&self, addr: [u8; 4]) {
+		if let Some(driver) = hardware::get_network_driver() {
+			driver.lock().set_ipv4_addr(addr);
+		}
+	}
 }
 
 /// Returns the value of the specified environment variable.
@@ -113,6 +121,7 @@ impl<'a> NetworkInterface<'a> {
 		let mut device = HermitNet::new(mtu);
 
 		let myip = Ipv4Address::from_str(hermit_var_or!("HERMIT_IP", "10.0.5.3")).unwrap();
+		device.set_ipv4_addr(myip.0);
 		let mygw = Ipv4Address::from_str(hermit_var_or!("HERMIT_GATEWAY", "10.0.5.1")).unwrap();
 		let mymask = Ipv4Address::from_str(hermit_var_or!("HERMIT_MASK", "255.255.255.0")).unwrap();
 
@@ -207,11 +216,11 @@ impl Device for HermitNet {
 
 #[doc(hidden)]
 pub(crate) struct RxToken {
-	buffer: Vec<u8>,
+	buffer: PooledBuffer,
 }
 
 impl RxToken {
-	pub(crate) fn new(buffer: Vec<u8>) -> Self {
+	pub(crate) fn new(buffer: PooledBuffer) -> Self {
 		Self { buffer }
 	}
 }
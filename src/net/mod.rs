@@ -139,6 +139,7 @@ impl<'a> NetworkInterface<'a> {
 			Some(dhcpv4::Event::Configured(config)) => {
 				info!("DHCP config acquired!");
 				info!("IP address:      {}", config.address);
+				self.device.set_ipv4_addr(config.address.address().0);
 				self.iface.update_ip_addrs(|addrs| {
 					if let Some(dest) = addrs.iter_mut().next() {
 						*dest = IpCidr::Ipv4(config.address);